@@ -0,0 +1,92 @@
+//! Pending kernel updates. A new kernel only runs after a reboot, so it is flagged apart
+//! from ordinary updates. The daemon and the CLI's agentless nodes report them alike.
+
+use crate::UpdateDetail;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelUpdate {
+    pub pending: bool,
+    /// Release of the newest kernel image a full upgrade would install, comparable with
+    /// the running release (`uname -r`). Unknown for kernels packaged without the release
+    /// in their name, like Raspberry Pi OS's `raspberrypi-kernel`.
+    pub candidate: Option<String>,
+}
+
+/// Looks for kernel images among `updates` and the packages a full upgrade would newly
+/// install; kernel upgrades usually arrive as a new `linux-image-<release>` package pulled
+/// in by an upgraded meta package like `linux-image-amd64`. Packages in `kept_back` won't
+/// be upgraded and don't count.
+pub fn pending(updates: &[UpdateDetail], new_packages: &[String], kept_back: &[String]) -> KernelUpdate {
+    let names: Vec<&str> = updates
+        .iter()
+        .map(|update| update.name.as_str())
+        .filter(|name| !kept_back.iter().any(|kept| kept == name))
+        .chain(new_packages.iter().map(String::as_str))
+        .filter(|name| is_kernel_package(name))
+        .collect();
+    KernelUpdate {
+        pending: !names.is_empty(),
+        candidate: names
+            .iter()
+            .filter_map(|name| release(name))
+            .max_by(|a, b| compare_releases(a, b))
+            .map(str::to_string),
+    }
+}
+
+fn is_kernel_package(name: &str) -> bool {
+    name.starts_with("linux-image-") || name == "raspberrypi-kernel"
+}
+
+/// The release in versioned image names like `linux-image-6.1.0-21-amd64`, as opposed to
+/// meta packages like `linux-image-generic`.
+fn release(name: &str) -> Option<&str> {
+    name.strip_prefix("linux-image-")
+        .filter(|release| release.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Compares runs of digits numerically, so that `6.1.0-21` sorts after `6.1.0-9`.
+fn compare_releases(a: &str, b: &str) -> Ordering {
+    let numbers = |release: &str| -> Vec<u64> {
+        release
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b)).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(name: &str) -> UpdateDetail {
+        UpdateDetail {
+            name: name.to_string(),
+            archive: Some("bookworm-security".to_string()),
+            security: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pending() {
+        let updates = vec![update("linux-image-amd64"), update("vim")];
+        let new_packages = vec![
+            "linux-image-6.1.0-9-amd64".to_string(),
+            "linux-image-6.1.0-21-amd64".to_string(),
+        ];
+        let kernel = pending(&updates, &new_packages, &[]);
+        assert!(kernel.pending);
+        assert_eq!(kernel.candidate.as_deref(), Some("6.1.0-21-amd64"));
+
+        let kernel = pending(&[update("raspberrypi-kernel")], &[], &[]);
+        assert!(kernel.pending);
+        assert_eq!(kernel.candidate, None);
+
+        let kept_back = vec!["linux-image-amd64".to_string()];
+        assert_eq!(pending(&updates, &[], &kept_back), KernelUpdate::default());
+        assert_eq!(pending(&[update("linux-firmware")], &[], &[]), KernelUpdate::default());
+    }
+}
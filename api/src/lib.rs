@@ -0,0 +1,1318 @@
+//! Types and constants of the cobbler daemon HTTP API, shared by `cobblerd`, the CLI and
+//! `cobbler-client` so their view of the wire format cannot drift apart.
+//!
+//! Fields that older daemons may omit are `#[serde(default)]`. The `openapi` feature derives
+//! utoipa schemas for the daemon's OpenAPI document.
+
+use serde::{Deserialize, Serialize};
+
+pub mod kernel;
+
+/// DNS-SD service type the daemon is announced under.
+pub const SERVICE_TYPE: &str = "_cobbler._tcp";
+/// Domain of multicast DNS-SD.
+pub const MDNS_DOMAIN: &str = "local.";
+/// Value of `service` in `GET /identify`.
+pub const SERVICE_NAME: &str = "cobblerd";
+/// Header carrying the API key on authenticated routes.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+/// Header carrying the request ID, echoed on every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Version of the HTTP API, bumped on incompatible changes.
+pub const API_VERSION: u32 = 1;
+
+/// Machine-readable reason of an error response. Clients should branch on the code, not on
+/// the English `message`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The API key is missing or wrong.
+    Unauthorized,
+    /// The request body or query is malformed.
+    InvalidRequest,
+    /// The requested resource (e.g. a job) does not exist.
+    NotFound,
+    /// A resource with the same name (e.g. an apt source) already exists.
+    AlreadyExists,
+    /// The node is not a Debian-based system with apt.
+    NotDebian,
+    /// A package the operation needs (named in `details`) is not installed.
+    NotInstalled,
+    /// A full upgrade or another package operation is already running.
+    UpgradeInProgress,
+    /// Another process holds the apt/dpkg lock.
+    LockHeld,
+    /// apt ran but failed.
+    AptFailed,
+    /// Scheduling or cancelling a reboot or shutdown failed.
+    RebootFailed,
+    /// An allowlisted command ran but exited unsuccessfully.
+    CommandFailed,
+    /// The job has already finished and can no longer be cancelled.
+    JobFinished,
+    /// A job or request ran longer than the daemon allows and was stopped.
+    Timeout,
+    /// The node runs on battery and is configured not to upgrade then.
+    OnBattery,
+    /// The node is on a metered connection and is configured not to upgrade then.
+    MeteredConnection,
+    /// The node has less free disk space than it is configured to require for an upgrade.
+    LowDiskSpace,
+    /// The daemon is not configured to allow the operation, e.g. on a service missing from
+    /// its allowlist.
+    NotAllowed,
+    /// systemctl failed to start, stop or restart a service.
+    ServiceFailed,
+    /// The daemon is shutting down, most likely to restart; retry after `retry_after`.
+    ShuttingDown,
+    /// The daemon is serving as many connections or requests as it is configured to; retry
+    /// after `retry_after`.
+    TooManyRequests,
+    /// Any other server-side failure.
+    Internal,
+    /// A code introduced by a newer daemon, or none sent by an older one.
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+/// Body of every error response.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorResponse {
+    #[serde(default)]
+    pub code: ErrorCode,
+    pub message: String,
+    /// Code-specific context, such as the offending package name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Object>))]
+    pub details: Option<serde_json::Value>,
+    /// Seconds after which retrying the request may succeed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            code,
+            message: message.into(),
+            details: None,
+            retry_after: None,
+            request_id: None,
+        }
+    }
+}
+
+/// Body of responses that only report what happened.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+/// `GET /identify`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdentifyResponse {
+    pub service: String,
+    pub id: String,
+    pub instance: String,
+    pub version: String,
+}
+
+/// `GET /version`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionResponse {
+    pub version: String,
+    pub git_commit: String,
+    pub build_date: String,
+    pub api_version: u32,
+    pub backend: String,
+}
+
+/// `GET /status`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusResponse {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub updates: Vec<String>,
+    /// Upgradable packages that apt defers because of a phased rollout (Ubuntu). They are
+    /// not part of `updates`.
+    #[serde(default)]
+    pub phased_updates: Vec<String>,
+    /// The archive each of `updates` comes from.
+    #[serde(default)]
+    pub update_details: Vec<UpdateDetail>,
+    /// How many of `updates` come from a security archive.
+    #[serde(default)]
+    pub security_updates: usize,
+    /// How many of `updates` don't.
+    #[serde(default)]
+    pub regular_updates: usize,
+    /// The highest severity of the vulnerabilities `updates` fix, see
+    /// [`UpdateDetail::severity`]. Unset if none are known.
+    #[serde(default)]
+    pub max_severity: Option<Severity>,
+    /// Upgradable packages a full upgrade would keep back, e.g. because they are held or
+    /// their new dependencies can't be satisfied. They are part of `updates`.
+    #[serde(default)]
+    pub kept_back: Vec<String>,
+    /// Installed packages a full upgrade would remove.
+    #[serde(default)]
+    pub removals: Vec<String>,
+    /// A full upgrade, install, removal or repair is running.
+    #[serde(default)]
+    pub is_upgrading: bool,
+    #[serde(default)]
+    pub reboot_required: bool,
+    #[serde(default)]
+    pub uptime_seconds: Option<u64>,
+    /// The running kernel release.
+    #[serde(default)]
+    pub kernel: Option<String>,
+    /// Whether the pending updates include a kernel, which needs a reboot to take effect.
+    #[serde(default)]
+    pub kernel_update_pending: bool,
+    /// Release of the kernel a full upgrade would install, comparable with `kernel`. Unset
+    /// if no kernel update is pending or its release isn't known in advance.
+    #[serde(default)]
+    pub kernel_candidate: Option<String>,
+    #[serde(default)]
+    pub last_upgrade: Option<u64>,
+    /// When the package lists were last refreshed, i.e. how fresh `updates` is. Unset
+    /// until the node first refreshes them.
+    #[serde(default)]
+    pub last_check: Option<u64>,
+    /// Job ID of a full upgrade the daemon lost track of because it restarted while the
+    /// upgrade ran. Cleared by the next full upgrade.
+    #[serde(default)]
+    pub interrupted_upgrade: Option<String>,
+    /// systemd services still running executables or libraries that an upgrade replaced,
+    /// and so the old code, until they restart.
+    #[serde(default)]
+    pub services_need_restart: Vec<String>,
+    /// Overall state derived from the fields above, see [`StatusResponse::assess_health`].
+    #[serde(default)]
+    pub health: HealthState,
+    /// Why `health` is not `ok`, worst first.
+    #[serde(default)]
+    pub health_reasons: Vec<String>,
+}
+
+impl StatusResponse {
+    /// Names accepted by the `fields` query parameter of `GET /status`.
+    pub const FIELDS: &'static [&'static str] = &[
+        "message",
+        "updates",
+        "phased_updates",
+        "update_details",
+        "security_updates",
+        "regular_updates",
+        "max_severity",
+        "kept_back",
+        "removals",
+        "is_upgrading",
+        "reboot_required",
+        "uptime_seconds",
+        "kernel",
+        "kernel_update_pending",
+        "kernel_candidate",
+        "last_upgrade",
+        "last_check",
+        "interrupted_upgrade",
+        "services_need_restart",
+        "health",
+        "health_reasons",
+    ];
+
+    /// Rates the node by its pending and security updates, reboot flag, interrupted upgrade,
+    /// services needing a restart and the age of its last successful upgrade, at `now` (Unix seconds). Returns the state and the reasons
+    /// for it, worst first.
+    pub fn assess_health(&self, now: u64) -> (HealthState, Vec<String>) {
+        let upgrade_age = self.last_upgrade.map(|timestamp| now.saturating_sub(timestamp));
+        let stale = |limit: u64| upgrade_age.is_some_and(|age| age >= limit);
+
+        let mut findings = Vec::new();
+        if self.security_updates > 0 {
+            let state = if stale(STALE_UPGRADE_SECS) { HealthState::Critical } else { HealthState::NeedsAttention };
+            findings.push((state, format!("{} security update(s) pending", self.security_updates)));
+        }
+        if !self.updates.is_empty() && stale(STALE_UPGRADE_SECS) {
+            let state = if stale(CRITICAL_UPGRADE_SECS) { HealthState::Critical } else { HealthState::NeedsAttention };
+            let days = upgrade_age.unwrap_or_default() / 86_400;
+            findings.push((state, format!("last upgrade {days} days ago")));
+        }
+        if self.reboot_required {
+            findings.push((HealthState::NeedsAttention, "reboot required".to_string()));
+        }
+        if self.interrupted_upgrade.is_some() {
+            findings.push((HealthState::NeedsAttention, "last upgrade was interrupted".to_string()));
+        }
+        if !self.services_need_restart.is_empty() {
+            let count = self.services_need_restart.len();
+            findings.push((HealthState::NeedsAttention, format!("{count} service(s) need a restart")));
+        }
+        if self.updates.len() >= MANY_UPDATES {
+            findings.push((HealthState::NeedsAttention, format!("{} updates pending", self.updates.len())));
+        }
+
+        findings.sort_by_key(|(state, _)| std::cmp::Reverse(*state));
+        let state = findings.first().map_or(HealthState::Ok, |(state, _)| *state);
+        (state, findings.into_iter().map(|(_, reason)| reason).collect())
+    }
+}
+
+/// Pending updates from which a node needs attention even without security updates.
+const MANY_UPDATES: usize = 20;
+/// Age of the last upgrade after which pending updates need attention.
+const STALE_UPGRADE_SECS: u64 = 7 * 86_400;
+/// Age of the last upgrade after which pending updates are critical.
+const CRITICAL_UPGRADE_SECS: u64 = 30 * 86_400;
+
+/// Node health at a glance, ordered from best to worst.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthState {
+    /// Up to date, or only a few regular updates pending.
+    #[default]
+    Ok,
+    /// Security updates, a pending reboot, an interrupted upgrade, services running outdated
+    /// code, many updates, or updates pending more than a week after the last upgrade.
+    NeedsAttention,
+    /// Security updates pending more than a week after the last upgrade, any updates
+    /// pending more than a month after it, or (as judged by clients) unreachable.
+    Critical,
+}
+
+impl HealthState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthState::Ok => "ok",
+            HealthState::NeedsAttention => "needs-attention",
+            HealthState::Critical => "critical",
+        }
+    }
+}
+
+/// A pending update and where its candidate version comes from.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateDetail {
+    pub name: String,
+    /// Archive of the candidate version, e.g. `bookworm-security`, `jammy-updates` or
+    /// `bookworm-backports`.
+    pub archive: Option<String>,
+    #[serde(default)]
+    pub security: bool,
+    /// Distribution advisories the update fixes, e.g. `DSA-5678-1` or `USN-6789-1`. Only
+    /// known if the daemon looks advisories up.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub advisories: Vec<String>,
+    /// CVEs the update fixes, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cves: Vec<String>,
+    /// The highest severity among the fixed vulnerabilities.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+}
+
+/// Severity of a vulnerability, ordered from least to most severe. Taken from the CVSS base
+/// score where there is one (low below 4.0, medium below 7.0, high below 9.0), and from the
+/// distribution's own rating otherwise.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Rated unimportant or negligible by the distribution.
+    Negligible,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Negligible => "negligible",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusQuery {
+    /// Comma-separated fields to include in the response, e.g.
+    /// `message,is_upgrading,reboot_required`. All fields if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<String>,
+    /// Run `apt-get update` before checking for updates (default `true`). The daemon still
+    /// runs it at most once per its update interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<bool>,
+}
+
+/// `GET /status/wait`
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusWaitQuery {
+    /// ETag of the status the client has; the request returns once the status differs from
+    /// it. Without one it returns right away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    /// Seconds to wait at most (default 60, at most 300).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Comma-separated fields, as for `GET /status`. Only changes to these count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<String>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    pub name: String,
+    pub installed_version: String,
+    pub candidate_version: Option<String>,
+    #[serde(default)]
+    pub upgradable: bool,
+    #[serde(default)]
+    pub held: bool,
+}
+
+impl PackageInfo {
+    /// Names accepted by the `fields` query parameter of `GET /packages`.
+    pub const FIELDS: &'static [&'static str] =
+        &["name", "installed_version", "candidate_version", "upgradable", "held"];
+}
+
+/// `GET /packages`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackagesResponse {
+    pub packages: Vec<PackageInfo>,
+}
+
+/// The `state` filter of `GET /packages`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageState {
+    Installed,
+    Upgradable,
+    Held,
+}
+
+impl PackageState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PackageState::Installed => "installed",
+            PackageState::Upgradable => "upgradable",
+            PackageState::Held => "held",
+        }
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackagesQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<PackageState>,
+    /// Comma-separated fields to include for each package, e.g. `name,candidate_version`.
+    /// All fields if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<String>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyQuery {
+    /// Comma-separated packages to show. All upgradable packages if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packages: Option<String>,
+}
+
+/// `GET /packages/policy`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PoliciesResponse {
+    pub policies: Vec<PackagePolicy>,
+}
+
+/// The pin priorities apt applies to the versions of a package, as shown by
+/// `apt-cache policy`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackagePolicy {
+    pub name: String,
+    pub installed: Option<String>,
+    pub candidate: Option<String>,
+    /// Known versions, newest first.
+    #[serde(default)]
+    pub versions: Vec<PolicyVersion>,
+}
+
+impl PackagePolicy {
+    pub fn version(&self, version: &str) -> Option<&PolicyVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+
+    pub fn newest(&self) -> Option<&PolicyVersion> {
+        self.versions.first()
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PolicyVersion {
+    pub version: String,
+    /// Effective pin priority of this version.
+    pub priority: i32,
+    /// Where the version comes from (repositories, or `/var/lib/dpkg/status` if installed),
+    /// each with its own priority.
+    #[serde(default)]
+    pub sources: Vec<PolicySource>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PolicySource {
+    pub priority: i32,
+    pub source: String,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionsQuery {
+    /// How many of the most recent transactions to return. 20 if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// `GET /packages/transactions`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionsResponse {
+    /// Newest first.
+    pub transactions: Vec<AptTransaction>,
+}
+
+/// An apt run as recorded in `/var/log/apt/history.log`, whether cobbler started it or not.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AptTransaction {
+    /// When apt started, in the node's local time as `YYYY-MM-DDTHH:MM:SS`.
+    pub start: String,
+    /// When apt finished; unset if it didn't, e.g. because it crashed.
+    #[serde(default)]
+    pub end: Option<String>,
+    /// The command that ran apt, e.g. `apt-get -y dist-upgrade`.
+    #[serde(default)]
+    pub command_line: Option<String>,
+    /// The user who ran it through sudo, e.g. `admin (1000)`.
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    #[serde(default)]
+    pub installed: Vec<PackageChange>,
+    #[serde(default)]
+    pub upgraded: Vec<PackageChange>,
+    #[serde(default)]
+    pub downgraded: Vec<PackageChange>,
+    /// Removed or purged packages.
+    #[serde(default)]
+    pub removed: Vec<PackageChange>,
+    /// The error apt ended with, e.g. `Sub-process /usr/bin/dpkg returned an error code (1)`.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// A package an apt transaction changed.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageChange {
+    pub name: String,
+    /// Architecture qualifier, e.g. `amd64` or `all`.
+    #[serde(default)]
+    pub arch: Option<String>,
+    /// The version before an upgrade or downgrade.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_version: Option<String>,
+    /// The version installed, or the one removed.
+    pub version: String,
+    /// Installed as a dependency rather than on request.
+    #[serde(default)]
+    pub automatic: bool,
+}
+
+/// `POST /packages/install` and `POST /packages/remove`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageActionRequest {
+    pub packages: Vec<String>,
+    /// The daemon's policy if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conffiles: Option<ConffilePolicy>,
+    /// Download rate limit in KB/s, `0` for none. The daemon's limit if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<u32>,
+}
+
+/// Also returned by `POST /sources` and `DELETE /sources/{name}`, with the output of
+/// `apt-get update`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageActionResponse {
+    pub message: String,
+    /// The last lines of apt's output.
+    #[serde(default)]
+    pub output: String,
+}
+
+/// `POST /packages/repair`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RepairResponse {
+    pub message: String,
+    /// Packages that were configured, installed or removed to finish the interrupted
+    /// operations. Empty if there was nothing to repair.
+    #[serde(default)]
+    pub fixed: Vec<String>,
+    /// The last lines of dpkg's and apt's output.
+    #[serde(default)]
+    pub output: String,
+}
+
+/// What a full upgrade does with updates held back by a phased rollout.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PhasedUpdates {
+    /// Install them even if this node is not part of the rollout yet.
+    Include,
+    /// Skip them even if this node is part of the rollout.
+    Exclude,
+}
+
+/// What dpkg does with a configuration file that was changed locally and also differs in
+/// the new package version, instead of asking. Files dpkg has a default for (e.g. ones not
+/// changed locally) get the default either way.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConffilePolicy {
+    /// Keep the local version (`--force-confold`).
+    #[default]
+    ConfOld,
+    /// Install the package's version (`--force-confnew`); dpkg saves the local one as
+    /// `.dpkg-old`.
+    ConfNew,
+}
+
+impl ConffilePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConffilePolicy::ConfOld => "confold",
+            ConffilePolicy::ConfNew => "confnew",
+        }
+    }
+}
+
+/// How far an upgrade may go to bring packages up to date, named after the apt command it
+/// runs.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpgradeStrategy {
+    /// Installs new dependencies but never removes a package, keeping back upgrades that would.
+    Upgrade,
+    /// Installs and removes packages as the upgrades require.
+    #[default]
+    FullUpgrade,
+    /// apt-get's name for the same, for fleets whose runbooks use it.
+    DistUpgrade,
+}
+
+impl UpgradeStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UpgradeStrategy::Upgrade => "upgrade",
+            UpgradeStrategy::FullUpgrade => "full-upgrade",
+            UpgradeStrategy::DistUpgrade => "dist-upgrade",
+        }
+    }
+}
+
+/// `POST /packages/full-upgrade`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FullUpgradeRequest {
+    /// apt's own rollout decision if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phased_updates: Option<PhasedUpdates>,
+    /// The daemon's policy if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conffiles: Option<ConffilePolicy>,
+    /// Download rate limit in KB/s, `0` for none. The daemon's limit if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<u32>,
+    /// Packages the upgrade must not touch, in addition to the daemon's, as names or
+    /// patterns where `*` matches any text, e.g. `nvidia-*`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Upgrade only the installed packages matching these names or patterns, e.g. `openssl*`,
+    /// instead of the whole system.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only: Vec<String>,
+    /// The daemon's strategy if absent. Ignored with `only`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<UpgradeStrategy>,
+}
+
+/// `POST /packages/prefetch`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefetchRequest {
+    /// Download rate limit in KB/s, `0` for none. The daemon's limit if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<u32>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeResponse {
+    pub message: String,
+    pub job_id: String,
+    /// Number of package operations ahead of the job; `0` if it started right away.
+    #[serde(default)]
+    pub queue_position: usize,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+    FullUpgrade,
+    /// Downloads the pending packages without installing them.
+    Prefetch,
+}
+
+impl JobKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobKind::FullUpgrade => "full-upgrade",
+            JobKind::Prefetch => "prefetch",
+        }
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for other package operations to finish.
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    /// The daemon restarted while the job ran, so its outcome is unknown.
+    Interrupted,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+            JobState::Interrupted => "interrupted",
+        }
+    }
+
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled | JobState::Interrupted
+        )
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobsQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<JobState>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<JobKind>,
+    /// Only jobs started at or after this Unix timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// How many jobs to return. 20 if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// How many of the matching jobs to skip, for the next page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+/// `GET /jobs`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobsResponse {
+    /// Newest first.
+    pub jobs: Vec<Job>,
+    /// How many jobs match the filters, across all pages.
+    pub total: usize,
+}
+
+/// `GET /snapshot`
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotQuery {
+    /// As for `GET /status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<bool>,
+}
+
+/// `GET /snapshot`: status, build, resource usage and jobs of a node in one response, for
+/// views that show all of them.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotResponse {
+    pub status: StatusResponse,
+    pub version: VersionResponse,
+    pub resources: ResourcesResponse,
+    pub jobs: JobsSummary,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JobsSummary {
+    /// Running and queued jobs, oldest first.
+    pub active: Vec<Job>,
+    /// The job that finished last, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_finished: Option<Job>,
+}
+
+/// `GET /jobs/{id}`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: JobKind,
+    pub state: JobState,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded_bytes: Option<u64>,
+    /// Number of package operations ahead of the job while it is queued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+    /// Why a failed job failed, when it was not apt's own doing (e.g. `TIMEOUT`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// What apt is doing, while the job runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<JobProgress>,
+}
+
+/// apt's progress as reported on its status file descriptor.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobProgress {
+    pub phase: JobPhase,
+    /// Completion of the current stage: downloading, or installing and removing packages.
+    pub percent: f64,
+    /// The package being processed; unset while downloading.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPhase {
+    Downloading,
+    Unpacking,
+    Configuring,
+    Removing,
+}
+
+impl JobPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobPhase::Downloading => "downloading",
+            JobPhase::Unpacking => "unpacking",
+            JobPhase::Configuring => "configuring",
+            JobPhase::Removing => "removing",
+        }
+    }
+}
+
+/// Query of `GET /jobs/{id}/log` and `GET /logs/{name}`
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Only the last `tail` lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<usize>,
+    /// The `next` of an earlier response, to get only the lines added since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+}
+
+/// `GET /jobs/{id}/log` and `GET /logs/{name}`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogResponse {
+    pub lines: Vec<String>,
+    /// Cursor to pass as `since` to continue after these lines.
+    pub next: u64,
+    /// The job has finished, so no more lines will follow. Always false for system logs.
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// `GET` and `PUT /config/unattended-upgrades`. `PUT` replaces the whole configuration.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnattendedUpgradesConfig {
+    /// `APT::Periodic::Unattended-Upgrade`
+    #[serde(default)]
+    pub enabled: bool,
+    /// `Unattended-Upgrade::Allowed-Origins`, e.g. `${distro_id}:${distro_codename}-security`
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// `Unattended-Upgrade::Origins-Pattern`, e.g. `origin=Debian,label=Debian-Security`
+    #[serde(default)]
+    pub origins_pattern: Vec<String>,
+    /// `Unattended-Upgrade::Automatic-Reboot`
+    #[serde(default)]
+    pub automatic_reboot: bool,
+    /// `Unattended-Upgrade::Automatic-Reboot-Time`: `HH:MM` or `now`
+    #[serde(default)]
+    pub automatic_reboot_time: Option<String>,
+}
+
+/// An apt repository entry, from a one-line `.list` or a deb822 `.sources` file.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AptSource {
+    /// File name in `sources.list.d` without extension, which `DELETE /sources/{name}`
+    /// takes. Unset for `/etc/apt/sources.list`.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub file: String,
+    pub enabled: bool,
+    /// `deb` and/or `deb-src`
+    pub types: Vec<String>,
+    pub uris: Vec<String>,
+    pub suites: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Keyring path or inline key the repository is verified with.
+    #[serde(default)]
+    pub signed_by: Option<String>,
+}
+
+/// `GET /sources`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourcesResponse {
+    pub sources: Vec<AptSource>,
+}
+
+/// `POST /sources`, which writes `/etc/apt/sources.list.d/{name}.sources`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AddSourceRequest {
+    /// File name without extension: letters, digits, `_`, `-` and `.`.
+    pub name: String,
+    /// Defaults to `["deb"]`.
+    #[serde(default)]
+    pub types: Vec<String>,
+    pub uris: Vec<String>,
+    pub suites: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Absolute path of a keyring on the node, or an ASCII-armored public key to embed.
+    #[serde(default)]
+    pub signed_by: Option<String>,
+}
+
+/// `POST /system/reboot`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebootRequest {
+    #[serde(default)]
+    pub delay_minutes: u32,
+}
+
+/// `POST /system/shutdown`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownRequest {
+    #[serde(default)]
+    pub delay_minutes: u32,
+}
+
+/// `GET /system/sensors`. Each part is empty where the hardware or kernel doesn't expose it,
+/// as in most VMs and containers.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SensorsResponse {
+    /// The CPU's or SoC's temperature, picked from `temperatures`.
+    #[serde(default)]
+    pub cpu_temperature_celsius: Option<f64>,
+    #[serde(default)]
+    pub temperatures: Vec<Temperature>,
+    #[serde(default)]
+    pub fans: Vec<Fan>,
+    /// Raspberry Pi firmware throttling, from `vcgencmd get_throttled`.
+    #[serde(default)]
+    pub throttling: Option<Throttling>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Temperature {
+    /// Thermal zone type or hwmon chip and label, e.g. `cpu-thermal` or `coretemp Core 0`.
+    pub name: String,
+    pub celsius: f64,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fan {
+    /// hwmon chip and label, e.g. `pwmfan fan1`.
+    pub name: String,
+    pub rpm: u32,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Throttling {
+    /// The bit field as `vcgencmd` reports it, e.g. `0x50005`.
+    pub raw: String,
+    /// Conditions in effect now.
+    #[serde(default)]
+    pub active: Vec<ThrottleCondition>,
+    /// Conditions that occurred since boot, including the active ones.
+    #[serde(default)]
+    pub occurred: Vec<ThrottleCondition>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThrottleCondition {
+    UnderVoltage,
+    FrequencyCapped,
+    Throttled,
+    SoftTemperatureLimit,
+}
+
+/// `GET /system/resources`. Parts the node can't report are unset or empty.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ResourcesResponse {
+    #[serde(default)]
+    pub load_average: Option<LoadAverage>,
+    /// Logical CPUs, to put `load_average` in relation.
+    #[serde(default)]
+    pub cpus: Option<usize>,
+    #[serde(default)]
+    pub memory: Option<MemoryUsage>,
+    /// Unset if the node has no swap.
+    #[serde(default)]
+    pub swap: Option<MemoryUsage>,
+    /// Local filesystems by mount point, as `df` lists them.
+    #[serde(default)]
+    pub disks: Vec<DiskUsage>,
+}
+
+impl ResourcesResponse {
+    /// The disk with the highest [`DiskUsage::used_percent`].
+    pub fn fullest_disk(&self) -> Option<&DiskUsage> {
+        self.disks
+            .iter()
+            .max_by(|a, b| a.used_percent().total_cmp(&b.used_percent()))
+    }
+}
+
+/// Run queue length averaged over 1, 5 and 15 minutes, from `/proc/loadavg`.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    /// What can still be used without swapping, including reclaimable caches (`MemAvailable`).
+    /// For swap, the free swap space.
+    pub available_bytes: u64,
+}
+
+impl MemoryUsage {
+    pub fn used_percent(&self) -> f64 {
+        percent(self.used_bytes, self.total_bytes)
+    }
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    /// Filesystem type, e.g. `ext4`.
+    pub filesystem: String,
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    /// Free space available to unprivileged users, i.e. without the blocks reserved for root.
+    pub available_bytes: u64,
+}
+
+impl DiskUsage {
+    /// Used share of the space available to unprivileged users, as `df` computes `Use%`.
+    pub fn used_percent(&self) -> f64 {
+        percent(self.used_bytes, self.used_bytes + self.available_bytes)
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / total as f64
+}
+
+/// `POST /commands/{name}`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommandResponse {
+    pub name: String,
+    pub exit_code: Option<i32>,
+    /// The last lines of the command's stdout and stderr.
+    #[serde(default)]
+    pub output: String,
+}
+
+/// `POST /services/restart-outdated`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestartServicesResponse {
+    pub restarted: Vec<String>,
+    /// Services left alone because restarting them would end sessions or the daemon itself.
+    #[serde(default)]
+    pub skipped: Vec<String>,
+    #[serde(default)]
+    pub failed: Vec<ServiceFailure>,
+}
+
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceFailure {
+    pub service: String,
+    /// What systemctl reported.
+    pub message: String,
+}
+
+/// `GET /services`
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServicesResponse {
+    /// Sorted by name.
+    pub services: Vec<ServiceInfo>,
+}
+
+/// A systemd service, as `GET /services` lists it and `POST /services/{name}/{action}`
+/// leaves it.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// Unit name, e.g. `nginx.service`.
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// systemd's `ActiveState`: `active`, `inactive`, `failed`, `activating`, ...
+    pub active_state: String,
+    /// systemd's `SubState`, e.g. `running`, `exited` or `dead`.
+    pub sub_state: String,
+    /// Started at boot.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The daemon's allowlist lets clients start, stop and restart it.
+    #[serde(default)]
+    pub allowed: bool,
+}
+
+/// What `POST /services/{name}/{action}` does.
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl ServiceAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ServiceAction::Start => "start",
+            ServiceAction::Stop => "stop",
+            ServiceAction::Restart => "restart",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_deserialization() {
+        let job: Job = serde_json::from_str(
+            r#"{"id":"1","type":"full-upgrade","state":"failed","started_at":1,"message":"boom"}"#,
+        )
+        .unwrap();
+        assert_eq!(job.kind, JobKind::FullUpgrade);
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.message, "boom");
+        assert_eq!(job.finished_at, None);
+    }
+
+    #[test]
+    fn test_status_response_defaults() {
+        let status: StatusResponse =
+            serde_json::from_str(r#"{"message":"System is up to date","updates":[]}"#).unwrap();
+        assert_eq!(status.message, "System is up to date");
+        assert!(!status.reboot_required);
+        assert_eq!(status.uptime_seconds, None);
+    }
+
+    #[test]
+    fn test_error_response_serialization() {
+        let error = ErrorResponse::new(ErrorCode::NotFound, "job not found");
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"code":"NOT_FOUND","message":"job not found"}"#
+        );
+
+        let error = ErrorResponse {
+            retry_after: Some(30),
+            ..ErrorResponse::new(ErrorCode::UpgradeInProgress, "a full upgrade is currently running")
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "code": "UPGRADE_IN_PROGRESS",
+                "message": "a full upgrade is currently running",
+                "retry_after": 30,
+            })
+        );
+
+        let error: ErrorResponse =
+            serde_json::from_str(r#"{"code":"DISK_FULL","message":"no space left"}"#).unwrap();
+        assert_eq!(error.code, ErrorCode::Unknown);
+    }
+
+    #[test]
+    fn test_assess_health() {
+        let now = 100 * 86_400;
+        let status = StatusResponse {
+            updates: vec!["vim".to_string()],
+            last_upgrade: Some(now - 86_400),
+            ..Default::default()
+        };
+        assert_eq!(status.assess_health(now), (HealthState::Ok, vec![]));
+
+        let status = StatusResponse {
+            security_updates: 1,
+            reboot_required: true,
+            ..status
+        };
+        let (state, reasons) = status.assess_health(now);
+        assert_eq!(state, HealthState::NeedsAttention);
+        assert_eq!(reasons, vec!["1 security update(s) pending", "reboot required"]);
+
+        let status = StatusResponse {
+            last_upgrade: Some(now - 10 * 86_400),
+            ..status
+        };
+        let (state, reasons) = status.assess_health(now);
+        assert_eq!(state, HealthState::Critical);
+        assert_eq!(reasons[..2], ["1 security update(s) pending", "last upgrade 10 days ago"]);
+        assert_eq!(serde_json::to_value(state).unwrap(), "critical");
+
+        let status = StatusResponse {
+            interrupted_upgrade: Some("5f0c6a8e".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            status.assess_health(now),
+            (HealthState::NeedsAttention, vec!["last upgrade was interrupted".to_string()])
+        );
+
+        let status = StatusResponse {
+            services_need_restart: vec!["nginx.service".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(status.assess_health(now).1, ["1 service(s) need a restart"]);
+    }
+
+    #[test]
+    fn test_fields_match_serialized_keys() {
+        fn keys(value: serde_json::Value) -> Vec<String> {
+            value.as_object().unwrap().keys().cloned().collect()
+        }
+        let mut expected: Vec<&str> = StatusResponse::FIELDS.to_vec();
+        expected.sort();
+        assert_eq!(keys(serde_json::to_value(StatusResponse::default()).unwrap()), expected);
+
+        let package = PackageInfo {
+            name: "vim".to_string(),
+            installed_version: "1".to_string(),
+            candidate_version: None,
+            upgradable: false,
+            held: false,
+        };
+        let mut expected: Vec<&str> = PackageInfo::FIELDS.to_vec();
+        expected.sort();
+        assert_eq!(keys(serde_json::to_value(package).unwrap()), expected);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::config::{Config, NodeConfig, Transport};
+use crate::discovery::AddressPreference;
+use crate::{inventory, ExitStatus};
+use clap::ValueEnum;
+use serde_yaml::{Mapping, Value};
+use std::error::Error;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InventoryFormat {
+    #[default]
+    Yaml,
+    Ini,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Host {
+    name: String,
+    vars: Vec<(&'static str, String)>,
+    groups: Vec<String>,
+}
+
+impl Host {
+    fn from_node(node: &NodeConfig) -> Host {
+        let host = address_host(&node.address);
+        let mut vars = vec![("ansible_host", host.to_string())];
+        match (node.transport, &node.ssh) {
+            (Transport::Ssh, Some(ssh)) => {
+                vars.extend(ssh.user.clone().map(|user| ("ansible_user", user)));
+                vars.extend(ssh.port.map(|port| ("ansible_port", port.to_string())));
+            }
+            (Transport::Ssh, None) => {}
+            (Transport::Agent, _) => vars.push(("cobbler_address", node.address.clone())),
+        }
+        Host {
+            name: node.name.clone().unwrap_or_else(|| host.to_string()),
+            vars,
+            groups: node.tags.iter().map(|tag| group_name(tag)).collect(),
+        }
+    }
+}
+
+/// Prints the configured nodes, plus those found via mDNS with `discover`, as an Ansible
+/// inventory. Tags become groups.
+pub fn run_export_inventory(
+    format: InventoryFormat,
+    discover: Option<AddressPreference>,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let nodes = inventory::fleet_nodes(config, discover)?;
+    let hosts: Vec<Host> = nodes.iter().map(Host::from_node).collect();
+    match format {
+        InventoryFormat::Yaml => print!("{}", render_yaml(&hosts)?),
+        InventoryFormat::Ini => print!("{}", render_ini(&hosts)),
+    }
+    Ok(ExitStatus::Ok)
+}
+
+/// The host part of `host:port`, `[v6]:port` or a bare host.
+fn address_host(address: &str) -> &str {
+    if let Some(rest) = address.strip_prefix('[') {
+        return rest.split_once(']').map_or(rest, |(host, _)| host);
+    }
+    match address.rsplit_once(':') {
+        Some((host, _)) if !host.contains(':') => host,
+        _ => address,
+    }
+}
+
+/// Ansible group names may only contain letters, digits and underscores.
+fn group_name(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Group names in order of first appearance.
+fn groups(hosts: &[Host]) -> Vec<&str> {
+    let mut groups: Vec<&str> = Vec::new();
+    for group in hosts.iter().flat_map(|host| &host.groups) {
+        if !groups.contains(&group.as_str()) {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+fn render_yaml(hosts: &[Host]) -> Result<String, serde_yaml::Error> {
+    let mut all_hosts = Mapping::new();
+    for host in hosts {
+        let vars: Mapping = host
+            .vars
+            .iter()
+            .map(|(key, value)| (Value::from(*key), Value::from(value.as_str())))
+            .collect();
+        all_hosts.insert(host.name.as_str().into(), Value::Mapping(vars));
+    }
+
+    let mut children = Mapping::new();
+    for group in groups(hosts) {
+        let members: Mapping = hosts
+            .iter()
+            .filter(|host| host.groups.iter().any(|g| g == group))
+            .map(|host| (Value::from(host.name.as_str()), Value::Null))
+            .collect();
+        let mut entry = Mapping::new();
+        entry.insert("hosts".into(), Value::Mapping(members));
+        children.insert(group.into(), Value::Mapping(entry));
+    }
+
+    let mut all = Mapping::new();
+    all.insert("hosts".into(), Value::Mapping(all_hosts));
+    if !children.is_empty() {
+        all.insert("children".into(), Value::Mapping(children));
+    }
+    let mut inventory = Mapping::new();
+    inventory.insert("all".into(), Value::Mapping(all));
+    serde_yaml::to_string(&inventory)
+}
+
+fn render_ini(hosts: &[Host]) -> String {
+    let mut out = String::new();
+    for host in hosts {
+        out.push_str(&host.name);
+        for (key, value) in &host.vars {
+            out.push_str(&format!(" {key}={value}"));
+        }
+        out.push('\n');
+    }
+    for group in groups(hosts) {
+        out.push_str(&format!("\n[{group}]\n"));
+        for host in hosts.iter().filter(|host| host.groups.iter().any(|g| g == group)) {
+            out.push_str(&host.name);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SshConfig;
+
+    fn hosts() -> Vec<Host> {
+        [
+            NodeConfig {
+                name: Some("web-1".to_string()),
+                address: "10.0.0.1:8080".to_string(),
+                tags: vec!["web".to_string(), "prod-eu".to_string()],
+                ..Default::default()
+            },
+            NodeConfig {
+                address: "[fd00::2]:8080".to_string(),
+                tags: vec!["web".to_string()],
+                ..Default::default()
+            },
+            NodeConfig {
+                name: Some("legacy-1".to_string()),
+                address: "legacy-1.example.com".to_string(),
+                transport: Transport::Ssh,
+                ssh: Some(SshConfig {
+                    user: Some("admin".to_string()),
+                    port: Some(2222),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        .map(Host::from_node)
+        .collect()
+    }
+
+    #[test]
+    fn test_render_yaml() {
+        let yaml: Value = serde_yaml::from_str(&render_yaml(&hosts()).unwrap()).unwrap();
+        let all = &yaml["all"];
+        assert_eq!(all["hosts"]["web-1"]["ansible_host"], "10.0.0.1");
+        assert_eq!(all["hosts"]["web-1"]["cobbler_address"], "10.0.0.1:8080");
+        assert_eq!(all["hosts"]["fd00::2"]["ansible_host"], "fd00::2");
+        assert_eq!(all["hosts"]["legacy-1"]["ansible_port"], "2222");
+        let web = all["children"]["web"]["hosts"].as_mapping().unwrap();
+        assert_eq!(web.len(), 2);
+        assert!(all["children"]["prod_eu"]["hosts"]["web-1"].is_null());
+    }
+
+    #[test]
+    fn test_render_ini() {
+        assert_eq!(
+            render_ini(&hosts()),
+            "\
+web-1 ansible_host=10.0.0.1 cobbler_address=10.0.0.1:8080
+fd00::2 ansible_host=fd00::2 cobbler_address=[fd00::2]:8080
+legacy-1 ansible_host=legacy-1.example.com ansible_user=admin ansible_port=2222
+
+[web]
+web-1
+fd00::2
+
+[prod_eu]
+web-1
+"
+        );
+        assert_eq!(address_host("raspi1.local:8080"), "raspi1.local");
+    }
+}
@@ -0,0 +1,129 @@
+//! The last successful status of each node, kept under the user cache dir so the fleet can
+//! still be viewed when nodes can't be reached, e.g. with the VPN down.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedStatus {
+    /// Unix time the status was fetched at
+    pub fetched_at: u64,
+    pub status: serde_json::Value,
+}
+
+/// Cached statuses by target, as given on the command line or taken from the config.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StatusCache {
+    nodes: BTreeMap<String, CachedStatus>,
+}
+
+impl StatusCache {
+    /// Reads the cache; a missing or unreadable one is treated as empty.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Merges these statuses into the cache file, keeping the newer status of each node, so
+    /// that concurrent runs, e.g. a `status --watch` and a cron job, don't drop each other's.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+        }
+        // The cache itself is replaced by renaming, so the lock is taken on a file of its own.
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock = File::create(&lock_path).map_err(|err| format!("failed to lock {}: {err}", path.display()))?;
+        lock.lock().map_err(|err| format!("failed to lock {}: {err}", path.display()))?;
+
+        let mut merged = Self::load(path);
+        for (target, entry) in &self.nodes {
+            if merged.get(target).is_none_or(|current| current.fetched_at <= entry.fetched_at) {
+                merged.nodes.insert(target.clone(), entry.clone());
+            }
+        }
+        // Readers never see a partly written file.
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        fs::write(&temp, serde_json::to_string(&merged)?)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        fs::rename(&temp, path).map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn get(&self, target: &str) -> Option<&CachedStatus> {
+        self.nodes.get(target)
+    }
+
+    pub fn insert(&mut self, target: &str, fetched_at: u64, status: serde_json::Value) {
+        self.nodes
+            .insert(target.to_string(), CachedStatus { fetched_at, status });
+    }
+}
+
+/// `$COBBLER_CACHE_DIR/status.json`, falling back to `$XDG_CACHE_HOME/cobbler` and
+/// `~/.cache/cobbler`.
+pub fn status_cache_path() -> Option<PathBuf> {
+    cache_dir(
+        env::var_os("COBBLER_CACHE_DIR"),
+        env::var_os("XDG_CACHE_HOME"),
+        env::var_os("HOME"),
+    )
+    .map(|dir| dir.join("status.json"))
+}
+
+fn cache_dir(explicit: Option<OsString>, xdg_cache_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let non_empty = |dir: Option<OsString>| dir.filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    non_empty(explicit)
+        .or_else(|| non_empty(xdg_cache_home).map(|dir| dir.join("cobbler")))
+        .or_else(|| non_empty(home).map(|dir| dir.join(".cache").join("cobbler")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_dir() {
+        let dir = |explicit: &str, xdg: &str, home: &str| {
+            cache_dir(Some(explicit.into()), Some(xdg.into()), Some(home.into()))
+        };
+        assert_eq!(dir("/tmp/c", "/x", "/home/a"), Some(PathBuf::from("/tmp/c")));
+        assert_eq!(dir("", "/x", "/home/a"), Some(PathBuf::from("/x/cobbler")));
+        assert_eq!(dir("", "", "/home/a"), Some(PathBuf::from("/home/a/.cache/cobbler")));
+        assert_eq!(cache_dir(None, None, None), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = env::temp_dir()
+            .join(format!("cobbler-cache-test-{}", std::process::id()))
+            .join("status.json");
+        assert!(StatusCache::load(&path).get("web1").is_none());
+
+        let mut cache = StatusCache::default();
+        cache.insert("web1", 100, serde_json::json!({ "updates": ["openssl"] }));
+        cache.save(&path).unwrap();
+        let loaded = StatusCache::load(&path);
+        assert_eq!(loaded.get("web1").map(|entry| entry.fetched_at), Some(100));
+        assert_eq!(loaded.get("web1").unwrap().status["updates"][0], "openssl");
+
+        // Another run's statuses are kept, and an older one doesn't replace a newer.
+        let mut other = StatusCache::default();
+        other.insert("web1", 50, serde_json::json!({}));
+        other.insert("db1", 120, serde_json::json!({}));
+        other.save(&path).unwrap();
+        let loaded = StatusCache::load(&path);
+        assert_eq!(loaded.get("web1").map(|entry| entry.fetched_at), Some(100));
+        assert_eq!(loaded.get("db1").map(|entry| entry.fetched_at), Some(120));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+pub const TOKEN_PLACEHOLDER: &str = "REPLACE_WITH_ACTUAL_TOKEN";
+const LOCAL_CONFIG_FILE: &str = ".cobbler.yaml";
+const SYSTEM_CONFIG_FILE: &str = "/etc/cobbler/config.yaml";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub nodes: Vec<NodeConfig>,
+    #[serde(default, skip_serializing_if = "NodeDefaults::is_empty")]
+    pub defaults: NodeDefaults,
+    /// Recurring fleet operations run by `cobbler agent`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<ScheduleConfig>,
+    /// Context used unless `--context` is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+    /// Named fleets, each replacing `nodes`, `defaults` and `schedules` while it is in use
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contexts: BTreeMap<String, Context>,
+    /// The context in use, whose settings are swapped into the top-level fields
+    #[serde(skip)]
+    pub active_context: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct Context {
+    #[serde(default)]
+    pub nodes: Vec<NodeConfig>,
+    #[serde(default, skip_serializing_if = "NodeDefaults::is_empty")]
+    pub defaults: NodeDefaults,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<ScheduleConfig>,
+}
+
+/// Settings for nodes that don't set them themselves.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct NodeDefaults {
+    /// Request timeout in seconds, overriding `COBBLER_TIMEOUT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_keyring: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2: Option<bool>,
+}
+
+impl NodeDefaults {
+    fn is_empty(&self) -> bool {
+        *self == NodeDefaults::default()
+    }
+}
+
+impl Config {
+    /// Swaps the context's nodes, defaults and schedules with the top-level ones, so that
+    /// commands see the context's fleet. Switching to the active context does nothing.
+    fn activate(&mut self, name: Option<&str>) -> Result<(), String> {
+        if self.active_context.as_deref() == name {
+            return Ok(());
+        }
+        if let Some(name) = name.filter(|name| !self.contexts.contains_key(*name)) {
+            return Err(unknown_context(&self.contexts, name));
+        }
+        if let Some(previous) = self.active_context.take() {
+            let context = self.contexts.get_mut(&previous).expect("the active context exists");
+            swap_context(&mut self.nodes, &mut self.defaults, &mut self.schedules, context);
+        }
+        if let Some(name) = name {
+            let context = self.contexts.get_mut(name).expect("checked above");
+            swap_context(&mut self.nodes, &mut self.defaults, &mut self.schedules, context);
+            self.active_context = Some(name.to_string());
+        }
+        Ok(())
+    }
+}
+
+fn unknown_context(contexts: &BTreeMap<String, Context>, name: &str) -> String {
+    let known: Vec<&str> = contexts.keys().map(String::as_str).collect();
+    format!("unknown context {name:?} (known: {})", known.join(", "))
+}
+
+fn swap_context(
+    nodes: &mut Vec<NodeConfig>,
+    defaults: &mut NodeDefaults,
+    schedules: &mut Vec<ScheduleConfig>,
+    context: &mut Context,
+) {
+    mem::swap(nodes, &mut context.nodes);
+    mem::swap(defaults, &mut context.defaults);
+    mem::swap(schedules, &mut context.schedules);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NodeConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// OS keyring entry holding the API key, used when `api_key` is not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_keyring: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Request timeout in seconds, overriding `COBBLER_TIMEOUT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Number of retries after connection failures, or timeouts of requests that are safe to repeat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Speak HTTP/2 without TLS (h2c) instead of HTTP/1.1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2: Option<bool>,
+    /// How to reach the node: its cobbler daemon, or SSH for hosts without one
+    #[serde(default, skip_serializing_if = "Transport::is_agent")]
+    pub transport: Transport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshConfig>,
+    /// MAC address for Wake-on-LAN, e.g. "dc:a6:32:01:02:03"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Agent,
+    Ssh,
+}
+
+impl Transport {
+    fn is_agent(&self) -> bool {
+        *self == Transport::Agent
+    }
+}
+
+/// Connection details for `transport: ssh`; the node's `address` is the SSH host.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SshConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ScheduleConfig {
+    pub name: String,
+    /// Interval between runs, e.g. "1h"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub every: Option<String>,
+    /// Time of day in UTC, optionally preceded by a weekday, e.g. "Sun 03:00"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+    /// cobbler arguments, e.g. "upgrade --rolling --tag production"
+    pub command: String,
+}
+
+pub fn resolve_config_path(explicit_path: Option<PathBuf>) -> (PathBuf, bool) {
+    if let Some(path) = explicit_path {
+        return (path, true);
+    }
+
+    let candidates = config_candidates(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"));
+    match candidates.iter().find(|path| path.exists()) {
+        Some(path) => (path.clone(), true),
+        None => (PathBuf::from(LOCAL_CONFIG_FILE), false),
+    }
+}
+
+fn config_candidates(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from(LOCAL_CONFIG_FILE)];
+
+    let xdg_config_home = xdg_config_home
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.filter(|dir| !dir.is_empty()).map(|dir| PathBuf::from(dir).join(".config")));
+    if let Some(dir) = xdg_config_home {
+        candidates.push(dir.join("cobbler").join("config.yaml"));
+    }
+
+    candidates.push(PathBuf::from(SYSTEM_CONFIG_FILE));
+    candidates
+}
+
+/// Loads the configuration with `context`, the one chosen with `--context`, or else
+/// `current_context` in use.
+pub fn load_config(path: &Path, context: Option<&str>) -> Result<Config, Box<dyn Error>> {
+    let mut config = read_config(path)?;
+    let context = context.map(str::to_string).or_else(|| config.current_context.clone());
+    config.activate(context.as_deref())?;
+    Ok(config)
+}
+
+/// Loads the configuration without switching to a context.
+fn read_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let content = fs::read_to_string(path)?;
+    let config = serde_yaml::from_str(&content)?;
+    Ok(config)
+}
+
+/// Saves the configuration, with changes to the active context's nodes stored in that context.
+pub fn save_config(path: &Path, config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut config = config.clone();
+    config.activate(None)?;
+    let content = serde_yaml::to_string(&config)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Makes `name` the context used unless `--context` is given, or uses the top-level nodes
+/// again without a name.
+pub fn run_use_context(path: &Path, name: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut config = read_config(path)?;
+    if let Some(name) = name.as_deref().filter(|name| !config.contexts.contains_key(*name)) {
+        return Err(unknown_context(&config.contexts, name).into());
+    }
+    config.current_context = name.clone();
+    save_config(path, &config)?;
+    match name {
+        Some(name) => println!("Switched to context {name:?}"),
+        None => println!("Switched to the top-level nodes"),
+    }
+    Ok(())
+}
+
+/// Lists the contexts, marking the one in use with `*`.
+pub fn run_get_contexts(config: &Config) {
+    for (name, context) in &config.contexts {
+        let active = config.active_context.as_ref() == Some(name);
+        let marker = if active { "*" } else { " " };
+        let nodes = if active { config.nodes.len() } else { context.nodes.len() };
+        println!("{marker} {name} ({nodes} nodes)");
+    }
+}
+
+/// Returns `(name, stored address, discovered address)` for discovered nodes whose ID
+/// matches a configured node that is stored under a different address.
+pub fn moved_nodes(config: &Config, discovered: &[(String, String)]) -> Vec<(String, String, String)> {
+    discovered
+        .iter()
+        .filter(|(_, id)| !id.is_empty())
+        .filter_map(|(addr, id)| {
+            let node = config.nodes.iter().find(|n| n.name.as_ref() == Some(id))?;
+            (node.address != *addr).then(|| (id.clone(), node.address.clone(), addr.clone()))
+        })
+        .collect()
+}
+
+pub fn merge_nodes(config: &mut Config, discovered: Vec<(String, String)>) -> bool {
+    let mut updated = false;
+    for (addr, id) in discovered {
+        let new_name = if id.is_empty() { None } else { Some(id) };
+
+        // Try finding by name first if name is available
+        let mut found_index = None;
+        if let Some(ref name) = new_name {
+            found_index = config.nodes.iter().position(|n| n.name.as_ref() == Some(name));
+        }
+
+        // If not found by name, try finding by address
+        if found_index.is_none() {
+            found_index = config.nodes.iter().position(|n| n.address == addr);
+        }
+
+        if let Some(index) = found_index {
+            let node = &mut config.nodes[index];
+            let mut node_updated = false;
+            if node.address != addr {
+                node.address = addr;
+                node_updated = true;
+            }
+            if node.name != new_name {
+                node.name = new_name;
+                node_updated = true;
+            }
+            if node_updated {
+                updated = true;
+            }
+        } else {
+            config.nodes.push(NodeConfig {
+                name: new_name,
+                address: addr,
+                api_key: Some(TOKEN_PLACEHOLDER.to_string()),
+                ..Default::default()
+            });
+            updated = true;
+        }
+    }
+    updated
+}
+
+/// Stores the MAC addresses announced by discovered daemons on the nodes with their
+/// address. Returns whether any node changed.
+pub fn record_macs(config: &mut Config, macs: &[(String, String)]) -> bool {
+    let mut updated = false;
+    for (address, mac) in macs {
+        for node in config.nodes.iter_mut().filter(|node| &node.address == address) {
+            if node.mac.as_ref() != Some(mac) {
+                node.mac = Some(mac.clone());
+                updated = true;
+            }
+        }
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_config_path() {
+        let explicit = Some(PathBuf::from("custom.yaml"));
+        let (path, exists) = resolve_config_path(explicit);
+        assert_eq!(path, PathBuf::from("custom.yaml"));
+        assert!(exists);
+    }
+
+    #[test]
+    fn test_config_candidates_prefers_xdg_config_home() {
+        let candidates = config_candidates(Some("/xdg".into()), Some("/home/user".into()));
+        assert_eq!(
+            candidates,
+            vec![
+                PathBuf::from(".cobbler.yaml"),
+                PathBuf::from("/xdg/cobbler/config.yaml"),
+                PathBuf::from("/etc/cobbler/config.yaml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_candidates_falls_back_to_home() {
+        let candidates = config_candidates(Some("".into()), Some("/home/user".into()));
+        assert_eq!(candidates[1], PathBuf::from("/home/user/.config/cobbler/config.yaml"));
+
+        let candidates = config_candidates(None, None);
+        assert_eq!(
+            candidates,
+            vec![PathBuf::from(".cobbler.yaml"), PathBuf::from("/etc/cobbler/config.yaml")]
+        );
+    }
+
+    #[test]
+    fn test_merge_nodes() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: None,
+                address: "1.1.1.1:8080".to_string(),
+                api_key: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let discovered = vec![
+            ("1.1.1.1:8080".to_string(), "node1".to_string()),
+            ("2.2.2.2:8080".to_string(), "node2".to_string()),
+        ];
+
+        let updated = merge_nodes(&mut config, discovered);
+        assert!(updated);
+        assert_eq!(config.nodes.len(), 2);
+        
+        // Existing node updated with name
+        assert_eq!(config.nodes[0].address, "1.1.1.1:8080");
+        assert_eq!(config.nodes[0].name, Some("node1".to_string()));
+        assert_eq!(config.nodes[0].api_key, None);
+
+        // New node added with name and placeholder token
+        assert_eq!(config.nodes[1].address, "2.2.2.2:8080");
+        assert_eq!(config.nodes[1].name, Some("node2".to_string()));
+        assert_eq!(config.nodes[1].api_key, Some(TOKEN_PLACEHOLDER.to_string()));
+    }
+
+    #[test]
+    fn test_merge_nodes_updates_name_but_preserves_token() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("OldName".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                api_key: Some("secret".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let discovered = vec![("1.1.1.1:8080".to_string(), "NewName".to_string())];
+
+        let updated = merge_nodes(&mut config, discovered);
+        assert!(updated);
+        assert_eq!(config.nodes[0].name, Some("NewName".to_string()));
+        assert_eq!(config.nodes[0].api_key, Some("secret".to_string()));
+    }
+
+    #[test]
+    fn test_record_macs() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                address: "1.1.1.1:8080".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let macs = vec![("1.1.1.1:8080".to_string(), "dc:a6:32:01:02:03".to_string())];
+        assert!(record_macs(&mut config, &macs));
+        assert_eq!(config.nodes[0].mac.as_deref(), Some("dc:a6:32:01:02:03"));
+        assert!(!record_macs(&mut config, &macs));
+    }
+
+    #[test]
+    fn test_merge_nodes_updates_custom_name() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("Custom".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                api_key: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let discovered = vec![("1.1.1.1:8080".to_string(), "node1".to_string())];
+
+        let updated = merge_nodes(&mut config, discovered);
+        assert!(updated);
+        assert_eq!(config.nodes[0].name, Some("node1".to_string()));
+    }
+
+    #[test]
+    fn test_merge_nodes_cleans_id_prefix_from_config() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("id=raspi1".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                api_key: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // Discovered node has the clean name
+        let discovered = vec![("1.1.1.1:8080".to_string(), "raspi1".to_string())];
+
+        let updated = merge_nodes(&mut config, discovered);
+        assert!(updated);
+        assert_eq!(config.nodes[0].name, Some("raspi1".to_string()));
+    }
+
+    #[test]
+    fn test_merge_nodes_prevents_duplicate_by_name() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("raspi1".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                api_key: Some("secret".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // raspi1 changed IP
+        let discovered = vec![("1.1.1.2:8080".to_string(), "raspi1".to_string())];
+
+        let updated = merge_nodes(&mut config, discovered);
+        assert!(updated);
+        assert_eq!(config.nodes.len(), 1);
+        assert_eq!(config.nodes[0].address, "1.1.1.2:8080");
+        assert_eq!(config.nodes[0].name, Some("raspi1".to_string()));
+        assert_eq!(config.nodes[0].api_key, Some("secret".to_string()));
+    }
+
+    #[test]
+    fn test_moved_nodes() {
+        let config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("raspi1".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let discovered = vec![
+            ("1.1.1.2:8080".to_string(), "raspi1".to_string()),
+            ("1.1.1.3:8080".to_string(), "raspi2".to_string()),
+            ("1.1.1.4:8080".to_string(), String::new()),
+        ];
+        assert_eq!(
+            moved_nodes(&config, &discovered),
+            vec![("raspi1".to_string(), "1.1.1.1:8080".to_string(), "1.1.1.2:8080".to_string())]
+        );
+        assert!(moved_nodes(&config, &[("1.1.1.1:8080".to_string(), "raspi1".to_string())]).is_empty());
+    }
+
+    #[test]
+    fn test_contexts() {
+        let mut config: Config = serde_yaml::from_str(
+            "nodes:\n  - address: 10.0.0.1:8080\ncurrent_context: home\ncontexts:\n  home:\n    nodes:\n      - address: 192.168.1.2:8080\n    defaults:\n      timeout: 30\n",
+        )
+        .unwrap();
+        config.activate(Some("home")).unwrap();
+        assert_eq!(config.nodes[0].address, "192.168.1.2:8080");
+        assert_eq!(config.defaults.timeout, Some(30));
+        assert!(config.activate(Some("office")).unwrap_err().contains("known: home"));
+
+        // Changes to the active context's nodes are saved in the context.
+        config.nodes.push(NodeConfig {
+            address: "192.168.1.3:8080".to_string(),
+            ..Default::default()
+        });
+        config.activate(None).unwrap();
+        assert_eq!(config.nodes.len(), 1);
+        assert_eq!(config.defaults, NodeDefaults::default());
+        assert_eq!(config.contexts["home"].nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_ssh_transport() {
+        let config: Config = serde_yaml::from_str(
+            "nodes:\n  - address: 10.0.0.1:8080\n  - address: legacy-1\n    transport: ssh\n    ssh:\n      user: admin\n      port: 2222\n",
+        )
+        .unwrap();
+        assert_eq!(config.nodes[0].transport, Transport::Agent);
+        assert_eq!(config.nodes[1].transport, Transport::Ssh);
+        assert_eq!(config.nodes[1].ssh.as_ref().unwrap().port, Some(2222));
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert_eq!(yaml.matches("transport").count(), 1);
+        assert!(!yaml.contains("identity_file"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Desktop notifications for `status --watch`: a node that gets security updates, fails an
+//! upgrade or stops answering is reported once, when that happens.
+
+use cobbler_api::{Job, JobState, StatusResponse};
+use notify_rust::Notification;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// What was seen of a node in the previous round.
+#[derive(Clone)]
+struct Seen {
+    reachable: bool,
+    security_updates: usize,
+    is_upgrading: bool,
+    /// The full upgrade job that was running, to look up how it ended
+    upgrade_job: Option<String>,
+    interrupted: bool,
+}
+
+/// Turns successive statuses of the watched nodes into notifications about what changed.
+#[derive(Default)]
+pub struct Watch {
+    nodes: HashMap<String, Seen>,
+    /// Whether showing a notification failed before, to warn only once
+    failed: bool,
+}
+
+impl Watch {
+    /// Records the status of `target`, `None` if it could not be reached, and notifies the
+    /// desktop of any change worth it. The first status of a node only sets its baseline.
+    /// `upgrade_jobs` lists the node's latest full upgrade jobs, newest first; it is only
+    /// called when an upgrade starts or ends.
+    pub fn observe(
+        &mut self,
+        target: &str,
+        status: Option<&StatusResponse>,
+        upgrade_jobs: impl FnOnce() -> Vec<Job>,
+    ) {
+        for message in self.changes(target, status, upgrade_jobs) {
+            self.show(target, &message);
+        }
+    }
+
+    fn changes(
+        &mut self,
+        target: &str,
+        status: Option<&StatusResponse>,
+        upgrade_jobs: impl FnOnce() -> Vec<Job>,
+    ) -> Vec<String> {
+        let previous = self.nodes.get(target).cloned();
+        let mut seen = match status {
+            Some(status) => Seen {
+                reachable: true,
+                security_updates: status.security_updates,
+                is_upgrading: status.is_upgrading,
+                upgrade_job: None,
+                interrupted: status.interrupted_upgrade.is_some(),
+            },
+            // Nothing is known about an unreachable node beyond that.
+            None => match &previous {
+                Some(previous) => Seen {
+                    reachable: false,
+                    ..previous.clone()
+                },
+                None => Seen {
+                    reachable: false,
+                    security_updates: 0,
+                    is_upgrading: false,
+                    upgrade_job: None,
+                    interrupted: false,
+                },
+            },
+        };
+        let was_upgrading = previous.as_ref().is_some_and(|previous| previous.is_upgrading);
+        let mut ended = None;
+        if seen.reachable && seen.is_upgrading != was_upgrading {
+            let jobs = upgrade_jobs();
+            if seen.is_upgrading {
+                // Installs and removals count as upgrading too, but aren't jobs.
+                seen.upgrade_job = jobs.into_iter().find(|job| job.state == JobState::Running).map(|job| job.id);
+            } else if let Some(id) = previous.as_ref().and_then(|previous| previous.upgrade_job.as_deref()) {
+                ended = jobs.into_iter().find(|job| job.id == id).map(|job| job.state);
+            }
+        } else if seen.is_upgrading {
+            seen.upgrade_job = previous.as_ref().and_then(|previous| previous.upgrade_job.clone());
+        }
+        self.nodes.insert(target.to_string(), seen.clone());
+        let Some(previous) = previous else {
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        if previous.reachable && !seen.reachable {
+            messages.push("is unreachable".to_string());
+        }
+        if !seen.reachable {
+            return messages;
+        }
+        if previous.security_updates == 0 && seen.security_updates > 0 {
+            messages.push(format!("has {} security update(s)", seen.security_updates));
+        }
+        if matches!(ended, Some(JobState::Failed)) || (!previous.interrupted && seen.interrupted) {
+            messages.push("failed to upgrade".to_string());
+        }
+        if ended == Some(JobState::Cancelled) {
+            messages.push("cancelled its upgrade".to_string());
+        }
+        messages
+    }
+
+    fn show(&mut self, target: &str, message: &str) {
+        let result = Notification::new()
+            .appname("cobbler")
+            .summary(&format!("{target} {message}"))
+            .show();
+        if let Err(err) = result {
+            if !self.failed {
+                warn!("can't show desktop notifications: {err}");
+                self.failed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobbler_api::JobKind;
+
+    fn job(id: &str, state: JobState) -> Job {
+        Job {
+            id: id.to_string(),
+            kind: JobKind::FullUpgrade,
+            state,
+            started_at: 100,
+            finished_at: None,
+            exit_code: None,
+            message: String::new(),
+            duration_seconds: None,
+            downloaded_bytes: None,
+            queue_position: None,
+            code: None,
+            progress: None,
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut watch = Watch::default();
+        let mut status = StatusResponse::default();
+        let none = Vec::new;
+        assert!(watch.changes("web1", Some(&status), none).is_empty());
+
+        status.security_updates = 2;
+        status.is_upgrading = true;
+        let running = || vec![job("a", JobState::Running)];
+        assert_eq!(watch.changes("web1", Some(&status), running), ["has 2 security update(s)"]);
+        assert_eq!(watch.changes("web1", None, none), ["is unreachable"]);
+        assert!(watch.changes("web1", None, none).is_empty());
+
+        status.is_upgrading = false;
+        let failed = || vec![job("a", JobState::Failed)];
+        assert_eq!(watch.changes("web1", Some(&status), failed), ["failed to upgrade"]);
+
+        status.is_upgrading = true;
+        watch.changes("web1", Some(&status), || vec![job("b", JobState::Running), job("a", JobState::Failed)]);
+        status.is_upgrading = false;
+        status.security_updates = 0;
+        let cancelled = || vec![job("b", JobState::Cancelled), job("a", JobState::Failed)];
+        assert_eq!(watch.changes("web1", Some(&status), cancelled), ["cancelled its upgrade"]);
+
+        // A successful upgrade, and an install that isn't a job, go unremarked.
+        status.is_upgrading = true;
+        watch.changes("web1", Some(&status), || vec![job("c", JobState::Running)]);
+        status.is_upgrading = false;
+        assert!(watch.changes("web1", Some(&status), || vec![job("c", JobState::Succeeded)]).is_empty());
+        status.is_upgrading = true;
+        watch.changes("web1", Some(&status), || vec![job("c", JobState::Succeeded)]);
+        status.is_upgrading = false;
+        assert!(watch.changes("web1", Some(&status), none).is_empty());
+
+        assert!(watch.changes("web2", None, none).is_empty());
+    }
+}
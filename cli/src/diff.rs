@@ -0,0 +1,214 @@
+use crate::{api_client, fetch_packages, find_node, glob, ApiClient, Config, ExitStatus};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::{self, Write};
+use std::thread;
+use tabwriter::TabWriter;
+
+type Versions = BTreeMap<String, String>;
+
+pub fn run_diff(
+    node: &str,
+    other: Option<&str>,
+    patterns: &[String],
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let fleet: Vec<String> = match other {
+        Some(other) => vec![other.to_string()],
+        None => config
+            .nodes
+            .iter()
+            .filter(|n| !is_same_node(config, &n.address, node))
+            .map(|n| n.address.clone())
+            .collect(),
+    };
+    if fleet.is_empty() {
+        return Err("no other configured nodes to compare against".into());
+    }
+
+    let client = api_client()?;
+    let mut targets = vec![node.to_string()];
+    targets.extend(fleet);
+    let mut results = fetch_versions(&client, &targets, config);
+
+    let left = match results.remove(0) {
+        (_, Ok(versions)) => versions,
+        (target, Err(err)) => {
+            eprintln!("error: {target}: {err}");
+            return Ok(ExitStatus::Unreachable);
+        }
+    };
+
+    let mut exit_status = ExitStatus::Ok;
+    let mut fleet_versions = Vec::new();
+    for (target, result) in results {
+        match result {
+            Ok(versions) => fleet_versions.push(versions),
+            Err(err) => {
+                eprintln!("{target}: skipped ({err})");
+                exit_status = ExitStatus::Unreachable;
+            }
+        }
+    }
+    if fleet_versions.is_empty() {
+        eprintln!("error: no node to compare against could be reached");
+        return Ok(ExitStatus::Unreachable);
+    }
+
+    let (label, right) = match other {
+        Some(other) => (other.to_string(), fleet_versions.remove(0)),
+        None => (
+            format!("MAJORITY ({} nodes)", fleet_versions.len()),
+            majority_versions(&fleet_versions),
+        ),
+    };
+
+    let differences = diff_versions(&left, &right, patterns);
+    print_differences(io::stdout(), node, &label, &differences)?;
+    Ok(exit_status)
+}
+
+fn is_same_node(config: &Config, address: &str, target: &str) -> bool {
+    address == target || find_node(config, target).is_some_and(|n| n.address == address)
+}
+
+fn fetch_versions(
+    client: &ApiClient,
+    targets: &[String],
+    config: &Config,
+) -> Vec<(String, Result<Versions, String>)> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|target| {
+                scope.spawn(move || {
+                    fetch_packages(client, target, None, config).map(|packages| {
+                        packages
+                            .into_iter()
+                            .map(|pkg| (pkg.name, pkg.installed_version))
+                            .collect()
+                    })
+                })
+            })
+            .collect();
+        targets
+            .iter()
+            .cloned()
+            .zip(handles)
+            .map(|(target, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err("package fetch thread panicked".to_string()));
+                (target, result)
+            })
+            .collect()
+    })
+}
+
+fn majority_versions(fleet: &[Versions]) -> Versions {
+    let names: BTreeSet<&String> = fleet.iter().flat_map(|versions| versions.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let mut counts: BTreeMap<Option<&String>, usize> = BTreeMap::new();
+            for versions in fleet {
+                *counts.entry(versions.get(name)).or_default() += 1;
+            }
+            let max = counts.values().copied().max().unwrap_or_default();
+            counts
+                .into_iter()
+                .rev()
+                .find(|(_, count)| *count == max)
+                .and_then(|(version, _)| version)
+                .map(|version| (name.clone(), version.clone()))
+        })
+        .collect()
+}
+
+fn diff_versions<'a>(
+    left: &'a Versions,
+    right: &'a Versions,
+    patterns: &[String],
+) -> Vec<(&'a str, Option<&'a str>, Option<&'a str>)> {
+    let names: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| patterns.is_empty() || patterns.iter().any(|pattern| glob::matches(pattern, name)))
+        .filter_map(|name| {
+            let (l, r) = (left.get(name), right.get(name));
+            (l != r).then(|| (name.as_str(), l.map(String::as_str), r.map(String::as_str)))
+        })
+        .collect()
+}
+
+fn print_differences<W: Write>(
+    writer: W,
+    left: &str,
+    right: &str,
+    differences: &[(&str, Option<&str>, Option<&str>)],
+) -> io::Result<()> {
+    if differences.is_empty() {
+        let mut writer = writer;
+        return writeln!(writer, "No differences found.");
+    }
+
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "PACKAGE\t{left}\t{right}")?;
+    for (name, l, r) in differences {
+        writeln!(tw, "{}\t{}\t{}", name, l.unwrap_or("-"), r.unwrap_or("-"))?;
+    }
+    tw.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(entries: &[(&str, &str)]) -> Versions {
+        entries
+            .iter()
+            .map(|(name, version)| (name.to_string(), version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_versions() {
+        let left = versions(&[("curl", "7.88.1"), ("vim", "9.0"), ("htop", "3.2")]);
+        let right = versions(&[("curl", "7.88.1"), ("vim", "9.1"), ("jq", "1.6")]);
+
+        let differences = diff_versions(&left, &right, &[]);
+        assert_eq!(
+            differences,
+            vec![
+                ("htop", Some("3.2"), None),
+                ("jq", None, Some("1.6")),
+                ("vim", Some("9.0"), Some("9.1")),
+            ]
+        );
+
+        let differences = diff_versions(&left, &right, &["v*".to_string()]);
+        assert_eq!(differences, vec![("vim", Some("9.0"), Some("9.1"))]);
+    }
+
+    #[test]
+    fn test_majority_versions() {
+        let fleet = vec![
+            versions(&[("openssl", "3.0.11"), ("vim", "9.0")]),
+            versions(&[("openssl", "3.0.11")]),
+            versions(&[("openssl", "3.0.9"), ("vim", "9.0")]),
+            versions(&[("openssl", "3.0.11"), ("htop", "3.2")]),
+        ];
+
+        let majority = majority_versions(&fleet);
+        assert_eq!(majority.get("openssl").map(String::as_str), Some("3.0.11"));
+        assert_eq!(majority.get("vim").map(String::as_str), Some("9.0"));
+        assert!(!majority.contains_key("htop"));
+    }
+
+    #[test]
+    fn test_print_differences() {
+        let mut buffer = Vec::new();
+        print_differences(&mut buffer, "web-1", "web-2", &[("vim", Some("9.0"), None)]).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "PACKAGE  web-1  web-2\nvim      9.0    -\n");
+    }
+}
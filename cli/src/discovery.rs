@@ -0,0 +1,484 @@
+use clap::ValueEnum;
+use cobbler_api::{IdentifyResponse, MDNS_DOMAIN, SERVICE_NAME, SERVICE_TYPE};
+use flume::RecvTimeoutError;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::Resolver;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use cobbler_client::blocking::Client;
+use cobbler_client::Node;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+pub const DEFAULT_DAEMON_PORT: u16 = 8080;
+const MIN_SCAN_PREFIX: u8 = 16;
+const SCAN_PARALLELISM: usize = 64;
+const SCAN_CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const SCAN_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// Prefer IPv4 addresses, falling back to IPv6
+    #[default]
+    Ipv4,
+    /// Prefer IPv6 addresses, falling back to IPv4
+    Ipv6,
+    /// Use the announced host name
+    Hostname,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredNode {
+    pub id: String,
+    pub host: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub instance: String,
+    pub properties: Vec<String>,
+}
+
+impl DiscoveredNode {
+    fn from_service_info(info: &ServiceInfo) -> Self {
+        let properties: Vec<String> = info
+            .get_properties()
+            .iter()
+            .map(|property| property.to_string())
+            .collect();
+        DiscoveredNode {
+            id: property_id(&properties),
+            host: info.get_hostname().trim_end_matches('.').to_string(),
+            addresses: info.get_addresses().iter().copied().collect(),
+            port: info.get_port(),
+            instance: instance_name(info.get_fullname(), MDNS_DOMAIN),
+            properties,
+        }
+    }
+
+    /// Stable identity of the daemon: its announced ID, or the instance name as a fallback.
+    pub fn key(&self) -> String {
+        if self.id.is_empty() {
+            self.instance.clone()
+        } else {
+            self.id.clone()
+        }
+    }
+
+    pub fn address(&self, prefer: AddressPreference) -> Option<String> {
+        choose_address(&self.addresses, &self.host, self.port, prefer)
+    }
+
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.iter().find_map(|property| {
+            property
+                .split_once('=')
+                .filter(|(name, _)| *name == key)
+                .map(|(_, value)| value)
+        })
+    }
+
+    pub fn addresses_display(&self) -> String {
+        let ipv4 = self.addresses.iter().filter(|addr| addr.is_ipv4());
+        let ipv6 = self.addresses.iter().filter(|addr| addr.is_ipv6());
+        ipv4.chain(ipv6)
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// JSON object for `--output json`, with TXT properties as a string map.
+    pub fn to_json(&self) -> serde_json::Value {
+        let properties: serde_json::Map<String, serde_json::Value> = self
+            .properties
+            .iter()
+            .map(|property| {
+                let (key, value) = property.split_once('=').unwrap_or((property, ""));
+                (key.to_string(), value.into())
+            })
+            .collect();
+        serde_json::json!({
+            "id": self.id,
+            "hostname": self.host,
+            "addresses": self.addresses,
+            "port": self.port,
+            "instance": self.instance,
+            "properties": properties,
+        })
+    }
+}
+
+pub enum DiscoveryEvent {
+    Appeared(DiscoveredNode),
+    Disappeared(DiscoveredNode),
+}
+
+/// Browses mDNS until `timeout` elapses, calling `on_node` once per daemon instance.
+pub fn browse_mdns(
+    timeout: Duration,
+    mut on_node: impl FnMut(DiscoveredNode) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    browse(Some(Instant::now() + timeout), |event| match event {
+        DiscoveryEvent::Appeared(node) if seen.insert(node.key()) => on_node(node),
+        _ => Ok(()),
+    })
+}
+
+/// Browses mDNS indefinitely, reporting daemons as they appear and disappear.
+pub fn follow_mdns(on_event: impl FnMut(DiscoveryEvent) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    browse(None, on_event)
+}
+
+fn browse(
+    deadline: Option<Instant>,
+    mut on_event: impl FnMut(DiscoveryEvent) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mdns = ServiceDaemon::new().map_err(|err| format!("create resolver: {err}"))?;
+    let service_name = format!("{}.{}", SERVICE_TYPE, MDNS_DOMAIN);
+    let receiver = mdns
+        .browse(&service_name)
+        .map_err(|err| format!("browse: {err}"))?;
+
+    let mut active: HashMap<String, DiscoveredNode> = HashMap::new();
+    let result = loop {
+        let event = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break Ok(());
+                }
+                receiver.recv_timeout(deadline - now)
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        let outcome = match event {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                let node = DiscoveredNode::from_service_info(&info);
+                match active.insert(info.get_fullname().to_string(), node.clone()) {
+                    None => {
+                        info!("resolved {} at {:?} port {}", info.get_fullname(), node.addresses, node.port);
+                        on_event(DiscoveryEvent::Appeared(node))
+                    }
+                    Some(_) => {
+                        debug!("re-announced {}", info.get_fullname());
+                        Ok(())
+                    }
+                }
+            }
+            Ok(ServiceEvent::ServiceRemoved(_, fullname)) => match active.remove(&fullname) {
+                Some(node) => {
+                    info!("{fullname} went away");
+                    on_event(DiscoveryEvent::Disappeared(node))
+                }
+                None => Ok(()),
+            },
+            Ok(ServiceEvent::SearchStopped(service_type)) => {
+                eprintln!("Search stopped for {}", service_type);
+                Ok(())
+            }
+            Ok(event) => {
+                debug!("mDNS event: {event:?}");
+                Ok(())
+            }
+            Err(RecvTimeoutError::Timeout) => break Ok(()),
+            Err(RecvTimeoutError::Disconnected) => break Err("browse: receiver disconnected".into()),
+        };
+        if let Err(err) = outcome {
+            break Err(err);
+        }
+    };
+
+    let _ = mdns.shutdown();
+    result
+}
+
+/// Looks up daemons registered via unicast DNS-SD (PTR, SRV and TXT records) in `domain`.
+pub fn browse_dns_sd(domain: &str) -> Result<Vec<DiscoveredNode>, Box<dyn Error>> {
+    let domain = format!("{}.", domain.trim_end_matches('.'));
+    let resolver = Resolver::from_system_conf().map_err(|err| format!("create DNS resolver: {err}"))?;
+    let service_name = format!("{SERVICE_TYPE}.{domain}");
+
+    let pointers = resolver
+        .lookup(service_name.as_str(), RecordType::PTR)
+        .map_err(|err| format!("lookup {service_name}: {err}"))?;
+
+    let mut nodes = Vec::new();
+    let mut seen = HashSet::new();
+    for record in pointers.iter() {
+        let RData::PTR(pointer) = record else {
+            continue;
+        };
+        let fullname = pointer.0.to_string();
+        match resolve_instance(&resolver, &fullname, &domain) {
+            Ok(node) => {
+                if seen.insert(node.key()) {
+                    nodes.push(node);
+                }
+            }
+            Err(err) => eprintln!("{fullname}: {err}"),
+        }
+    }
+    Ok(nodes)
+}
+
+fn resolve_instance(resolver: &Resolver, fullname: &str, domain: &str) -> Result<DiscoveredNode, Box<dyn Error>> {
+    let srv = resolver.srv_lookup(fullname)?;
+    let record = srv.iter().next().ok_or("no SRV record")?;
+    let host = record.target().to_string();
+
+    let properties: Vec<String> = resolver
+        .txt_lookup(fullname)
+        .map(|txt| {
+            txt.iter()
+                .flat_map(|record| record.txt_data().iter())
+                .map(|data| String::from_utf8_lossy(data).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let addresses = resolver
+        .lookup_ip(host.as_str())
+        .map(|ips| ips.iter().collect())
+        .unwrap_or_default();
+
+    Ok(DiscoveredNode {
+        id: property_id(&properties),
+        host: host.trim_end_matches('.').to_string(),
+        addresses,
+        port: record.port(),
+        instance: instance_name(fullname, domain),
+        properties,
+    })
+}
+
+/// Probes every host of an IPv4 subnet (`CIDR[:port]`) for a cobbler daemon.
+pub fn scan_subnet(spec: &str) -> Result<Vec<DiscoveredNode>, Box<dyn Error>> {
+    let (hosts, port) = parse_scan_target(spec)?;
+    let names: Vec<String> = hosts.iter().map(|host| host.to_string()).collect();
+    let identities = probe_hosts(&names, port)?;
+
+    Ok(hosts
+        .into_iter()
+        .zip(identities)
+        .filter_map(|(host, identity)| {
+            let identity = identity?;
+            Some(DiscoveredNode {
+                id: identity.id,
+                host: host.to_string(),
+                addresses: vec![IpAddr::V4(host)],
+                port,
+                instance: identity.instance,
+                properties: vec![format!("version={}", identity.version)],
+            })
+        })
+        .collect())
+}
+
+/// Asks each of `hosts` (names or IP addresses) for its identity, in parallel. `None` for
+/// hosts without a cobbler daemon on `port`.
+pub fn probe_hosts(hosts: &[String], port: u16) -> Result<Vec<Option<IdentifyResponse>>, Box<dyn Error>> {
+    let client = Client::with_connect_timeout(SCAN_PROBE_TIMEOUT, SCAN_CONNECT_TIMEOUT)?;
+
+    let mut identities = Vec::new();
+    for batch in hosts.chunks(SCAN_PARALLELISM) {
+        thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|host| {
+                    let client = &client;
+                    scope.spawn(move || identify(client, host, port))
+                })
+                .collect();
+            identities.extend(handles.into_iter().map(|handle| handle.join().ok().flatten()));
+        });
+    }
+    Ok(identities)
+}
+
+fn identify(client: &Client, host: &str, port: u16) -> Option<IdentifyResponse> {
+    let host = if host.contains(':') { format!("[{host}]") } else { host.to_string() };
+    let identity = client.identify(&Node::new(format!("{host}:{port}"))).ok()?;
+    (identity.service == SERVICE_NAME).then_some(identity)
+}
+
+fn parse_scan_target(spec: &str) -> Result<(Vec<Ipv4Addr>, u16), String> {
+    let (network, prefix_and_port) = spec
+        .split_once('/')
+        .ok_or_else(|| format!("invalid scan target {spec}: expected CIDR[:port]"))?;
+    let (prefix, port) = match prefix_and_port.split_once(':') {
+        Some((prefix, port)) => (prefix, port.parse().map_err(|_| format!("invalid port in {spec}"))?),
+        None => (prefix_and_port, DEFAULT_DAEMON_PORT),
+    };
+    let network: Ipv4Addr = network
+        .parse()
+        .map_err(|_| format!("invalid IPv4 network in {spec}"))?;
+    let prefix: u8 = prefix
+        .parse()
+        .ok()
+        .filter(|prefix| *prefix <= 32)
+        .ok_or_else(|| format!("invalid prefix length in {spec}"))?;
+    if prefix < MIN_SCAN_PREFIX {
+        return Err(format!("refusing to scan more than a /{MIN_SCAN_PREFIX} network"));
+    }
+
+    let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+    let first = u32::from(network) & mask;
+    let last = first | !mask;
+    let hosts = if prefix >= 31 {
+        (first..=last).map(Ipv4Addr::from).collect()
+    } else {
+        (first + 1..last).map(Ipv4Addr::from).collect()
+    };
+    Ok((hosts, port))
+}
+
+pub fn clean_node_id(id: &str) -> &str {
+    id.strip_prefix("id=").unwrap_or(id)
+}
+
+fn property_id(properties: &[String]) -> String {
+    properties
+        .iter()
+        .find(|property| property.starts_with("id="))
+        .map(|property| clean_node_id(property).to_string())
+        .unwrap_or_default()
+}
+
+fn instance_name(fullname: &str, domain: &str) -> String {
+    let suffix = format!(".{SERVICE_TYPE}.{domain}");
+    fullname
+        .strip_suffix(&suffix)
+        .unwrap_or(fullname)
+        .to_string()
+}
+
+fn choose_address(
+    addresses: &[IpAddr],
+    hostname: &str,
+    port: u16,
+    prefer: AddressPreference,
+) -> Option<String> {
+    if prefer == AddressPreference::Hostname && !hostname.is_empty() {
+        return Some(format!("{hostname}:{port}"));
+    }
+
+    let rank = |addr: &IpAddr| {
+        let family = match (prefer, addr) {
+            (AddressPreference::Ipv6, IpAddr::V6(_)) => 0,
+            (AddressPreference::Ipv6, IpAddr::V4(_)) => 1,
+            (_, IpAddr::V4(_)) => 0,
+            (_, IpAddr::V6(_)) => 1,
+        };
+        let link_local = match addr {
+            IpAddr::V4(v4) => v4.is_link_local(),
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+        };
+        (family, link_local, *addr)
+    };
+
+    addresses.iter().min_by_key(|addr| rank(addr)).map(|addr| match addr {
+        IpAddr::V4(v4) => format!("{v4}:{port}"),
+        IpAddr::V6(v6) => format!("[{v6}]:{port}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_to_json() {
+        let node = DiscoveredNode {
+            id: "raspi1".to_string(),
+            host: "raspi1.local".to_string(),
+            addresses: vec!["192.168.1.20".parse().unwrap()],
+            port: 8080,
+            instance: "cobblerd-raspi1".to_string(),
+            properties: vec!["id=raspi1".to_string(), "updates=3".to_string(), "flag".to_string()],
+        };
+        assert_eq!(
+            node.to_json(),
+            serde_json::json!({
+                "id": "raspi1",
+                "hostname": "raspi1.local",
+                "addresses": ["192.168.1.20"],
+                "port": 8080,
+                "instance": "cobblerd-raspi1",
+                "properties": {"id": "raspi1", "updates": "3", "flag": ""},
+            })
+        );
+    }
+
+    #[test]
+    fn test_choose_address() {
+        let addresses: Vec<IpAddr> = ["fe80::1", "2001:db8::5", "169.254.3.4", "192.168.1.20"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+
+        assert_eq!(
+            choose_address(&addresses, "raspi1.local", 8080, AddressPreference::Ipv4).as_deref(),
+            Some("192.168.1.20:8080")
+        );
+        assert_eq!(
+            choose_address(&addresses, "raspi1.local", 8080, AddressPreference::Ipv6).as_deref(),
+            Some("[2001:db8::5]:8080")
+        );
+        assert_eq!(
+            choose_address(&addresses, "raspi1.local", 8080, AddressPreference::Hostname).as_deref(),
+            Some("raspi1.local:8080")
+        );
+        assert_eq!(
+            choose_address(&addresses[..1], "", 8080, AddressPreference::Hostname).as_deref(),
+            Some("[fe80::1]:8080")
+        );
+        assert_eq!(choose_address(&[], "", 8080, AddressPreference::Ipv4), None);
+    }
+
+    #[test]
+    fn test_parse_scan_target() {
+        let (hosts, port) = parse_scan_target("192.168.1.77/24").unwrap();
+        assert_eq!(port, DEFAULT_DAEMON_PORT);
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(192, 168, 1, 254));
+
+        let (hosts, port) = parse_scan_target("10.0.0.5/32:9090").unwrap();
+        assert_eq!(hosts, vec![Ipv4Addr::new(10, 0, 0, 5)]);
+        assert_eq!(port, 9090);
+
+        assert!(parse_scan_target("10.0.0.0/8").is_err());
+        assert!(parse_scan_target("10.0.0.0/33").is_err());
+        assert!(parse_scan_target("10.0.0.0").is_err());
+        assert!(parse_scan_target("10.0.0.0/24:http").is_err());
+    }
+
+    #[test]
+    fn test_clean_node_id() {
+        assert_eq!(clean_node_id("id=raspi1"), "raspi1");
+        assert_eq!(clean_node_id("raspi1"), "raspi1");
+        assert_eq!(clean_node_id(""), "");
+    }
+
+    #[test]
+    fn test_instance_name_and_id() {
+        assert_eq!(
+            instance_name("cobblerd-raspi1._cobbler._tcp.example.com.", "example.com."),
+            "cobblerd-raspi1"
+        );
+        assert_eq!(instance_name("cobblerd-raspi1._cobbler._tcp.local.", MDNS_DOMAIN), "cobblerd-raspi1");
+
+        let properties = vec!["version=0.1.0".to_string(), "id=raspi1".to_string()];
+        assert_eq!(property_id(&properties), "raspi1");
+        assert_eq!(property_id(&[]), "");
+
+        let node = DiscoveredNode {
+            properties,
+            ..Default::default()
+        };
+        assert_eq!(node.property("version"), Some("0.1.0"));
+        assert_eq!(node.property("os"), None);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::{Config, NodeConfig, Transport};
+use crate::discovery::AddressPreference;
+use crate::{inventory, ExitStatus};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// One target group of a Prometheus `file_sd_configs` file.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct TargetGroup {
+    targets: Vec<String>,
+    labels: BTreeMap<&'static str, String>,
+}
+
+/// Writes the daemons' `/metrics` endpoints as Prometheus file-based service discovery
+/// targets, and with `interval` keeps rewriting the file as the fleet changes.
+pub fn run_export_sd(
+    out: &Path,
+    discover: Option<AddressPreference>,
+    interval: Option<Duration>,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let Some(interval) = interval else {
+        export_sd(out, discover, config, &mut None)?;
+        return Ok(ExitStatus::Ok);
+    };
+    let mut written = None;
+    loop {
+        if let Err(err) = export_sd(out, discover, config, &mut written) {
+            eprintln!("error: {err}");
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Writes the targets unless they equal `written`, the content written last.
+fn export_sd(
+    out: &Path,
+    discover: Option<AddressPreference>,
+    config: &Config,
+    written: &mut Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let groups = target_groups(&inventory::fleet_nodes(config, discover)?);
+    let content = serde_json::to_string_pretty(&groups)? + "\n";
+    if written.as_ref() != Some(&content) {
+        write_atomically(out, &content)?;
+        println!("Wrote {} target(s) to {}", groups.len(), out.display());
+        *written = Some(content);
+    }
+    Ok(())
+}
+
+/// One group per daemon, so that each target keeps its own labels. Agentless nodes have
+/// no metrics endpoint.
+fn target_groups(nodes: &[NodeConfig]) -> Vec<TargetGroup> {
+    nodes
+        .iter()
+        .filter(|node| node.transport == Transport::Agent)
+        .map(|node| {
+            let mut labels = BTreeMap::new();
+            labels.extend(node.name.clone().map(|name| ("node", name)));
+            if !node.tags.is_empty() {
+                // Wrapped in commas like Prometheus' own `__meta_*_tags`, for regexes like `.*,web,.*`.
+                labels.insert("tags", format!(",{},", node.tags.join(",")));
+            }
+            TargetGroup {
+                targets: vec![node.address.clone()],
+                labels,
+            }
+        })
+        .collect()
+}
+
+/// Prometheus rereads the file when it changes; renaming keeps it from seeing a
+/// partially written one.
+fn write_atomically(path: &Path, content: &str) -> Result<(), Box<dyn Error>> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, content).map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    fs::rename(&temp, path).map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_groups() {
+        let nodes = vec![
+            NodeConfig {
+                name: Some("web-1".to_string()),
+                address: "10.0.0.1:8080".to_string(),
+                tags: vec!["web".to_string(), "prod".to_string()],
+                ..Default::default()
+            },
+            NodeConfig {
+                address: "10.0.0.2:8080".to_string(),
+                ..Default::default()
+            },
+            NodeConfig {
+                address: "legacy-1".to_string(),
+                transport: Transport::Ssh,
+                ..Default::default()
+            },
+        ];
+        let json = serde_json::to_value(target_groups(&nodes)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"targets": ["10.0.0.1:8080"], "labels": {"node": "web-1", "tags": ",web,prod,"}},
+                {"targets": ["10.0.0.2:8080"], "labels": {}},
+            ])
+        );
+    }
+}
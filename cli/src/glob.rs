@@ -0,0 +1,47 @@
+/// Matches `text` against a shell-style pattern supporting `*` and `?`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("openssl", "openssl"));
+        assert!(matches("libssl*", "libssl3"));
+        assert!(matches("*ssl*", "libssl3"));
+        assert!(matches("linux-image-?.*", "linux-image-6.1.0-18-amd64"));
+        assert!(matches("*", ""));
+        assert!(!matches("openssl", "openssl-dev"));
+        assert!(!matches("lib?ssl", "libssl"));
+        assert!(!matches("*-dev", "libc6"));
+    }
+}
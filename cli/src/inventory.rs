@@ -0,0 +1,319 @@
+use crate::config::{load_config, save_config, Config, NodeConfig, Transport};
+use crate::discovery::{self, AddressPreference};
+use crate::{get_default_timeout, ExitStatus};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const TAG_SEPARATOR: char = ';';
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeFormat {
+    #[default]
+    Yaml,
+    Json,
+    Csv,
+}
+
+impl NodeFormat {
+    pub fn from_path(path: &Path) -> Option<NodeFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(NodeFormat::Yaml),
+            "json" => Some(NodeFormat::Json),
+            "csv" => Some(NodeFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NodeDocument {
+    Config(Config),
+    List(Vec<NodeConfig>),
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CsvNode {
+    #[serde(default)]
+    name: String,
+    address: String,
+    #[serde(default)]
+    api_key: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    timeout: Option<u64>,
+    #[serde(default)]
+    retries: Option<u32>,
+    #[serde(default)]
+    mac: String,
+}
+
+impl From<CsvNode> for NodeConfig {
+    fn from(row: CsvNode) -> Self {
+        let non_empty = |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        NodeConfig {
+            name: non_empty(row.name),
+            address: row.address.trim().to_string(),
+            api_key: non_empty(row.api_key),
+            tags: row
+                .tags
+                .split(TAG_SEPARATOR)
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            timeout: row.timeout,
+            retries: row.retries,
+            mac: non_empty(row.mac),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&NodeConfig> for CsvNode {
+    fn from(node: &NodeConfig) -> Self {
+        CsvNode {
+            name: node.name.clone().unwrap_or_default(),
+            address: node.address.clone(),
+            api_key: node.api_key.clone().unwrap_or_default(),
+            tags: node.tags.join(&TAG_SEPARATOR.to_string()),
+            timeout: node.timeout,
+            retries: node.retries,
+            mac: node.mac.clone().unwrap_or_default(),
+        }
+    }
+}
+
+pub fn run_import(
+    file: &Path,
+    format: Option<NodeFormat>,
+    config_path: &Path,
+    context: Option<&str>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let format = format
+        .or_else(|| NodeFormat::from_path(file))
+        .ok_or_else(|| format!("cannot infer format of {}; use --format", file.display()))?;
+    let content = fs::read_to_string(file).map_err(|err| format!("{}: {err}", file.display()))?;
+    let nodes = parse_nodes(&content, format)?;
+    save_imported(nodes, config_path, context)
+}
+
+/// Merges `nodes` into `context` of the configuration file with [`import_nodes`].
+pub fn save_imported(
+    nodes: Vec<NodeConfig>,
+    config_path: &Path,
+    context: Option<&str>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let mut config = load_config(config_path, context)?;
+    let (added, updated) = import_nodes(&mut config, nodes);
+    if added + updated > 0 {
+        save_config(config_path, &config)?;
+    }
+    println!(
+        "Imported {added} new and updated {updated} node(s) in {}",
+        config_path.display()
+    );
+    Ok(ExitStatus::Ok)
+}
+
+pub fn run_export(format: NodeFormat, config: &Config) -> Result<ExitStatus, Box<dyn Error>> {
+    print!("{}", format_nodes(config, format)?);
+    Ok(ExitStatus::Ok)
+}
+
+/// The configured nodes, plus the daemons found via mDNS that are not configured if
+/// `discover` is set.
+pub fn fleet_nodes(config: &Config, discover: Option<AddressPreference>) -> Result<Vec<NodeConfig>, Box<dyn Error>> {
+    let mut nodes = config.nodes.clone();
+    if let Some(prefer) = discover {
+        discovery::browse_mdns(get_default_timeout(), |discovered| {
+            let Some(address) = discovered.address(prefer) else {
+                return Ok(());
+            };
+            let known = nodes
+                .iter()
+                .any(|node| node.address == address || node.name.as_deref() == Some(discovered.id.as_str()));
+            if !known {
+                nodes.push(NodeConfig {
+                    name: Some(discovered.id.clone()).filter(|id| !id.is_empty()),
+                    address,
+                    ..Default::default()
+                });
+            }
+            Ok(())
+        })?;
+    }
+    Ok(nodes)
+}
+
+pub fn parse_nodes(content: &str, format: NodeFormat) -> Result<Vec<NodeConfig>, Box<dyn Error>> {
+    let nodes = match format {
+        NodeFormat::Yaml => document_nodes(serde_yaml::from_str(content)?),
+        NodeFormat::Json => document_nodes(serde_json::from_str(content)?),
+        NodeFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes())
+            .deserialize::<CsvNode>()
+            .map(|row| row.map(NodeConfig::from))
+            .collect::<Result<_, _>>()?,
+    };
+
+    if let Some(index) = nodes.iter().position(|node| node.address.is_empty()) {
+        return Err(format!("node #{} has no address", index + 1).into());
+    }
+    Ok(nodes)
+}
+
+fn document_nodes(document: NodeDocument) -> Vec<NodeConfig> {
+    match document {
+        NodeDocument::Config(config) => config.nodes,
+        NodeDocument::List(nodes) => nodes,
+    }
+}
+
+pub fn format_nodes(config: &Config, format: NodeFormat) -> Result<String, Box<dyn Error>> {
+    match format {
+        NodeFormat::Yaml => Ok(serde_yaml::to_string(config)?),
+        NodeFormat::Json => Ok(serde_json::to_string_pretty(config)? + "\n"),
+        NodeFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for node in &config.nodes {
+                writer.serialize(CsvNode::from(node))?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+    }
+}
+
+/// Merges imported nodes into the config, matching existing entries by name or address.
+/// Returns the number of added and updated nodes.
+pub fn import_nodes(config: &mut Config, nodes: Vec<NodeConfig>) -> (usize, usize) {
+    let (mut added, mut updated) = (0, 0);
+    for node in nodes {
+        let existing = node
+            .name
+            .as_ref()
+            .and_then(|name| config.nodes.iter().position(|n| n.name.as_ref() == Some(name)))
+            .or_else(|| config.nodes.iter().position(|n| n.address == node.address));
+
+        let Some(index) = existing else {
+            config.nodes.push(node);
+            added += 1;
+            continue;
+        };
+
+        let current = &mut config.nodes[index];
+        let merged = NodeConfig {
+            name: node.name.or_else(|| current.name.clone()),
+            address: node.address,
+            api_key: node.api_key.or_else(|| current.api_key.clone()),
+            api_key_keyring: node.api_key_keyring.or_else(|| current.api_key_keyring.clone()),
+            tags: if node.tags.is_empty() { current.tags.clone() } else { node.tags },
+            timeout: node.timeout.or(current.timeout),
+            retries: node.retries.or(current.retries),
+            http2: node.http2.or(current.http2),
+            transport: if node.transport == Transport::Agent { current.transport } else { node.transport },
+            ssh: node.ssh.or_else(|| current.ssh.clone()),
+            mac: node.mac.or_else(|| current.mac.clone()),
+        };
+        if merged != *current {
+            *current = merged;
+            updated += 1;
+        }
+    }
+    (added, updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(NodeFormat::from_path(Path::new("nodes.YML")), Some(NodeFormat::Yaml));
+        assert_eq!(NodeFormat::from_path(Path::new("nodes.json")), Some(NodeFormat::Json));
+        assert_eq!(NodeFormat::from_path(Path::new("nodes.csv")), Some(NodeFormat::Csv));
+        assert_eq!(NodeFormat::from_path(Path::new("nodes.txt")), None);
+    }
+
+    #[test]
+    fn test_parse_nodes() {
+        let yaml = parse_nodes("nodes:\n  - address: 10.0.0.1:8080\n    tags: [web]\n", NodeFormat::Yaml).unwrap();
+        assert_eq!(yaml[0].tags, vec!["web"]);
+
+        let json = parse_nodes(r#"[{"name":"db-1","address":"10.0.0.2:8080"}]"#, NodeFormat::Json).unwrap();
+        assert_eq!(json[0].name.as_deref(), Some("db-1"));
+
+        let csv = parse_nodes(
+            "name,address,api_key,tags,timeout,retries\nweb-1, 10.0.0.1:8080,,web;prod,120,\n,10.0.0.3:8080,,,,\n",
+            NodeFormat::Csv,
+        )
+        .unwrap();
+        assert_eq!(csv.len(), 2);
+        assert_eq!(csv[0].address, "10.0.0.1:8080");
+        assert_eq!(csv[0].tags, vec!["web", "prod"]);
+        assert_eq!(csv[0].timeout, Some(120));
+        assert_eq!(csv[0].api_key, None);
+        assert_eq!(csv[1].name, None);
+
+        assert!(parse_nodes("[{\"address\":\"\"}]", NodeFormat::Json).is_err());
+    }
+
+    #[test]
+    fn test_format_nodes_round_trip() {
+        let config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("web-1".to_string()),
+                address: "10.0.0.1:8080".to_string(),
+                tags: vec!["web".to_string(), "prod".to_string()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        for format in [NodeFormat::Yaml, NodeFormat::Json, NodeFormat::Csv] {
+            let content = format_nodes(&config, format).unwrap();
+            let nodes = parse_nodes(&content, format).unwrap();
+            assert_eq!(nodes[0].name.as_deref(), Some("web-1"), "{format:?}");
+            assert_eq!(nodes[0].tags, config.nodes[0].tags, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn test_import_nodes() {
+        let mut config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("web-1".to_string()),
+                address: "10.0.0.1:8080".to_string(),
+                api_key: Some("secret".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let imported = vec![
+            NodeConfig {
+                name: Some("web-1".to_string()),
+                address: "10.0.1.1:8080".to_string(),
+                ..Default::default()
+            },
+            NodeConfig {
+                address: "10.0.0.2:8080".to_string(),
+                ..Default::default()
+            },
+            NodeConfig {
+                address: "10.0.0.2:8080".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(import_nodes(&mut config, imported), (1, 1));
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.nodes[0].address, "10.0.1.1:8080");
+        assert_eq!(config.nodes[0].api_key.as_deref(), Some("secret"));
+    }
+}
@@ -0,0 +1,160 @@
+//! `cobbler jobs`: the recent jobs of a daemon, to find the failed upgrade to look into
+//! without knowing its job ID.
+
+use crate::{api_client, client_node, Config};
+use clap::ValueEnum;
+use cobbler_api::{Job, JobState, JobsQuery, JobsResponse};
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tabwriter::TabWriter;
+
+/// The `--state` filter, as clap values.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateFilter {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+impl From<StateFilter> for JobState {
+    fn from(state: StateFilter) -> Self {
+        match state {
+            StateFilter::Queued => JobState::Queued,
+            StateFilter::Running => JobState::Running,
+            StateFilter::Succeeded => JobState::Succeeded,
+            StateFilter::Failed => JobState::Failed,
+            StateFilter::Cancelled => JobState::Cancelled,
+            StateFilter::Interrupted => JobState::Interrupted,
+        }
+    }
+}
+
+pub fn run_jobs(
+    target: &str,
+    state: Option<StateFilter>,
+    since: Option<Duration>,
+    limit: usize,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let client = api_client()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let query = JobsQuery {
+        state: state.map(JobState::from),
+        since: since.map(|since| now.saturating_sub(since).as_secs()),
+        limit: Some(limit),
+        ..Default::default()
+    };
+    let response = client
+        .jobs(&client_node(target, config), &query)
+        .map_err(|err| format!("{target}: {err}"))?;
+    print_jobs(io::stdout(), &response)?;
+    Ok(())
+}
+
+fn print_jobs<W: Write>(mut writer: W, response: &JobsResponse) -> io::Result<()> {
+    if response.jobs.is_empty() {
+        return writeln!(writer, "No jobs found.");
+    }
+    let mut tw = TabWriter::new(&mut writer).padding(2);
+    writeln!(tw, "ID\tTYPE\tSTATE\tSTARTED\tDURATION\tMESSAGE")?;
+    for job in &response.jobs {
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            job.id,
+            job.kind.as_str(),
+            job.state.as_str(),
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(job.started_at)),
+            duration(job),
+            job.message
+        )?;
+    }
+    tw.flush()?;
+    drop(tw);
+    if response.total > response.jobs.len() {
+        writeln!(writer, "\nShowing {} of {} jobs; raise --limit to see more.", response.jobs.len(), response.total)?;
+    }
+    Ok(())
+}
+
+/// How long the job ran, e.g. `3m 12s`; empty while it hasn't finished.
+fn duration(job: &Job) -> String {
+    let Some(finished_at) = job.finished_at else {
+        return String::new();
+    };
+    humantime::format_duration(Duration::from_secs(finished_at.saturating_sub(job.started_at))).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+    use cobbler_api::JobKind;
+
+    #[test]
+    fn test_cli_parse_jobs() {
+        let cli = Cli::parse_from(["cobbler", "jobs", "web1", "--state", "failed", "--since", "2d"]);
+        if let Commands::Jobs {
+            target,
+            state,
+            since,
+            limit,
+        } = cli.command
+        {
+            assert_eq!(target, "web1");
+            assert_eq!(state, Some(StateFilter::Failed));
+            assert_eq!(since, Some(Duration::from_secs(2 * 86400)));
+            assert_eq!(limit, 20);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "jobs", "web1", "--state", "sleeping"]).is_err());
+    }
+
+    #[test]
+    fn test_print_jobs() {
+        let job = |id: &str, state: JobState, finished_at: Option<u64>, message: &str| Job {
+            id: id.to_string(),
+            kind: JobKind::FullUpgrade,
+            state,
+            started_at: 1_714_557_600,
+            finished_at,
+            exit_code: None,
+            message: message.to_string(),
+            duration_seconds: None,
+            downloaded_bytes: None,
+            queue_position: None,
+            code: None,
+            progress: None,
+        };
+        let response = JobsResponse {
+            jobs: vec![
+                job("b2", JobState::Succeeded, Some(1_714_557_645), "full upgrade completed"),
+                job("a1", JobState::Failed, Some(1_714_557_792), "apt-get failed with exit code 100"),
+            ],
+            total: 5,
+        };
+        let mut out = Vec::new();
+        print_jobs(&mut out, &response).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+ID  TYPE          STATE      STARTED               DURATION  MESSAGE
+b2  full-upgrade  succeeded  2024-05-01T10:00:00Z  45s       full upgrade completed
+a1  full-upgrade  failed     2024-05-01T10:00:00Z  3m 12s    apt-get failed with exit code 100
+
+Showing 2 of 5 jobs; raise --limit to see more.
+"
+        );
+        assert_eq!(duration(&job("c3", JobState::Running, None, "")), "");
+
+        let mut out = Vec::new();
+        print_jobs(&mut out, &JobsResponse { jobs: Vec::new(), total: 0 }).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No jobs found.\n");
+    }
+}
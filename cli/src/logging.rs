@@ -0,0 +1,44 @@
+//! Diagnostic logging to stderr, driven by `-v`/`-q`. Regular output never goes through it.
+
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+/// `RUST_LOG` takes precedence over the flags.
+pub fn init(verbose: u8, quiet: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(directives(verbose, quiet)));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .with_target(false)
+        .init();
+}
+
+/// Warnings by default; `-v` adds retries and discovery events, `-vv` every HTTP request and
+/// `-vvv` the internals of the HTTP and mDNS libraries.
+fn directives(verbose: u8, quiet: bool) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "warn,cobbler=info,cobbler_client=info",
+        (false, 2) => "info,cobbler=debug,cobbler_client=debug",
+        (false, _) => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directives() {
+        assert_eq!(directives(0, false), "warn");
+        assert_eq!(directives(2, false), "info,cobbler=debug,cobbler_client=debug");
+        assert_eq!(directives(5, false), "trace");
+        assert_eq!(directives(0, true), "error");
+        for verbose in 0..4 {
+            assert!(directives(verbose, false).parse::<EnvFilter>().is_ok());
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! `cobbler logs`: the output of a daemon's jobs and the apt and dpkg logs of its node, to
+//! look into a failed upgrade without logging in.
+
+use crate::{api_client, client_node, Config};
+use clap::Args;
+use cobbler_api::{LogQuery, LogResponse};
+use std::error::Error;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Lines of the apt or dpkg log shown without `--tail`.
+const DEFAULT_TAIL: usize = 50;
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct LogSource {
+    /// Output of the job with this ID
+    #[arg(long, value_name = "ID")]
+    pub job: Option<String>,
+
+    /// apt's terminal log (/var/log/apt/term.log)
+    #[arg(long)]
+    pub apt: bool,
+
+    /// dpkg's log (/var/log/dpkg.log)
+    #[arg(long)]
+    pub dpkg: bool,
+}
+
+/// Prints the selected log of `target`; with `follow`, keeps printing the lines added to it
+/// until the job finishes, or until interrupted for the apt and dpkg logs.
+pub fn run_logs(
+    target: &str,
+    source: &LogSource,
+    tail: Option<usize>,
+    follow: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let client = api_client()?;
+    let node = client_node(target, config);
+    let fetch = |query: &LogQuery| match &source.job {
+        Some(job) => client.job_log(&node, job, query),
+        None => client.system_log(&node, if source.apt { "apt" } else { "dpkg" }, query),
+    };
+
+    let mut query = LogQuery {
+        tail: tail.or(source.job.is_none().then_some(DEFAULT_TAIL)),
+        since: None,
+    };
+    loop {
+        let response = fetch(&query).map_err(|err| format!("{target}: {err}"))?;
+        print_lines(io::stdout().lock(), &response)?;
+        if !follow || response.complete {
+            return Ok(());
+        }
+        query = LogQuery {
+            tail: None,
+            since: Some(response.next),
+        };
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+fn print_lines<W: Write>(mut writer: W, response: &LogResponse) -> io::Result<()> {
+    for line in &response.lines {
+        writeln!(writer, "{line}")?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+
+    #[test]
+    fn test_cli_parse_logs() {
+        let cli = Cli::parse_from(["cobbler", "logs", "web1", "--job", "42", "--follow"]);
+        if let Commands::Logs {
+            target,
+            source,
+            tail,
+            follow,
+        } = cli.command
+        {
+            assert_eq!(target, "web1");
+            assert_eq!(source.job.as_deref(), Some("42"));
+            assert_eq!(tail, None);
+            assert!(follow);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "logs", "web1", "--dpkg", "--tail", "10"]).is_ok());
+        assert!(Cli::try_parse_from(["cobbler", "logs", "web1"]).is_err());
+        assert!(Cli::try_parse_from(["cobbler", "logs", "web1", "--apt", "--dpkg"]).is_err());
+    }
+
+    #[test]
+    fn test_print_lines() {
+        let response = LogResponse {
+            lines: vec!["Setting up vim (2:9.0.1378-2+deb12u1) ...".to_string(), String::new()],
+            next: 2,
+            complete: true,
+        };
+        let mut out = Vec::new();
+        print_lines(&mut out, &response).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Setting up vim (2:9.0.1378-2+deb12u1) ...\n\n");
+    }
+}
@@ -1,102 +1,96 @@
-use clap::{Parser, Subcommand};
-use flume::RecvTimeoutError;
-use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+mod ansible;
+mod cache;
+mod config;
+mod desktop;
+mod diff;
+mod discovery;
+mod file_sd;
+mod glob;
+mod inventory;
+mod jobs;
+mod logging;
+mod logs;
+mod output;
+mod progress;
+mod reboot;
+mod report;
+mod schedule;
+mod secrets;
+mod services;
+mod sources;
+mod ssh;
+mod ssh_config;
+mod summary;
+mod unattended;
+mod upgrade;
+mod validate;
+mod wake;
+
+use cache::StatusCache;
+use clap::{Args, Parser, Subcommand};
+use config::{
+    load_config, merge_nodes, moved_nodes, record_macs, resolve_config_path, save_config, Config, NodeConfig, NodeDefaults,
+};
+use discovery::{AddressPreference, DiscoveredNode, DiscoveryEvent};
+use output::{Color, ColorChoice, OutputFormat, RowWriter};
+use progress::Progress;
+use std::collections::HashMap;
 use std::error::Error;
-use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::time::{Duration, UNIX_EPOCH};
+use cobbler_client::blocking::Client as ApiClient;
+use cobbler_api::{
+    AptTransaction, CommandResponse, ConffilePolicy, ErrorCode, ErrorResponse, FullUpgradeRequest, HealthState, PackageInfo, PackagePolicy, PackageState, PhasedUpdates, PrefetchRequest, RepairResponse, ResourcesResponse, RestartServicesResponse, StatusResponse, UpgradeStrategy,
+};
+use cobbler_client::{Method, StatusCode};
 use tabwriter::TabWriter;
-
-const SERVICE_TYPE: &str = "_cobbler._tcp";
-const SERVICE_DOMAIN: &str = "local.";
-const TOKEN_PLACEHOLDER: &str = "REPLACE_WITH_ACTUAL_TOKEN";
-
-#[derive(Serialize, Deserialize, Default, Debug)]
-struct Config {
-    #[serde(default)]
-    nodes: Vec<NodeConfig>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct NodeConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
-    address: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    api_key: Option<String>,
+use tracing::{debug, warn};
+
+/// Process exit codes; when several conditions apply the highest code wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ExitStatus {
+    Ok = 0,
+    Error = 1,
+    Unreachable = 2,
+    UpdatesPending = 3,
+    OperationFailed = 4,
 }
 
-fn resolve_config_path(explicit_path: Option<PathBuf>) -> (PathBuf, bool) {
-    if let Some(path) = explicit_path {
-        return (path, true);
-    }
-
-    let default_path = PathBuf::from(".cobbler.yaml");
-    if default_path.exists() {
-        (default_path, true)
-    } else {
-        (default_path, false)
+impl ExitStatus {
+    fn code(self) -> i32 {
+        self as i32
     }
 }
 
-fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
-    if !path.exists() {
-        return Ok(Config::default());
-    }
-    let content = fs::read_to_string(path)?;
-    let config = serde_yaml::from_str(&content)?;
-    Ok(config)
+fn find_node<'a>(config: &'a Config, target: &str) -> Option<&'a NodeConfig> {
+    config
+        .nodes
+        .iter()
+        .find(|n| n.address == target || n.name.as_deref() == Some(target))
 }
 
-fn save_config(path: &Path, config: &Config) -> Result<(), Box<dyn Error>> {
-    let content = serde_yaml::to_string(config)?;
-    fs::write(path, content)?;
-    Ok(())
+/// The config entry of a target reached over SSH instead of a cobbler daemon.
+fn ssh_node<'a>(config: &'a Config, target: &str) -> Option<&'a NodeConfig> {
+    find_node(config, target).filter(|node| node.transport == config::Transport::Ssh)
 }
 
-fn merge_nodes(config: &mut Config, discovered: Vec<(String, String)>) -> bool {
-    let mut updated = false;
-    for (addr, id) in discovered {
-        let new_name = if id.is_empty() { None } else { Some(id) };
-
-        // Try finding by name first if name is available
-        let mut found_index = None;
-        if let Some(ref name) = new_name {
-            found_index = config.nodes.iter().position(|n| n.name.as_ref() == Some(name));
-        }
-
-        // If not found by name, try finding by address
-        if found_index.is_none() {
-            found_index = config.nodes.iter().position(|n| n.address == addr);
+fn select_targets(targets: Vec<String>, tags: &[String], config: &Config) -> Vec<String> {
+    let mut selected = targets;
+    for node in config
+        .nodes
+        .iter()
+        .filter(|n| n.tags.iter().any(|tag| tags.contains(tag)))
+    {
+        if !selected.contains(&node.address) {
+            selected.push(node.address.clone());
         }
+    }
 
-        if let Some(index) = found_index {
-            let node = &mut config.nodes[index];
-            let mut node_updated = false;
-            if node.address != addr {
-                node.address = addr;
-                node_updated = true;
-            }
-            if node.name != new_name {
-                node.name = new_name;
-                node_updated = true;
-            }
-            if node_updated {
-                updated = true;
-            }
-        } else {
-            config.nodes.push(NodeConfig {
-                name: new_name,
-                address: addr,
-                api_key: Some(TOKEN_PLACEHOLDER.to_string()),
-            });
-            updated = true;
-        }
+    if selected.is_empty() && tags.is_empty() {
+        selected = config.nodes.iter().map(|n| n.address.clone()).collect();
     }
-    updated
+    selected
 }
 
 fn get_default_timeout() -> Duration {
@@ -119,6 +113,28 @@ struct Cli {
     #[arg(short, long, env = "COBBLER_CONFIG")]
     config: Option<PathBuf>,
 
+    /// Named context of the configuration file to use instead of its current one
+    #[arg(long, global = true, env = "COBBLER_CONTEXT")]
+    context: Option<String>,
+
+    /// Log retries and discovery events; repeat to log every HTTP request (-vv) and library
+    /// internals (-vvv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log errors only, e.g. no retry notices
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Color status output: green for nodes that are up to date, yellow for pending updates,
+    /// red for unreachable or failed nodes
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorChoice,
+
+    /// Don't ask before upgrading, rebooting or removing packages on the selected nodes
+    #[arg(short, long, global = true)]
+    yes: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -134,6 +150,26 @@ enum Commands {
         /// Create and/or update a config file with newly found daemons
         #[arg(short = 'u', long = "update-config")]
         update_config: bool,
+
+        /// Query unicast DNS-SD records in this domain instead of using mDNS
+        #[arg(long, conflicts_with = "scan")]
+        domain: Option<String>,
+
+        /// Probe every host of an IPv4 subnet instead of using mDNS (e.g. 192.168.1.0/24:8080)
+        #[arg(long, value_name = "CIDR[:PORT]")]
+        scan: Option<String>,
+
+        /// Keep browsing and report daemons as they appear and disappear
+        #[arg(short, long, conflicts_with_all = ["domain", "scan"])]
+        follow: bool,
+
+        /// Which address to record for daemons announcing several
+        #[arg(long, value_enum, default_value_t)]
+        prefer: AddressPreference,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
     },
     /// Show status of cobbler daemons
     Status {
@@ -141,10 +177,64 @@ enum Commands {
         #[arg(short, long)]
         all: bool,
 
+        /// Which address to use for discovered daemons announcing several
+        #[arg(long, value_enum, default_value_t, requires = "all")]
+        prefer: AddressPreference,
+
+        /// Exit with code 3 if any node has pending updates
+        #[arg(long)]
+        check: bool,
+
+        /// Show the last successful status of each node from the local cache without querying it
+        #[arg(long, conflicts_with = "all")]
+        cached: bool,
+
+        /// Keep refreshing the status until interrupted
+        #[arg(short, long, conflicts_with = "cached")]
+        watch: bool,
+
+        /// Time between refreshes in watch mode
+        #[arg(long, default_value = "1m", value_parser = humantime::parse_duration, requires = "watch")]
+        interval: Duration,
+
+        /// Show a desktop notification when a node gets security updates, fails to upgrade or
+        /// becomes unreachable
+        #[arg(long, requires = "watch")]
+        notify: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        filter: StatusFilter,
+
         /// Targets (host:port)
         targets: Vec<String>,
     },
+    /// Show how many nodes are reachable, up to date, need security updates or a reboot
+    Summary {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Write a patch report of the fleet to a Markdown or HTML file
+    Report {
+        /// File to write the report to
+        file: PathBuf,
+
+        /// Report format; defaults to the file extension, or Markdown
+        #[arg(long, value_enum)]
+        format: Option<report::ReportFormat>,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
     /// Manage packages on cobbler daemons
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Packages {
         /// Perform a full system upgrade
         #[arg(long, required = true)]
@@ -153,17 +243,502 @@ enum Commands {
         /// Targets (host:port)
         #[arg(num_args = 0..)]
         targets: Vec<String>,
+
+        #[command(subcommand)]
+        action: Option<PackagesCommand>,
+    },
+    /// Upgrade cobbler daemons and wait for the upgrades to finish
+    Upgrade {
+        /// Upgrade the targets in batches instead of all at once
+        #[arg(long)]
+        rolling: bool,
+
+        /// Number of nodes upgraded at the same time in rolling mode
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+        max_parallel: u16,
+
+        /// Abort remaining batches as soon as an upgrade fails
+        #[arg(long)]
+        fail_fast: bool,
+
+        /// Wait for each node to answer a status request after its upgrade (and reboot), and
+        /// require it to report no interrupted upgrade, nor a reboot still pending after one
+        #[arg(long)]
+        health_check: bool,
+
+        /// Reboot nodes that require it after upgrading and wait for them to come back
+        #[arg(long)]
+        reboot: bool,
+
+        /// Maximum time to wait for each node's upgrade job (e.g. "30m", "1h")
+        #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+        wait_timeout: Duration,
+
+        /// Also install updates held back by a phased rollout (Ubuntu)
+        #[arg(long, conflicts_with = "exclude_phased")]
+        include_phased: bool,
+
+        /// Skip phased updates even on nodes that are part of the rollout (Ubuntu)
+        #[arg(long)]
+        exclude_phased: bool,
+
+        /// Keep locally changed configuration files that packages update (the daemon's
+        /// default unless configured otherwise)
+        #[arg(long, conflicts_with = "confnew")]
+        confold: bool,
+
+        /// Install the packages' versions of locally changed configuration files
+        #[arg(long)]
+        confnew: bool,
+
+        /// Limit package downloads on each node to this many KB/s (0 lifts the node's own
+        /// limit)
+        #[arg(long, value_name = "KBPS")]
+        download_limit: Option<u32>,
+
+        /// Leave packages matching this pattern untouched, e.g. "nvidia-*" (may be repeated
+        /// or comma-separated; needs the cobbler daemon)
+        #[arg(long, value_name = "PATTERN", value_delimiter = ',')]
+        exclude: Vec<String>,
+
+        /// Upgrade only the installed packages matching this pattern, e.g. "openssl*" (may be
+        /// repeated or comma-separated; needs the cobbler daemon)
+        #[arg(long, value_name = "PATTERN", value_delimiter = ',')]
+        only: Vec<String>,
+
+        /// "upgrade" never removes packages and keeps back upgrades that would; the node's
+        /// daemon decides by default ("full-upgrade" unless configured otherwise)
+        #[arg(long, value_enum)]
+        strategy: Option<upgrade::Strategy>,
+
+        /// Only show what the upgrade would do on each node, including kept-back packages
+        /// and removals. Shows the nodes' own plan, so it can't be combined with --strategy,
+        /// --exclude or --only
+        #[arg(long, conflicts_with_all = ["strategy", "exclude", "only"])]
+        dry_run: bool,
+
+        /// Wake nodes that do not answer via Wake-on-LAN and wait for them before upgrading
+        #[arg(long)]
+        wake: bool,
+
+        /// Maximum time to wait for each woken node to come up (e.g. "5m")
+        #[arg(long, default_value = "5m", value_parser = humantime::parse_duration, requires = "wake")]
+        wake_timeout: Duration,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Download pending packages into the nodes' apt cache without installing them, so that
+    /// a later upgrade needs no network
+    Prefetch {
+        /// Limit package downloads on each node to this many KB/s (0 lifts the node's own
+        /// limit)
+        #[arg(long, value_name = "KBPS")]
+        download_limit: Option<u32>,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Inspect the cobbler configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Run a command from the daemons' allowlist, e.g. to restart a service after patching
+    Exec {
+        /// Name of the command in the daemon's commands file
+        name: String,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Send Wake-on-LAN packets to nodes with a configured MAC address
+    Wake {
+        /// Wait for the nodes to come up, at most this long (e.g. "5m")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        wait: Option<Duration>,
+
+        /// Broadcast address to send the packets to
+        #[arg(long, default_value_t = std::net::Ipv4Addr::BROADCAST)]
+        broadcast: std::net::Ipv4Addr,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Reboot cobbler daemon hosts and wait for them to come back
+    Reboot {
+        /// Only reboot nodes that report a pending reboot
+        #[arg(long)]
+        if_required: bool,
+
+        /// Reboot one node at a time, waiting for each to come back first
+        #[arg(long)]
+        rolling: bool,
+
+        /// Maximum time to wait for each node to come back (e.g. "5m")
+        #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+        wait_timeout: Duration,
+
+        /// Select configured nodes carrying this tag (repeatable)
+        #[arg(long)]
+        tag: Vec<String>,
+
+        /// Targets (host:port or node name); defaults to all configured nodes
+        targets: Vec<String>,
+    },
+    /// Power off cobbler daemon hosts, e.g. lab machines after maintenance
+    Shutdown {
+        /// Power off after this long (e.g. "15m"), rounded up to whole minutes
+        #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "cancel")]
+        delay: Option<Duration>,
+
+        /// Cancel a scheduled shutdown or reboot instead
+        #[arg(long)]
+        cancel: bool,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Restart services that still run executables or libraries an upgrade replaced
+    RestartServices {
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Compare installed package versions between two nodes
+    Diff {
+        /// Node to compare (host:port or node name)
+        node: String,
+
+        /// Node to compare against; defaults to the majority of all other configured nodes
+        other: Option<String>,
+
+        /// Only compare packages matching this glob (repeatable)
+        #[arg(long = "package", value_name = "GLOB")]
+        packages: Vec<String>,
+    },
+    /// List the recent jobs of a cobbler daemon, newest first
+    Jobs {
+        /// Target (host:port or node name)
+        target: String,
+
+        /// Only jobs in this state
+        #[arg(long, value_enum)]
+        state: Option<jobs::StateFilter>,
+
+        /// Only jobs started within this time, e.g. "1d"
+        #[arg(long, value_parser = humantime::parse_duration)]
+        since: Option<Duration>,
+
+        /// How many jobs to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show the output of a job or the apt or dpkg log of a cobbler daemon
+    Logs {
+        /// Target (host:port or node name)
+        target: String,
+
+        #[command(flatten)]
+        source: logs::LogSource,
+
+        /// Only show the last N lines; defaults to all of a job's output and 50 lines of a log
+        #[arg(long, value_name = "N")]
+        tail: Option<usize>,
+
+        /// Keep printing new lines until the job finishes, or until interrupted
+        #[arg(short, long)]
+        follow: bool,
     },
+    /// List, start, stop or restart systemd services on cobbler daemons
+    Services {
+        #[command(subcommand)]
+        action: services::ServicesCommand,
+    },
+    /// List, add or remove apt repositories on cobbler daemons
+    Sources {
+        #[command(subcommand)]
+        action: sources::SourcesCommand,
+    },
+    /// Show or change unattended-upgrades settings on cobbler daemons
+    UnattendedUpgrades {
+        #[command(subcommand)]
+        action: unattended::UnattendedCommand,
+    },
+    /// Run the schedules of the configuration file until interrupted
+    Agent,
+    /// Export the fleet for other tools
+    Export {
+        #[command(subcommand)]
+        action: ExportCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportCommand {
+    /// Print the configured nodes as an Ansible inventory, with tags as groups
+    AnsibleInventory {
+        /// Inventory format
+        #[arg(long, value_enum, default_value_t)]
+        format: ansible::InventoryFormat,
+
+        /// Also include daemons discovered via mDNS that are not configured
+        #[arg(short, long)]
+        all: bool,
+
+        /// Which address to use for discovered daemons announcing several
+        #[arg(long, value_enum, default_value_t, requires = "all")]
+        prefer: AddressPreference,
+    },
+    /// Write the daemons' metrics endpoints as Prometheus file-based discovery targets
+    PrometheusSd {
+        /// Target file for `file_sd_configs`
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+
+        /// Also include daemons discovered via mDNS that are not configured
+        #[arg(short, long)]
+        all: bool,
+
+        /// Which address to use for discovered daemons announcing several
+        #[arg(long, value_enum, default_value_t, requires = "all")]
+        prefer: AddressPreference,
+
+        /// Keep running and refresh the file at this interval (e.g. "5m")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        interval: Option<Duration>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the path of the configuration file in use
+    Path,
+    /// Make a context the one used unless --context is given
+    UseContext {
+        /// Context name; omit to use the top-level nodes again
+        name: Option<String>,
+    },
+    /// List the contexts of the configuration file
+    GetContexts,
+    /// Merge nodes from a YAML, JSON or CSV file, or from SSH config hosts, into the configuration
+    Import {
+        /// File to import
+        #[arg(required_unless_present = "from_ssh_config")]
+        file: Option<PathBuf>,
+
+        /// File format; inferred from the file extension by default
+        #[arg(long, value_enum, conflicts_with = "from_ssh_config")]
+        format: Option<inventory::NodeFormat>,
+
+        /// Import the Host entries of an OpenSSH client config (default: ~/.ssh/config)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, conflicts_with = "file")]
+        from_ssh_config: Option<Option<PathBuf>>,
+
+        /// Port to probe SSH config hosts for cobblerd on
+        #[arg(long, default_value_t = discovery::DEFAULT_DAEMON_PORT, requires = "from_ssh_config")]
+        port: u16,
+
+        /// Add SSH config hosts without cobblerd as agentless nodes instead of skipping them
+        #[arg(long, requires = "from_ssh_config")]
+        agentless: bool,
+
+        /// Add SSH config hosts without checking that cobblerd answers
+        #[arg(long, requires = "from_ssh_config", conflicts_with = "agentless")]
+        no_probe: bool,
+    },
+    /// Print the configured nodes as YAML, JSON or CSV
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: inventory::NodeFormat,
+    },
+    /// Manage API keys stored in the OS keyring
+    Keyring {
+        #[command(subcommand)]
+        action: secrets::KeyringCommand,
+    },
+    /// Check the configuration file for unknown fields, duplicate nodes and invalid values
+    Validate {
+        /// Also check that every node answers a status request
+        #[arg(long)]
+        connect: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PackagesCommand {
+    /// List installed packages on a cobbler daemon
+    List {
+        /// Target (host:port or node name)
+        target: String,
+
+        #[command(flatten)]
+        filter: PackageFilter,
+    },
+    /// Show apt pin priorities, explaining why a candidate isn't the newest version
+    Policy {
+        /// Target (host:port or node name)
+        target: String,
+
+        /// Packages to show; defaults to all upgradable packages
+        packages: Vec<String>,
+    },
+    /// Show recent apt transactions from a cobbler daemon's apt history, including those
+    /// cobbler didn't start
+    Transactions {
+        /// Target (host:port or node name)
+        target: String,
+
+        /// How many transactions to show, newest first
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Install packages on cobbler daemons
+    Install {
+        /// Packages to install
+        #[arg(required = true)]
+        packages: Vec<String>,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Remove packages from cobbler daemons
+    Remove {
+        /// Packages to remove
+        #[arg(required = true)]
+        packages: Vec<String>,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Finish interrupted installs (dpkg --configure -a, apt-get install -f)
+    Repair {
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+}
+
+#[derive(Args, Debug, Default)]
+struct TargetSelection {
+    /// Targets (host:port or node name); defaults to all configured nodes
+    #[arg(long, value_delimiter = ',')]
+    targets: Vec<String>,
+
+    /// Select configured nodes carrying this tag (repeatable)
+    #[arg(long)]
+    tag: Vec<String>,
+}
+
+#[derive(Args, Debug, Default)]
+struct StatusFilter {
+    /// Only show nodes with pending updates
+    #[arg(long)]
+    only_outdated: bool,
+
+    /// Only show nodes that require a reboot
+    #[arg(long)]
+    reboot_required: bool,
+
+    /// Only show nodes with pending security updates
+    #[arg(long)]
+    security: bool,
+
+    /// Only show nodes with a pending kernel update
+    #[arg(long)]
+    kernel_update: bool,
+
+    /// Only show nodes with a pending update matching this glob (repeatable)
+    #[arg(long = "package", value_name = "GLOB")]
+    packages: Vec<String>,
+
+    /// Only show nodes with at least this many pending updates
+    #[arg(long, value_name = "N")]
+    min_updates: Option<usize>,
+}
+
+impl StatusFilter {
+    fn is_active(&self) -> bool {
+        self.only_outdated
+            || self.reboot_required
+            || self.security
+            || self.kernel_update
+            || !self.packages.is_empty()
+            || self.min_updates.is_some()
+    }
+
+    fn matches(&self, node: &StatusResponse) -> bool {
+        (!self.only_outdated || !node.updates.is_empty())
+            && (!self.reboot_required || node.reboot_required)
+            && (!self.security || node.security_updates > 0)
+            && (!self.kernel_update || node.kernel_update_pending)
+            && self.min_updates.is_none_or(|min| node.updates.len() >= min)
+            && (self.packages.is_empty()
+                || node
+                    .updates
+                    .iter()
+                    .any(|update| self.packages.iter().any(|pattern| glob::matches(pattern, update))))
+    }
+}
+
+#[derive(Args, Debug, Default)]
+#[group(multiple = false)]
+struct PackageFilter {
+    /// Only list packages with a newer candidate version
+    #[arg(long)]
+    upgradable: bool,
+
+    /// List all installed packages (default)
+    #[arg(long)]
+    installed: bool,
+
+    /// Only list packages on hold
+    #[arg(long)]
+    held: bool,
+}
+
+impl PackageFilter {
+    fn state(&self) -> Option<PackageState> {
+        if self.upgradable {
+            Some(PackageState::Upgradable)
+        } else if self.held {
+            Some(PackageState::Held)
+        } else if self.installed {
+            Some(PackageState::Installed)
+        } else {
+            None
+        }
+    }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            let status = if err.use_stderr() { ExitStatus::Error } else { ExitStatus::Ok };
+            std::process::exit(status.code());
+        }
+    };
+    logging::init(cli.verbose, cli.quiet);
+    let color = cli.color.enabled(io::stdout().is_terminal());
     let (config_path, config_exists) = resolve_config_path(cli.config);
-    let config = match load_config(&config_path) {
+    let context = cli.context.as_deref();
+    // Validation reports what's wrong with a config that fails to load.
+    if let Commands::Config {
+        action: ConfigCommand::Validate { connect },
+    } = cli.command
+    {
+        if !config_exists {
+            eprintln!("error: no config file was found or set");
+            std::process::exit(ExitStatus::Error.code());
+        }
+        exit(validate::run_validate(&config_path, connect));
+    }
+    let config = match load_config(&config_path, context) {
         Ok(c) => c,
         Err(err) => {
             eprintln!("error: failed to load config: {err}");
-            std::process::exit(1);
+            std::process::exit(ExitStatus::Error.code());
         }
     };
 
@@ -171,342 +746,1126 @@ fn main() {
         Commands::Discover {
             timeout,
             update_config,
-        } => run_discover(Duration::from_secs(timeout), update_config, &config_path),
-        Commands::Status { all, targets } => {
+            domain,
+            scan,
+            follow,
+            prefer,
+            output,
+        } => {
+            if follow {
+                run_discover_follow(update_config, prefer, output, &config_path, context).map(|_| ExitStatus::Ok)
+            } else {
+                let source = match (domain, scan) {
+                    (Some(domain), _) => DiscoverySource::DnsSd(domain),
+                    (_, Some(subnet)) => DiscoverySource::Scan(subnet),
+                    _ => DiscoverySource::Mdns(Duration::from_secs(timeout)),
+                };
+                run_discover(source, update_config, prefer, output, &config_path, context).map(|_| ExitStatus::Ok)
+            }
+        }
+        Commands::Status {
+            all,
+            prefer,
+            check,
+            cached,
+            watch,
+            interval,
+            notify,
+            output,
+            filter,
+            targets,
+        } => {
             if targets.is_empty() && !all && !config_exists {
-                println!("No config file was found or set.");
+                eprintln!("No config file was found or set.");
             }
-            run_status(all, targets, &config)
+            let discover = all.then_some(prefer);
+            let options = StatusOptions {
+                check,
+                cached,
+                watch: watch.then_some(interval),
+                notify,
+                output,
+                color,
+            };
+            run_status(discover, options, &filter, targets, &config)
         }
+        Commands::Summary { output, selection } => summary::run_summary(output, selection, &config),
+        Commands::Report {
+            file,
+            format,
+            selection,
+        } => report::run_report(&file, format, selection, &config),
+        Commands::Packages {
+            action: Some(PackagesCommand::List { target, filter }),
+            ..
+        } => run_packages_list(&target, filter.state(), &config).map(|_| ExitStatus::Ok),
+        Commands::Packages {
+            action: Some(PackagesCommand::Policy { target, packages }),
+            ..
+        } => run_packages_policy(&target, &packages, &config).map(|_| ExitStatus::Ok),
+        Commands::Packages {
+            action: Some(PackagesCommand::Transactions { target, limit }),
+            ..
+        } => run_packages_transactions(&target, limit, &config).map(|_| ExitStatus::Ok),
+        Commands::Packages {
+            action: Some(PackagesCommand::Install { packages, selection }),
+            ..
+        } => run_package_action("install", &packages, selection, cli.yes, &config),
+        Commands::Packages {
+            action: Some(PackagesCommand::Remove { packages, selection }),
+            ..
+        } => run_package_action("remove", &packages, selection, cli.yes, &config),
+        Commands::Packages {
+            action: Some(PackagesCommand::Repair { selection }),
+            ..
+        } => run_node_action(selection, &config, |client, node| {
+            client.repair(node).map(|response| repair_message(&response))
+        }),
         Commands::Packages {
             full_upgrade,
             targets,
+            action: None,
         } => {
             if targets.is_empty() && !config_exists {
                 println!("No config file was found or set.");
             }
-            run_packages(full_upgrade, targets, &config)
+            run_packages(full_upgrade, targets, cli.yes, &config)
+        }
+        Commands::Upgrade {
+            rolling,
+            max_parallel,
+            fail_fast,
+            health_check,
+            reboot,
+            wait_timeout,
+            include_phased,
+            exclude_phased,
+            confold,
+            confnew,
+            download_limit,
+            exclude,
+            only,
+            strategy,
+            dry_run,
+            wake,
+            wake_timeout,
+            selection,
+        } if !dry_run => upgrade::run_upgrade(
+            upgrade::UpgradeOptions {
+                rolling,
+                max_parallel: max_parallel.into(),
+                fail_fast,
+                health_check,
+                reboot,
+                wait_timeout,
+                wake: wake.then_some(wake_timeout),
+                yes: cli.yes,
+                request: FullUpgradeRequest {
+                    phased_updates: if include_phased {
+                        Some(PhasedUpdates::Include)
+                    } else if exclude_phased {
+                        Some(PhasedUpdates::Exclude)
+                    } else {
+                        None
+                    },
+                    conffiles: if confold {
+                        Some(ConffilePolicy::ConfOld)
+                    } else if confnew {
+                        Some(ConffilePolicy::ConfNew)
+                    } else {
+                        None
+                    },
+                    download_limit,
+                    exclude,
+                    only,
+                    strategy: strategy.map(UpgradeStrategy::from),
+                },
+            },
+            selection,
+            &config,
+        ),
+        Commands::Upgrade { selection, .. } => upgrade::run_dry_run(selection, &config),
+        Commands::Config {
+            action: ConfigCommand::Path,
+        } => {
+            println!("{}", config_path.display());
+            if !config_exists {
+                eprintln!("No config file was found; this path will be created on first write.");
+            }
+            Ok(ExitStatus::Ok)
+        }
+        Commands::Config {
+            action:
+                ConfigCommand::Import {
+                    file,
+                    format,
+                    from_ssh_config,
+                    port,
+                    agentless,
+                    no_probe,
+                },
+        } => match (from_ssh_config, file) {
+            (Some(ssh_config), _) => {
+                let options = ssh_config::SshImportOptions {
+                    port,
+                    agentless,
+                    no_probe,
+                };
+                ssh_config::run_import_ssh_config(ssh_config, options, &config_path, context)
+            }
+            (None, Some(file)) => inventory::run_import(&file, format, &config_path, context),
+            (None, None) => unreachable!("clap requires a file or --from-ssh-config"),
+        },
+        Commands::Config {
+            action: ConfigCommand::Export { format },
+        } => inventory::run_export(format, &config),
+        Commands::Prefetch {
+            download_limit,
+            selection,
+        } => {
+            let request = PrefetchRequest { download_limit };
+            run_node_action(selection, &config, |client, node| {
+                client
+                    .prefetch(node, &request)
+                    .map(|response| format!("{} (job {})", response.message, response.job_id))
+            })
+        }
+        Commands::Exec { name, selection } => run_node_action(selection, &config, |client, node| {
+            client.run_command(node, &name).map(|response| command_message(&response))
+        }),
+        Commands::Wake {
+            wait,
+            broadcast,
+            selection,
+        } => wake::run_wake(selection, broadcast, wait, &config),
+        Commands::Reboot {
+            if_required,
+            rolling,
+            wait_timeout,
+            tag,
+            targets,
+        } => reboot::run_reboot(
+            targets,
+            &tag,
+            reboot::RebootOptions {
+                if_required,
+                rolling,
+                wait_timeout,
+                yes: cli.yes,
+            },
+            &config,
+        ),
+        Commands::Shutdown {
+            delay,
+            cancel,
+            selection,
+        } => reboot::run_shutdown(selection, delay, cancel, cli.yes, &config),
+        Commands::RestartServices { selection } => run_node_action(selection, &config, |client, node| {
+            client
+                .restart_outdated_services(node)
+                .map(|response| restart_services_message(&response))
+        }),
+        Commands::Diff {
+            node,
+            other,
+            packages,
+        } => diff::run_diff(&node, other.as_deref(), &packages, &config),
+        Commands::Jobs {
+            target,
+            state,
+            since,
+            limit,
+        } => jobs::run_jobs(&target, state, since, limit, &config).map(|_| ExitStatus::Ok),
+        Commands::Logs {
+            target,
+            source,
+            tail,
+            follow,
+        } => logs::run_logs(&target, &source, tail, follow, &config).map(|_| ExitStatus::Ok),
+        Commands::Services { action } => services::run_services(action, &config),
+        Commands::Sources { action } => sources::run_sources(action, &config),
+        Commands::UnattendedUpgrades { action } => unattended::run_unattended(action, &config),
+        Commands::Agent => schedule::run_agent(&config, &config_path),
+        Commands::Export {
+            action: ExportCommand::AnsibleInventory { format, all, prefer },
+        } => ansible::run_export_inventory(format, all.then_some(prefer), &config),
+        Commands::Export {
+            action:
+                ExportCommand::PrometheusSd {
+                    out,
+                    all,
+                    prefer,
+                    interval,
+                },
+        } => file_sd::run_export_sd(&out, all.then_some(prefer), interval, &config),
+        Commands::Config {
+            action: ConfigCommand::UseContext { name },
+        } => config::run_use_context(&config_path, name).map(|()| ExitStatus::Ok),
+        Commands::Config {
+            action: ConfigCommand::GetContexts,
+        } => {
+            config::run_get_contexts(&config);
+            Ok(ExitStatus::Ok)
         }
+        Commands::Config {
+            action: ConfigCommand::Keyring { action },
+        } => secrets::run_keyring(action, &config_path, context),
+        Commands::Config {
+            action: ConfigCommand::Validate { .. },
+        } => unreachable!("handled before loading the config"),
     };
+    exit(result)
+}
 
-    if let Err(err) = result {
-        eprintln!("error: {err}");
-        std::process::exit(1);
+fn exit(result: Result<ExitStatus, Box<dyn Error>>) -> ! {
+    match result {
+        Ok(status) => std::process::exit(status.code()),
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(ExitStatus::Error.code());
+        }
     }
 }
 
+enum DiscoverySource {
+    Mdns(Duration),
+    DnsSd(String),
+    Scan(String),
+}
+
 fn run_discover(
-    timeout: Duration,
+    source: DiscoverySource,
     update_config: bool,
+    prefer: AddressPreference,
+    output: OutputFormat,
     config_path: &Path,
+    context: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
-    println!("Discovery will take {} seconds", timeout.as_secs());
-    let mdns = ServiceDaemon::new().map_err(|err| format!("create resolver: {err}"))?;
-    let service_name = format!(
-        "{}.{}",
-        SERVICE_TYPE.trim_end_matches('.'),
-        SERVICE_DOMAIN
-    );
-    let receiver = mdns
-        .browse(&service_name)
-        .map_err(|err| format!("browse: {err}"))?;
-
-    let deadline = Instant::now() + timeout;
-    let mut seen = HashSet::new();
+    let notice = |message: String| {
+        if output.is_machine_readable() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    };
+
     let mut header_printed = false;
     let mut discovered_nodes = Vec::new();
+    let mut discovered_macs = Vec::new();
+    let mut json_nodes = Vec::new();
+    let mut writer = RowWriter::new(io::stdout(), output);
+    let progress = Progress::spinner("discovering");
+    let mut found = 0;
+
+    let mut record = |node: DiscoveredNode| -> Result<(), Box<dyn Error>> {
+        found += 1;
+        progress.set_message(format!("{found} found"));
+        if output == OutputFormat::Json {
+            header_printed = true;
+            json_nodes.push(node.to_json());
+        } else {
+            progress.suspend(|| -> io::Result<()> {
+                if !header_printed {
+                    writer.write_row(&discover_header(output))?;
+                    header_printed = true;
+                }
+                writer.write_row(&discover_row(&node, output))?;
+                writer.flush()
+            })?;
+        }
 
-    let stdout = io::stdout();
-    let mut writer = TabWriter::new(stdout).padding(2);
+        if let Some(address) = node.address(prefer) {
+            discovered_macs.extend(node.property("mac").map(|mac| (address.clone(), mac.to_string())));
+            discovered_nodes.push((address, node.key()));
+        }
+        Ok(())
+    };
 
-    loop {
-        let now = Instant::now();
-        if now >= deadline {
-            break;
-        }
-
-        let remaining = deadline - now;
-        match receiver.recv_timeout(remaining) {
-            Ok(event) => match event {
-                ServiceEvent::ServiceResolved(info) => {
-                    let fullname = info.get_fullname().to_string();
-                    if seen.insert(fullname) {
-                        if !header_printed {
-                            writeln!(writer, "ID\tHOST\tADDRESS\tPORT\tINSTANCE")?;
-                            header_printed = true;
-                        }
-                        writeln!(
-                            writer,
-                            "{}\t{}\t{}\t{}\t{}",
-                            entry_id(&info),
-                            entry_host(&info),
-                            entry_addresses(&info),
-                            info.get_port(),
-                            entry_instance(&info)
-                        )?;
-                        writer.flush()?;
-
-                        if let Some(addr) = info.get_addresses().iter().next() {
-                            discovered_nodes.push((
-                                format!("{}:{}", addr, info.get_port()),
-                                entry_id(&info),
-                            ));
-                        }
-                    }
-                }
-                ServiceEvent::SearchStopped(service_type) => {
-                    eprintln!("Search stopped for {}", service_type);
-                }
-                _ => {}
-            },
-            Err(RecvTimeoutError::Timeout) => break,
-            Err(RecvTimeoutError::Disconnected) => {
-                return Err("browse: receiver disconnected".into());
+    match source {
+        DiscoverySource::Mdns(timeout) => {
+            notice(format!("Discovery will take {} seconds", timeout.as_secs()));
+            discovery::browse_mdns(timeout, record)?;
+        }
+        DiscoverySource::DnsSd(domain) => {
+            notice(format!("Querying DNS-SD records in {domain}"));
+            for node in discovery::browse_dns_sd(&domain)? {
+                record(node)?;
+            }
+        }
+        DiscoverySource::Scan(subnet) => {
+            notice(format!("Scanning {subnet}"));
+            for node in discovery::scan_subnet(&subnet)? {
+                record(node)?;
             }
         }
     }
+    progress.finish();
 
-    let _ = mdns.shutdown();
-
-    if !header_printed {
-        println!("No cobbler daemons found.");
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&json_nodes)?);
+    } else if !header_printed {
+        notice("No cobbler daemons found.".to_string());
     }
 
     if update_config {
-        let mut config = load_config(config_path)?;
-        if merge_nodes(&mut config, discovered_nodes) {
+        let mut config = load_config(config_path, context)?;
+        confirm_moved_nodes(&config, &mut discovered_nodes)?;
+        let merged = merge_nodes(&mut config, discovered_nodes);
+        if record_macs(&mut config, &discovered_macs) || merged {
             save_config(config_path, &config)?;
-            println!("Configuration updated: {}", config_path.display());
+            notice(format!("Configuration updated: {}", config_path.display()));
         } else {
-            println!("No new daemons found to add to configuration.");
+            notice("No new daemons found to add to configuration.".to_string());
         }
     }
 
     Ok(())
 }
 
+fn run_discover_follow(
+    update_config: bool,
+    prefer: AddressPreference,
+    output: OutputFormat,
+    config_path: &Path,
+    context: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    eprintln!("Watching for cobbler daemons, press Ctrl-C to stop");
+
+    let mut writer = RowWriter::new(io::stdout(), output);
+    if output != OutputFormat::Json {
+        let mut header = vec!["EVENT"];
+        header.extend(discover_header(output));
+        writer.write_row(&header)?;
+        writer.flush()?;
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::Parser;
-
-    #[test]
-    fn test_cli_parse_discover_default() {
-        let cli = Cli::parse_from(&["cobbler", "discover"]);
-        if let Commands::Discover {
-            timeout,
-            update_config,
-        } = cli.command
-        {
-            assert_eq!(timeout, 5);
-            assert!(!update_config);
+    discovery::follow_mdns(|event| {
+        let (label, node) = match &event {
+            DiscoveryEvent::Appeared(node) => ("appeared", node),
+            DiscoveryEvent::Disappeared(node) => ("disappeared", node),
+        };
+        if output == OutputFormat::Json {
+            let mut object = node.to_json();
+            object["event"] = label.into();
+            println!("{object}");
         } else {
-            panic!("Wrong command");
+            let mut row = vec![label.to_string()];
+            row.extend(discover_row(node, output));
+            writer.write_row(&row)?;
+            writer.flush()?;
         }
-    }
 
-    #[test]
-    fn test_cli_parse_discover_timeout() {
-        let cli = Cli::parse_from(&["cobbler", "discover", "-t", "10", "-u"]);
-        if let Commands::Discover {
-            timeout,
-            update_config,
-        } = cli.command
-        {
-            assert_eq!(timeout, 10);
-            assert!(update_config);
-        } else {
-            panic!("Wrong command");
+        if let (true, DiscoveryEvent::Appeared(node)) = (update_config, &event) {
+            if let Some(address) = node.address(prefer) {
+                let mut config = load_config(config_path, context)?;
+                let macs: Vec<_> = node.property("mac").map(|mac| (address.clone(), mac.to_string())).into_iter().collect();
+                let mut discovered = vec![(address, node.key())];
+                confirm_moved_nodes(&config, &mut discovered)?;
+                let merged = merge_nodes(&mut config, discovered);
+                if record_macs(&mut config, &macs) || merged {
+                    save_config(config_path, &config)?;
+                    eprintln!("Configuration updated: {}", config_path.display());
+                }
+            }
         }
-    }
+        Ok(())
+    })
+}
 
-    #[test]
-    fn test_resolve_config_path() {
-        let explicit = Some(PathBuf::from("custom.yaml"));
-        let (path, exists) = resolve_config_path(explicit);
-        assert_eq!(path, PathBuf::from("custom.yaml"));
-        assert!(exists);
+/// Asks before moving a configured node to a new address. Declined nodes are dropped
+/// from `discovered`; without a terminal on stdin every move is accepted.
+fn confirm_moved_nodes(config: &Config, discovered: &mut Vec<(String, String)>) -> io::Result<()> {
+    let interactive = io::stdin().is_terminal();
+    for (name, old, new) in moved_nodes(config, discovered) {
+        if !interactive {
+            eprintln!("{name} moved from {old} to {new}, updating stored address");
+            continue;
+        }
 
-        let (path, _) = resolve_config_path(None);
-        assert_eq!(path, PathBuf::from(".cobbler.yaml"));
+        eprint!("{name} moved from {old} to {new}. Update stored address? [Y/n] ");
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if matches!(answer.trim().to_ascii_lowercase().as_str(), "n" | "no") {
+            discovered.retain(|(addr, id)| !(addr == &new && id == &name));
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_get_default_timeout() {
-        std::env::set_var("COBBLER_TIMEOUT", "15");
-        assert_eq!(get_default_timeout(), Duration::from_secs(15));
-
-        std::env::set_var("COBBLER_TIMEOUT", "1m");
-        assert_eq!(get_default_timeout(), Duration::from_secs(60));
+/// Lists the nodes a destructive action is about to hit and asks before going ahead.
+/// Without a terminal on stdin, e.g. in scheduled runs, there is no one to ask, so it fails
+/// unless `yes` is set.
+fn confirm_targets(action: &str, targets: &[String], yes: bool) -> io::Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::other(format!(
+            "refusing to {action} on {} node(s) without confirmation; pass --yes when not running in a terminal",
+            targets.len()
+        )));
+    }
 
-        std::env::remove_var("COBBLER_TIMEOUT");
-        assert_eq!(get_default_timeout(), Duration::from_secs(60));
+    eprintln!("About to {action} on {} node(s):", targets.len());
+    for target in targets {
+        eprintln!("  {target}");
     }
+    eprint!("Continue? [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let confirmed = is_yes(&answer);
+    if !confirmed {
+        eprintln!("Aborted.");
+    }
+    Ok(confirmed)
+}
 
-    #[test]
-    fn test_merge_nodes() {
-        let mut config = Config {
-            nodes: vec![NodeConfig {
-                name: None,
-                address: "1.1.1.1:8080".to_string(),
-                api_key: None,
-            }],
-        };
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
 
-        let discovered = vec![
-            ("1.1.1.1:8080".to_string(), "node1".to_string()),
-            ("2.2.2.2:8080".to_string(), "node2".to_string()),
-        ];
+fn discover_header(output: OutputFormat) -> Vec<&'static str> {
+    let mut header = vec![
+        "ID", "HOST", "ADDRESS", "PORT", "INSTANCE", "VERSION", "OS", "UPDATES", "REBOOT",
+    ];
+    if output.is_detailed() {
+        header.push("PROPERTIES");
+    }
+    header
+}
 
-        let updated = merge_nodes(&mut config, discovered);
-        assert!(updated);
-        assert_eq!(config.nodes.len(), 2);
-        
-        // Existing node updated with name
-        assert_eq!(config.nodes[0].address, "1.1.1.1:8080");
-        assert_eq!(config.nodes[0].name, Some("node1".to_string()));
-        assert_eq!(config.nodes[0].api_key, None);
+fn discover_row(node: &DiscoveredNode, output: OutputFormat) -> Vec<String> {
+    let property = |key: &str| node.property(key).unwrap_or_default().to_string();
+    let reboot = match node.property("reboot_required") {
+        Some("true") => "yes",
+        Some("false") => "no",
+        _ => "",
+    };
 
-        // New node added with name and placeholder token
-        assert_eq!(config.nodes[1].address, "2.2.2.2:8080");
-        assert_eq!(config.nodes[1].name, Some("node2".to_string()));
-        assert_eq!(config.nodes[1].api_key, Some(TOKEN_PLACEHOLDER.to_string()));
+    let mut row = vec![
+        node.id.clone(),
+        node.host.clone(),
+        node.addresses_display(),
+        node.port.to_string(),
+        node.instance.clone(),
+        property("version"),
+        property("os"),
+        property("updates"),
+        reboot.to_string(),
+    ];
+    if output.is_detailed() {
+        row.push(node.properties.join(" "));
     }
+    row
+}
 
-    #[test]
-    fn test_merge_nodes_updates_name_but_preserves_token() {
-        let mut config = Config {
-            nodes: vec![NodeConfig {
-                name: Some("OldName".to_string()),
-                address: "1.1.1.1:8080".to_string(),
-                api_key: Some("secret".to_string()),
-            }],
-        };
-
-        let discovered = vec![("1.1.1.1:8080".to_string(), "NewName".to_string())];
+#[derive(Clone, Copy)]
+struct StatusOptions {
+    check: bool,
+    /// Read from the status cache instead of querying the nodes.
+    cached: bool,
+    /// Refresh interval in watch mode
+    watch: Option<Duration>,
+    /// Show desktop notifications in watch mode
+    notify: bool,
+    output: OutputFormat,
+    color: bool,
+}
 
-        let updated = merge_nodes(&mut config, discovered);
-        assert!(updated);
-        assert_eq!(config.nodes[0].name, Some("NewName".to_string()));
-        assert_eq!(config.nodes[0].api_key, Some("secret".to_string()));
+fn run_status(
+    discover: Option<AddressPreference>,
+    options: StatusOptions,
+    filter: &StatusFilter,
+    mut targets: Vec<String>,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    if let Some(prefer) = discover {
+        targets.extend(discover_targets(prefer)?);
     }
 
-    #[test]
-    fn test_merge_nodes_updates_custom_name() {
-        let mut config = Config {
-            nodes: vec![NodeConfig {
-                name: Some("Custom".to_string()),
-                address: "1.1.1.1:8080".to_string(),
-                api_key: None,
-            }],
-        };
+    if targets.is_empty() {
+        for node in &config.nodes {
+            targets.push(node.address.clone());
+        }
+    }
 
-        let discovered = vec![("1.1.1.1:8080".to_string(), "node1".to_string())];
+    if targets.is_empty() {
+        eprintln!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
 
-        let updated = merge_nodes(&mut config, discovered);
-        assert!(updated);
-        assert_eq!(config.nodes[0].name, Some("node1".to_string()));
+    // Only the detailed formats show resource usage.
+    let with_resources = options.output.is_detailed();
+    let Some(interval) = options.watch else {
+        let fetched = fetch_statuses(options.cached, with_resources, &targets, config)?;
+        return print_status(options, filter, fetched);
+    };
+    let mut watch = desktop::Watch::default();
+    let client = api_client()?;
+    loop {
+        let fetched = fetch_statuses(options.cached, with_resources, &targets, config)?;
+        if options.notify {
+            for (target, result) in &fetched.results {
+                // A status shown from the cache means the node didn't answer.
+                let status = match result {
+                    Ok((status, json)) if status.is_success() && !fetched.cached_at.contains_key(target) => {
+                        Some(parse_node_status(json))
+                    }
+                    _ => None,
+                };
+                watch.observe(target, status.as_ref(), || recent_upgrades(&client, target, config));
+            }
+        }
+        if io::stdout().is_terminal() {
+            // Clear the screen and move to the top, like watch(1).
+            print!("\x1b[2J\x1b[H");
+        }
+        print_status(options, filter, fetched)?;
+        std::thread::sleep(interval);
     }
+}
 
-    #[test]
-    fn test_merge_nodes_cleans_id_prefix_from_config() {
-        let mut config = Config {
-            nodes: vec![NodeConfig {
-                name: Some("id=raspi1".to_string()),
-                address: "1.1.1.1:8080".to_string(),
-                api_key: None,
-            }],
+/// The statuses of one round, live or from the cache.
+struct FetchedStatuses {
+    exit_status: ExitStatus,
+    now: u64,
+    /// When each status taken from the cache was fetched
+    cached_at: HashMap<String, u64>,
+    results: Vec<(String, StatusResult)>,
+    /// Resource usage of the nodes that reported it, if asked for
+    resources: HashMap<String, ResourcesResponse>,
+}
+
+fn fetch_statuses(
+    cached: bool,
+    with_resources: bool,
+    targets: &[String],
+    config: &Config,
+) -> Result<FetchedStatuses, Box<dyn Error>> {
+    let now = std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let cache_path = cache::status_cache_path();
+    let mut cache = cache_path.as_deref().map(StatusCache::load).unwrap_or_default();
+    // When each shown status was fetched, for those taken from the cache.
+    let mut cached_at = HashMap::new();
+
+    let mut exit_status = ExitStatus::Ok;
+    let mut results = Vec::new();
+    if cached {
+        for target in targets {
+            let result = match cache.get(target) {
+                Some(entry) => {
+                    cached_at.insert(target.clone(), entry.fetched_at);
+                    Ok((StatusCode::OK, Some(entry.status.clone())))
+                }
+                None => {
+                    exit_status = exit_status.max(ExitStatus::Unreachable);
+                    Err("no cached status".to_string())
+                }
+            };
+            results.push((target.clone(), result));
+        }
+        return Ok(FetchedStatuses {
+            exit_status,
+            now,
+            cached_at,
+            results,
+            resources: HashMap::new(),
+        });
+    }
+
+    let client = api_client()?;
+    let mut resources = HashMap::new();
+    let progress = Progress::bar("fetching status", targets.len());
+    for target in targets {
+        progress.set_message(target.clone());
+        if let Some(node) = ssh_node(config, target) {
+            let result = ssh::status(node).map(|status| (StatusCode::OK, serde_json::to_value(status).ok()));
+            if result.is_err() {
+                exit_status = exit_status.max(ExitStatus::Unreachable);
+            }
+            results.push((target.clone(), result));
+            progress.inc();
+            continue;
+        }
+        let node = client_node(target, config);
+        // One round-trip for both where the daemon has `GET /snapshot`.
+        if with_resources {
+            match client.snapshot(&node) {
+                Ok(snapshot) => {
+                    resources.insert(target.clone(), snapshot.resources);
+                    results.push((target.clone(), Ok((StatusCode::OK, serde_json::to_value(snapshot.status).ok()))));
+                    progress.inc();
+                    continue;
+                }
+                Err(err) => debug!("{target}: no snapshot, asking for status and resources separately: {err}"),
+            }
+        }
+        let result = match client.send_json(&node, Method::GET, "/status", None) {
+            Ok(resp) => {
+                // A node without apt answers fine; it just has nothing to report.
+                let not_debian = resp.error().is_some_and(|error| error.code == ErrorCode::NotDebian);
+                // The node is up, but failed to answer; see `error_exit_status`.
+                if !resp.status.is_success() && !not_debian {
+                    exit_status = exit_status.max(ExitStatus::OperationFailed);
+                }
+                Ok((resp.status, resp.body))
+            }
+            Err(err) => {
+                exit_status = exit_status.max(ExitStatus::Unreachable);
+                Err(err.to_string())
+            }
         };
+        // Daemons predating `GET /system/resources` just go without.
+        if with_resources && result.is_ok() {
+            match client.resources(&node) {
+                Ok(usage) => {
+                    resources.insert(target.clone(), usage);
+                }
+                Err(err) => debug!("{target}: no resource usage: {err}"),
+            }
+        }
+        results.push((target.clone(), result));
+        progress.inc();
+    }
+    progress.finish();
+
+    for (target, result) in &mut results {
+        match result {
+            Ok((status, Some(json))) if status.is_success() => cache.insert(target, now, json.clone()),
+            Err(err) => {
+                if let Some(entry) = cache.get(target) {
+                    let since = UNIX_EPOCH + Duration::from_secs(entry.fetched_at);
+                    warn!(
+                        "{target}: {err}; showing its last known status, stale since {}",
+                        humantime::format_rfc3339_seconds(since)
+                    );
+                    cached_at.insert(target.clone(), entry.fetched_at);
+                    *result = Ok((StatusCode::OK, Some(entry.status.clone())));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(path) = &cache_path {
+        if let Err(err) = cache.save(path) {
+            warn!("not caching the status: {err}");
+        }
+    }
 
-        // Discovered node has the clean name
-        let discovered = vec![("1.1.1.1:8080".to_string(), "raspi1".to_string())];
+    Ok(FetchedStatuses {
+        exit_status,
+        now,
+        cached_at,
+        results,
+        resources,
+    })
+}
 
-        let updated = merge_nodes(&mut config, discovered);
-        assert!(updated);
-        assert_eq!(config.nodes[0].name, Some("raspi1".to_string()));
+fn print_status(
+    options: StatusOptions,
+    filter: &StatusFilter,
+    fetched: FetchedStatuses,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let FetchedStatuses {
+        mut exit_status,
+        now,
+        cached_at,
+        mut results,
+        resources,
+    } = fetched;
+    if filter.is_active() {
+        results.retain(|(_, result)| {
+            matches!(result, Ok((_, json)) if filter.matches(&parse_node_status(json)))
+        });
+        if results.is_empty() {
+            eprintln!("No nodes match the given filters.");
+            return Ok(exit_status);
+        }
     }
 
-    #[test]
-    fn test_merge_nodes_prevents_duplicate_by_name() {
-        let mut config = Config {
-            nodes: vec![NodeConfig {
-                name: Some("raspi1".to_string()),
-                address: "1.1.1.1:8080".to_string(),
-                api_key: Some("secret".to_string()),
-            }],
-        };
+    let pending = results
+        .iter()
+        .any(|(_, result)| matches!(result, Ok((_, Some(json))) if has_pending_updates(json)));
+    if options.check && pending {
+        exit_status = exit_status.max(ExitStatus::UpdatesPending);
+    }
+
+    if options.output == OutputFormat::Table {
+        print_status_table(io::stdout(), options.color, now, &cached_at, &results)?;
+    } else if options.output == OutputFormat::Json {
+        print_status_documents(io::stdout(), &cached_at, &resources, &results)?;
+    } else {
+        print_status_rows(io::stdout(), options.output, options.color, &cached_at, &resources, &results)?;
+    }
+
+    Ok(exit_status)
+}
 
-        // raspi1 changed IP
-        let discovered = vec![("1.1.1.2:8080".to_string(), "raspi1".to_string())];
+type StatusResult = Result<(StatusCode, Option<serde_json::Value>), String>;
 
-        let updated = merge_nodes(&mut config, discovered);
-        assert!(updated);
-        assert_eq!(config.nodes.len(), 1);
-        assert_eq!(config.nodes[0].address, "1.1.1.2:8080");
-        assert_eq!(config.nodes[0].name, Some("raspi1".to_string()));
-        assert_eq!(config.nodes[0].api_key, Some("secret".to_string()));
+/// Green for nodes that are up to date, yellow for pending updates or other reasons for
+/// attention, red for unreachable, failing or critical nodes. Nodes without apt stay plain.
+fn status_color(result: &StatusResult) -> Option<Color> {
+    let (status, json) = match result {
+        Ok((status, json)) => (status, json),
+        Err(_) => return Some(Color::Red),
+    };
+    if !status.is_success() {
+        let not_debian = error_response(json).is_some_and(|error| error.code == ErrorCode::NotDebian);
+        return (!not_debian).then_some(Color::Red);
     }
+    let node = parse_node_status(json);
+    Some(match node.health {
+        HealthState::Critical => Color::Red,
+        HealthState::NeedsAttention => Color::Yellow,
+        HealthState::Ok if !node.updates.is_empty() => Color::Yellow,
+        HealthState::Ok => Color::Green,
+    })
+}
 
-    #[test]
-    fn test_clean_node_id() {
-        assert_eq!(clean_node_id("id=raspi1"), "raspi1");
-        assert_eq!(clean_node_id("raspi1"), "raspi1");
-        assert_eq!(clean_node_id(""), "");
+/// Statuses shown from the cache are dimmed, as they may no longer hold.
+fn row_color(target: &str, cached_at: &HashMap<String, u64>, result: &StatusResult) -> Option<Color> {
+    if cached_at.contains_key(target) {
+        return Some(Color::Dim);
     }
+    status_color(result)
+}
+
+fn error_response(json: &Option<serde_json::Value>) -> Option<ErrorResponse> {
+    json.clone().and_then(|json| serde_json::from_value(json).ok())
 }
 
+/// One line per node; why nodes could not report follows below the table. Statuses from the
+/// cache are marked with their age.
+fn print_status_table<W: Write>(
+    mut writer: W,
+    color: bool,
+    now: u64,
+    cached_at: &HashMap<String, u64>,
+    results: &[(String, StatusResult)],
+) -> io::Result<()> {
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    let mut rows = RowWriter::new(&mut writer, OutputFormat::Table).with_color(color);
+    rows.write_row(&["NODE", "UPDATES", "SECURITY", "REBOOT", "UPGRADING", "LAST_CHECK"])?;
+    let mut errors = Vec::new();
+    for (target, result) in results {
+        let (state, error) = match result {
+            Ok((status, json)) if status.is_success() => {
+                let node = parse_node_status(json);
+                let last_check = node.last_check.map(|at| format_age(now.saturating_sub(at)));
+                let name = match cached_at.get(target) {
+                    Some(at) => format!("{target} (cached {})", format_age(now.saturating_sub(*at))),
+                    None => target.clone(),
+                };
+                let row = [
+                    name,
+                    node.updates.len().to_string(),
+                    node.security_updates.to_string(),
+                    yes_no(node.reboot_required),
+                    yes_no(node.is_upgrading),
+                    last_check.unwrap_or_else(|| "-".to_string()),
+                ];
+                rows.write_colored_row(&row, row_color(target, cached_at, result))?;
+                continue;
+            }
+            Ok((status, json)) => match error_response(json) {
+                Some(error) if error.code == ErrorCode::NotDebian => ("not-debian", None),
+                Some(error) if !error.message.is_empty() => ("error", Some(format!("{status} {}", error.message))),
+                _ => ("error", Some(status.to_string())),
+            },
+            Err(err) => ("unreachable", Some(err.clone())),
+        };
+        rows.write_colored_row(&[target, state, "-", "-", "-", "-"], status_color(result))?;
+        errors.extend(error.map(|error| format!("{target}: {error}")));
+    }
+    rows.flush()?;
+    drop(rows);
+
+    if !errors.is_empty() {
+        writeln!(writer)?;
+        for error in errors {
+            writeln!(writer, "{error}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Renders e.g. `45s ago`, `12m ago`, `3h ago` or `2d ago`.
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s ago"),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+fn print_status_documents<W: Write>(
+    mut writer: W,
+    cached_at: &HashMap<String, u64>,
+    resources: &HashMap<String, ResourcesResponse>,
+    results: &[(String, StatusResult)],
+) -> io::Result<()> {
+    let documents: Vec<_> = results
+        .iter()
+        .map(|(target, result)| match result {
+            Ok((status, body)) => {
+                let mut document = serde_json::json!({
+                    "target": target,
+                    "status": status.as_u16(),
+                    "response": body,
+                });
+                if let Some(at) = cached_at.get(target) {
+                    document["cached_at"] = (*at).into();
+                }
+                if let Some(usage) = resources.get(target) {
+                    document["resources"] = serde_json::json!(usage);
+                }
+                document
+            }
+            Err(err) => serde_json::json!({ "target": target, "error": err }),
+        })
+        .collect();
+    writeln!(writer, "{}", serde_json::to_string_pretty(&documents)?)
+}
 
-fn clean_node_id(id: &str) -> &str {
-    id.strip_prefix("id=").unwrap_or(id)
+fn print_status_rows<W: Write>(
+    writer: W,
+    output: OutputFormat,
+    color: bool,
+    cached_at: &HashMap<String, u64>,
+    resources: &HashMap<String, ResourcesResponse>,
+    results: &[(String, StatusResult)],
+) -> io::Result<()> {
+    let mut rows = RowWriter::new(writer, output).with_color(color);
+    rows.write_row(&[
+        "TARGET", "STATUS", "HEALTH", "UPDATES", "SECURITY", "UPGRADING", "REBOOT", "KERNEL", "UPTIME",
+        "LAST_UPGRADE", "MESSAGE", "LOAD", "MEMORY", "DISK",
+    ])?;
+    for (target, result) in results {
+        let usage = resources.get(target);
+        let row = match result {
+            Ok((status, json)) => {
+                let node = parse_node_status(json);
+                // Error responses carry nothing to judge the node by.
+                let health = if status.is_success() { node.health.as_str() } else { "" };
+                let status = if cached_at.contains_key(target) {
+                    "cached".to_string()
+                } else {
+                    status.as_u16().to_string()
+                };
+                status_row(target, status, health, &node, usage, output)
+            }
+            Err(err) => status_row(
+                target,
+                "unreachable".to_string(),
+                HealthState::Critical.as_str(),
+                &StatusResponse {
+                    message: err.clone(),
+                    ..Default::default()
+                },
+                None,
+                output,
+            ),
+        };
+        rows.write_colored_row(&row, row_color(target, cached_at, result))?;
+    }
+    rows.flush()
 }
 
-fn entry_id(entry: &ServiceInfo) -> String {
-    let props = entry.get_properties();
-    props
-        .get("id")
-        .map(|value| clean_node_id(&value.to_string()).to_string())
+fn parse_node_status(json: &Option<serde_json::Value>) -> StatusResponse {
+    json.clone()
+        .and_then(|json| serde_json::from_value(json).ok())
         .unwrap_or_default()
 }
 
-fn entry_host(entry: &ServiceInfo) -> String {
-    entry.get_hostname().trim_end_matches('.').to_string()
+fn status_row(
+    target: &str,
+    status: String,
+    health: &str,
+    node: &StatusResponse,
+    resources: Option<&ResourcesResponse>,
+    output: OutputFormat,
+) -> Vec<String> {
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    let uptime = node.uptime_seconds.map(|seconds| {
+        if output.is_delimited() {
+            seconds.to_string()
+        } else {
+            humantime::format_duration(Duration::from_secs(seconds)).to_string()
+        }
+    });
+    let last_upgrade = node.last_upgrade.map(|timestamp| {
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string()
+    });
+    let mut kernel = node.kernel.clone().unwrap_or_default();
+    if node.kernel_update_pending && !output.is_delimited() {
+        match &node.kernel_candidate {
+            Some(candidate) => kernel = format!("{kernel} -> {candidate}"),
+            None => kernel.push_str(" (update pending)"),
+        }
+    }
+    let percent = |value: f64| {
+        if output.is_delimited() {
+            format!("{value:.0}")
+        } else {
+            format!("{value:.0}%")
+        }
+    };
+    let load = resources
+        .and_then(|resources| resources.load_average)
+        .map(|load| format!("{:.2}", load.one));
+    let memory = resources
+        .and_then(|resources| resources.memory)
+        .map(|memory| percent(memory.used_percent()));
+    // The fullest disk, with its mount point unless the output is machine-readable.
+    let disk = resources.and_then(ResourcesResponse::fullest_disk).map(|disk| {
+        let used = percent(disk.used_percent());
+        if output.is_delimited() {
+            used
+        } else {
+            format!("{used} {}", disk.mount_point)
+        }
+    });
+
+    vec![
+        target.to_string(),
+        status,
+        health.to_string(),
+        node.updates.len().to_string(),
+        node.security_updates.to_string(),
+        yes_no(node.is_upgrading),
+        yes_no(node.reboot_required),
+        kernel,
+        uptime.unwrap_or_default(),
+        last_upgrade.unwrap_or_default(),
+        node.message.clone(),
+        // Last, so that scripts reading the columns before them by position keep working.
+        load.unwrap_or_default(),
+        memory.unwrap_or_default(),
+        disk.unwrap_or_default(),
+    ]
 }
 
-fn entry_addresses(entry: &ServiceInfo) -> String {
-    let mut parts = Vec::new();
-    let addrs = entry.get_addresses();
-    for addr in addrs.iter().filter(|addr| addr.is_ipv4()) {
-        parts.push(addr.to_string());
+fn has_pending_updates(status: &serde_json::Value) -> bool {
+    status["updates"].as_array().is_some_and(|updates| !updates.is_empty())
+}
+
+fn discover_targets(prefer: AddressPreference) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut targets = Vec::new();
+    discovery::browse_mdns(get_default_timeout(), |node| {
+        targets.extend(node.address(prefer));
+        Ok(())
+    })?;
+    Ok(targets)
+}
+
+/// Describes the target as a client node, using its config entry if there is one and the
+/// defaults for what the entry doesn't set.
+fn client_node(target: &str, config: &Config) -> cobbler_client::Node {
+    let node = find_node(config, target);
+    let address = node.map_or(target, |n| n.address.as_str());
+    let defaults = &config.defaults;
+    cobbler_client::Node {
+        name: node.map(|_| target.to_string()),
+        address: address.to_string(),
+        api_key: node_api_key(address, node, defaults),
+        timeout: node.and_then(|n| n.timeout).or(defaults.timeout).map(Duration::from_secs),
+        retries: node.and_then(|n| n.retries).or(defaults.retries).unwrap_or(0),
+        http2: node.and_then(|n| n.http2).or(defaults.http2).unwrap_or(false),
     }
-    for addr in addrs.iter().filter(|addr| addr.is_ipv6()) {
-        parts.push(addr.to_string());
+}
+
+/// The node's API key from the config, or else from the OS keyring.
+fn node_api_key(address: &str, node: Option<&NodeConfig>, defaults: &NodeDefaults) -> Option<String> {
+    if let Some(key) = node.and_then(|n| n.api_key.clone()) {
+        return Some(key);
     }
-    parts.join(",")
+    let entry = node.and_then(|n| n.api_key_keyring.as_deref()).or(defaults.api_key_keyring.as_deref())?;
+    secrets::api_key(entry)
+        .map_err(|err| warn!("{address}: failed to read API key {entry:?} from the keyring: {err}"))
+        .ok()
 }
 
-fn entry_instance(entry: &ServiceInfo) -> String {
-    let fullname = entry.get_fullname();
-    let suffix = format!(
-        ".{}.{}",
-        SERVICE_TYPE.trim_end_matches('.'),
-        SERVICE_DOMAIN
-    );
-    fullname
-        .strip_suffix(&suffix)
-        .unwrap_or(fullname)
-        .to_string()
+fn api_client() -> Result<ApiClient, cobbler_client::Error> {
+    Ok(ApiClient::new(get_default_timeout())?.on_retry(|node, err, attempt, retries| {
+        warn!("{}: {err}; retrying ({attempt}/{retries})", node.label());
+    }))
 }
 
-fn run_status(
-    discover_all: bool,
-    mut targets: Vec<String>,
-    config: &Config,
-) -> Result<(), Box<dyn Error>> {
-    if discover_all {
-        targets.extend(discover_targets()?);
+/// The latest full upgrade jobs of `target`, newest first; none if they can't be fetched.
+fn recent_upgrades(client: &ApiClient, target: &str, config: &Config) -> Vec<cobbler_api::Job> {
+    let query = cobbler_api::JobsQuery {
+        kind: Some(cobbler_api::JobKind::FullUpgrade),
+        limit: Some(5),
+        ..Default::default()
+    };
+    match client.jobs(&client_node(target, config), &query) {
+        Ok(response) => response.jobs,
+        Err(err) => {
+            debug!("{target}: failed to fetch upgrade jobs: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Exit status for a failed request: the node answered with an error, or it could not be
+/// reached at all.
+fn error_exit_status(err: &cobbler_client::Error) -> ExitStatus {
+    match err {
+        cobbler_client::Error::Status { .. } => ExitStatus::OperationFailed,
+        _ => ExitStatus::Unreachable,
     }
+}
 
+fn fetch_status(client: &ApiClient, target: &str, config: &Config) -> Result<StatusResponse, String> {
+    if let Some(node) = ssh_node(config, target) {
+        return ssh::status(node);
+    }
+    client.status(&client_node(target, config)).map_err(|err| err.to_string())
+}
+
+/// Runs `fetch` for all targets in parallel, returning the results in target order. A
+/// progress bar with `label` counts the finished targets.
+fn fetch_all<T, F>(label: &str, targets: &[String], fetch: F) -> Vec<(String, T)>
+where
+    T: Send,
+    F: Fn(&str) -> T + Sync,
+{
+    let progress = Progress::bar(label, targets.len());
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|target| {
+                let fetch = &fetch;
+                let progress = &progress;
+                scope.spawn(move || {
+                    let result = fetch(target);
+                    progress.inc();
+                    result
+                })
+            })
+            .collect();
+        targets
+            .iter()
+            .cloned()
+            .zip(handles)
+            .map(|(target, handle)| {
+                let result = handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                (target, result)
+            })
+            .collect()
+    })
+}
+
+fn run_packages(
+    _full_upgrade: bool,
+    mut targets: Vec<String>,
+    yes: bool,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
     if targets.is_empty() {
         for node in &config.nodes {
             targets.push(node.address.clone());
@@ -515,39 +1874,38 @@ fn run_status(
 
     if targets.is_empty() {
         println!("No targets found.");
-        return Ok(());
+        return Ok(ExitStatus::Ok);
+    }
+    if !confirm_targets("start a full upgrade", &targets, yes)? {
+        return Ok(ExitStatus::Error);
     }
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(get_default_timeout())
-        .build()?;
+    let client = api_client()?;
 
     let mut tw = TabWriter::new(io::stdout());
     writeln!(tw, "TARGET\tSTATUS")?;
 
+    let mut exit_status = ExitStatus::Ok;
     for target in targets {
-        let url = resolve_url(&target);
-        let status_url = format!("{}/status", url);
+        let node = client_node(&target, config);
 
-        let mut request = client.get(&status_url);
-
-        if let Some(node) = config.nodes.iter().find(|n| n.address == target) {
-            if let Some(api_key) = &node.api_key {
-                request = request.header("X-API-Key", api_key);
-            }
-        }
-
-        let (status, body) = match request.send() {
+        let (status, body) = match client.send_json(&node, Method::POST, "/packages/full-upgrade", None) {
             Ok(resp) => {
-                let status = resp.status().to_string();
-                let body = match resp.json::<serde_json::Value>() {
-                    Ok(json) => serde_json::to_string_pretty(&json)
+                if !resp.status.is_success() {
+                    exit_status = exit_status.max(ExitStatus::OperationFailed);
+                }
+                let status = resp.status.to_string();
+                let body = match resp.body {
+                    Some(json) => serde_json::to_string_pretty(&json)
                         .unwrap_or_else(|_| "Failed to pretty-print JSON".to_string()),
-                    Err(_) => "Could not parse response as JSON".to_string(),
+                    None => "Upgrade triggered successfully".to_string(),
                 };
                 (status, body)
             }
-            Err(err) => (format!("Error: {}", err), "".to_string()),
+            Err(err) => {
+                exit_status = exit_status.max(ExitStatus::Unreachable);
+                (format!("Error: {}", err), "".to_string())
+            }
         };
 
         writeln!(tw, "{}\t{}", target, status)?;
@@ -558,121 +1916,1015 @@ fn run_status(
 
     tw.flush()?;
 
+    Ok(exit_status)
+}
+
+fn run_packages_list(
+    target: &str,
+    state: Option<PackageState>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let client = api_client()?;
+
+    let packages = fetch_packages(&client, target, state, config).map_err(|err| format!("{target}: {err}"))?;
+    print_packages(io::stdout(), &packages)?;
     Ok(())
 }
 
-fn discover_targets() -> Result<Vec<String>, Box<dyn Error>> {
-    let mut targets = Vec::new();
-    let mdns = ServiceDaemon::new().map_err(|err| format!("create resolver: {err}"))?;
-    let service_name = format!("{}.{}", SERVICE_TYPE.trim_end_matches('.'), SERVICE_DOMAIN);
-    let receiver = mdns
-        .browse(&service_name)
-        .map_err(|err| format!("browse: {err}"))?;
+fn fetch_packages(
+    client: &ApiClient,
+    target: &str,
+    state: Option<PackageState>,
+    config: &Config,
+) -> Result<Vec<PackageInfo>, String> {
+    client
+        .packages(&client_node(target, config), state)
+        .map_err(|err| err.to_string())
+}
 
-    let timeout = get_default_timeout();
-    let deadline = Instant::now() + timeout;
-    let mut seen = HashSet::new();
+fn print_packages<W: Write>(writer: W, packages: &[PackageInfo]) -> io::Result<()> {
+    if packages.is_empty() {
+        let mut writer = writer;
+        return writeln!(writer, "No packages found.");
+    }
 
-    loop {
-        let now = Instant::now();
-        if now >= deadline {
-            break;
-        }
-
-        let remaining = deadline - now;
-        match receiver.recv_timeout(remaining) {
-            Ok(event) => {
-                if let ServiceEvent::ServiceResolved(info) = event {
-                    for addr in info.get_addresses() {
-                        let target = format!("{}:{}", addr, info.get_port());
-                        if seen.insert(target.clone()) {
-                            targets.push(target);
-                        }
-                    }
-                }
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "NAME\tINSTALLED\tCANDIDATE\tHELD")?;
+    for pkg in packages {
+        let candidate = if pkg.upgradable {
+            pkg.candidate_version.as_deref().unwrap_or("-")
+        } else {
+            "-"
+        };
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}",
+            pkg.name,
+            pkg.installed_version,
+            candidate,
+            if pkg.held { "yes" } else { "no" }
+        )?;
+    }
+    tw.flush()
+}
+
+fn run_packages_policy(target: &str, packages: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    let client = api_client()?;
+
+    let policies = client
+        .policies(&client_node(target, config), packages)
+        .map_err(|err| format!("{target}: {err}"))?;
+    print_policies(io::stdout(), &policies)?;
+    Ok(())
+}
+
+fn print_policies<W: Write>(writer: W, policies: &[PackagePolicy]) -> io::Result<()> {
+    if policies.is_empty() {
+        let mut writer = writer;
+        return writeln!(writer, "No packages found.");
+    }
+
+    let version = |policy: &PackagePolicy, version: Option<&str>| match version {
+        Some(version) => match policy.version(version) {
+            Some(entry) => format!("{version} ({})", entry.priority),
+            None => version.to_string(),
+        },
+        None => "-".to_string(),
+    };
+
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "NAME\tINSTALLED\tCANDIDATE\tNEWEST\tNOTE")?;
+    for policy in policies {
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}",
+            policy.name,
+            version(policy, policy.installed.as_deref()),
+            version(policy, policy.candidate.as_deref()),
+            version(policy, policy.newest().map(|newest| newest.version.as_str())),
+            policy_note(policy)
+        )?;
+    }
+    tw.flush()
+}
+
+fn run_packages_transactions(target: &str, limit: usize, config: &Config) -> Result<(), Box<dyn Error>> {
+    let client = api_client()?;
+
+    let transactions = client
+        .transactions(&client_node(target, config), Some(limit))
+        .map_err(|err| format!("{target}: {err}"))?;
+    print_transactions(io::stdout(), &transactions)?;
+    Ok(())
+}
+
+/// Prints each transaction as a header line followed by its changes, oldest last.
+fn print_transactions<W: Write>(mut writer: W, transactions: &[AptTransaction]) -> io::Result<()> {
+    if transactions.is_empty() {
+        return writeln!(writer, "No transactions found.");
+    }
+
+    for (index, transaction) in transactions.iter().enumerate() {
+        if index > 0 {
+            writeln!(writer)?;
+        }
+        let mut header = transaction.start.replacen('T', " ", 1);
+        if let Some(command_line) = &transaction.command_line {
+            header = format!("{header}  {command_line}");
+        }
+        if let Some(user) = transaction.requested_by.as_deref().and_then(|by| by.split_whitespace().next()) {
+            header = format!("{header}  (by {user})");
+        }
+        writeln!(writer, "{header}")?;
+
+        let mut tw = TabWriter::new(&mut writer).padding(2);
+        let changes = [
+            ("install", &transaction.installed),
+            ("upgrade", &transaction.upgraded),
+            ("downgrade", &transaction.downgraded),
+            ("remove", &transaction.removed),
+        ];
+        for (action, packages) in changes {
+            for package in packages {
+                let version = match &package.old_version {
+                    Some(old_version) => format!("{old_version} -> {}", package.version),
+                    None => package.version.clone(),
+                };
+                let automatic = if package.automatic { " (automatic)" } else { "" };
+                writeln!(tw, "  {action}\t{} {version}{automatic}", package.name)?;
             }
-            Err(RecvTimeoutError::Timeout) => break,
-            Err(err) => return Err(format!("mDNS error: {err}").into()),
         }
+        if let Some(error) = &transaction.error {
+            writeln!(tw, "  error\t{error}")?;
+        }
+        if transaction.end.is_none() && transaction.error.is_none() {
+            writeln!(tw, "  note\tno end recorded: still running, or interrupted")?;
+        }
+        tw.flush()?;
     }
-    Ok(targets)
+    Ok(())
 }
 
-fn resolve_url(target: &str) -> String {
-    if target.starts_with("http://") || target.starts_with("https://") {
-        target.trim_end_matches('/').to_string()
-    } else if target.contains(':') && target.split(':').last().unwrap().chars().all(|c| c.is_ascii_digit()) {
-        let parts: Vec<&str> = target.split(':').collect();
-        let host = parts[..parts.len() - 1].join(":");
-        let port = parts.last().unwrap();
+/// Explains why apt's candidate is not the newest known version.
+fn policy_note(policy: &PackagePolicy) -> String {
+    let Some(newest) = policy.newest() else {
+        return "-".to_string();
+    };
+    let Some(candidate) = policy.candidate.as_deref().and_then(|candidate| policy.version(candidate)) else {
+        return "no candidate".to_string();
+    };
+    if candidate.version == newest.version {
+        "-".to_string()
+    } else if newest.priority < 0 {
+        "newest version is pinned away".to_string()
+    } else if newest.priority < candidate.priority {
+        format!(
+            "newest version has a lower priority ({} < {})",
+            newest.priority, candidate.priority
+        )
+    } else {
+        "candidate is pinned".to_string()
+    }
+}
 
-        if host.contains(':') && !host.starts_with('[') {
-            format!("http://[{}]:{}", host, port)
-        } else {
-            format!("http://{}:{}", host, port)
-        }
+fn run_package_action(
+    action: &str,
+    packages: &[String],
+    selection: TargetSelection,
+    yes: bool,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+    if action == "remove" && !confirm_targets(&format!("remove {}", packages.join(", ")), &targets, yes)? {
+        return Ok(ExitStatus::Error);
+    }
+
+    let client = api_client()?;
+
+    let path = format!("/packages/{action}");
+    let body = serde_json::json!({ "packages": packages });
+    let mut results = Vec::new();
+    let mut exit_status = ExitStatus::Ok;
+    for target in targets {
+        let node = client_node(&target, config);
+        let result = match client.send_json(&node, Method::POST, &path, Some(&body)) {
+            Ok(resp) => {
+                if !resp.status.is_success() {
+                    exit_status = exit_status.max(ExitStatus::OperationFailed);
+                }
+                let message = resp.message().to_string();
+                (target, resp.status.to_string(), message)
+            }
+            Err(err) => {
+                exit_status = exit_status.max(ExitStatus::Unreachable);
+                (target, format!("Error: {}", err), String::new())
+            }
+        };
+        results.push(result);
+    }
+
+    print_action_results(io::stdout(), &results)?;
+    Ok(exit_status)
+}
+
+fn repair_message(response: &RepairResponse) -> String {
+    if response.fixed.is_empty() {
+        response.message.clone()
     } else {
-        format!("http://{}", target.trim_end_matches('/'))
+        format!("{}: {}", response.message, response.fixed.join(", "))
     }
 }
 
+fn restart_services_message(response: &RestartServicesResponse) -> String {
+    let mut parts = Vec::new();
+    if !response.restarted.is_empty() {
+        parts.push(format!("restarted {}", response.restarted.join(", ")));
+    }
+    if !response.failed.is_empty() {
+        let failed: Vec<_> = response
+            .failed
+            .iter()
+            .map(|failure| format!("{} ({})", failure.service, failure.message))
+            .collect();
+        parts.push(format!("failed {}", failed.join(", ")));
+    }
+    if !response.skipped.is_empty() {
+        parts.push(format!("skipped {}", response.skipped.join(", ")));
+    }
+    if parts.is_empty() {
+        return "no services need a restart".to_string();
+    }
+    parts.join("; ")
+}
 
-fn run_packages(
-    _full_upgrade: bool,
-    mut targets: Vec<String>,
+/// The last line of the command's output, which is usually the interesting one.
+fn command_message(response: &CommandResponse) -> String {
+    match response.output.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => line.trim().to_string(),
+        None => format!("{} exited with code 0", response.name),
+    }
+}
+
+/// Runs `action` on each selected node in turn and prints its message, or the error, per
+/// node.
+fn run_node_action(
+    selection: TargetSelection,
     config: &Config,
-) -> Result<(), Box<dyn Error>> {
+    action: impl Fn(&ApiClient, &cobbler_client::Node) -> Result<String, cobbler_client::Error>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
     if targets.is_empty() {
-        for node in &config.nodes {
-            targets.push(node.address.clone());
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let client = api_client()?;
+    let mut results = Vec::new();
+    let mut exit_status = ExitStatus::Ok;
+    for target in targets {
+        let result = match action(&client, &client_node(&target, config)) {
+            Ok(message) => (target, StatusCode::OK.to_string(), message),
+            Err(err) => {
+                exit_status = exit_status.max(error_exit_status(&err));
+                match err {
+                    cobbler_client::Error::Status { status, error } => (target, status.to_string(), error.message),
+                    err => (target, format!("Error: {err}"), String::new()),
+                }
+            }
+        };
+        results.push(result);
+    }
+
+    print_action_results(io::stdout(), &results)?;
+    Ok(exit_status)
+}
+
+fn print_action_results<W: Write>(writer: W, results: &[(String, String, String)]) -> io::Result<()> {
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "TARGET\tSTATUS\tMESSAGE")?;
+    for (target, status, message) in results {
+        writeln!(tw, "{}\t{}\t{}", target, status, message.replace('\n', " "))?;
+    }
+    tw.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use cobbler_api::{DiskUsage, LoadAverage, MemoryUsage, PackageChange, PolicyVersion};
+
+    #[test]
+    fn test_cli_parse_discover_default() {
+        let cli = Cli::parse_from(["cobbler", "discover"]);
+        if let Commands::Discover {
+            timeout,
+            update_config,
+            prefer,
+            output,
+            ..
+        } = cli.command
+        {
+            assert_eq!(timeout, 5);
+            assert!(!update_config);
+            assert_eq!(prefer, AddressPreference::Ipv4);
+            assert_eq!(output, OutputFormat::Table);
+        } else {
+            panic!("Wrong command");
         }
     }
 
-    if targets.is_empty() {
-        println!("No targets found.");
-        return Ok(());
+    #[test]
+    fn test_cli_parse_context_anywhere() {
+        let cli = Cli::parse_from(["cobbler", "status", "--context", "home"]);
+        assert_eq!(cli.context.as_deref(), Some("home"));
+        let cli = Cli::parse_from(["cobbler", "--context", "home", "status"]);
+        assert_eq!(cli.context.as_deref(), Some("home"));
     }
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(get_default_timeout())
-        .build()?;
+    #[test]
+    fn test_cli_parse_discover_timeout() {
+        let cli = Cli::parse_from(["cobbler", "discover", "-t", "10", "-u"]);
+        if let Commands::Discover {
+            timeout,
+            update_config,
+            ..
+        } = cli.command
+        {
+            assert_eq!(timeout, 10);
+            assert!(update_config);
+        } else {
+            panic!("Wrong command");
+        }
+    }
 
-    let mut tw = TabWriter::new(io::stdout());
-    writeln!(tw, "TARGET\tSTATUS")?;
+    #[test]
+    fn test_get_default_timeout() {
+        std::env::set_var("COBBLER_TIMEOUT", "15");
+        assert_eq!(get_default_timeout(), Duration::from_secs(15));
 
-    for target in targets {
-        let url = resolve_url(&target);
-        let upgrade_url = format!("{}/packages/full-upgrade", url);
+        std::env::set_var("COBBLER_TIMEOUT", "1m");
+        assert_eq!(get_default_timeout(), Duration::from_secs(60));
 
-        let mut request = client.post(&upgrade_url);
+        std::env::remove_var("COBBLER_TIMEOUT");
+        assert_eq!(get_default_timeout(), Duration::from_secs(60));
+    }
 
-        if let Some(node) = config.nodes.iter().find(|n| n.address == target) {
-            if let Some(api_key) = &node.api_key {
-                request = request.header("X-API-Key", api_key);
-            }
+    #[test]
+    fn test_cli_parse_packages_full_upgrade() {
+        let cli = Cli::parse_from(["cobbler", "packages", "--full-upgrade", "a:8080", "b:8080"]);
+        if let Commands::Packages {
+            full_upgrade,
+            targets,
+            action,
+        } = cli.command
+        {
+            assert!(full_upgrade);
+            assert_eq!(targets, vec!["a:8080", "b:8080"]);
+            assert!(action.is_none());
+        } else {
+            panic!("Wrong command");
         }
+    }
 
-        let (status, body) = match request.send() {
-            Ok(resp) => {
-                let status = resp.status().to_string();
-                let body = match resp.json::<serde_json::Value>() {
-                    Ok(json) => serde_json::to_string_pretty(&json)
-                        .unwrap_or_else(|_| "Failed to pretty-print JSON".to_string()),
-                    Err(_) => "Upgrade triggered successfully".to_string(),
-                };
-                (status, body)
-            }
-            Err(err) => (format!("Error: {}", err), "".to_string()),
+    #[test]
+    fn test_cli_parse_packages_list() {
+        let cli = Cli::parse_from(["cobbler", "packages", "list", "raspi1", "--upgradable"]);
+        if let Commands::Packages {
+            action: Some(PackagesCommand::List { target, filter }),
+            ..
+        } = cli.command
+        {
+            assert_eq!(target, "raspi1");
+            assert_eq!(filter.state(), Some(PackageState::Upgradable));
+        } else {
+            panic!("Wrong command");
+        }
+
+        assert!(Cli::try_parse_from(["cobbler", "packages", "list", "raspi1", "--held", "--installed"]).is_err());
+        assert!(Cli::try_parse_from(["cobbler", "packages"]).is_err());
+    }
+
+    #[test]
+    fn test_find_node_by_address_or_name() {
+        let config = Config {
+            nodes: vec![NodeConfig {
+                name: Some("raspi1".to_string()),
+                address: "1.1.1.1:8080".to_string(),
+                api_key: None,
+                ..Default::default()
+            }],
+            ..Default::default()
         };
 
-        writeln!(tw, "{}\t{}", target, status)?;
-        if !body.is_empty() {
-            writeln!(tw, "\t{}", body.replace('\n', "\n\t"))?;
+        assert!(find_node(&config, "raspi1").is_some());
+        assert!(find_node(&config, "1.1.1.1:8080").is_some());
+        assert!(find_node(&config, "raspi2").is_none());
+    }
+
+    #[test]
+    fn test_client_node_applies_node_settings() {
+        let config: Config = serde_yaml::from_str(
+            "nodes:\n  - name: offsite\n    address: 10.8.0.2:8080\n    api_key: secret\n    timeout: 120\n    retries: 3\n    http2: true\n",
+        )
+        .unwrap();
+
+        let node = client_node("offsite", &config);
+        assert_eq!(node.label(), "offsite");
+        assert_eq!(node.address, "10.8.0.2:8080");
+        assert_eq!(node.api_key.as_deref(), Some("secret"));
+        assert_eq!(node.timeout, Some(Duration::from_secs(120)));
+        assert_eq!(node.retries, 3);
+        assert!(node.http2);
+
+        let node = client_node("10.0.0.1:8080", &config);
+        assert_eq!(node, cobbler_client::Node::new("10.0.0.1:8080"));
+    }
+
+    #[test]
+    fn test_print_packages() {
+        let packages = vec![
+            PackageInfo {
+                name: "vim".to_string(),
+                installed_version: "9.0".to_string(),
+                candidate_version: Some("9.1".to_string()),
+                upgradable: true,
+                held: false,
+            },
+            PackageInfo {
+                name: "curl".to_string(),
+                installed_version: "8.0".to_string(),
+                candidate_version: Some("8.0".to_string()),
+                upgradable: false,
+                held: true,
+            },
+        ];
+
+        let mut out = Vec::new();
+        print_packages(&mut out, &packages).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines[0], "NAME  INSTALLED  CANDIDATE  HELD");
+        assert_eq!(lines[1], "vim   9.0        9.1        no");
+        assert_eq!(lines[2], "curl  8.0        -          yes");
+    }
+
+    #[test]
+    fn test_print_transactions() {
+        let change = |name: &str, old_version: Option<&str>, version: &str| PackageChange {
+            name: name.to_string(),
+            old_version: old_version.map(str::to_string),
+            version: version.to_string(),
+            ..Default::default()
+        };
+        let transactions = vec![
+            AptTransaction {
+                start: "2024-05-02T08:00:00".to_string(),
+                command_line: Some("apt-get install broken".to_string()),
+                installed: vec![PackageChange {
+                    automatic: true,
+                    ..change("libbroken1", None, "1.0-1")
+                }],
+                ..Default::default()
+            },
+            AptTransaction {
+                start: "2024-05-01T10:12:03".to_string(),
+                end: Some("2024-05-01T10:12:30".to_string()),
+                command_line: Some("apt-get -y dist-upgrade".to_string()),
+                requested_by: Some("admin (1000)".to_string()),
+                upgraded: vec![change("libc6", Some("2.36-9+deb12u4"), "2.36-9+deb12u7")],
+                removed: vec![change("old-tool", None, "1.0-1")],
+                ..Default::default()
+            },
+        ];
+
+        let mut out = Vec::new();
+        print_transactions(&mut out, &transactions).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2024-05-02 08:00:00  apt-get install broken
+  install  libbroken1 1.0-1 (automatic)
+  note     no end recorded: still running, or interrupted
+
+2024-05-01 10:12:03  apt-get -y dist-upgrade  (by admin)
+  upgrade  libc6 2.36-9+deb12u4 -> 2.36-9+deb12u7
+  remove   old-tool 1.0-1
+"
+        );
+    }
+
+    #[test]
+    fn test_print_policies() {
+        let version = |version: &str, priority| PolicyVersion {
+            version: version.to_string(),
+            priority,
+            sources: vec![],
+        };
+        let policies = vec![
+            PackagePolicy {
+                name: "vim".to_string(),
+                installed: Some("9.0".to_string()),
+                candidate: Some("9.0".to_string()),
+                versions: vec![version("9.1", 100), version("9.0", 500)],
+            },
+            PackagePolicy {
+                name: "curl".to_string(),
+                installed: Some("8.0".to_string()),
+                candidate: Some("8.1".to_string()),
+                versions: vec![version("8.1", 500), version("8.0", 100)],
+            },
+        ];
+
+        let mut out = Vec::new();
+        print_policies(&mut out, &policies).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines[0], "NAME  INSTALLED  CANDIDATE  NEWEST     NOTE");
+        assert_eq!(lines[1], "vim   9.0 (500)  9.0 (500)  9.1 (100)  newest version has a lower priority (100 < 500)");
+        assert_eq!(lines[2], "curl  8.0 (100)  8.1 (500)  8.1 (500)  -");
+    }
+
+    #[test]
+    fn test_cli_parse_packages_install() {
+        let cli = Cli::parse_from([
+            "cobbler", "packages", "install", "vim", "curl", "--targets", "a:8080,b:8080", "--tag", "web",
+        ]);
+        if let Commands::Packages {
+            action: Some(PackagesCommand::Install { packages, selection }),
+            ..
+        } = cli.command
+        {
+            assert_eq!(packages, vec!["vim", "curl"]);
+            assert_eq!(selection.targets, vec!["a:8080", "b:8080"]);
+            assert_eq!(selection.tag, vec!["web"]);
+        } else {
+            panic!("Wrong command");
         }
+
+        assert!(Cli::try_parse_from(["cobbler", "packages", "remove"]).is_err());
     }
 
-    tw.flush()?;
+    #[test]
+    fn test_select_targets() {
+        let config = Config {
+            nodes: vec![
+                NodeConfig {
+                    address: "1.1.1.1:8080".to_string(),
+                    tags: vec!["web".to_string()],
+                    ..Default::default()
+                },
+                NodeConfig {
+                    address: "2.2.2.2:8080".to_string(),
+                    tags: vec!["db".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
 
-    Ok(())
-}
+        assert_eq!(select_targets(vec![], &[], &config), vec!["1.1.1.1:8080", "2.2.2.2:8080"]);
+        assert_eq!(select_targets(vec![], &["db".to_string()], &config), vec!["2.2.2.2:8080"]);
+        assert_eq!(
+            select_targets(vec!["1.1.1.1:8080".to_string()], &["web".to_string()], &config),
+            vec!["1.1.1.1:8080"]
+        );
+        assert!(select_targets(vec![], &["none".to_string()], &config).is_empty());
+    }
+
+    #[test]
+    fn test_cli_parse_upgrade() {
+        let cli = Cli::parse_from([
+            "cobbler", "upgrade", "--rolling", "--max-parallel", "3", "--fail-fast", "--tag", "web",
+        ]);
+        if let Commands::Upgrade {
+            rolling,
+            max_parallel,
+            fail_fast,
+            health_check,
+            wait_timeout,
+            selection,
+            ..
+        } = cli.command
+        {
+            assert!(rolling);
+            assert_eq!(max_parallel, 3);
+            assert!(fail_fast);
+            assert!(!health_check);
+            assert_eq!(wait_timeout, Duration::from_secs(3600));
+            assert_eq!(selection.tag, vec!["web"]);
+        } else {
+            panic!("Wrong command");
+        }
+
+        assert!(Cli::try_parse_from(["cobbler", "upgrade", "--max-parallel", "0"]).is_err());
+        assert!(Cli::try_parse_from(["cobbler", "upgrade", "--include-phased", "--exclude-phased"]).is_err());
+        assert!(Cli::try_parse_from(["cobbler", "upgrade", "--confold", "--confnew"]).is_err());
+        assert!(matches!(
+            Cli::parse_from(["cobbler", "upgrade", "--download-limit", "512"]).command,
+            Commands::Upgrade { download_limit: Some(512), .. }
+        ));
+        assert!(matches!(
+            Cli::parse_from(["cobbler", "upgrade", "--dry-run"]).command,
+            Commands::Upgrade { dry_run: true, .. }
+        ));
+        for option in ["--strategy=upgrade", "--exclude=nvidia-*", "--only=openssl*"] {
+            assert!(Cli::try_parse_from(["cobbler", "upgrade", "--dry-run", option]).is_err());
+        }
+        if let Commands::Upgrade { exclude, .. } =
+            Cli::parse_from(["cobbler", "upgrade", "--exclude", "nvidia-*,libnvidia-*", "--exclude", "postgresql-16"]).command
+        {
+            assert_eq!(exclude, ["nvidia-*", "libnvidia-*", "postgresql-16"]);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(matches!(
+            Cli::parse_from(["cobbler", "upgrade", "--only", "openssl*,libssl*"]).command,
+            Commands::Upgrade { only, .. } if only == ["openssl*", "libssl*"]
+        ));
+        assert!(matches!(
+            Cli::parse_from(["cobbler", "upgrade", "--strategy", "upgrade"]).command,
+            Commands::Upgrade { strategy: Some(upgrade::Strategy::Upgrade), .. }
+        ));
+        assert!(Cli::try_parse_from(["cobbler", "upgrade", "--strategy", "safe-upgrade"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_prefetch() {
+        let cli = Cli::parse_from(["cobbler", "prefetch", "--tag", "remote", "--download-limit", "256"]);
+        if let Commands::Prefetch {
+            download_limit,
+            selection,
+        } = cli.command
+        {
+            assert_eq!(download_limit, Some(256));
+            assert_eq!(selection.tag, vec!["remote"]);
+        } else {
+            panic!("Wrong command");
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_exec() {
+        let cli = Cli::parse_from(["cobbler", "exec", "restart-nginx", "--tag", "web"]);
+        let Commands::Exec { name, selection } = cli.command else {
+            panic!("expected exec command");
+        };
+        assert_eq!(name, "restart-nginx");
+        assert_eq!(selection.tag, vec!["web"]);
+        assert!(selection.targets.is_empty());
+
+        let response = CommandResponse {
+            name: name.clone(),
+            exit_code: Some(0),
+            output: "Reloading nginx\nnginx is running\n".to_string(),
+        };
+        assert_eq!(command_message(&response), "nginx is running");
+    }
+
+    #[test]
+    fn test_cli_parse_shutdown() {
+        let cli = Cli::parse_from(["cobbler", "shutdown", "--delay", "15m", "--tag", "lab"]);
+        if let Commands::Shutdown {
+            delay,
+            cancel,
+            selection,
+        } = cli.command
+        {
+            assert_eq!(delay, Some(Duration::from_secs(900)));
+            assert!(!cancel);
+            assert_eq!(selection.tag, vec!["lab"]);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "shutdown", "--cancel", "--delay", "5m"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_reboot() {
+        let cli = Cli::parse_from(["cobbler", "reboot", "--if-required", "--rolling", "raspi1", "raspi2"]);
+        if let Commands::Reboot {
+            if_required,
+            rolling,
+            wait_timeout,
+            tag,
+            targets,
+        } = cli.command
+        {
+            assert!(if_required);
+            assert!(rolling);
+            assert_eq!(wait_timeout, Duration::from_secs(600));
+            assert!(tag.is_empty());
+            assert_eq!(targets, vec!["raspi1", "raspi2"]);
+        } else {
+            panic!("Wrong command");
+        }
+    }
+
+    #[test]
+    fn test_restart_services_message() {
+        let mut response = RestartServicesResponse::default();
+        assert_eq!(restart_services_message(&response), "no services need a restart");
+        response.restarted = vec!["nginx.service".to_string(), "cron.service".to_string()];
+        response.skipped = vec!["dbus.service".to_string()];
+        response.failed = vec![cobbler_api::ServiceFailure {
+            service: "app.service".to_string(),
+            message: "timed out".to_string(),
+        }];
+        assert_eq!(
+            restart_services_message(&response),
+            "restarted nginx.service, cron.service; failed app.service (timed out); skipped dbus.service"
+        );
+    }
+
+    #[test]
+    fn test_repair_message() {
+        let mut response = RepairResponse {
+            message: "nothing to repair".to_string(),
+            fixed: vec![],
+            output: String::new(),
+        };
+        assert_eq!(repair_message(&response), "nothing to repair");
+
+        response.message = "repaired 2 package(s)".to_string();
+        response.fixed = vec!["libssl3".to_string(), "openssl".to_string()];
+        assert_eq!(repair_message(&response), "repaired 2 package(s): libssl3, openssl");
+    }
+
+    #[test]
+    fn test_exit_status_precedence() {
+        assert_eq!(ExitStatus::Ok.max(ExitStatus::Unreachable), ExitStatus::Unreachable);
+        assert_eq!(ExitStatus::UpdatesPending.max(ExitStatus::Unreachable), ExitStatus::UpdatesPending);
+        assert_eq!(ExitStatus::OperationFailed.code(), 4);
+    }
+
+    #[test]
+    fn test_has_pending_updates() {
+        assert!(has_pending_updates(&serde_json::json!({"updates": ["vim"]})));
+        assert!(!has_pending_updates(&serde_json::json!({"updates": []})));
+        assert!(!has_pending_updates(&serde_json::json!({"message": "error"})));
+    }
+
+    #[test]
+    fn test_cli_parse_status_output() {
+        let cli = Cli::parse_from(["cobbler", "status", "--output", "csv", "web-1"]);
+        if let Commands::Status { output, targets, .. } = cli.command {
+            assert_eq!(output, OutputFormat::Csv);
+            assert_eq!(targets, vec!["web-1"]);
+        } else {
+            panic!("Wrong command");
+        }
+    }
+
+    #[test]
+    fn test_status_filter() {
+        let node = StatusResponse {
+            updates: vec!["openssl".to_string(), "libssl3".to_string()],
+            reboot_required: true,
+            ..Default::default()
+        };
+        let up_to_date = StatusResponse::default();
+
+        let filter = StatusFilter::default();
+        assert!(!filter.is_active());
+        assert!(filter.matches(&up_to_date));
+
+        let filter = StatusFilter {
+            only_outdated: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&node));
+        assert!(!filter.matches(&up_to_date));
+
+        let filter = StatusFilter {
+            packages: vec!["openssl*".to_string()],
+            reboot_required: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&node));
+
+        let filter = StatusFilter {
+            packages: vec!["linux-image-*".to_string()],
+            ..Default::default()
+        };
+        assert!(!filter.matches(&node));
+
+        let filter = StatusFilter {
+            min_updates: Some(3),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&node));
+
+        let filter = StatusFilter {
+            security: true,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&node));
+        assert!(filter.matches(&StatusResponse {
+            security_updates: 1,
+            ..node.clone()
+        }));
+
+        let filter = StatusFilter {
+            kernel_update: true,
+            ..Default::default()
+        };
+        assert!(filter.is_active());
+        assert!(!filter.matches(&node));
+        assert!(filter.matches(&StatusResponse {
+            kernel_update_pending: true,
+            ..node
+        }));
+    }
+
+    #[test]
+    fn test_status_row_flags_kernel_update() {
+        let node = StatusResponse {
+            kernel: Some("6.1.0-18-amd64".to_string()),
+            kernel_update_pending: true,
+            kernel_candidate: Some("6.1.0-21-amd64".to_string()),
+            ..Default::default()
+        };
+        let row = status_row("web1", "200".to_string(), "ok", &node, None, OutputFormat::Wide);
+        assert_eq!(row[7], "6.1.0-18-amd64 -> 6.1.0-21-amd64");
+        assert_eq!(status_row("web1", "200".to_string(), "ok", &node, None, OutputFormat::Csv)[7], "6.1.0-18-amd64");
 
+        let node = StatusResponse {
+            kernel_candidate: None,
+            ..node
+        };
+        let row = status_row("web1", "200".to_string(), "ok", &node, None, OutputFormat::Wide);
+        assert_eq!(row[7], "6.1.0-18-amd64 (update pending)");
+    }
+
+    #[test]
+    fn test_status_row_shows_resources() {
+        let disk = |mount_point: &str, used_bytes: u64| DiskUsage {
+            mount_point: mount_point.to_string(),
+            filesystem: "ext4".to_string(),
+            total_bytes: 100,
+            used_bytes,
+            available_bytes: 100 - used_bytes,
+        };
+        let resources = ResourcesResponse {
+            load_average: Some(LoadAverage {
+                one: 0.5,
+                five: 0.25,
+                fifteen: 0.125,
+            }),
+            memory: Some(MemoryUsage {
+                total_bytes: 4,
+                used_bytes: 3,
+                available_bytes: 1,
+            }),
+            disks: vec![disk("/", 40), disk("/var", 93), disk("/boot/efi", 2)],
+            ..Default::default()
+        };
+        let node = StatusResponse::default();
+        let row = status_row("web1", "200".to_string(), "ok", &node, Some(&resources), OutputFormat::Wide);
+        assert_eq!(row[11..14], ["0.50", "75%", "93% /var"]);
+        let row = status_row("web1", "200".to_string(), "ok", &node, Some(&resources), OutputFormat::Tsv);
+        assert_eq!(row[11..14], ["0.50", "75", "93"]);
+        let row = status_row("web1", "200".to_string(), "ok", &node, None, OutputFormat::Wide);
+        assert_eq!(row[11..14], ["", "", ""]);
+    }
+
+    #[test]
+    fn test_discover_row_shows_txt_metadata() {
+        let node = DiscoveredNode {
+            id: "raspi1".to_string(),
+            host: "raspi1.local".to_string(),
+            addresses: vec!["192.168.1.20".parse().unwrap()],
+            port: 8080,
+            instance: "cobblerd-raspi1".to_string(),
+            properties: vec![
+                "id=raspi1".to_string(),
+                "version=0.1.0".to_string(),
+                "os=Debian GNU/Linux 13 (trixie)".to_string(),
+                "updates=4".to_string(),
+                "reboot_required=true".to_string(),
+            ],
+        };
+
+        let row = discover_row(&node, OutputFormat::Table);
+        assert_eq!(row.len(), discover_header(OutputFormat::Table).len());
+        assert_eq!(row[5..], ["0.1.0", "Debian GNU/Linux 13 (trixie)", "4", "yes"]);
+        assert_eq!(
+            discover_row(&node, OutputFormat::Wide).len(),
+            discover_header(OutputFormat::Wide).len()
+        );
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES\n"));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("n\n"));
+        assert!(!is_yes("yep\n"));
+        assert!(Cli::parse_from(["cobbler", "reboot", "-y"]).yes);
+    }
+
+    #[test]
+    fn test_status_color() {
+        let ok = |json: serde_json::Value| -> StatusResult { Ok((StatusCode::OK, Some(json))) };
+        assert_eq!(status_color(&ok(serde_json::json!({}))), Some(Color::Green));
+        assert_eq!(status_color(&ok(serde_json::json!({ "updates": ["vim"] }))), Some(Color::Yellow));
+        assert_eq!(status_color(&ok(serde_json::json!({ "health": "critical" }))), Some(Color::Red));
+        assert_eq!(status_color(&Err("connection refused".to_string())), Some(Color::Red));
+
+        let error = |code: ErrorCode| -> StatusResult {
+            let body = serde_json::to_value(ErrorResponse::new(code, "")).unwrap();
+            Ok((StatusCode::PRECONDITION_FAILED, Some(body)))
+        };
+        assert_eq!(status_color(&error(ErrorCode::NotDebian)), None);
+        assert_eq!(status_color(&error(ErrorCode::LockHeld)), Some(Color::Red));
+    }
+
+    #[test]
+    fn test_print_status_table() {
+        let results = vec![
+            (
+                "web1:8080".to_string(),
+                Ok((
+                    StatusCode::OK,
+                    Some(serde_json::json!({
+                        "updates": ["vim", "curl"],
+                        "security_updates": 1,
+                        "reboot_required": true,
+                        "last_check": 1_000_000 - 300,
+                    })),
+                )),
+            ),
+            (
+                "nas:8080".to_string(),
+                Ok((
+                    StatusCode::PRECONDITION_FAILED,
+                    serde_json::to_value(ErrorResponse::new(ErrorCode::NotDebian, "not Debian")).ok(),
+                )),
+            ),
+            ("web2:8080".to_string(), Err("connection refused".to_string())),
+            ("db1:8080".to_string(), Ok((StatusCode::OK, Some(serde_json::json!({}))))),
+        ];
+        let cached_at = HashMap::from([("db1:8080".to_string(), 1_000_000 - 7200)]);
+
+        let mut buffer = Vec::new();
+        print_status_table(&mut buffer, false, 1_000_000, &cached_at, &results).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "NODE                      UPDATES      SECURITY  REBOOT  UPGRADING  LAST_CHECK\n\
+             web1:8080                 2            1         yes     no         5m ago\n\
+             nas:8080                  not-debian   -         -       -          -\n\
+             web2:8080                 unreachable  -         -       -          -\n\
+             db1:8080 (cached 2h ago)  0            0         no      no         -\n\
+             \n\
+             web2:8080: connection refused\n"
+        );
+        assert_eq!(format_age(59), "59s ago");
+        assert_eq!(format_age(7200), "2h ago");
+        assert_eq!(format_age(3 * 86400), "3d ago");
+    }
+
+    #[test]
+    fn test_print_status_rows() {
+        let results = vec![
+            (
+                "10.0.0.1:8080".to_string(),
+                Ok((
+                    StatusCode::OK,
+                    Some(serde_json::json!({
+                        "message": "2 updates available",
+                        "updates": ["vim", "curl"],
+                        "security_updates": 1,
+                        "is_upgrading": false,
+                        "reboot_required": true,
+                        "uptime_seconds": 3600,
+                        "kernel": "6.1.0-18-amd64",
+                        "last_upgrade": 0,
+                        "health": "needs-attention"
+                    })),
+                )),
+            ),
+            ("10.0.0.2:8080".to_string(), Err("connection refused".to_string())),
+        ];
+
+        let mut buffer = Vec::new();
+        print_status_rows(&mut buffer, OutputFormat::Csv, true, &HashMap::new(), &HashMap::new(), &results).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "TARGET,STATUS,HEALTH,UPDATES,SECURITY,UPGRADING,REBOOT,KERNEL,UPTIME,LAST_UPGRADE,MESSAGE,LOAD,MEMORY,DISK"
+        );
+        assert_eq!(
+            lines[1],
+            "10.0.0.1:8080,200,needs-attention,2,1,no,yes,6.1.0-18-amd64,3600,1970-01-01T00:00:00Z,2 updates available,,,"
+        );
+        assert_eq!(lines[2], "10.0.0.2:8080,unreachable,critical,0,0,no,no,,,,connection refused,,,");
+    }
+}
@@ -0,0 +1,211 @@
+use clap::ValueEnum;
+use std::env;
+use std::io::{self, Write};
+use tabwriter::TabWriter;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns
+    #[default]
+    Table,
+    /// Aligned columns with additional details
+    Wide,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+    /// JSON documents
+    Json,
+}
+
+impl OutputFormat {
+    pub fn is_delimited(self) -> bool {
+        matches!(self, OutputFormat::Csv | OutputFormat::Tsv)
+    }
+
+    /// Whether stdout must carry data only, with informational messages sent to stderr.
+    pub fn is_machine_readable(self) -> bool {
+        self.is_delimited() || self == OutputFormat::Json
+    }
+
+    pub fn is_detailed(self) -> bool {
+        self != OutputFormat::Table
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    /// Always color, e.g. when piping into `less -R`
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+    /// Faint, for stale data
+    Dim,
+}
+
+impl Color {
+    pub fn paint(self, text: &str) -> String {
+        let code = match self {
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Red => 31,
+            Color::Dim => 2,
+        };
+        format!("\x1b[{code}m{text}\x1b[0m")
+    }
+}
+
+enum Sink<W: Write> {
+    Aligned(TabWriter<W>),
+    Delimited(W),
+}
+
+pub struct RowWriter<W: Write> {
+    format: OutputFormat,
+    sink: Sink<W>,
+    color: bool,
+}
+
+impl<W: Write> RowWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        let sink = if format.is_delimited() {
+            Sink::Delimited(writer)
+        } else {
+            Sink::Aligned(TabWriter::new(writer).padding(2))
+        };
+        RowWriter {
+            format,
+            sink,
+            color: false,
+        }
+    }
+
+    /// Enables [`write_colored_row`](Self::write_colored_row) for aligned output; delimited
+    /// output stays plain.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.sink = match self.sink {
+            Sink::Aligned(writer) => {
+                self.color = color;
+                Sink::Aligned(writer.ansi(color))
+            }
+            sink => sink,
+        };
+        self
+    }
+
+    pub fn write_row<S: AsRef<str>>(&mut self, fields: &[S]) -> io::Result<()> {
+        self.write_colored_row(fields, None)
+    }
+
+    pub fn write_colored_row<S: AsRef<str>>(&mut self, fields: &[S], color: Option<Color>) -> io::Result<()> {
+        let mut line = format_row(self.format, fields);
+        if let Some(color) = color.filter(|_| self.color) {
+            line = line.split('\t').map(|field| color.paint(field)).collect::<Vec<_>>().join("\t");
+        }
+        match &mut self.sink {
+            Sink::Aligned(writer) => writeln!(writer, "{line}"),
+            Sink::Delimited(writer) => writeln!(writer, "{line}"),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Aligned(writer) => writer.flush(),
+            Sink::Delimited(writer) => writer.flush(),
+        }
+    }
+}
+
+fn format_row<S: AsRef<str>>(format: OutputFormat, fields: &[S]) -> String {
+    let fields = fields.iter().map(|field| field.as_ref());
+    match format {
+        OutputFormat::Csv => fields.map(csv_field).collect::<Vec<_>>().join(","),
+        _ => fields.map(plain_field).collect::<Vec<_>>().join("\t"),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn plain_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_row() {
+        let fields = ["web-1", "a,b", "say \"hi\"", "tab\there"];
+        assert_eq!(
+            format_row(OutputFormat::Csv, &fields),
+            "web-1,\"a,b\",\"say \"\"hi\"\"\",tab\there"
+        );
+        assert_eq!(
+            format_row(OutputFormat::Tsv, &fields),
+            "web-1\ta,b\tsay \"hi\"\ttab here"
+        );
+    }
+
+    #[test]
+    fn test_row_writer_colors() {
+        let mut buffer = Vec::new();
+        let mut writer = RowWriter::new(&mut buffer, OutputFormat::Wide).with_color(true);
+        writer.write_row(&["TARGET", "STATUS"]).unwrap();
+        writer.write_colored_row(&["10.0.0.1:8080", "unreachable"], Some(Color::Red)).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "TARGET         STATUS\n\x1b[31m10.0.0.1:8080\x1b[0m  \x1b[31munreachable\x1b[0m\n"
+        );
+
+        let mut buffer = Vec::new();
+        let mut writer = RowWriter::new(&mut buffer, OutputFormat::Csv).with_color(true);
+        writer.write_colored_row(&["10.0.0.1:8080", "unreachable"], Some(Color::Red)).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "10.0.0.1:8080,unreachable\n");
+
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+
+    #[test]
+    fn test_row_writer_aligns_table() {
+        let mut buffer = Vec::new();
+        let mut writer = RowWriter::new(&mut buffer, OutputFormat::Wide);
+        writer.write_row(&["TARGET", "STATUS"]).unwrap();
+        writer.write_row(&["10.0.0.1:8080", "200"]).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "TARGET         STATUS\n10.0.0.1:8080  200\n"
+        );
+    }
+}
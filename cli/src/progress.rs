@@ -0,0 +1,74 @@
+//! Progress on stderr for operations that take a while, so that a slow fleet doesn't look
+//! like a hung tool. Nothing is drawn unless stderr is a terminal.
+
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use std::time::Duration;
+
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// e.g. `upgrading [######              ] 3/10 web1`
+const BAR_TEMPLATE: &str = "{prefix} [{bar:20}] {pos}/{len} {wide_msg}";
+/// e.g. `- discovering (4s) 2 found`
+const SPINNER_TEMPLATE: &str = "{spinner} {prefix} ({elapsed}) {wide_msg}";
+
+/// A progress bar or spinner. It is cleared when finished or dropped.
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    /// A bar counting `total` steps.
+    pub fn bar(label: &str, total: usize) -> Self {
+        let style = ProgressStyle::with_template(BAR_TEMPLATE).expect("valid template").progress_chars("## ");
+        Self::new(ProgressBar::new(total as u64), style, label)
+    }
+
+    /// A spinner with the elapsed time, for operations of unknown length.
+    pub fn spinner(label: &str) -> Self {
+        let style = ProgressStyle::with_template(SPINNER_TEMPLATE).expect("valid template").tick_chars("|/-\\ ");
+        let progress = Self::new(ProgressBar::new_spinner(), style, label);
+        progress.bar.enable_steady_tick(TICK_INTERVAL);
+        progress
+    }
+
+    fn new(bar: ProgressBar, style: ProgressStyle, label: &str) -> Self {
+        let bar = bar
+            .with_style(style)
+            .with_prefix(label.to_string())
+            .with_finish(ProgressFinish::AndClear);
+        bar.tick();
+        Progress { bar }
+    }
+
+    pub fn inc(&self) {
+        self.bar.inc(1);
+    }
+
+    pub fn set_message(&self, message: impl Into<String>) {
+        self.bar.set_message(message.into());
+    }
+
+    /// Prints a line to stderr above the progress, also when it isn't drawn.
+    pub fn println(&self, line: &str) {
+        self.suspend(|| eprintln!("{line}"));
+    }
+
+    /// Hides the progress while `f` writes to the terminal. `f` must not use the progress.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.suspend(f)
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates() {
+        assert!(ProgressStyle::with_template(BAR_TEMPLATE).is_ok());
+        assert!(ProgressStyle::with_template(SPINNER_TEMPLATE).is_ok());
+    }
+}
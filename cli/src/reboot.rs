@@ -0,0 +1,195 @@
+use crate::{
+    api_client, client_node, confirm_targets, fetch_status, run_node_action, select_targets, ssh, ssh_node, ApiClient, Config,
+    ExitStatus, TargetSelection,
+};
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REBOOT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct RebootOptions {
+    pub if_required: bool,
+    pub rolling: bool,
+    pub wait_timeout: Duration,
+    /// Skip the confirmation prompt
+    pub yes: bool,
+}
+
+pub fn run_reboot(
+    targets: Vec<String>,
+    tags: &[String],
+    options: RebootOptions,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(targets, tags, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+    let action = if options.if_required { "reboot where required" } else { "reboot" };
+    if !confirm_targets(action, &targets, options.yes)? {
+        return Ok(ExitStatus::Error);
+    }
+
+    let client = api_client()?;
+    let batch_size = if options.rolling { 1 } else { targets.len() };
+    let mut failed = 0;
+
+    for batch in targets.chunks(batch_size) {
+        let results: Vec<(String, Result<String, String>)> = thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|target| {
+                    let client = &client;
+                    let options = &options;
+                    scope.spawn(move || {
+                        reboot_node(client, target, options.if_required, options.wait_timeout, config)
+                            .map(describe)
+                    })
+                })
+                .collect();
+            batch
+                .iter()
+                .cloned()
+                .zip(handles)
+                .map(|(target, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|_| Err("reboot thread panicked".to_string()));
+                    (target, result)
+                })
+                .collect()
+        });
+
+        for (target, result) in &results {
+            match result {
+                Ok(message) => println!("{target}: {message}"),
+                Err(message) => {
+                    failed += 1;
+                    println!("{target}: failed ({message})");
+                }
+            }
+        }
+
+        if failed > 0 && options.rolling {
+            eprintln!("error: aborting rolling reboot after {} failed", batch.join(", "));
+            return Ok(ExitStatus::OperationFailed);
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("error: {failed} of {} reboot(s) failed", targets.len());
+        return Ok(ExitStatus::OperationFailed);
+    }
+    Ok(ExitStatus::Ok)
+}
+
+/// Reboots the node and waits for it to come back, returning how long that took, or `None`
+/// if `if_required` is set and the node needs no reboot.
+pub fn reboot_node(
+    client: &ApiClient,
+    target: &str,
+    if_required: bool,
+    wait_timeout: Duration,
+    config: &Config,
+) -> Result<Option<Duration>, String> {
+    let before = fetch_status(client, target, config)?;
+    if if_required && !before.reboot_required {
+        return Ok(None);
+    }
+
+    match ssh_node(config, target) {
+        Some(node) => ssh::reboot(node)?,
+        None => client
+            .reboot(&client_node(target, config))
+            .map_err(|err| err.to_string())?,
+    }
+
+    let started = Instant::now();
+    let deadline = started + wait_timeout;
+    loop {
+        thread::sleep(REBOOT_POLL_INTERVAL);
+        if let Ok(after) = fetch_status(client, target, config) {
+            if has_rebooted(before.uptime_seconds, after.uptime_seconds) {
+                return Ok(Some(started.elapsed()));
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "node did not come back within {}",
+                humantime::format_duration(wait_timeout)
+            ));
+        }
+    }
+}
+
+/// Describes the outcome of [`reboot_node`].
+pub fn describe(rebooted: Option<Duration>) -> String {
+    match rebooted {
+        Some(elapsed) => format!("rebooted and back after {}s", elapsed.as_secs()),
+        None => "no reboot required".to_string(),
+    }
+}
+
+/// Schedules nodes to power off after `delay`, or with `cancel` calls off a scheduled
+/// shutdown or reboot instead.
+pub fn run_shutdown(
+    selection: TargetSelection,
+    delay: Option<Duration>,
+    cancel: bool,
+    yes: bool,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+    if !cancel && !confirm_targets("power off", &targets, yes)? {
+        return Ok(ExitStatus::Error);
+    }
+
+    let delay_minutes = delay_minutes(delay.unwrap_or_default());
+    let selection = TargetSelection {
+        targets,
+        tag: Vec::new(),
+    };
+    run_node_action(selection, config, |client, node| match cancel {
+        true => client.cancel_shutdown(node),
+        false => client.shutdown(node, delay_minutes),
+    })
+}
+
+/// `shutdown` counts in whole minutes; rounding up never powers a node off early.
+fn delay_minutes(delay: Duration) -> u32 {
+    delay.as_secs().div_ceil(60).try_into().unwrap_or(u32::MAX)
+}
+
+fn has_rebooted(uptime_before: Option<u64>, uptime_after: Option<u64>) -> bool {
+    match (uptime_before, uptime_after) {
+        (Some(before), Some(after)) => after < before,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_rebooted() {
+        assert!(has_rebooted(Some(86_400), Some(30)));
+        assert!(!has_rebooted(Some(86_400), Some(86_410)));
+        assert!(!has_rebooted(None, Some(30)));
+        assert!(!has_rebooted(Some(86_400), None));
+    }
+
+    #[test]
+    fn test_delay_minutes() {
+        assert_eq!(delay_minutes(Duration::ZERO), 0);
+        assert_eq!(delay_minutes(Duration::from_secs(60)), 1);
+        assert_eq!(delay_minutes(Duration::from_secs(90)), 2);
+        assert_eq!(delay_minutes(Duration::from_secs(u64::MAX)), u32::MAX);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::config::ScheduleConfig;
+use crate::schedule;
+use crate::{api_client, fetch_all, fetch_status, select_targets, Config, ExitStatus, TargetSelection};
+use clap::ValueEnum;
+use cobbler_api::StatusResponse;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn from_path(path: &Path) -> Option<ReportFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            "html" | "htm" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+type NodeResult = (String, Result<StatusResponse, String>);
+
+/// Scheduled commands that change nodes, as opposed to e.g. status checks.
+const MAINTENANCE_COMMANDS: [&str; 4] = ["upgrade", "reboot", "shutdown", "restart-services"];
+
+pub fn run_report(
+    file: &Path,
+    format: Option<ReportFormat>,
+    selection: TargetSelection,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let format = format.or_else(|| ReportFormat::from_path(file)).unwrap_or_default();
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        eprintln!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let client = api_client()?;
+    let results = fetch_all("fetching status", &targets, |target| fetch_status(&client, target, config));
+    let now = SystemTime::now();
+    let generated_at = humantime::format_rfc3339_seconds(now).to_string();
+    let windows = maintenance_windows(
+        &config.schedules,
+        now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+    );
+    let report = match format {
+        ReportFormat::Markdown => render_markdown(&generated_at, &results, &windows),
+        ReportFormat::Html => render_html(&generated_at, &results, &windows),
+    };
+    fs::write(file, report).map_err(|err| format!("failed to write {}: {err}", file.display()))?;
+    println!("Report for {} node(s) written to {}", results.len(), file.display());
+
+    if results.iter().any(|(_, result)| result.is_err()) {
+        return Ok(ExitStatus::Unreachable);
+    }
+    Ok(ExitStatus::Ok)
+}
+
+/// The cells of a node's row in the overview table, unescaped.
+fn node_cells(target: &str, result: &Result<StatusResponse, String>) -> [String; 6] {
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    match result {
+        Ok(status) => [
+            target.to_string(),
+            status.updates.len().to_string(),
+            status.security_updates.to_string(),
+            match (status.kernel_update_pending, &status.kernel_candidate) {
+                (true, Some(candidate)) => candidate.clone(),
+                (pending, _) => yes_no(pending),
+            },
+            yes_no(status.reboot_required),
+            status
+                .last_upgrade
+                .map(|timestamp| {
+                    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string()
+                })
+                .unwrap_or_else(|| "-".to_string()),
+        ],
+        Err(err) => [
+            target.to_string(),
+            format!("unreachable: {err}"),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+        ],
+    }
+}
+
+/// The configured schedules that upgrade, reboot or shut down nodes, as rows of schedule
+/// name, next run and command. Runs at a fixed time come first, soonest first.
+fn maintenance_windows(schedules: &[ScheduleConfig], now: u64) -> Vec<[String; 3]> {
+    let mut windows: Vec<(Option<u64>, [String; 3])> = schedules
+        .iter()
+        .filter(|schedule| {
+            let command = schedule.command.split_whitespace().next().unwrap_or_default();
+            MAINTENANCE_COMMANDS.contains(&command)
+        })
+        .filter_map(|schedule| {
+            let next = schedule::next_run(schedule, now).ok()?;
+            let when = match (next, &schedule.every) {
+                (Some(time), _) => {
+                    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(time)).to_string()
+                }
+                (None, every) => format!("every {}", every.as_deref().unwrap_or_default()),
+            };
+            Some((next, [schedule.name.clone(), when, format!("cobbler {}", schedule.command)]))
+        })
+        .collect();
+    windows.sort_by_key(|(next, _)| (next.is_none(), *next));
+    windows.into_iter().map(|(_, window)| window).collect()
+}
+
+const WINDOW_HEADER: [&str; 3] = ["Schedule", "Next run", "Command"];
+
+const HEADER: [&str; 6] = ["Node", "Updates", "Security", "Kernel update", "Reboot required", "Last upgrade"];
+
+fn overview(results: &[NodeResult]) -> String {
+    let up_to_date = results
+        .iter()
+        .filter(|(_, result)| result.as_ref().is_ok_and(|status| status.updates.is_empty()))
+        .count();
+    let unreachable = results.iter().filter(|(_, result)| result.is_err()).count();
+    format!(
+        "{} node(s): {up_to_date} up to date, {} with pending updates, {unreachable} unreachable.",
+        results.len(),
+        results.len() - up_to_date - unreachable
+    )
+}
+
+/// Pending security updates per node, with the archive they come from and, if the node
+/// looks them up, the severity and CVEs they fix.
+fn security_updates(status: &StatusResponse) -> Vec<String> {
+    status
+        .update_details
+        .iter()
+        .filter(|update| update.security)
+        .map(|update| {
+            let mut notes: Vec<String> = update.archive.iter().cloned().collect();
+            if let Some(severity) = update.severity {
+                notes.push(severity.as_str().to_string());
+            }
+            if !update.cves.is_empty() {
+                notes.push(update.cves.join(" "));
+            }
+            if notes.is_empty() {
+                update.name.clone()
+            } else {
+                format!("{} ({})", update.name, notes.join(", "))
+            }
+        })
+        .collect()
+}
+
+fn render_markdown(generated_at: &str, results: &[NodeResult], windows: &[[String; 3]]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|");
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# Patch report\n\nGenerated {generated_at}. {}\n", overview(results));
+    let _ = writeln!(out, "| {} |", HEADER.join(" | "));
+    let _ = writeln!(out, "|{}", "---|".repeat(HEADER.len()));
+    for (target, result) in results {
+        let cells: Vec<String> = node_cells(target, result).iter().map(|text| cell(text)).collect();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+
+    let _ = writeln!(out, "\n## Pending security updates");
+    let mut any = false;
+    for (target, status) in results.iter().filter_map(|(target, result)| Some((target, result.as_ref().ok()?))) {
+        let updates = security_updates(status);
+        if updates.is_empty() {
+            continue;
+        }
+        any = true;
+        let _ = writeln!(out, "\n### {target}\n");
+        for update in updates {
+            let _ = writeln!(out, "- {update}");
+        }
+    }
+    if !any {
+        let _ = writeln!(out, "\nNone.");
+    }
+
+    let _ = writeln!(out, "\n## Upcoming maintenance windows\n");
+    if windows.is_empty() {
+        let _ = writeln!(out, "None scheduled.");
+    } else {
+        let _ = writeln!(out, "| {} |", WINDOW_HEADER.join(" | "));
+        let _ = writeln!(out, "|{}", "---|".repeat(WINDOW_HEADER.len()));
+        for window in windows {
+            let cells: Vec<String> = window.iter().map(|text| cell(text)).collect();
+            let _ = writeln!(out, "| {} |", cells.join(" | "));
+        }
+    }
+    out
+}
+
+fn render_html(generated_at: &str, results: &[NodeResult], windows: &[[String; 3]]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Patch report</title>\n</head>\n<body>"
+    );
+    let _ = writeln!(
+        out,
+        "<h1>Patch report</h1>\n<p>Generated {}. {}</p>",
+        escape_html(generated_at),
+        escape_html(&overview(results))
+    );
+    let _ = writeln!(out, "<table>\n<tr>{}</tr>", HEADER.map(|name| format!("<th>{name}</th>")).concat());
+    for (target, result) in results {
+        let cells = node_cells(target, result).map(|text| format!("<td>{}</td>", escape_html(&text)));
+        let _ = writeln!(out, "<tr>{}</tr>", cells.concat());
+    }
+    let _ = writeln!(out, "</table>\n<h2>Pending security updates</h2>");
+
+    let mut any = false;
+    for (target, status) in results.iter().filter_map(|(target, result)| Some((target, result.as_ref().ok()?))) {
+        let updates = security_updates(status);
+        if updates.is_empty() {
+            continue;
+        }
+        any = true;
+        let items: String = updates
+            .iter()
+            .map(|update| format!("<li>{}</li>", escape_html(update)))
+            .collect();
+        let _ = writeln!(out, "<h3>{}</h3>\n<ul>{items}</ul>", escape_html(target));
+    }
+    if !any {
+        let _ = writeln!(out, "<p>None.</p>");
+    }
+
+    let _ = writeln!(out, "<h2>Upcoming maintenance windows</h2>");
+    if windows.is_empty() {
+        let _ = writeln!(out, "<p>None scheduled.</p>");
+    } else {
+        let _ = writeln!(out, "<table>\n<tr>{}</tr>", WINDOW_HEADER.map(|name| format!("<th>{name}</th>")).concat());
+        for window in windows {
+            let cells = window.clone().map(|text| format!("<td>{}</td>", escape_html(&text)));
+            let _ = writeln!(out, "<tr>{}</tr>", cells.concat());
+        }
+        let _ = writeln!(out, "</table>");
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobbler_api::{Severity, UpdateDetail};
+
+    fn results() -> Vec<NodeResult> {
+        vec![
+            (
+                "web1".to_string(),
+                Ok(StatusResponse {
+                    updates: vec!["openssl".to_string(), "vim".to_string()],
+                    update_details: vec![UpdateDetail {
+                        name: "openssl".to_string(),
+                        archive: Some("bookworm-security".to_string()),
+                        security: true,
+                        cves: vec!["CVE-2024-0727".to_string(), "CVE-2024-2511".to_string()],
+                        severity: Some(Severity::Medium),
+                        ..Default::default()
+                    }],
+                    security_updates: 1,
+                    kernel_update_pending: true,
+                    kernel_candidate: Some("6.1.0-21-amd64".to_string()),
+                    last_upgrade: Some(0),
+                    ..Default::default()
+                }),
+            ),
+            ("web2".to_string(), Err("connection refused | <timeout>".to_string())),
+        ]
+    }
+
+    fn windows() -> Vec<[String; 3]> {
+        let schedule = |name: &str, every: Option<&str>, at: Option<&str>, command: &str| ScheduleConfig {
+            name: name.to_string(),
+            every: every.map(str::to_string),
+            at: at.map(str::to_string),
+            command: command.to_string(),
+        };
+        maintenance_windows(
+            &[
+                schedule("check", Some("1h"), None, "status --check"),
+                schedule("staging", Some("6h"), None, "upgrade --tag staging"),
+                schedule("weekly", None, Some("Sun 03:00"), "upgrade --rolling --tag production"),
+                schedule("nightly", None, Some("01:00"), "reboot --tag staging"),
+            ],
+            12 * 3600,
+        )
+    }
+
+    #[test]
+    fn test_maintenance_windows() {
+        let windows = windows();
+        let names: Vec<&str> = windows.iter().map(|window| window[0].as_str()).collect();
+        assert_eq!(names, ["nightly", "weekly", "staging"]);
+        assert_eq!(windows[2][1], "every 6h");
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let report = render_markdown("2026-01-01T00:00:00Z", &results(), &windows());
+        assert!(report.contains("2 node(s): 0 up to date, 1 with pending updates, 1 unreachable."));
+        assert!(report.contains("| web1 | 2 | 1 | 6.1.0-21-amd64 | no | 1970-01-01T00:00:00Z |"));
+        assert!(report.contains("| web2 | unreachable: connection refused \\| <timeout> |"));
+        assert!(report.contains("### web1\n\n- openssl (bookworm-security, medium, CVE-2024-0727 CVE-2024-2511)\n"));
+        assert!(report.contains("| weekly | 1970-01-04T03:00:00Z | cobbler upgrade --rolling --tag production |"));
+        assert!(render_markdown("", &[], &[]).contains("## Upcoming maintenance windows\n\nNone scheduled."));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let report = render_html("2026-01-01T00:00:00Z", &results(), &windows());
+        assert!(report.contains("<td>web1</td><td>2</td><td>1</td><td>6.1.0-21-amd64</td>"));
+        assert!(report.contains("<td>unreachable: connection refused | &lt;timeout&gt;</td>"));
+        assert!(report.contains("<h3>web1</h3>\n<ul><li>openssl (bookworm-security, medium, CVE-2024-0727 CVE-2024-2511)</li></ul>"));
+        assert!(report.contains("<tr><td>nightly</td><td>1970-01-02T01:00:00Z</td><td>cobbler reboot --tag staging</td></tr>"));
+        assert_eq!(ReportFormat::from_path(Path::new("fleet.HTML")), Some(ReportFormat::Html));
+        assert_eq!(ReportFormat::from_path(Path::new("fleet.txt")), None);
+    }
+}
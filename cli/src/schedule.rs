@@ -0,0 +1,260 @@
+use crate::config::{Config, ScheduleConfig};
+use crate::{Cli, Commands, ExitStatus};
+use clap::Parser;
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 24 * 60 * 60;
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+/// Upper bound for a single sleep, so that clock adjustments are noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recurrence {
+    Every(Duration),
+    /// Seconds after midnight UTC, on one weekday (0 is Monday) or on every day.
+    At {
+        weekday: Option<u64>,
+        second_of_day: u64,
+    },
+}
+
+impl Recurrence {
+    fn parse(schedule: &ScheduleConfig) -> Result<Recurrence, String> {
+        match (&schedule.every, &schedule.at) {
+            (Some(every), None) => match humantime::parse_duration(every) {
+                Ok(interval) if interval.as_secs() > 0 => Ok(Recurrence::Every(interval)),
+                Ok(_) => Err("`every` must be at least one second".to_string()),
+                Err(err) => Err(format!("invalid interval {every:?}: {err}")),
+            },
+            (None, Some(at)) => {
+                parse_at(at).ok_or_else(|| format!("invalid time {at:?}, expected e.g. \"03:00\" or \"Sun 03:00\""))
+            }
+            _ => Err("exactly one of `every` and `at` is required".to_string()),
+        }
+    }
+
+    /// The first run after `time` (seconds since the Unix epoch).
+    fn next_after(self, time: u64) -> u64 {
+        match self {
+            Recurrence::Every(interval) => time + interval.as_secs(),
+            Recurrence::At { weekday, second_of_day } => (time / DAY..)
+                .map(|day| day * DAY + second_of_day)
+                .find(|&run| run > time && weekday.is_none_or(|weekday| weekday_of(run) == weekday))
+                .expect("every weekday occurs within a week"),
+        }
+    }
+}
+
+/// Parses "HH:MM", optionally preceded by a weekday like "Sun" or "sunday".
+fn parse_at(at: &str) -> Option<Recurrence> {
+    let mut parts = at.split_whitespace().rev();
+    let (hours, minutes) = parts.next()?.split_once(':')?;
+    let (hours, minutes): (u64, u64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    let weekday = match parts.next() {
+        Some(day) => {
+            let day = day.to_ascii_lowercase();
+            Some(WEEKDAYS.iter().position(|name| day.starts_with(name))? as u64)
+        }
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Recurrence::At {
+        weekday,
+        second_of_day: hours * 3600 + minutes * 60,
+    })
+}
+
+/// The weekday of a Unix timestamp, 0 being Monday; 1970-01-01 was a Thursday.
+fn weekday_of(time: u64) -> u64 {
+    (time / DAY + 3) % 7
+}
+
+#[derive(Debug)]
+struct Job {
+    name: String,
+    recurrence: Recurrence,
+    args: Vec<String>,
+    next: u64,
+}
+
+/// Validates the schedules and their commands. `every` schedules first run right away.
+fn prepare(schedules: &[ScheduleConfig], now: u64) -> Result<Vec<Job>, String> {
+    schedules
+        .iter()
+        .map(|schedule| {
+            let invalid = |reason: String| format!("schedule {:?}: {reason}", schedule.name);
+            let recurrence = Recurrence::parse(schedule).map_err(invalid)?;
+            let args: Vec<String> = schedule.command.split_whitespace().map(str::to_string).collect();
+            match Cli::try_parse_from(std::iter::once("cobbler").chain(args.iter().map(String::as_str))) {
+                Ok(Cli {
+                    command: Commands::Agent,
+                    ..
+                }) => return Err(invalid("`agent` can't be scheduled".to_string())),
+                Ok(_) => {}
+                Err(err) => {
+                    let reason = err.to_string();
+                    return Err(invalid(format!(
+                        "invalid command: {}",
+                        reason.lines().next().unwrap_or_default().trim_start_matches("error: ")
+                    )));
+                }
+            }
+            let next = match recurrence {
+                Recurrence::Every(_) => now,
+                Recurrence::At { .. } => recurrence.next_after(now),
+            };
+            Ok(Job {
+                name: schedule.name.clone(),
+                recurrence,
+                args,
+                next,
+            })
+        })
+        .collect()
+}
+
+/// Checks a schedule's recurrence and command without running it.
+pub fn check(schedule: &ScheduleConfig) -> Result<(), String> {
+    prepare(std::slice::from_ref(schedule), 0).map(|_| ())
+}
+
+/// The next run of a schedule after `now`, or `None` for `every` schedules, whose runs
+/// count from the agent's start.
+pub fn next_run(schedule: &ScheduleConfig, now: u64) -> Result<Option<u64>, String> {
+    match Recurrence::parse(schedule)? {
+        Recurrence::Every(_) => Ok(None),
+        recurrence => Ok(Some(recurrence.next_after(now))),
+    }
+}
+
+/// Runs the configured schedules until interrupted. Each run executes this binary with
+/// the schedule's arguments and the same configuration file. Runs are sequential; runs
+/// missed while another one was busy are skipped.
+pub fn run_agent(config: &Config, config_path: &Path) -> Result<ExitStatus, Box<dyn Error>> {
+    let mut jobs = prepare(&config.schedules, unix_now())?;
+    if jobs.is_empty() {
+        return Err(format!("no schedules configured in {}", config_path.display()).into());
+    }
+    let exe = std::env::current_exe()?;
+    for job in &jobs {
+        println!("{}: next run at {}", job.name, format_time(job.next));
+    }
+
+    loop {
+        let job = jobs
+            .iter_mut()
+            .min_by_key(|job| job.next)
+            .expect("there is at least one job");
+        let now = unix_now();
+        if job.next > now {
+            thread::sleep(Duration::from_secs(job.next - now).min(MAX_SLEEP));
+            continue;
+        }
+
+        println!("{}: running `cobbler {}`", job.name, job.args.join(" "));
+        // Scheduling a command is its confirmation.
+        let mut command = Command::new(&exe);
+        command.arg("--config").arg(config_path);
+        if let Some(context) = &config.active_context {
+            command.arg("--context").arg(context);
+        }
+        match command.arg("--yes").args(&job.args).status() {
+            Ok(status) if status.success() => println!("{}: succeeded", job.name),
+            Ok(status) => eprintln!("{}: failed ({status})", job.name),
+            Err(err) => eprintln!("{}: failed to start: {err}", job.name),
+        }
+
+        let now = unix_now();
+        while job.next <= now {
+            job.next = job.recurrence.next_after(job.next);
+        }
+        println!("{}: next run at {}", job.name, format_time(job.next));
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+fn format_time(time: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(time)).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(every: Option<&str>, at: Option<&str>, command: &str) -> ScheduleConfig {
+        ScheduleConfig {
+            name: "test".to_string(),
+            every: every.map(str::to_string),
+            at: at.map(str::to_string),
+            command: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Thursday 1970-01-01 12:00 UTC
+        let noon = 12 * 3600;
+        let daily = parse_at("03:00").unwrap();
+        assert_eq!(daily.next_after(noon), DAY + 3 * 3600);
+        assert_eq!(daily.next_after(3 * 3600 - 1), 3 * 3600);
+
+        let sunday = parse_at("Sun 03:00").unwrap();
+        assert_eq!(sunday.next_after(noon), 3 * DAY + 3 * 3600);
+        assert_eq!(weekday_of(3 * DAY), 6);
+        assert_eq!(sunday.next_after(3 * DAY + 3 * 3600), 10 * DAY + 3 * 3600);
+
+        assert_eq!(
+            Recurrence::Every(Duration::from_secs(3600)).next_after(noon),
+            noon + 3600
+        );
+        assert_eq!(
+            parse_at("sunday 3:30"),
+            Some(Recurrence::At {
+                weekday: Some(6),
+                second_of_day: 3 * 3600 + 1800
+            })
+        );
+        assert_eq!(parse_at("24:00"), None);
+        assert_eq!(parse_at("Someday 03:00"), None);
+        assert_eq!(parse_at("Sun Mon 03:00"), None);
+    }
+
+    #[test]
+    fn test_prepare() {
+        let schedules = vec![
+            schedule(Some("1h"), None, "status --check"),
+            schedule(None, Some("Sun 03:00"), "upgrade --rolling --tag production"),
+        ];
+        let jobs = prepare(&schedules, 12 * 3600).unwrap();
+        assert_eq!(jobs[0].next, 12 * 3600);
+        assert_eq!(jobs[1].next, 3 * DAY + 3 * 3600);
+        assert_eq!(jobs[1].args, vec!["upgrade", "--rolling", "--tag", "production"]);
+        assert_eq!(next_run(&schedules[0], 12 * 3600), Ok(None));
+        assert_eq!(next_run(&schedules[1], 12 * 3600), Ok(Some(3 * DAY + 3 * 3600)));
+
+        for invalid in [
+            schedule(Some("1h"), Some("03:00"), "status"),
+            schedule(None, None, "status"),
+            schedule(Some("0s"), None, "status"),
+            schedule(Some("1h"), None, "frobnicate"),
+            schedule(Some("1h"), None, "agent"),
+        ] {
+            assert!(prepare(&[invalid], 0).unwrap_err().starts_with("schedule \"test\": "));
+        }
+    }
+}
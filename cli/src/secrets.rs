@@ -0,0 +1,105 @@
+//! API keys kept in the OS keyring (Secret Service, macOS Keychain or Windows Credential
+//! Manager) instead of the configuration file. Nodes reference them by entry name with
+//! `api_key_keyring`.
+
+use crate::config::{load_config, save_config, NodeConfig, TOKEN_PLACEHOLDER};
+use crate::ExitStatus;
+use clap::Subcommand;
+use keyring::Entry;
+use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+/// The keyring service all entries are stored under.
+const SERVICE: &str = "cobbler";
+
+#[derive(Subcommand)]
+pub enum KeyringCommand {
+    /// Move a node's API key into the OS keyring and reference it from the configuration
+    Store {
+        /// Node name or address
+        node: String,
+
+        /// Keyring entry to store the key as (default: the node's name or address)
+        #[arg(long)]
+        entry: Option<String>,
+    },
+    /// Remove an API key from the OS keyring
+    Delete {
+        /// Keyring entry
+        entry: String,
+    },
+}
+
+/// Reads the API key stored as `entry`.
+pub fn api_key(entry: &str) -> Result<String, keyring::Error> {
+    Entry::new(SERVICE, entry)?.get_password()
+}
+
+pub fn run_keyring(
+    action: KeyringCommand,
+    config_path: &Path,
+    context: Option<&str>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    match action {
+        KeyringCommand::Store { node, entry } => {
+            let mut config = load_config(config_path, context)?;
+            let node = config
+                .nodes
+                .iter_mut()
+                .find(|n| n.address == node || n.name.as_deref() == Some(node.as_str()))
+                .ok_or_else(|| format!("no node {node:?} in {}", config_path.display()))?;
+            let entry = entry.unwrap_or_else(|| default_entry(node));
+            // The plaintext key moves to the keyring; without one, ask for it.
+            let key = match node.api_key.take() {
+                Some(key) if key != TOKEN_PLACEHOLDER => key,
+                _ => read_api_key(&entry)?,
+            };
+            Entry::new(SERVICE, &entry)?.set_password(&key)?;
+            node.api_key_keyring = Some(entry.clone());
+            save_config(config_path, &config)?;
+            println!("Stored the API key in the keyring as {entry:?} and updated {}", config_path.display());
+        }
+        KeyringCommand::Delete { entry } => {
+            Entry::new(SERVICE, &entry)?.delete_credential()?;
+            println!("Deleted {entry:?} from the keyring");
+        }
+    }
+    Ok(ExitStatus::Ok)
+}
+
+fn default_entry(node: &NodeConfig) -> String {
+    node.name.clone().unwrap_or_else(|| node.address.clone())
+}
+
+/// Reads a key from stdin, without echoing it on a terminal.
+fn read_api_key(entry: &str) -> Result<String, Box<dyn Error>> {
+    let line = if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("API key for {entry}: "))?
+    } else {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        line
+    };
+    let key = line.trim();
+    if key.is_empty() {
+        return Err("no API key given".into());
+    }
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_entry() {
+        let mut node = NodeConfig {
+            address: "10.0.0.1:8080".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(default_entry(&node), "10.0.0.1:8080");
+        node.name = Some("web-1".to_string());
+        assert_eq!(default_entry(&node), "web-1");
+    }
+}
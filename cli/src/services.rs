@@ -0,0 +1,136 @@
+use crate::{api_client, client_node, run_node_action, Config, ExitStatus, TargetSelection};
+use clap::Subcommand;
+use cobbler_api::{ServiceAction, ServiceInfo};
+use std::error::Error;
+use std::io::{self, Write};
+use tabwriter::TabWriter;
+
+#[derive(Subcommand)]
+pub enum ServicesCommand {
+    /// List the systemd services of a cobbler daemon
+    List {
+        /// Target (host:port or node name)
+        target: String,
+    },
+    /// Start a service on cobbler daemons that allowlist it
+    Start {
+        /// Service unit, e.g. "nginx" or "nginx.service"
+        service: String,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Stop a service on cobbler daemons that allowlist it
+    Stop {
+        /// Service unit, e.g. "nginx" or "nginx.service"
+        service: String,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Restart a service on cobbler daemons that allowlist it
+    Restart {
+        /// Service unit, e.g. "nginx" or "nginx.service"
+        service: String,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+}
+
+pub fn run_services(command: ServicesCommand, config: &Config) -> Result<ExitStatus, Box<dyn Error>> {
+    let (service, action, selection) = match command {
+        ServicesCommand::List { target } => {
+            let client = api_client()?;
+            let services = client
+                .services(&client_node(&target, config))
+                .map_err(|err| format!("{target}: {err}"))?;
+            print_services(io::stdout(), &services)?;
+            return Ok(ExitStatus::Ok);
+        }
+        ServicesCommand::Start { service, selection } => (service, ServiceAction::Start, selection),
+        ServicesCommand::Stop { service, selection } => (service, ServiceAction::Stop, selection),
+        ServicesCommand::Restart { service, selection } => (service, ServiceAction::Restart, selection),
+    };
+    run_node_action(selection, config, |client, node| {
+        client
+            .service_action(node, &service, action)
+            .map(|info| format!("{} {}", info.name, state(&info)))
+    })
+}
+
+/// E.g. `active (running)`.
+fn state(service: &ServiceInfo) -> String {
+    format!("{} ({})", service.active_state, service.sub_state)
+}
+
+fn print_services<W: Write>(writer: W, services: &[ServiceInfo]) -> io::Result<()> {
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "SERVICE\tSTATE\tENABLED\tALLOWED\tDESCRIPTION")?;
+    for service in services {
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}",
+            service.name,
+            state(service),
+            if service.enabled { "yes" } else { "no" },
+            if service.allowed { "yes" } else { "no" },
+            service.description
+        )?;
+    }
+    tw.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+
+    #[test]
+    fn test_cli_parse_restart() {
+        let cli = Cli::parse_from(["cobbler", "services", "restart", "nginx", "--tag", "web"]);
+        if let Commands::Services {
+            action: ServicesCommand::Restart { service, selection },
+        } = cli.command
+        {
+            assert_eq!(service, "nginx");
+            assert_eq!(selection.tag, vec!["web"]);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "services", "stop"]).is_err());
+    }
+
+    #[test]
+    fn test_print_services() {
+        let services = vec![
+            ServiceInfo {
+                name: "cron.service".to_string(),
+                description: "Regular background program processing daemon".to_string(),
+                active_state: "failed".to_string(),
+                sub_state: "failed".to_string(),
+                enabled: true,
+                allowed: false,
+            },
+            ServiceInfo {
+                name: "nginx.service".to_string(),
+                description: "A high performance web server".to_string(),
+                active_state: "active".to_string(),
+                sub_state: "running".to_string(),
+                enabled: true,
+                allowed: true,
+            },
+        ];
+        let mut out = Vec::new();
+        print_services(&mut out, &services).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+SERVICE        STATE             ENABLED  ALLOWED  DESCRIPTION
+cron.service   failed (failed)   yes      no       Regular background program processing daemon
+nginx.service  active (running)  yes      yes      A high performance web server
+"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{api_client, client_node, run_node_action, Config, ExitStatus, TargetSelection};
+use clap::Subcommand;
+use cobbler_api::{AddSourceRequest, AptSource};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tabwriter::TabWriter;
+
+#[derive(Subcommand)]
+pub enum SourcesCommand {
+    /// List the apt repositories of a cobbler daemon
+    List {
+        /// Target (host:port or node name)
+        target: String,
+    },
+    /// Add an apt repository and update the package lists
+    Add {
+        /// Name of the file in sources.list.d (without extension)
+        name: String,
+
+        /// Repository URI (repeatable)
+        #[arg(long = "uri", required = true)]
+        uris: Vec<String>,
+
+        /// Suite, e.g. "bookworm" (repeatable)
+        #[arg(long = "suite", required = true)]
+        suites: Vec<String>,
+
+        /// Component, e.g. "main" (repeatable); omit for flat repositories
+        #[arg(long = "component")]
+        components: Vec<String>,
+
+        /// Source type (repeatable)
+        #[arg(long = "type", value_parser = ["deb", "deb-src"], default_value = "deb")]
+        types: Vec<String>,
+
+        /// Keyring on the nodes the repository is signed with
+        #[arg(long, value_name = "PATH", conflicts_with = "key_file")]
+        signed_by: Option<String>,
+
+        /// Local ASCII-armored public key to embed in the source file
+        #[arg(long, value_name = "FILE")]
+        key_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Remove an apt repository and update the package lists
+    Remove {
+        /// Name of the file in sources.list.d (without extension)
+        name: String,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+}
+
+pub fn run_sources(command: SourcesCommand, config: &Config) -> Result<ExitStatus, Box<dyn Error>> {
+    match command {
+        SourcesCommand::List { target } => {
+            let client = api_client()?;
+            let sources = client
+                .sources(&client_node(&target, config))
+                .map_err(|err| format!("{target}: {err}"))?;
+            print_sources(io::stdout(), &sources)?;
+            Ok(ExitStatus::Ok)
+        }
+        SourcesCommand::Add {
+            name,
+            uris,
+            suites,
+            components,
+            types,
+            signed_by,
+            key_file,
+            selection,
+        } => {
+            let signed_by = match key_file {
+                Some(path) => Some(
+                    fs::read_to_string(&path).map_err(|err| format!("failed to read {}: {err}", path.display()))?,
+                ),
+                None => signed_by,
+            };
+            let request = AddSourceRequest {
+                name,
+                types,
+                uris,
+                suites,
+                components,
+                signed_by,
+            };
+            run_node_action(selection, config, |client, node| {
+                client.add_source(node, &request).map(|response| response.message)
+            })
+        }
+        SourcesCommand::Remove { name, selection } => {
+            run_node_action(selection, config, |client, node| {
+                client.remove_source(node, &name).map(|response| response.message)
+            })
+        }
+    }
+}
+
+fn print_sources<W: Write>(writer: W, sources: &[AptSource]) -> io::Result<()> {
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "NAME\tENABLED\tTYPES\tURIS\tSUITES\tCOMPONENTS")?;
+    for source in sources {
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            source.name.as_deref().unwrap_or("-"),
+            if source.enabled { "yes" } else { "no" },
+            source.types.join(" "),
+            source.uris.join(" "),
+            source.suites.join(" "),
+            if source.components.is_empty() { "-".to_string() } else { source.components.join(" ") }
+        )?;
+    }
+    tw.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+
+    #[test]
+    fn test_cli_parse_add() {
+        let cli = Cli::parse_from([
+            "cobbler",
+            "sources",
+            "add",
+            "internal",
+            "--uri",
+            "https://apt.example.com/debian",
+            "--suite",
+            "bookworm",
+            "--component",
+            "main",
+            "--signed-by",
+            "/usr/share/keyrings/internal.gpg",
+            "--tag",
+            "web",
+        ]);
+        if let Commands::Sources {
+            action: SourcesCommand::Add {
+                name,
+                types,
+                components,
+                signed_by,
+                selection,
+                ..
+            },
+        } = cli.command
+        {
+            assert_eq!(name, "internal");
+            assert_eq!(types, vec!["deb"]);
+            assert_eq!(components, vec!["main"]);
+            assert_eq!(signed_by.as_deref(), Some("/usr/share/keyrings/internal.gpg"));
+            assert_eq!(selection.tag, vec!["web"]);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "sources", "add", "internal", "--suite", "bookworm"]).is_err());
+        assert!(Cli::try_parse_from([
+            "cobbler", "sources", "add", "x", "--uri", "u:", "--suite", "s", "--signed-by", "/k", "--key-file", "k.asc",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_print_sources() {
+        let sources = vec![
+            AptSource {
+                name: None,
+                file: "/etc/apt/sources.list".to_string(),
+                enabled: true,
+                types: vec!["deb".to_string()],
+                uris: vec!["http://deb.debian.org/debian".to_string()],
+                suites: vec!["bookworm".to_string()],
+                components: vec!["main".to_string(), "contrib".to_string()],
+                signed_by: None,
+            },
+            AptSource {
+                name: Some("flat".to_string()),
+                file: "/etc/apt/sources.list.d/flat.sources".to_string(),
+                enabled: false,
+                types: vec!["deb".to_string()],
+                uris: vec!["https://repo.example.com".to_string()],
+                suites: vec!["./".to_string()],
+                components: vec![],
+                signed_by: None,
+            },
+        ];
+        let mut out = Vec::new();
+        print_sources(&mut out, &sources).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[1].starts_with("-     yes"));
+        assert!(lines[1].ends_with("main contrib"));
+        assert!(lines[2].starts_with("flat  no"));
+        assert!(lines[2].ends_with("-"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Agentless nodes (`transport: ssh`). The CLI runs the apt commands cobblerd would run
+//! through the system `ssh` client and reports the results in the daemon's response types,
+//! so that they show up like any other node.
+
+use crate::config::NodeConfig;
+use cobbler_api::kernel;
+use cobbler_api::{ConffilePolicy, FullUpgradeRequest, PhasedUpdates, StatusResponse, UpdateDetail, UpgradeStrategy};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Runs privileged commands through `sudo -n` unless logged in as root.
+const PRELUDE: &str = "\
+set -e
+if [ \"$(id -u)\" -eq 0 ]; then SUDO=; else SUDO='sudo -n'; fi
+";
+
+const STATUS_SCRIPT: &str = "\
+$SUDO apt-get update -qq >/dev/null
+echo '@@ plan'
+LC_ALL=C apt-get -s -o Debug::NoLocking=1 dist-upgrade
+echo '@@ kernel'
+uname -r
+echo '@@ uptime'
+cat /proc/uptime
+echo '@@ reboot'
+if [ -e /var/run/reboot-required ]; then echo yes; else echo no; fi
+";
+
+/// The apt-get command and its options are passed as script arguments.
+const UPGRADE_SCRIPT: &str = "\
+$SUDO apt-get update -qq >/dev/null
+$SUDO env DEBIAN_FRONTEND=noninteractive apt-get -y \
+    -o Dpkg::Options::=--force-confdef \"$@\"
+";
+
+/// Detaches the reboot so that the SSH session ends cleanly first.
+const REBOOT_SCRIPT: &str = "\
+$SUDO true
+($SUDO sh -c 'sleep 1; reboot') >/dev/null 2>&1 &
+";
+
+pub fn status(node: &NodeConfig) -> Result<StatusResponse, String> {
+    let mut status = parse_status(&run(node, STATUS_SCRIPT, &[])?);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    // The script has just refreshed the package lists.
+    status.last_check = Some(now);
+    (status.health, status.health_reasons) = status.assess_health(now);
+    Ok(status)
+}
+
+pub fn full_upgrade(node: &NodeConfig, request: &FullUpgradeRequest) -> Result<String, String> {
+    // Holding packages over SSH would leave them held if the connection dropped mid-upgrade.
+    if !request.exclude.is_empty() {
+        return Err("excluding packages needs the cobbler daemon".to_string());
+    }
+    if !request.only.is_empty() {
+        return Err("upgrading selected packages needs the cobbler daemon".to_string());
+    }
+    let phased = request.phased_updates.map(|phased| match phased {
+        PhasedUpdates::Include => "APT::Get::Always-Include-Phased-Updates=true",
+        PhasedUpdates::Exclude => "APT::Get::Never-Include-Phased-Updates=true",
+    });
+    let conffiles = match request.conffiles.unwrap_or_default() {
+        ConffilePolicy::ConfOld => "Dpkg::Options::=--force-confold",
+        ConffilePolicy::ConfNew => "Dpkg::Options::=--force-confnew",
+    };
+    let download_limit: Vec<String> = match request.download_limit {
+        Some(kbps) if kbps > 0 => ["http", "https"]
+            .iter()
+            .map(|scheme| format!("Acquire::{scheme}::Dl-Limit={kbps}"))
+            .collect(),
+        _ => Vec::new(),
+    };
+    // Like the daemon's `apt upgrade`, which installs new dependencies.
+    let strategy: &[&str] = match request.strategy.unwrap_or_default() {
+        UpgradeStrategy::Upgrade => &["upgrade", "--with-new-pkgs"],
+        strategy => &[strategy.as_str()],
+    };
+    let args: Vec<&str> = phased
+        .into_iter()
+        .chain([conffiles])
+        .chain(download_limit.iter().map(String::as_str))
+        .flat_map(|option| ["-o", option])
+        .chain(strategy.iter().copied())
+        .collect();
+    run(node, UPGRADE_SCRIPT, &args)
+        .map(|_| "full upgrade completed successfully".to_string())
+        .map_err(|err| format!("full upgrade failed: {err}"))
+}
+
+pub fn reboot(node: &NodeConfig) -> Result<(), String> {
+    run(node, REBOOT_SCRIPT, &[]).map(|_| ())
+}
+
+/// Feeds `script` to `sh` on the node and returns its stdout, or the last line of its
+/// stderr if it fails.
+fn run(node: &NodeConfig, script: &str, args: &[&str]) -> Result<String, String> {
+    let mut command = Command::new("ssh");
+    command
+        .args(["-o", "BatchMode=yes", "-o"])
+        .arg(format!("ConnectTimeout={CONNECT_TIMEOUT_SECS}"));
+    if let Some(ssh) = &node.ssh {
+        if let Some(user) = &ssh.user {
+            command.arg("-l").arg(user);
+        }
+        if let Some(port) = ssh.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &ssh.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+    }
+    // `--` keeps an address starting with `-` from being taken for an option.
+    command
+        .arg("--")
+        .arg(&node.address)
+        .args(["sh", "-s", "--"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command.spawn().map_err(|err| format!("failed to run ssh: {err}"))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(format!("{PRELUDE}{script}").as_bytes())
+        .map_err(|err| format!("failed to send commands over ssh: {err}"))?;
+    let output = child.wait_with_output().map_err(|err| format!("ssh failed: {err}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+            Some(line) => line.trim().to_string(),
+            None => format!("ssh exited with {}", output.status),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_status(output: &str) -> StatusResponse {
+    let plan: Vec<&str> = section(output, "plan").collect();
+    let kept_back = indented_after(&plan, "have been kept back:");
+    let mut update_details = Vec::new();
+    let mut new_packages = Vec::new();
+    let mut removals = Vec::new();
+    for line in &plan {
+        if let Some(rest) = line.strip_prefix("Inst ") {
+            let (name, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            if rest.starts_with('[') {
+                update_details.push(update_detail(name, rest));
+            } else {
+                new_packages.push(name.to_string());
+            }
+        } else if let Some(rest) = line.strip_prefix("Remv ") {
+            removals.extend(rest.split_whitespace().next().map(str::to_string));
+        }
+    }
+    // Kept-back packages are upgradable too, but the simulation doesn't say from where.
+    update_details.extend(kept_back.iter().map(|name| UpdateDetail {
+        name: name.clone(),
+        archive: None,
+        security: false,
+        ..Default::default()
+    }));
+
+    let kernel_update = kernel::pending(&update_details, &new_packages, &kept_back);
+    let security_updates = update_details.iter().filter(|update| update.security).count();
+    let updates: Vec<String> = update_details.iter().map(|update| update.name.clone()).collect();
+
+    StatusResponse {
+        message: if updates.is_empty() {
+            "System is up to date".to_string()
+        } else {
+            format!("System has {} outdated packages", updates.len())
+        },
+        phased_updates: indented_after(&plan, "deferred due to phasing:"),
+        security_updates,
+        regular_updates: updates.len() - security_updates,
+        updates,
+        update_details,
+        kept_back,
+        removals,
+        reboot_required: section(output, "reboot").any(|line| line == "yes"),
+        uptime_seconds: section(output, "uptime")
+            .next()
+            .and_then(|line| line.split('.').next()?.parse().ok()),
+        kernel: section(output, "kernel").next().map(str::to_string),
+        kernel_update_pending: kernel_update.pending,
+        kernel_candidate: kernel_update.candidate,
+        ..Default::default()
+    }
+}
+
+/// Parses the rest of a simulated install line like
+/// `[2.36-9] (2.36-9+deb12u4 Debian:12.5/stable, Debian-Security:12/stable-security [amd64])`.
+/// The archive is the security suite if there is one, like the daemon reports it.
+fn update_detail(name: &str, rest: &str) -> UpdateDetail {
+    let origins = rest
+        .split_once('(')
+        .and_then(|(_, candidate)| candidate.split_once(' '))
+        .map(|(_, origins)| origins.trim_end_matches(')'))
+        .map(|origins| origins.rsplit_once(" [").map_or(origins, |(origins, _)| origins))
+        .unwrap_or_default();
+    let suites: Vec<&str> = origins
+        .split(", ")
+        .filter_map(|origin| origin.rsplit_once('/').map(|(_, suite)| suite))
+        .collect();
+    let security_suite = suites.iter().find(|suite| suite.ends_with("-security"));
+    UpdateDetail {
+        name: name.to_string(),
+        archive: security_suite.or(suites.first()).map(|suite| suite.to_string()),
+        security: security_suite.is_some(),
+        ..Default::default()
+    }
+}
+
+fn section<'a>(output: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    output
+        .lines()
+        .skip_while(move |line| line.strip_prefix("@@ ") != Some(name))
+        .skip(1)
+        .take_while(|line| !line.starts_with("@@ "))
+}
+
+/// The package names listed below the line ending in `header`.
+fn indented_after(lines: &[&str], header: &str) -> Vec<String> {
+    lines
+        .iter()
+        .skip_while(|line| !line.trim_end().ends_with(header))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .flat_map(|line| line.split_whitespace())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let output = "\
+@@ plan
+NOTE: This is only a simulation!
+Reading package lists...
+The following packages will be REMOVED:
+  libfoo1
+The following NEW packages will be installed:
+  linux-image-6.1.0-21-amd64
+The following packages have been kept back:
+  nginx
+The following packages will be upgraded:
+  libc6 linux-image-amd64 vim
+3 upgraded, 1 newly installed, 1 to remove and 1 not upgraded.
+Remv libfoo1 [1.0-1]
+Inst libc6 [2.36-9] (2.36-9+deb12u4 Debian:12.5/stable, Debian-Security:12/stable-security [amd64])
+Inst linux-image-6.1.0-21-amd64 (6.1.90-1 Debian-Security:12/stable-security [amd64])
+Inst linux-image-amd64 [6.1.76-1] (6.1.90-1 Debian-Security:12/stable-security [amd64])
+Inst vim [2:9.0.1378-2] (2:9.0.1378-2+deb12u1 Debian:12.5/stable-updates [amd64])
+Conf libc6 (2.36-9+deb12u4 Debian:12.5/stable, Debian-Security:12/stable-security [amd64])
+@@ kernel
+6.1.0-18-amd64
+@@ uptime
+86400.52 172000.10
+@@ reboot
+no
+";
+        let status = parse_status(output);
+        assert_eq!(status.updates, vec!["libc6", "linux-image-amd64", "vim", "nginx"]);
+        assert_eq!(status.update_details[0].archive.as_deref(), Some("stable-security"));
+        assert!(status.update_details[0].security);
+        assert_eq!(status.update_details[2].archive.as_deref(), Some("stable-updates"));
+        assert!(!status.update_details[2].security);
+        assert_eq!((status.security_updates, status.regular_updates), (2, 2));
+        assert_eq!(status.kept_back, vec!["nginx"]);
+        assert_eq!(status.removals, vec!["libfoo1"]);
+        assert!(status.kernel_update_pending);
+        assert_eq!(status.kernel_candidate.as_deref(), Some("6.1.0-21-amd64"));
+        assert_eq!(status.kernel.as_deref(), Some("6.1.0-18-amd64"));
+        assert_eq!(status.uptime_seconds, Some(86400));
+        assert!(!status.reboot_required);
+        assert_eq!(status.message, "System has 4 outdated packages");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::config::{NodeConfig, SshConfig, Transport};
+use crate::discovery;
+use crate::inventory::save_imported;
+use crate::ExitStatus;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A concrete host from a `Host` stanza of an OpenSSH client configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SshHost {
+    alias: String,
+    hostname: Option<String>,
+    user: Option<String>,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+}
+
+impl SshHost {
+    fn hostname(&self) -> &str {
+        self.hostname.as_deref().unwrap_or(&self.alias)
+    }
+}
+
+/// How `config import --from-ssh-config` turns hosts into nodes.
+pub struct SshImportOptions {
+    /// Port cobblerd is expected on.
+    pub port: u16,
+    /// Add hosts without a daemon as agentless nodes instead of skipping them.
+    pub agentless: bool,
+    /// Add every host as a daemon without probing.
+    pub no_probe: bool,
+}
+
+/// Imports the hosts of an OpenSSH client configuration that run cobblerd on `port`,
+/// confirmed by probing them unless `no_probe` is set.
+pub fn run_import_ssh_config(
+    file: Option<PathBuf>,
+    options: SshImportOptions,
+    config_path: &Path,
+    context: Option<&str>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let file = match file {
+        Some(file) => file,
+        None => dirs_home()
+            .map(|home| home.join(".ssh").join("config"))
+            .ok_or("cannot locate ~/.ssh/config; pass the path to --from-ssh-config")?,
+    };
+    let content = fs::read_to_string(&file).map_err(|err| format!("{}: {err}", file.display()))?;
+    let hosts = parse_ssh_config(&content);
+    if hosts.is_empty() {
+        println!("No concrete hosts found in {}", file.display());
+        return Ok(ExitStatus::Ok);
+    }
+
+    let present = if options.no_probe {
+        vec![true; hosts.len()]
+    } else {
+        let names: Vec<String> = hosts.iter().map(|host| host.hostname().to_string()).collect();
+        discovery::probe_hosts(&names, options.port)?
+            .iter()
+            .map(Option::is_some)
+            .collect()
+    };
+
+    let mut nodes = Vec::new();
+    for (host, present) in hosts.into_iter().zip(present) {
+        match (present, options.agentless) {
+            (true, _) => nodes.push(daemon_node(host, options.port)),
+            (false, true) => nodes.push(agentless_node(host)),
+            (false, false) => eprintln!("{}: no cobbler daemon on port {}, skipped", host.alias, options.port),
+        }
+    }
+    save_imported(nodes, config_path, context)
+}
+
+fn dirs_home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn daemon_node(host: SshHost, port: u16) -> NodeConfig {
+    let hostname = host.hostname();
+    let hostname = if hostname.contains(':') {
+        format!("[{hostname}]")
+    } else {
+        hostname.to_string()
+    };
+    NodeConfig {
+        address: format!("{hostname}:{port}"),
+        name: Some(host.alias),
+        ..Default::default()
+    }
+}
+
+/// Reached through the alias, so that ssh applies the rest of its stanza, such as
+/// `ProxyJump`, too.
+fn agentless_node(host: SshHost) -> NodeConfig {
+    NodeConfig {
+        address: host.alias.clone(),
+        transport: Transport::Ssh,
+        ssh: Some(SshConfig {
+            user: host.user,
+            port: host.port,
+            identity_file: host.identity_file,
+        }),
+        name: Some(host.alias),
+        ..Default::default()
+    }
+}
+
+/// Collects the hosts of `Host` stanzas, one per alias. Patterns (`*`, `?`, negations)
+/// and `Match` blocks are skipped; `Include` is not followed.
+fn parse_ssh_config(content: &str) -> Vec<SshHost> {
+    let mut hosts: Vec<SshHost> = Vec::new();
+    // Index into `hosts` where the current stanza's aliases begin, if in a `Host` stanza.
+    let mut stanza: Option<usize> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((keyword, value)) => (
+                keyword,
+                value.trim_start_matches(|c: char| c.is_whitespace() || c == '='),
+            ),
+            None => (line, ""),
+        };
+        let value = value.trim().trim_matches('"');
+
+        match keyword.to_ascii_lowercase().as_str() {
+            "host" => {
+                stanza = Some(hosts.len());
+                hosts.extend(
+                    value
+                        .split_whitespace()
+                        .filter(|alias| !alias.contains(['*', '?', '!']))
+                        .map(|alias| SshHost {
+                            alias: alias.to_string(),
+                            ..Default::default()
+                        }),
+                );
+            }
+            "match" => stanza = None,
+            keyword => {
+                let Some(start) = stanza else { continue };
+                // The first value obtained for each option wins, as in ssh.
+                for host in &mut hosts[start..] {
+                    match keyword {
+                        "hostname" => {
+                            host.hostname.get_or_insert_with(|| value.to_string());
+                        }
+                        "user" => {
+                            host.user.get_or_insert_with(|| value.to_string());
+                        }
+                        "port" if host.port.is_none() => host.port = value.parse().ok(),
+                        "identityfile" => {
+                            host.identity_file.get_or_insert_with(|| PathBuf::from(value));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    hosts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::DEFAULT_DAEMON_PORT;
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let content = "\
+# fleet
+Host *
+    User fallback
+
+Host web1 web1-alt
+    HostName 10.0.0.1
+    User admin
+    User ignored
+    Port=2222
+
+Host db1
+  IdentityFile \"~/.ssh/db key\"
+
+Match host db1
+    User matched
+
+Host *.internal !bastion
+";
+        let hosts = parse_ssh_config(content);
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts[0].alias, "web1");
+        assert_eq!(hosts[0].hostname(), "10.0.0.1");
+        assert_eq!(hosts[0].user.as_deref(), Some("admin"));
+        assert_eq!(hosts[0].port, Some(2222));
+        assert_eq!(hosts[1].alias, "web1-alt");
+        assert_eq!(hosts[1].hostname(), "10.0.0.1");
+        assert_eq!(hosts[2].hostname(), "db1");
+        assert_eq!(hosts[2].user, None);
+        assert_eq!(hosts[2].identity_file, Some(PathBuf::from("~/.ssh/db key")));
+
+        let node = daemon_node(hosts[0].clone(), DEFAULT_DAEMON_PORT);
+        assert_eq!(node.name.as_deref(), Some("web1"));
+        assert_eq!(node.address, "10.0.0.1:8080");
+        let node = agentless_node(hosts.into_iter().next().unwrap());
+        assert_eq!(node.name.as_deref(), Some("web1"));
+        assert_eq!(node.address, "web1");
+        assert_eq!(node.ssh.unwrap().port, Some(2222));
+    }
+}
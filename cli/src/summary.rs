@@ -0,0 +1,133 @@
+use crate::{api_client, client_node, error_exit_status, fetch_all, select_targets, Config, ExitStatus, TargetSelection};
+use crate::output::{OutputFormat, RowWriter};
+use cobbler_api::StatusResponse;
+use cobbler_client::ErrorCode;
+use serde::Serialize;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// Node counts across the fleet. Nodes that answer with an error, e.g. `NOT_DEBIAN` or
+/// `LOCK_HELD`, are reachable but counted nowhere else.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+struct FleetSummary {
+    nodes: usize,
+    reachable: usize,
+    up_to_date: usize,
+    security_updates: usize,
+    reboot_required: usize,
+    upgrading: usize,
+}
+
+impl FleetSummary {
+    fn add(&mut self, result: &Result<StatusResponse, cobbler_client::Error>) {
+        self.nodes += 1;
+        let status = match result {
+            Ok(status) => status,
+            Err(cobbler_client::Error::Status { .. }) => {
+                self.reachable += 1;
+                return;
+            }
+            Err(_) => return,
+        };
+        self.reachable += 1;
+        self.up_to_date += usize::from(status.updates.is_empty());
+        self.security_updates += usize::from(status.security_updates > 0);
+        self.reboot_required += usize::from(status.reboot_required);
+        self.upgrading += usize::from(status.is_upgrading);
+    }
+}
+
+pub fn run_summary(
+    output: OutputFormat,
+    selection: TargetSelection,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        eprintln!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let client = api_client()?;
+    let results = fetch_all("fetching status", &targets, |target| client.status(&client_node(target, config)));
+    let mut summary = FleetSummary::default();
+    for (_, result) in &results {
+        summary.add(result);
+    }
+
+    print_summary(io::stdout(), output, &summary)?;
+    // Like `status`, a node without apt is fine; any other failure is not.
+    Ok(results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().err())
+        .filter(|err| err.code() != Some(ErrorCode::NotDebian))
+        .map(error_exit_status)
+        .max()
+        .unwrap_or(ExitStatus::Ok))
+}
+
+fn print_summary<W: Write>(mut writer: W, output: OutputFormat, summary: &FleetSummary) -> io::Result<()> {
+    if output == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut writer, summary)?;
+        return writeln!(writer);
+    }
+
+    let mut rows = RowWriter::new(writer, output);
+    rows.write_row(&["NODES", "REACHABLE", "UP_TO_DATE", "SECURITY", "REBOOT", "UPGRADING"])?;
+    rows.write_row(&[
+        summary.nodes,
+        summary.reachable,
+        summary.up_to_date,
+        summary.security_updates,
+        summary.reboot_required,
+        summary.upgrading,
+    ]
+    .map(|count| count.to_string()))?;
+    rows.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobbler_api::ErrorResponse;
+    use cobbler_client::StatusCode;
+
+    #[test]
+    fn test_summary_counts_nodes() {
+        let mut summary = FleetSummary::default();
+        summary.add(&Ok(StatusResponse::default()));
+        summary.add(&Ok(StatusResponse {
+            updates: vec!["openssl".to_string()],
+            security_updates: 1,
+            reboot_required: true,
+            ..Default::default()
+        }));
+        summary.add(&Ok(StatusResponse {
+            is_upgrading: true,
+            ..Default::default()
+        }));
+        summary.add(&Err(cobbler_client::Error::Status {
+            status: StatusCode::PRECONDITION_FAILED,
+            error: ErrorResponse::new(ErrorCode::NotDebian, "Not a Debian-based system"),
+        }));
+        summary.add(&Err(cobbler_client::Error::Runtime(io::Error::other("no runtime"))));
+        assert_eq!(
+            summary,
+            FleetSummary {
+                nodes: 5,
+                reachable: 4,
+                up_to_date: 2,
+                security_updates: 1,
+                reboot_required: 1,
+                upgrading: 1,
+            }
+        );
+
+        let mut out = Vec::new();
+        print_summary(&mut out, OutputFormat::Csv, &summary).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "NODES,REACHABLE,UP_TO_DATE,SECURITY,REBOOT,UPGRADING\n5,4,2,1,1,1\n"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{api_client, client_node, error_exit_status, select_targets, Config, ExitStatus, TargetSelection};
+use clap::{Args, Subcommand};
+use cobbler_api::UnattendedUpgradesConfig;
+use std::error::Error;
+use std::io::{self, Write};
+use tabwriter::TabWriter;
+
+#[derive(Subcommand)]
+pub enum UnattendedCommand {
+    /// Show the effective unattended-upgrades settings
+    Show {
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+    /// Change unattended-upgrades settings; unspecified settings are kept per node
+    Set {
+        #[command(flatten)]
+        changes: UnattendedChanges,
+
+        #[command(flatten)]
+        selection: TargetSelection,
+    },
+}
+
+#[derive(Args, Debug, Default)]
+pub struct UnattendedChanges {
+    /// Enable automatic upgrades
+    #[arg(long, conflicts_with = "disable")]
+    enable: bool,
+
+    /// Disable automatic upgrades
+    #[arg(long)]
+    disable: bool,
+
+    /// Replace the allowed origins, e.g. "${distro_id}:${distro_codename}-security" (repeatable)
+    #[arg(long = "allowed-origin", value_name = "ORIGIN")]
+    allowed_origins: Vec<String>,
+
+    /// Replace the origin patterns, e.g. "origin=Debian,label=Debian-Security" (repeatable)
+    #[arg(long = "origins-pattern", value_name = "PATTERN")]
+    origins_pattern: Vec<String>,
+
+    /// Reboot automatically when an upgrade requires it
+    #[arg(long, value_name = "BOOL")]
+    auto_reboot: Option<bool>,
+
+    /// Time of the automatic reboot (HH:MM or "now")
+    #[arg(long, value_name = "HH:MM")]
+    reboot_time: Option<String>,
+}
+
+impl UnattendedChanges {
+    fn is_empty(&self) -> bool {
+        !self.enable
+            && !self.disable
+            && self.allowed_origins.is_empty()
+            && self.origins_pattern.is_empty()
+            && self.auto_reboot.is_none()
+            && self.reboot_time.is_none()
+    }
+
+    fn apply(&self, config: &mut UnattendedUpgradesConfig) {
+        if self.enable || self.disable {
+            config.enabled = self.enable;
+        }
+        if !self.allowed_origins.is_empty() {
+            config.allowed_origins = self.allowed_origins.clone();
+        }
+        if !self.origins_pattern.is_empty() {
+            config.origins_pattern = self.origins_pattern.clone();
+        }
+        if let Some(auto_reboot) = self.auto_reboot {
+            config.automatic_reboot = auto_reboot;
+        }
+        if let Some(time) = &self.reboot_time {
+            config.automatic_reboot_time = Some(time.clone());
+        }
+    }
+}
+
+pub fn run_unattended(command: UnattendedCommand, config: &Config) -> Result<ExitStatus, Box<dyn Error>> {
+    let (selection, changes) = match command {
+        UnattendedCommand::Show { selection } => (selection, None),
+        UnattendedCommand::Set { changes, selection } => {
+            if changes.is_empty() {
+                return Err("nothing to change; see `cobbler unattended-upgrades set --help`".into());
+            }
+            (selection, Some(changes))
+        }
+    };
+
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let client = api_client()?;
+    let mut exit_status = ExitStatus::Ok;
+    let mut results = Vec::new();
+    for target in targets {
+        let node = client_node(&target, config);
+        let result = client.unattended_upgrades(&node).and_then(|mut settings| match &changes {
+            Some(changes) => {
+                changes.apply(&mut settings);
+                client.set_unattended_upgrades(&node, &settings)
+            }
+            None => Ok(settings),
+        });
+        if let Err(err) = &result {
+            exit_status = exit_status.max(error_exit_status(err));
+        }
+        results.push((target, result.map_err(|err| err.to_string())));
+    }
+
+    print_settings(io::stdout(), &results)?;
+    Ok(exit_status)
+}
+
+fn print_settings<W: Write>(
+    writer: W,
+    results: &[(String, Result<UnattendedUpgradesConfig, String>)],
+) -> io::Result<()> {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "TARGET\tENABLED\tAUTO_REBOOT\tREBOOT_TIME\tORIGINS")?;
+    for (target, result) in results {
+        match result {
+            Ok(settings) => {
+                let origins: Vec<&str> = settings
+                    .allowed_origins
+                    .iter()
+                    .chain(&settings.origins_pattern)
+                    .map(String::as_str)
+                    .collect();
+                writeln!(
+                    tw,
+                    "{}\t{}\t{}\t{}\t{}",
+                    target,
+                    yes_no(settings.enabled),
+                    yes_no(settings.automatic_reboot),
+                    settings.automatic_reboot_time.as_deref().unwrap_or("-"),
+                    if origins.is_empty() { "-".to_string() } else { origins.join(" ") }
+                )?;
+            }
+            Err(err) => writeln!(tw, "{target}\tError: {err}\t\t\t")?,
+        }
+    }
+    tw.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+
+    #[test]
+    fn test_cli_parse_set() {
+        let cli = Cli::parse_from([
+            "cobbler",
+            "unattended-upgrades",
+            "set",
+            "--enable",
+            "--auto-reboot",
+            "true",
+            "--reboot-time",
+            "03:00",
+            "--origins-pattern",
+            "origin=Debian,label=Debian-Security",
+            "--tag",
+            "web",
+        ]);
+        if let Commands::UnattendedUpgrades {
+            action: UnattendedCommand::Set { changes, selection },
+        } = cli.command
+        {
+            assert!(changes.enable);
+            assert_eq!(changes.auto_reboot, Some(true));
+            assert_eq!(changes.reboot_time.as_deref(), Some("03:00"));
+            assert_eq!(changes.origins_pattern, vec!["origin=Debian,label=Debian-Security"]);
+            assert_eq!(selection.tag, vec!["web"]);
+        } else {
+            panic!("Wrong command");
+        }
+        assert!(Cli::try_parse_from(["cobbler", "unattended-upgrades", "set", "--enable", "--disable"]).is_err());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut settings = UnattendedUpgradesConfig {
+            enabled: true,
+            origins_pattern: vec!["origin=Debian,label=Debian-Security".to_string()],
+            automatic_reboot_time: Some("02:00".to_string()),
+            ..Default::default()
+        };
+        let changes = UnattendedChanges {
+            disable: true,
+            auto_reboot: Some(true),
+            ..Default::default()
+        };
+        assert!(!changes.is_empty());
+        changes.apply(&mut settings);
+
+        assert!(!settings.enabled);
+        assert!(settings.automatic_reboot);
+        assert_eq!(settings.origins_pattern.len(), 1);
+        assert_eq!(settings.automatic_reboot_time.as_deref(), Some("02:00"));
+        assert!(UnattendedChanges::default().is_empty());
+    }
+
+    #[test]
+    fn test_print_settings() {
+        let results = vec![
+            (
+                "web1:8080".to_string(),
+                Ok(UnattendedUpgradesConfig {
+                    enabled: true,
+                    allowed_origins: vec!["${distro_id}:${distro_codename}-security".to_string()],
+                    ..Default::default()
+                }),
+            ),
+            ("web2:8080".to_string(), Err("412 Precondition Failed".to_string())),
+        ];
+        let mut out = Vec::new();
+        print_settings(&mut out, &results).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[1].starts_with("web1:8080  yes"));
+        assert!(lines[1].ends_with("${distro_id}:${distro_codename}-security"));
+        assert!(lines[2].contains("Error: 412 Precondition Failed"));
+    }
+}
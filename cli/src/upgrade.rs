@@ -0,0 +1,412 @@
+use crate::progress::Progress;
+use crate::{api_client, client_node, confirm_targets, fetch_all, fetch_status, reboot, select_targets, ssh, ssh_node, wake, ApiClient, Config, ExitStatus, TargetSelection};
+use clap::ValueEnum;
+use cobbler_client::{ErrorCode, FullUpgradeRequest, Job, JobProgress, JobState, StatusResponse, UpgradeStrategy};
+use std::error::Error;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use tabwriter::TabWriter;
+
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a node coming back from a reboot, or a daemon restarting after upgrading
+/// itself, is asked again during the health check.
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// The `--strategy` values.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Upgrade,
+    FullUpgrade,
+    DistUpgrade,
+}
+
+impl From<Strategy> for UpgradeStrategy {
+    fn from(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Upgrade => UpgradeStrategy::Upgrade,
+            Strategy::FullUpgrade => UpgradeStrategy::FullUpgrade,
+            Strategy::DistUpgrade => UpgradeStrategy::DistUpgrade,
+        }
+    }
+}
+
+pub struct UpgradeOptions {
+    pub rolling: bool,
+    pub max_parallel: usize,
+    pub fail_fast: bool,
+    pub health_check: bool,
+    pub reboot: bool,
+    pub wait_timeout: Duration,
+    /// Wake nodes that are down via Wake-on-LAN first, waiting this long for them
+    pub wake: Option<Duration>,
+    /// Skip the confirmation prompt
+    pub yes: bool,
+    pub request: FullUpgradeRequest,
+}
+
+pub fn run_upgrade(
+    options: UpgradeOptions,
+    selection: TargetSelection,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+    if !confirm_targets("run a full upgrade", &targets, options.yes)? {
+        return Ok(ExitStatus::Error);
+    }
+
+    let client = api_client()?;
+    let batches = plan_batches(&targets, options.rolling, options.max_parallel);
+    let mut failed = Vec::new();
+    let progress = Progress::bar("upgrading", targets.len());
+
+    for (index, batch) in batches.iter().enumerate() {
+        progress.suspend(|| println!("Batch {}/{}: {}", index + 1, batches.len(), batch.join(", ")));
+
+        let results: Vec<(String, Result<String, String>)> = thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|target| {
+                    let client = &client;
+                    let options = &options;
+                    let progress = &progress;
+                    scope.spawn(move || {
+                        let result = upgrade_node(client, target, options, progress, config);
+                        progress.inc();
+                        result
+                    })
+                })
+                .collect();
+            batch
+                .iter()
+                .cloned()
+                .zip(handles)
+                .map(|(target, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|_| Err("upgrade thread panicked".to_string()));
+                    (target, result)
+                })
+                .collect()
+        });
+
+        progress.suspend(|| {
+            for (target, result) in &results {
+                match result {
+                    Ok(message) => println!("  {target}: succeeded ({message})"),
+                    Err(message) => println!("  {target}: failed ({message})"),
+                }
+            }
+        });
+        failed.extend(results.iter().filter(|(_, result)| result.is_err()).map(|(target, _)| target.clone()));
+
+        let remaining = batches.len() - index - 1;
+        if !failed.is_empty() && options.fail_fast && remaining > 0 {
+            progress.finish();
+            eprintln!(
+                "error: aborting after {} failed upgrade(s) ({}), {remaining} batch(es) skipped",
+                failed.len(),
+                failed.join(", ")
+            );
+            return Ok(ExitStatus::OperationFailed);
+        }
+    }
+    progress.finish();
+
+    if !failed.is_empty() {
+        eprintln!("error: {} of {} upgrade(s) failed: {}", failed.len(), targets.len(), failed.join(", "));
+        return Ok(ExitStatus::OperationFailed);
+    }
+
+    println!("All {} node(s) upgraded successfully.", targets.len());
+    Ok(ExitStatus::Ok)
+}
+
+/// Prints the pending updates of each node and what a full upgrade would keep back or
+/// remove there, without upgrading.
+pub fn run_dry_run(selection: TargetSelection, config: &Config) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let client = api_client()?;
+    let results = fetch_all("fetching status", &targets, |target| fetch_status(&client, target, config));
+
+    print_plan(io::stdout(), &results)?;
+    if results.iter().any(|(_, result)| result.is_err()) {
+        return Ok(ExitStatus::Unreachable);
+    }
+    Ok(ExitStatus::Ok)
+}
+
+fn print_plan<W: Write>(writer: W, results: &[(String, Result<StatusResponse, String>)]) -> io::Result<()> {
+    let list = |names: &[String]| if names.is_empty() { "-".to_string() } else { names.join(", ") };
+
+    let mut tw = TabWriter::new(writer).padding(2);
+    writeln!(tw, "TARGET\tUPDATES\tSECURITY\tKEPT_BACK\tREMOVALS")?;
+    for (target, result) in results {
+        match result {
+            Ok(status) => writeln!(
+                tw,
+                "{}\t{}\t{}\t{}\t{}",
+                target,
+                status.updates.len(),
+                status.security_updates,
+                list(&status.kept_back),
+                list(&status.removals)
+            )?,
+            Err(err) => writeln!(tw, "{target}\tError: {err}\t\t\t")?,
+        }
+    }
+    tw.flush()
+}
+
+fn plan_batches(targets: &[String], rolling: bool, max_parallel: usize) -> Vec<Vec<String>> {
+    let batch_size = if rolling { max_parallel.max(1) } else { targets.len().max(1) };
+    targets.chunks(batch_size).map(|chunk| chunk.to_vec()).collect()
+}
+
+fn upgrade_node(
+    client: &ApiClient,
+    target: &str,
+    options: &UpgradeOptions,
+    progress: &Progress,
+    config: &Config,
+) -> Result<String, String> {
+    if let Some(timeout) = options.wake {
+        wake::wake_node(client, target, timeout, config)?;
+    }
+    let mut message = match ssh_node(config, target) {
+        Some(node) => ssh::full_upgrade(node, &options.request)?,
+        None => {
+            let job_id = trigger_upgrade(client, target, options, progress, config)?;
+            let job = wait_for_job(client, target, &job_id, options.wait_timeout, progress, config)?;
+            if job.state != JobState::Succeeded {
+                return Err(format!("{} (see `cobbler logs {target} --job {}`)", job.message, job.id));
+            }
+            job.message
+        }
+    };
+    let mut rebooted = false;
+    if options.reboot {
+        let outcome = reboot::reboot_node(client, target, true, options.wait_timeout, config)?;
+        rebooted = outcome.is_some();
+        message = format!("{message}; {}", reboot::describe(outcome));
+    }
+
+    if options.health_check {
+        check_health(client, target, rebooted, options.wait_timeout, config)?;
+    }
+    Ok(message)
+}
+
+/// Triggers the full upgrade, waiting for the node to become idle if another package
+/// operation is running there.
+fn trigger_upgrade(
+    client: &ApiClient,
+    target: &str,
+    options: &UpgradeOptions,
+    progress: &Progress,
+    config: &Config,
+) -> Result<String, String> {
+    let deadline = Instant::now() + options.wait_timeout;
+    let node = client_node(target, config);
+
+    loop {
+        match client.full_upgrade(&node, &options.request) {
+            Err(err) if is_retryable(&err) => {
+                let delay = err.retry_after().unwrap_or(JOB_POLL_INTERVAL);
+                if Instant::now() + delay > deadline {
+                    return Err(err.to_string());
+                }
+                let reason = match &err {
+                    cobbler_client::Error::Status { error, .. } if error.code != ErrorCode::UpgradeInProgress => {
+                        &error.message
+                    }
+                    _ => "another package operation is running",
+                };
+                progress.println(&format!("{target}: {reason}, retrying in {}s", delay.as_secs()));
+                thread::sleep(delay);
+            }
+            result => return result.map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// A busy node is always worth waiting for; one whose power or connection guard applies
+/// only if it defers rather than refuses the upgrade.
+fn is_retryable(err: &cobbler_client::Error) -> bool {
+    match err.code() {
+        Some(ErrorCode::UpgradeInProgress | ErrorCode::LockHeld) => true,
+        Some(ErrorCode::OnBattery | ErrorCode::MeteredConnection) => err.retry_after().is_some(),
+        _ => false,
+    }
+}
+
+fn wait_for_job(
+    client: &ApiClient,
+    target: &str,
+    job_id: &str,
+    timeout: Duration,
+    progress: &Progress,
+    config: &Config,
+) -> Result<Job, String> {
+    let deadline = Instant::now() + timeout;
+    let node = client_node(target, config);
+    let mut queue_position = None;
+    let mut job_progress = None;
+
+    loop {
+        match client.job(&node, job_id) {
+            Ok(job) if job.state.is_finished() => return Ok(job),
+            Ok(job) => {
+                if let Some(position) = job.queue_position.filter(|&p| Some(p) != queue_position) {
+                    progress.println(&format!("{target}: queued behind {position} package operation(s)"));
+                }
+                queue_position = job.queue_position;
+                if let Some(current) = job.progress.as_ref().filter(|&p| Some(p) != job_progress.as_ref()) {
+                    progress.println(&format!("{target}: {}", format_progress(current)));
+                }
+                job_progress = job.progress;
+            }
+            Err(err) => progress.println(&format!("{target}: polling job {job_id} failed: {err}")),
+        }
+
+        if Instant::now() + JOB_POLL_INTERVAL > deadline {
+            return Err(format!("job {job_id} did not finish within {}", humantime::format_duration(timeout)));
+        }
+        thread::sleep(JOB_POLL_INTERVAL);
+    }
+}
+
+/// Renders e.g. `[#########           ]  45% unpacking vim`.
+fn format_progress(progress: &JobProgress) -> String {
+    let filled = (progress.percent / 100.0 * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let filled = filled.min(PROGRESS_BAR_WIDTH);
+    let mut line = format!(
+        "[{}{}] {:>3.0}% {}",
+        "#".repeat(filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - filled),
+        progress.percent,
+        progress.phase.as_str()
+    );
+    if let Some(package) = &progress.package {
+        line.push(' ');
+        line.push_str(package);
+    }
+    line
+}
+
+/// Waits up to `timeout` for the node to answer a status request, as it may still be
+/// booting or its daemon restarting, then verifies that the upgrade left it sound.
+fn check_health(client: &ApiClient, target: &str, rebooted: bool, timeout: Duration, config: &Config) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match fetch_status(client, target, config) {
+            Ok(status) => break status,
+            Err(err) if Instant::now() + HEALTH_POLL_INTERVAL > deadline => {
+                return Err(format!("health check failed: {err}"));
+            }
+            Err(_) => thread::sleep(HEALTH_POLL_INTERVAL),
+        }
+    };
+    verify_health(&status, rebooted).map_err(|reason| format!("health check failed: {reason}"))
+}
+
+fn verify_health(status: &StatusResponse, rebooted: bool) -> Result<(), String> {
+    if let Some(job) = &status.interrupted_upgrade {
+        return Err(format!("upgrade {job} was interrupted"));
+    }
+    // A reboot that leaves another pending didn't boot into what was installed.
+    if rebooted && status.reboot_required {
+        return Err("still requires a reboot after rebooting".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("10.0.0.{i}:8080")).collect()
+    }
+
+    #[test]
+    fn test_print_plan() {
+        let results = vec![
+            (
+                "web1:8080".to_string(),
+                Ok(StatusResponse {
+                    updates: vec!["linux-image-generic".to_string(), "vim".to_string()],
+                    kept_back: vec!["linux-image-generic".to_string()],
+                    removals: vec!["libfoo1".to_string(), "python3-legacy".to_string()],
+                    ..Default::default()
+                }),
+            ),
+            ("web2:8080".to_string(), Ok(StatusResponse::default())),
+        ];
+        let mut out = Vec::new();
+        print_plan(&mut out, &results).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[1].starts_with("web1:8080  2        0         linux-image-generic  libfoo1, python3-legacy"));
+        assert!(lines[2].ends_with("-                    -"));
+    }
+
+    #[test]
+    fn test_format_progress() {
+        let progress = JobProgress {
+            phase: cobbler_client::JobPhase::Unpacking,
+            percent: 45.0,
+            package: Some("vim".to_string()),
+        };
+        assert_eq!(format_progress(&progress), "[#########           ]  45% unpacking vim");
+
+        let progress = JobProgress {
+            phase: cobbler_client::JobPhase::Downloading,
+            percent: 100.0,
+            package: None,
+        };
+        assert_eq!(format_progress(&progress), "[####################] 100% downloading");
+    }
+
+    #[test]
+    fn test_plan_batches_rolling() {
+        let batches = plan_batches(&targets(5), true, 2);
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0], vec!["10.0.0.1:8080", "10.0.0.2:8080"]);
+        assert_eq!(batches[2], vec!["10.0.0.5:8080"]);
+
+        assert_eq!(plan_batches(&targets(3), true, 0).len(), 3);
+    }
+
+    #[test]
+    fn test_plan_batches_all_at_once() {
+        let batches = plan_batches(&targets(5), false, 2);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 5);
+    }
+
+    #[test]
+    fn test_verify_health() {
+        let mut status = StatusResponse {
+            reboot_required: true,
+            ..Default::default()
+        };
+        // A reboot pending without `--reboot` is for the operator to schedule.
+        assert!(verify_health(&status, false).is_ok());
+        assert!(verify_health(&status, true).unwrap_err().contains("still requires a reboot"));
+        status.reboot_required = false;
+        assert!(verify_health(&status, true).is_ok());
+        status.interrupted_upgrade = Some("j1".to_string());
+        assert_eq!(verify_health(&status, true).unwrap_err(), "upgrade j1 was interrupted");
+    }
+}
@@ -0,0 +1,376 @@
+use crate::config::{Config, NodeConfig, ScheduleConfig, Transport, TOKEN_PLACEHOLDER};
+use crate::{api_client, fetch_all, fetch_status, schedule, wake, ExitStatus};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use yaml_rust2::parser::Parser;
+use yaml_rust2::Event;
+
+/// A mistake in the configuration file, with the line it was found on if known.
+#[derive(Debug, PartialEq, Eq)]
+struct Problem {
+    line: Option<usize>,
+    message: String,
+}
+
+impl Problem {
+    /// Formats the problem like compiler diagnostics, e.g. `config.yaml:4: unknown field ...`.
+    fn describe(&self, path: &Path) -> String {
+        match self.line {
+            Some(line) => format!("{}:{line}: {}", path.display(), self.message),
+            None => format!("{}: {}", path.display(), self.message),
+        }
+    }
+}
+
+/// Checks the configuration file and prints every problem found. With `connect`, also
+/// checks that each node answers a status request.
+pub fn run_validate(config_path: &Path, connect: bool) -> Result<ExitStatus, Box<dyn Error>> {
+    let content =
+        fs::read_to_string(config_path).map_err(|err| format!("failed to read {}: {err}", config_path.display()))?;
+    let (config, mut problems) = validate(&content);
+    let mistakes = problems.len();
+
+    if let (Some(config), true) = (config, connect) {
+        let lines = key_lines(&content).unwrap_or_default();
+        let client = api_client()?;
+        for (prefix, fleet) in fleets(config) {
+            let targets: Vec<String> = fleet.nodes.iter().map(target).collect();
+            let results = fetch_all("connecting", &targets, |target| fetch_status(&client, target, &fleet));
+            for (index, (target, result)) in results.into_iter().enumerate() {
+                if let Err(err) = result {
+                    problems.push(Problem {
+                        line: lines.get(&format!("{prefix}nodes[{index}]")).copied(),
+                        message: format!("node {target} is unreachable: {err}"),
+                    });
+                }
+            }
+        }
+        problems.sort_by_key(|problem| problem.line);
+    }
+
+    for problem in &problems {
+        println!("{}", problem.describe(config_path));
+    }
+    if problems.is_empty() {
+        eprintln!("{} is valid.", config_path.display());
+        return Ok(ExitStatus::Ok);
+    }
+    eprintln!("{} problem(s) found.", problems.len());
+    Ok(if mistakes > 0 {
+        ExitStatus::Error
+    } else {
+        ExitStatus::Unreachable
+    })
+}
+
+/// Parses the configuration like `load_config` does, and lists malformed entries, unknown
+/// fields that loading would ignore, duplicate nodes and invalid values.
+fn validate(content: &str) -> (Option<Config>, Vec<Problem>) {
+    let lines = match key_lines(content) {
+        Ok(lines) => lines,
+        Err(problem) => return (None, vec![problem]),
+    };
+
+    let mut unknown = Vec::new();
+    let deserializer = serde_yaml::Deserializer::from_str(content);
+    let config: Config = match serde_ignored::deserialize(deserializer, |path| unknown.push(path_key(&path))) {
+        Ok(config) => config,
+        Err(err) => {
+            let location = err.location();
+            let mut message = err.to_string();
+            if let Some(location) = &location {
+                let suffix = format!(" at line {} column {}", location.line(), location.column());
+                message = message.trim_end_matches(&suffix).to_string();
+            }
+            let problem = Problem {
+                line: location.map(|location| location.line()),
+                message,
+            };
+            return (None, vec![problem]);
+        }
+    };
+
+    let line = |key: &str| lines.get(key).copied();
+    let mut problems: Vec<Problem> = unknown
+        .iter()
+        .map(|key| Problem {
+            line: line(key),
+            message: format!("unknown field `{key}`"),
+        })
+        .collect();
+    let mut problem = |key: String, message: String| {
+        problems.push(Problem {
+            line: line(&key),
+            message,
+        })
+    };
+
+    check_fleet("", &config.nodes, &config.schedules, &mut problem);
+    for (name, context) in &config.contexts {
+        let prefix = format!("contexts.{name}.");
+        check_fleet(&prefix, &context.nodes, &context.schedules, &mut problem);
+    }
+    if let Some(name) = config
+        .current_context
+        .as_ref()
+        .filter(|name| !config.contexts.contains_key(*name))
+    {
+        problem(
+            "current_context".to_string(),
+            format!("context {name:?} is not defined"),
+        );
+    }
+
+    problems.sort_by_key(|problem| problem.line);
+    (Some(config), problems)
+}
+
+/// Checks the nodes and schedules of the top level or of a context, whose keys start with
+/// `prefix`.
+fn check_fleet(
+    prefix: &str,
+    nodes: &[NodeConfig],
+    schedules: &[ScheduleConfig],
+    problem: &mut impl FnMut(String, String),
+) {
+    let mut names = HashMap::new();
+    let mut addresses = HashMap::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let key = |field: &str| format!("{prefix}nodes[{index}].{field}");
+        if node.address.trim().is_empty() {
+            problem(key("address"), "address is empty".to_string());
+        } else if let Some(first) = addresses.insert(node.address.as_str(), index) {
+            problem(
+                key("address"),
+                format!("address {:?} is already used by {prefix}nodes[{first}]", node.address),
+            );
+        }
+        if let Some(name) = &node.name {
+            if let Some(first) = names.insert(name.as_str(), index) {
+                problem(
+                    key("name"),
+                    format!("name {name:?} is already used by {prefix}nodes[{first}]"),
+                );
+            }
+        }
+        if node.api_key.as_deref() == Some(TOKEN_PLACEHOLDER) {
+            problem(
+                key("api_key"),
+                "api_key is still the placeholder from `discover`".to_string(),
+            );
+        }
+        if node.api_key.is_some() && node.api_key_keyring.is_some() {
+            problem(
+                key("api_key_keyring"),
+                "api_key_keyring is ignored because api_key is set".to_string(),
+            );
+        }
+        if node.transport == Transport::Ssh && node.address.contains(':') && !node.address.starts_with('[') {
+            problem(
+                key("address"),
+                "SSH nodes take a host; set `ssh.port` for a non-default port".to_string(),
+            );
+        }
+        if node.transport == Transport::Agent && node.ssh.is_some() {
+            problem(key("ssh"), "`ssh` is ignored unless `transport` is ssh".to_string());
+        }
+        if let Some(Err(err)) = node.mac.as_deref().map(wake::parse_mac) {
+            problem(key("mac"), err);
+        }
+    }
+
+    let mut names = HashMap::new();
+    for (index, entry) in schedules.iter().enumerate() {
+        if let Some(first) = names.insert(entry.name.as_str(), index) {
+            problem(
+                format!("{prefix}schedules[{index}].name"),
+                format!("name {:?} is already used by {prefix}schedules[{first}]", entry.name),
+            );
+        }
+        if let Err(err) = schedule::check(entry) {
+            problem(format!("{prefix}schedules[{index}]"), err);
+        }
+    }
+}
+
+/// The top-level fleet and that of each context, with the prefix of their keys.
+fn fleets(mut config: Config) -> Vec<(String, Config)> {
+    let contexts = std::mem::take(&mut config.contexts);
+    let mut fleets = vec![(String::new(), config)];
+    for (name, context) in contexts {
+        let fleet = Config {
+            nodes: context.nodes,
+            defaults: context.defaults,
+            ..Default::default()
+        };
+        fleets.push((format!("contexts.{name}."), fleet));
+    }
+    fleets
+}
+
+/// How `fetch_status` finds the node: by its name if it has one.
+fn target(node: &NodeConfig) -> String {
+    node.name.clone().unwrap_or_else(|| node.address.clone())
+}
+
+/// Formats a path like `nodes[1].ssh.user`, as serde_yaml does in its errors.
+fn path_key(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{index}]", path_key(parent)),
+        Path::Map { parent, key } => join_key(&path_key(parent), key),
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => path_key(parent),
+    }
+}
+
+fn join_key(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{parent}.{key}")
+    }
+}
+
+enum Frame {
+    /// The key whose value comes next, if a key was read.
+    Map {
+        path: String,
+        key: Option<String>,
+    },
+    Seq {
+        path: String,
+        index: usize,
+    },
+}
+
+impl Frame {
+    fn child_path(&self) -> String {
+        match self {
+            Frame::Map { path, key } => join_key(path, key.as_deref().unwrap_or_default()),
+            Frame::Seq { path, index } => format!("{path}[{index}]"),
+        }
+    }
+
+    /// Moves on after a value was read.
+    fn advance(&mut self) {
+        match self {
+            Frame::Map { key, .. } => *key = None,
+            Frame::Seq { index, .. } => *index += 1,
+        }
+    }
+}
+
+/// Maps the path of every key and sequence item to the line it starts on.
+fn key_lines(content: &str) -> Result<HashMap<String, usize>, Problem> {
+    let mut parser = Parser::new_from_str(content);
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut lines = HashMap::new();
+    loop {
+        let (event, marker) = parser.next_token().map_err(|err| Problem {
+            line: Some(err.marker().line()),
+            message: format!("invalid YAML: {}", err.info()),
+        })?;
+        let line = marker.line();
+        match event {
+            Event::StreamEnd => break,
+            Event::Scalar(value, ..) => match stack.last_mut() {
+                Some(Frame::Map { path, key }) if key.is_none() => {
+                    lines.entry(join_key(path, &value)).or_insert(line);
+                    *key = Some(value);
+                }
+                Some(frame) => {
+                    if let Frame::Seq { .. } = frame {
+                        lines.entry(frame.child_path()).or_insert(line);
+                    }
+                    frame.advance();
+                }
+                None => {}
+            },
+            Event::Alias(_) => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.advance();
+                }
+            }
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                let path = stack.last().map(Frame::child_path).unwrap_or_default();
+                if let Some(Frame::Seq { .. }) = stack.last() {
+                    lines.entry(path.clone()).or_insert(line);
+                }
+                stack.push(match event {
+                    Event::MappingStart(..) => Frame::Map { path, key: None },
+                    _ => Frame::Seq { path, index: 0 },
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                stack.pop();
+                if let Some(frame) = stack.last_mut() {
+                    frame.advance();
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &str) -> Vec<String> {
+        validate(content)
+            .1
+            .iter()
+            .map(|problem| problem.describe(Path::new("c.yaml")))
+            .collect()
+    }
+
+    #[test]
+    fn test_key_lines() {
+        let lines = key_lines("nodes:\n  - address: a:1\n    ssh:\n      user: x\n  - name: b\n").unwrap();
+        assert_eq!(lines["nodes"], 1);
+        assert_eq!(lines["nodes[0]"], 2);
+        assert_eq!(lines["nodes[0].ssh.user"], 4);
+        assert_eq!(lines["nodes[1].name"], 5);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(messages("nodes:\n  - address: 10.0.0.1:8080\n").is_empty());
+        assert_eq!(
+            messages(
+                "nodes:\n  - address: 10.0.0.1:8080\n    name: web\n    tgas: [prod]\n\
+                 \x20 - address: 10.0.0.1:8080\n    name: web\n    mac: dc:a6\n\
+                 schedules:\n  - name: nightly\n    every: 1d\n    at: \"03:00\"\n    command: status\n"
+            ),
+            vec![
+                "c.yaml:4: unknown field `nodes[0].tgas`",
+                "c.yaml:5: address \"10.0.0.1:8080\" is already used by nodes[0]",
+                "c.yaml:6: name \"web\" is already used by nodes[0]",
+                "c.yaml:7: invalid MAC address \"dc:a6\"",
+                "c.yaml:9: schedule \"nightly\": exactly one of `every` and `at` is required",
+            ]
+        );
+        assert_eq!(
+            messages(
+                "current_context: lab\ncontexts:\n  home:\n    nodes:\n      - address: 10.0.0.1:8080\n\
+                 \x20     - address: 10.0.0.1:8080\n"
+            ),
+            vec![
+                "c.yaml:1: context \"lab\" is not defined",
+                "c.yaml:6: address \"10.0.0.1:8080\" is already used by contexts.home.nodes[0]",
+            ]
+        );
+        assert_eq!(
+            messages("nodes:\n  - address: 10.0.0.1:8080\n    timeout: soon\n"),
+            vec!["c.yaml:3: nodes[0].timeout: invalid type: string \"soon\", expected u64"]
+        );
+        assert_eq!(
+            messages("nodes: [\n"),
+            vec!["c.yaml:2: invalid YAML: while parsing a node, did not find expected node content"]
+        );
+    }
+}
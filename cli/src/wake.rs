@@ -0,0 +1,139 @@
+use crate::{api_client, fetch_all, fetch_status, find_node, select_targets, ApiClient, Config, ExitStatus, TargetSelection};
+use std::error::Error;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The discard port, which Wake-on-LAN conventionally uses.
+const WOL_PORT: u16 = 9;
+const WAKE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sends Wake-on-LAN packets to the targets, and with `wait` waits for each of them to
+/// answer a status request.
+pub fn run_wake(
+    selection: TargetSelection,
+    broadcast: Ipv4Addr,
+    wait: Option<Duration>,
+    config: &Config,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let targets = select_targets(selection.targets, &selection.tag, config);
+    if targets.is_empty() {
+        println!("No targets found.");
+        return Ok(ExitStatus::Ok);
+    }
+
+    let mut woken = Vec::new();
+    let mut failed = 0;
+    for target in targets {
+        match send_wake(&target, broadcast, config) {
+            Ok(mac) => {
+                println!("{target}: magic packet sent to {mac}");
+                woken.push(target);
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{target}: failed ({err})");
+            }
+        }
+    }
+
+    if let Some(timeout) = wait {
+        let client = api_client()?;
+        for (target, result) in fetch_all("waiting for nodes", &woken, |target| wait_until_up(&client, target, timeout, config)) {
+            match result {
+                Ok(elapsed) => println!("{target}: up after {}s", elapsed.as_secs()),
+                Err(err) => {
+                    failed += 1;
+                    println!("{target}: failed ({err})");
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Ok(ExitStatus::OperationFailed);
+    }
+    Ok(ExitStatus::Ok)
+}
+
+/// Wakes a node that does not answer and waits for it to come up. Nodes that are already
+/// up are left alone.
+pub fn wake_node(client: &ApiClient, target: &str, timeout: Duration, config: &Config) -> Result<(), String> {
+    if fetch_status(client, target, config).is_ok() {
+        return Ok(());
+    }
+    send_wake(target, Ipv4Addr::BROADCAST, config)?;
+    let elapsed = wait_until_up(client, target, timeout, config)?;
+    eprintln!("{target}: woken up after {}s", elapsed.as_secs());
+    Ok(())
+}
+
+/// Sends the magic packet for the target's configured MAC address and returns that address.
+fn send_wake<'a>(target: &str, broadcast: Ipv4Addr, config: &'a Config) -> Result<&'a str, String> {
+    let mac = find_node(config, target)
+        .and_then(|node| node.mac.as_deref())
+        .ok_or("no MAC address configured")?;
+    let packet = magic_packet(parse_mac(mac)?);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|err| err.to_string())?;
+    socket.set_broadcast(true).map_err(|err| err.to_string())?;
+    socket
+        .send_to(&packet, (broadcast, WOL_PORT))
+        .map_err(|err| format!("failed to send magic packet: {err}"))?;
+    Ok(mac)
+}
+
+fn wait_until_up(client: &ApiClient, target: &str, timeout: Duration, config: &Config) -> Result<Duration, String> {
+    let started = Instant::now();
+    loop {
+        if fetch_status(client, target, config).is_ok() {
+            return Ok(started.elapsed());
+        }
+        if started.elapsed() >= timeout {
+            return Err(format!("node did not come up within {}", humantime::format_duration(timeout)));
+        }
+        thread::sleep(WAKE_POLL_INTERVAL);
+    }
+}
+
+/// Parses `aa:bb:cc:dd:ee:ff`, also with dashes as separators.
+pub fn parse_mac(mac: &str) -> Result<[u8; 6], String> {
+    let invalid = || format!("invalid MAC address {mac:?}");
+    let octets: Vec<u8> = mac
+        .split([':', '-'])
+        .map(|octet| {
+            if octet.len() != 2 || !octet.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            u8::from_str_radix(octet, 16).map_err(|_| invalid())
+        })
+        .collect::<Result<_, _>>()?;
+    octets.try_into().map_err(|_| invalid())
+}
+
+/// Six `0xff` bytes followed by the MAC address sixteen times.
+fn magic_packet(mac: [u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = parse_mac("DC:A6:32:01:02:0f").unwrap();
+        assert_eq!(mac, [0xdc, 0xa6, 0x32, 0x01, 0x02, 0x0f]);
+        assert_eq!(parse_mac("dc-a6-32-01-02-0f"), Ok(mac));
+        assert!(parse_mac("dc:a6:32:01:02").is_err());
+        assert!(parse_mac("dc:a6:32:01:02:0g").is_err());
+
+        let packet = magic_packet(mac);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(packet[..6], [0xff; 6]);
+        assert_eq!(packet[96..], mac);
+    }
+}
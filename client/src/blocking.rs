@@ -0,0 +1,219 @@
+//! Synchronous wrapper around [`crate::Client`] for callers without a tokio runtime.
+
+use crate::{
+    AddSourceRequest, AptSource, AptTransaction, CommandResponse, Error, FullUpgradeRequest, IdentifyResponse, Job, JobsQuery, JobsResponse, JsonResponse, LogQuery, LogResponse, Method, Node, PackageActionResponse,
+    PackageInfo, PackagePolicy, PackageState, PrefetchRequest, RepairResponse, ResourcesResponse, RestartServicesResponse, SensorsResponse, SnapshotResponse, ServiceAction, ServiceInfo, StatusResponse, UnattendedUpgradesConfig, UpgradeResponse,
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Runs the async client on a private runtime. It can be shared between threads, and
+/// calls from several threads proceed concurrently.
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    pub fn new(timeout: Duration) -> Result<Self, Error> {
+        Self::wrap(|| crate::Client::new(timeout))
+    }
+
+    /// See [`crate::Client::with_connect_timeout`].
+    pub fn with_connect_timeout(timeout: Duration, connect_timeout: Duration) -> Result<Self, Error> {
+        Self::wrap(|| crate::Client::with_connect_timeout(timeout, connect_timeout))
+    }
+
+    fn wrap(client: impl FnOnce() -> Result<crate::Client, Error>) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+        let inner = {
+            let _guard = runtime.enter();
+            client()?
+        };
+        Ok(Client {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn on_retry(mut self, hook: impl Fn(&Node, &Error, u32, u32) + Send + Sync + 'static) -> Self {
+        self.inner = self.inner.on_retry(hook);
+        self
+    }
+
+    pub fn send_json(
+        &self,
+        node: &Node,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<JsonResponse, Error> {
+        self.block_on(self.inner.send_json(node, method, path, body))
+    }
+
+    pub fn identify(&self, node: &Node) -> Result<IdentifyResponse, Error> {
+        self.block_on(self.inner.identify(node))
+    }
+
+    pub fn status(&self, node: &Node) -> Result<StatusResponse, Error> {
+        self.block_on(self.inner.status(node))
+    }
+
+    pub fn packages(&self, node: &Node, state: Option<PackageState>) -> Result<Vec<PackageInfo>, Error> {
+        self.block_on(self.inner.packages(node, state))
+    }
+
+    pub fn policies(&self, node: &Node, packages: &[String]) -> Result<Vec<PackagePolicy>, Error> {
+        self.block_on(self.inner.policies(node, packages))
+    }
+
+    pub fn transactions(&self, node: &Node, limit: Option<usize>) -> Result<Vec<AptTransaction>, Error> {
+        self.block_on(self.inner.transactions(node, limit))
+    }
+
+    pub fn full_upgrade(&self, node: &Node, options: &FullUpgradeRequest) -> Result<String, Error> {
+        self.block_on(self.inner.full_upgrade(node, options))
+    }
+
+    pub fn prefetch(&self, node: &Node, options: &PrefetchRequest) -> Result<UpgradeResponse, Error> {
+        self.block_on(self.inner.prefetch(node, options))
+    }
+
+    pub fn sources(&self, node: &Node) -> Result<Vec<AptSource>, Error> {
+        self.block_on(self.inner.sources(node))
+    }
+
+    pub fn add_source(&self, node: &Node, source: &AddSourceRequest) -> Result<PackageActionResponse, Error> {
+        self.block_on(self.inner.add_source(node, source))
+    }
+
+    pub fn remove_source(&self, node: &Node, name: &str) -> Result<PackageActionResponse, Error> {
+        self.block_on(self.inner.remove_source(node, name))
+    }
+
+    pub fn unattended_upgrades(&self, node: &Node) -> Result<UnattendedUpgradesConfig, Error> {
+        self.block_on(self.inner.unattended_upgrades(node))
+    }
+
+    pub fn set_unattended_upgrades(
+        &self,
+        node: &Node,
+        config: &UnattendedUpgradesConfig,
+    ) -> Result<UnattendedUpgradesConfig, Error> {
+        self.block_on(self.inner.set_unattended_upgrades(node, config))
+    }
+
+    pub fn repair(&self, node: &Node) -> Result<RepairResponse, Error> {
+        self.block_on(self.inner.repair(node))
+    }
+
+    pub fn services(&self, node: &Node) -> Result<Vec<ServiceInfo>, Error> {
+        self.block_on(self.inner.services(node))
+    }
+
+    pub fn service_action(&self, node: &Node, name: &str, action: ServiceAction) -> Result<ServiceInfo, Error> {
+        self.block_on(self.inner.service_action(node, name, action))
+    }
+
+    pub fn restart_outdated_services(&self, node: &Node) -> Result<RestartServicesResponse, Error> {
+        self.block_on(self.inner.restart_outdated_services(node))
+    }
+
+    pub fn jobs(&self, node: &Node, query: &JobsQuery) -> Result<JobsResponse, Error> {
+        self.block_on(self.inner.jobs(node, query))
+    }
+
+    pub fn job(&self, node: &Node, job_id: &str) -> Result<Job, Error> {
+        self.block_on(self.inner.job(node, job_id))
+    }
+
+    pub fn job_log(&self, node: &Node, job_id: &str, query: &LogQuery) -> Result<LogResponse, Error> {
+        self.block_on(self.inner.job_log(node, job_id, query))
+    }
+
+    pub fn system_log(&self, node: &Node, name: &str, query: &LogQuery) -> Result<LogResponse, Error> {
+        self.block_on(self.inner.system_log(node, name, query))
+    }
+
+    pub fn cancel_job(&self, node: &Node, job_id: &str) -> Result<String, Error> {
+        self.block_on(self.inner.cancel_job(node, job_id))
+    }
+
+    pub fn run_command(&self, node: &Node, name: &str) -> Result<CommandResponse, Error> {
+        self.block_on(self.inner.run_command(node, name))
+    }
+
+    pub fn reboot(&self, node: &Node) -> Result<(), Error> {
+        self.block_on(self.inner.reboot(node))
+    }
+
+    pub fn sensors(&self, node: &Node) -> Result<SensorsResponse, Error> {
+        self.block_on(self.inner.sensors(node))
+    }
+
+    pub fn resources(&self, node: &Node) -> Result<ResourcesResponse, Error> {
+        self.block_on(self.inner.resources(node))
+    }
+
+    pub fn snapshot(&self, node: &Node) -> Result<SnapshotResponse, Error> {
+        self.block_on(self.inner.snapshot(node))
+    }
+
+    pub fn shutdown(&self, node: &Node, delay_minutes: u32) -> Result<String, Error> {
+        self.block_on(self.inner.shutdown(node, delay_minutes))
+    }
+
+    pub fn cancel_shutdown(&self, node: &Node) -> Result<String, Error> {
+        self.block_on(self.inner.cancel_shutdown(node))
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_status_from_threads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let node = Node::new(listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                let body = r#"{"message":"System is up to date","updates":[],"reboot_required":true}"#;
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let client = Client::new(Duration::from_secs(5)).unwrap();
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..2).map(|_| scope.spawn(|| client.status(&node))).collect();
+            for handle in handles {
+                let status = handle.join().unwrap().unwrap();
+                assert!(status.reboot_required);
+                assert_eq!(status.message, "System is up to date");
+            }
+        });
+        server.join().unwrap();
+    }
+}
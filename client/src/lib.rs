@@ -0,0 +1,730 @@
+//! HTTP client for the cobbler daemon (`cobblerd`) API.
+//!
+//! [`Client`] is async and runs on the caller's tokio runtime. With the `blocking` feature,
+//! [`blocking::Client`] offers the same calls for synchronous code.
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+pub use cobbler_api as api;
+pub use cobbler_api::{
+    AddSourceRequest, AptSource, AptTransaction, CommandResponse, ConffilePolicy, ErrorCode, ErrorResponse, FullUpgradeRequest, IdentifyResponse, Job, JobKind, JobPhase,
+    JobProgress, JobState, JobsQuery, JobsResponse, LogQuery, LogResponse, PackageActionResponse, PackageChange, PackageInfo, PackagePolicy, PackageState, PhasedUpdates,
+    RepairResponse, ResourcesResponse, RestartServicesResponse, SensorsResponse, ServiceAction, ServiceFailure, ServiceInfo, StatusResponse, UnattendedUpgradesConfig,
+    PrefetchRequest, UpgradeResponse, UpgradeStrategy, SnapshotResponse, JobsSummary,
+};
+pub use reqwest::{Method, StatusCode};
+
+use cobbler_api::{
+    MessageResponse, PackagesQuery, PackagesResponse, PoliciesResponse, PolicyQuery, ServicesResponse, ShutdownRequest, SourcesResponse, TransactionsQuery, TransactionsResponse,
+    API_KEY_HEADER,
+};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Base delay between retries; the n-th retry waits n times as long.
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A daemon to talk to and how to reach it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Node {
+    /// Name used in retry notices; defaults to the address.
+    pub name: Option<String>,
+    /// `host:port`, `[v6]:port` or a full `http(s)://` URL.
+    pub address: String,
+    pub api_key: Option<String>,
+    /// Overrides the client's default request timeout.
+    pub timeout: Option<Duration>,
+    /// How often a request is retried after a connection error or, if it is safe to repeat,
+    /// a timeout. See [`Client::send`].
+    pub retries: u32,
+    /// Speak HTTP/2 from the first byte. Over plain HTTP (h2c) only daemons that support it
+    /// answer; over TLS, HTTP/2 is negotiated anyway when the other end offers it.
+    pub http2: bool,
+}
+
+impl Node {
+    pub fn new(address: impl Into<String>) -> Self {
+        Node {
+            address: address.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.address)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or its response could not be read.
+    Http(reqwest::Error),
+    /// The daemon answered with a non-success status. `error.code` is `Unknown` if the
+    /// body was not an error envelope.
+    Status { status: StatusCode, error: ErrorResponse },
+    /// The response body did not have the expected shape.
+    Decode { what: &'static str, source: reqwest::Error },
+    /// The runtime backing the blocking client could not be started.
+    Runtime(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(err) => write!(f, "{err}"),
+            Error::Status { status, error } if error.message.is_empty() => write!(f, "{status}"),
+            Error::Status { status, error } => write!(f, "{status} {}", error.message),
+            Error::Decode { what, source } => write!(f, "unexpected {what} response: {source}"),
+            Error::Runtime(err) => write!(f, "failed to start runtime: {err}"),
+        }
+    }
+}
+
+impl Error {
+    /// The daemon's error code, to branch on instead of matching the message.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Status { error, .. } => Some(error.code),
+            _ => None,
+        }
+    }
+
+    /// How long the daemon suggests waiting before retrying.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Status { error, .. } => error.retry_after.map(Duration::from_secs),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(err) | Error::Decode { source: err, .. } => Some(err),
+            Error::Runtime(err) => Some(err),
+            Error::Status { .. } => None,
+        }
+    }
+}
+
+/// Status and (if it was JSON) body of a response, whether it succeeded or not.
+#[derive(Debug, Clone)]
+pub struct JsonResponse {
+    pub status: StatusCode,
+    pub body: Option<serde_json::Value>,
+}
+
+impl JsonResponse {
+    /// The `message` field that every daemon response carries, or an empty string.
+    pub fn message(&self) -> &str {
+        self.body
+            .as_ref()
+            .and_then(|body| body["message"].as_str())
+            .unwrap_or_default()
+    }
+
+    /// The error envelope of a non-success response.
+    pub fn error(&self) -> Option<ErrorResponse> {
+        if self.status.is_success() {
+            return None;
+        }
+        self.body.clone().and_then(|body| serde_json::from_value(body).ok())
+    }
+}
+
+/// Called before each retry with the node, the error, the attempt number and the retry limit.
+pub type RetryHook = Arc<dyn Fn(&Node, &Error, u32, u32) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// For nodes with `http2` set.
+    http2: reqwest::Client,
+    on_retry: Option<RetryHook>,
+}
+
+impl Client {
+    /// Creates a client whose requests time out after `timeout` unless the node overrides it.
+    pub fn new(timeout: Duration) -> Result<Self, Error> {
+        Self::build(|| reqwest::Client::builder().timeout(timeout))
+    }
+
+    /// Like [`Client::new`], but also gives up connecting after `connect_timeout`, for
+    /// probing addresses that may have nothing listening.
+    pub fn with_connect_timeout(timeout: Duration, connect_timeout: Duration) -> Result<Self, Error> {
+        Self::build(|| reqwest::Client::builder().timeout(timeout).connect_timeout(connect_timeout))
+    }
+
+    fn build(builder: impl Fn() -> reqwest::ClientBuilder) -> Result<Self, Error> {
+        let http = builder().build().map_err(Error::Http)?;
+        let http2 = builder().http2_prior_knowledge().build().map_err(Error::Http)?;
+        Ok(Client { http, http2, on_retry: None })
+    }
+
+    /// Sends all requests with `http`, also to nodes with `http2` set.
+    pub fn with_http_client(http: reqwest::Client) -> Self {
+        Client {
+            http2: http.clone(),
+            http,
+            on_retry: None,
+        }
+    }
+
+    pub fn on_retry(mut self, hook: impl Fn(&Node, &Error, u32, u32) + Send + Sync + 'static) -> Self {
+        self.on_retry = Some(Arc::new(hook));
+        self
+    }
+
+    /// Builds a request for `path` on the node, with its API key and timeout applied.
+    pub fn request(&self, node: &Node, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}{}", resolve_url(&node.address), path);
+        debug!("{}: {method} {url}", node.label());
+        let http = if node.http2 { &self.http2 } else { &self.http };
+        let mut request = http.request(method, url);
+        if let Some(api_key) = &node.api_key {
+            request = request.header(API_KEY_HEADER, api_key);
+        }
+        if let Some(timeout) = node.timeout {
+            request = request.timeout(timeout);
+        }
+        request
+    }
+
+    /// Sends the request, retrying it up to `node.retries` times where that can't make the
+    /// daemon act twice: after connection errors, when nothing was sent, and after `429` and
+    /// `503` answers with a `Retry-After`, a busy or restarting daemon turning it away.
+    /// Timeouts are only retried for `GET` and `HEAD` requests, since the daemon may still be
+    /// carrying out anything else; see [`Client::send_repeatable`].
+    pub async fn send(&self, node: &Node, request: RequestBuilder) -> Result<Response, Error> {
+        self.send_retrying(node, request, false).await
+    }
+
+    /// Like [`Client::send`], but also retries timeouts of a request that the caller knows
+    /// is safe to repeat, such as one that only cancels or replaces something.
+    pub async fn send_repeatable(&self, node: &Node, request: RequestBuilder) -> Result<Response, Error> {
+        self.send_retrying(node, request, true).await
+    }
+
+    async fn send_retrying(&self, node: &Node, request: RequestBuilder, repeatable: bool) -> Result<Response, Error> {
+        let (http, request) = request.build_split();
+        let request = request.map_err(Error::Http)?;
+        let repeatable = repeatable || request.method().is_safe();
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let Some(retry) = request.try_clone().filter(|_| attempt < node.retries) else {
+                let result = http.execute(request).await;
+                log_result(node, started, &result);
+                return result.map_err(Error::Http);
+            };
+            let result = http.execute(retry).await;
+            log_result(node, started, &result);
+            match result {
+                Err(err) if err.is_connect() || (repeatable && err.is_timeout()) => {
+                    attempt += 1;
+                    if let Some(hook) = &self.on_retry {
+                        hook(node, &Error::Http(err), attempt, node.retries);
+                    }
+                    tokio::time::sleep(RETRY_DELAY * attempt).await;
+                }
+                Ok(resp) if is_retryable(&resp) => {
+                    attempt += 1;
+                    let err = status_error(resp).await;
+                    if let Some(hook) = &self.on_retry {
+                        hook(node, &err, attempt, node.retries);
+                    }
+                    tokio::time::sleep(err.retry_after().unwrap_or(RETRY_DELAY * attempt)).await;
+                }
+                result => return result.map_err(Error::Http),
+            }
+        }
+    }
+
+    /// Sends a request with an optional JSON body and returns the response without
+    /// treating error statuses as failures.
+    pub async fn send_json(
+        &self,
+        node: &Node,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<JsonResponse, Error> {
+        let mut request = self.request(node, method, path);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let resp = self.send(node, request).await?;
+        Ok(JsonResponse {
+            status: resp.status(),
+            body: resp.json().await.ok(),
+        })
+    }
+
+    /// Which daemon answers at the node; see [`cobbler_api::SERVICE_NAME`].
+    pub async fn identify(&self, node: &Node) -> Result<IdentifyResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/identify")).await?;
+        decode(resp, "identity").await
+    }
+
+    pub async fn status(&self, node: &Node) -> Result<StatusResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/status")).await?;
+        decode(resp, "status").await
+    }
+
+    pub async fn packages(&self, node: &Node, state: Option<PackageState>) -> Result<Vec<PackageInfo>, Error> {
+        let request = self.request(node, Method::GET, "/packages").query(&PackagesQuery { state, ..Default::default() });
+        let resp = self.checked(node, request).await?;
+        decode::<PackagesResponse>(resp, "packages").await.map(|response| response.packages)
+    }
+
+    /// Pin priorities of `packages`, or of all upgradable packages if empty.
+    pub async fn policies(&self, node: &Node, packages: &[String]) -> Result<Vec<PackagePolicy>, Error> {
+        let query = PolicyQuery {
+            packages: (!packages.is_empty()).then(|| packages.join(",")),
+        };
+        let request = self.request(node, Method::GET, "/packages/policy").query(&query);
+        let resp = self.checked(node, request).await?;
+        decode::<PoliciesResponse>(resp, "policies").await.map(|response| response.policies)
+    }
+
+    /// The `limit` most recent apt transactions (the node's default if `None`), newest first.
+    pub async fn transactions(&self, node: &Node, limit: Option<usize>) -> Result<Vec<AptTransaction>, Error> {
+        let request = self.request(node, Method::GET, "/packages/transactions").query(&TransactionsQuery { limit });
+        let resp = self.checked(node, request).await?;
+        decode::<TransactionsResponse>(resp, "transactions").await.map(|response| response.transactions)
+    }
+
+    /// Triggers a full upgrade and returns its job ID.
+    pub async fn full_upgrade(&self, node: &Node, options: &FullUpgradeRequest) -> Result<String, Error> {
+        let request = self.request(node, Method::POST, "/packages/full-upgrade").json(options);
+        let resp = self.checked(node, request).await?;
+        decode::<UpgradeResponse>(resp, "upgrade").await.map(|response| response.job_id)
+    }
+
+    /// Starts downloading the pending packages into the node's apt cache.
+    pub async fn prefetch(&self, node: &Node, options: &PrefetchRequest) -> Result<UpgradeResponse, Error> {
+        let request = self.request(node, Method::POST, "/packages/prefetch").json(options);
+        let resp = self.checked(node, request).await?;
+        decode(resp, "prefetch").await
+    }
+
+    pub async fn sources(&self, node: &Node) -> Result<Vec<AptSource>, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/sources")).await?;
+        decode::<SourcesResponse>(resp, "sources").await.map(|response| response.sources)
+    }
+
+    /// Adds an apt repository; the node updates its package lists before answering.
+    pub async fn add_source(&self, node: &Node, source: &AddSourceRequest) -> Result<PackageActionResponse, Error> {
+        let request = self.request(node, Method::POST, "/sources").json(source);
+        let resp = self.checked(node, request).await?;
+        decode(resp, "source").await
+    }
+
+    /// Removes the `sources.list.d` file `name`; the node updates its package lists before answering.
+    pub async fn remove_source(&self, node: &Node, name: &str) -> Result<PackageActionResponse, Error> {
+        let request = self.request(node, Method::DELETE, &format!("/sources/{name}"));
+        let resp = self.checked(node, request).await?;
+        decode(resp, "source").await
+    }
+
+    pub async fn unattended_upgrades(&self, node: &Node) -> Result<UnattendedUpgradesConfig, Error> {
+        let request = self.request(node, Method::GET, "/config/unattended-upgrades");
+        let resp = self.checked(node, request).await?;
+        decode(resp, "unattended-upgrades").await
+    }
+
+    /// Replaces the unattended-upgrades settings and returns them as the node now applies them.
+    pub async fn set_unattended_upgrades(
+        &self,
+        node: &Node,
+        config: &UnattendedUpgradesConfig,
+    ) -> Result<UnattendedUpgradesConfig, Error> {
+        let request = self.request(node, Method::PUT, "/config/unattended-upgrades").json(config);
+        let resp = self.checked_repeatable(node, request).await?;
+        decode(resp, "unattended-upgrades").await
+    }
+
+    /// Finishes interrupted package operations (`dpkg --configure -a`, `apt-get install -f`).
+    pub async fn repair(&self, node: &Node) -> Result<RepairResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::POST, "/packages/repair")).await?;
+        decode(resp, "repair").await
+    }
+
+    /// The system services of the node, sorted by name.
+    pub async fn services(&self, node: &Node) -> Result<Vec<ServiceInfo>, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/services")).await?;
+        decode::<ServicesResponse>(resp, "services").await.map(|response| response.services)
+    }
+
+    /// Starts, stops or restarts a service the node allowlists, and returns its state afterwards.
+    pub async fn service_action(&self, node: &Node, name: &str, action: ServiceAction) -> Result<ServiceInfo, Error> {
+        let request = self.request(node, Method::POST, &format!("/services/{name}/{}", action.as_str()));
+        let resp = self.checked(node, request).await?;
+        decode(resp, "service").await
+    }
+
+    /// Restarts the services still running executables or libraries that an upgrade replaced.
+    pub async fn restart_outdated_services(&self, node: &Node) -> Result<RestartServicesResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::POST, "/services/restart-outdated")).await?;
+        decode(resp, "service restart").await
+    }
+
+    /// The node's retained jobs matching `query`, newest first, and how many match in total.
+    pub async fn jobs(&self, node: &Node, query: &JobsQuery) -> Result<JobsResponse, Error> {
+        let request = self.request(node, Method::GET, "/jobs").query(query);
+        let resp = self.checked(node, request).await?;
+        decode(resp, "jobs").await
+    }
+
+    pub async fn job(&self, node: &Node, job_id: &str) -> Result<Job, Error> {
+        let request = self.request(node, Method::GET, &format!("/jobs/{job_id}"));
+        let resp = self.checked(node, request).await?;
+        decode(resp, "job").await
+    }
+
+    /// Output of a job's process, or with `query.since` only the lines after an earlier response.
+    pub async fn job_log(&self, node: &Node, job_id: &str, query: &LogQuery) -> Result<LogResponse, Error> {
+        let request = self.request(node, Method::GET, &format!("/jobs/{job_id}/log")).query(query);
+        let resp = self.checked(node, request).await?;
+        decode(resp, "job log").await
+    }
+
+    /// Lines of the node's `apt` or `dpkg` log, the end of it unless `query.since` is set.
+    pub async fn system_log(&self, node: &Node, name: &str, query: &LogQuery) -> Result<LogResponse, Error> {
+        let request = self.request(node, Method::GET, &format!("/logs/{name}")).query(query);
+        let resp = self.checked(node, request).await?;
+        decode(resp, "log").await
+    }
+
+    /// Cancels a queued or running job. A running job is stopped asynchronously; poll it
+    /// with [`Client::job`] to see when it has ended.
+    pub async fn cancel_job(&self, node: &Node, job_id: &str) -> Result<String, Error> {
+        let request = self.request(node, Method::POST, &format!("/jobs/{job_id}/cancel"));
+        let resp = self.checked_repeatable(node, request).await?;
+        decode::<MessageResponse>(resp, "cancel").await.map(|response| response.message)
+    }
+
+    /// Runs the command the node allowlists under `name` and waits for it to finish.
+    pub async fn run_command(&self, node: &Node, name: &str) -> Result<CommandResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::POST, &format!("/commands/{name}"))).await?;
+        decode(resp, "command").await
+    }
+
+    /// Schedules an immediate reboot.
+    pub async fn reboot(&self, node: &Node) -> Result<(), Error> {
+        self.checked(node, self.request(node, Method::POST, "/system/reboot")).await?;
+        Ok(())
+    }
+
+    /// Temperatures, fans and throttling of the node, as far as its hardware exposes them.
+    pub async fn sensors(&self, node: &Node) -> Result<SensorsResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/system/sensors")).await?;
+        decode(resp, "sensors").await
+    }
+
+    /// Load, memory, swap and disk usage of the node.
+    pub async fn resources(&self, node: &Node) -> Result<ResourcesResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/system/resources")).await?;
+        decode(resp, "resources").await
+    }
+
+    /// Status, build, resource usage and jobs of the node in one request. Daemons older than
+    /// `GET /snapshot` answer [`Error::Status`] with 404.
+    pub async fn snapshot(&self, node: &Node) -> Result<SnapshotResponse, Error> {
+        let resp = self.checked(node, self.request(node, Method::GET, "/snapshot")).await?;
+        decode(resp, "snapshot").await
+    }
+
+    /// Schedules the node to power off after `delay_minutes`, and returns the node's confirmation.
+    pub async fn shutdown(&self, node: &Node, delay_minutes: u32) -> Result<String, Error> {
+        let request = self
+            .request(node, Method::POST, "/system/shutdown")
+            .json(&ShutdownRequest { delay_minutes });
+        let resp = self.checked(node, request).await?;
+        decode::<MessageResponse>(resp, "shutdown").await.map(|response| response.message)
+    }
+
+    /// Cancels a scheduled shutdown, or a scheduled reboot, as both are one `shutdown -c`.
+    pub async fn cancel_shutdown(&self, node: &Node) -> Result<String, Error> {
+        let resp = self.checked_repeatable(node, self.request(node, Method::DELETE, "/system/shutdown")).await?;
+        decode::<MessageResponse>(resp, "shutdown").await.map(|response| response.message)
+    }
+
+    async fn checked(&self, node: &Node, request: RequestBuilder) -> Result<Response, Error> {
+        success(self.send(node, request).await?).await
+    }
+
+    async fn checked_repeatable(&self, node: &Node, request: RequestBuilder) -> Result<Response, Error> {
+        success(self.send_repeatable(node, request).await?).await
+    }
+}
+
+async fn success(resp: Response) -> Result<Response, Error> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+    Err(status_error(resp).await)
+}
+
+fn is_retryable(resp: &Response) -> bool {
+    matches!(resp.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE)
+        && resp.headers().contains_key(RETRY_AFTER)
+}
+
+async fn status_error(resp: Response) -> Error {
+    let status = resp.status();
+    let error = resp
+        .json::<ErrorResponse>()
+        .await
+        .unwrap_or_else(|_| ErrorResponse::new(ErrorCode::Unknown, ""));
+    Error::Status { status, error }
+}
+
+fn log_result(node: &Node, started: Instant, result: &reqwest::Result<Response>) {
+    match result {
+        Ok(resp) => debug!("{}: {} from {} after {:?}", node.label(), resp.status(), resp.url(), started.elapsed()),
+        Err(err) => info!("{}: {}", node.label(), error_chain(err)),
+    }
+}
+
+/// The error followed by those of its sources that its message doesn't already include,
+/// which reqwest's messages don't always.
+pub fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        let text = err.to_string();
+        if !message.contains(&text) {
+            message.push_str(": ");
+            message.push_str(&text);
+        }
+        source = err.source();
+    }
+    message
+}
+
+async fn decode<T: serde::de::DeserializeOwned>(resp: Response, what: &'static str) -> Result<T, Error> {
+    resp.json().await.map_err(|source| Error::Decode { what, source })
+}
+
+/// Turns a node address into a base URL, bracketing bare IPv6 hosts.
+pub fn resolve_url(target: &str) -> String {
+    if target.starts_with("http://") || target.starts_with("https://") {
+        target.trim_end_matches('/').to_string()
+    } else if target.contains(':') && target.split(':').next_back().unwrap().chars().all(|c| c.is_ascii_digit()) {
+        let parts: Vec<&str> = target.split(':').collect();
+        let host = parts[..parts.len() - 1].join(":");
+        let port = parts.last().unwrap();
+
+        if host.contains(':') && !host.starts_with('[') {
+            format!("http://[{}]:{}", host, port)
+        } else {
+            format!("http://{}:{}", host, port)
+        }
+    } else {
+        format!("http://{}", target.trim_end_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_resolve_url() {
+        assert_eq!(resolve_url("10.0.0.1:8080"), "http://10.0.0.1:8080");
+        assert_eq!(resolve_url("fd00::1:8080"), "http://[fd00::1]:8080");
+        assert_eq!(resolve_url("[fd00::1]:8080"), "http://[fd00::1]:8080");
+        assert_eq!(resolve_url("https://raspi1.lan/"), "https://raspi1.lan");
+        assert_eq!(resolve_url("raspi1.lan"), "http://raspi1.lan");
+    }
+
+    #[test]
+    fn test_request_applies_node_settings() {
+        let client = Client::new(Duration::from_secs(5)).unwrap();
+        let node = Node {
+            address: "10.8.0.2:8080".to_string(),
+            api_key: Some("secret".to_string()),
+            timeout: Some(Duration::from_secs(120)),
+            ..Default::default()
+        };
+        let request = client.request(&node, Method::GET, "/status").build().unwrap();
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(120)));
+        assert_eq!(request.url().as_str(), "http://10.8.0.2:8080/status");
+        assert_eq!(request.headers()[API_KEY_HEADER], "secret");
+
+        let request = client.request(&Node::new("10.0.0.1:8080"), Method::GET, "/status").build().unwrap();
+        assert_eq!(request.timeout(), None);
+        assert!(request.headers().get(API_KEY_HEADER).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let node = Node {
+            address: listener.local_addr().unwrap().to_string(),
+            http2: true,
+            ..Default::default()
+        };
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut preface = [0; 24];
+            stream.read_exact(&mut preface).unwrap();
+            preface
+        });
+
+        let client = Client::new(Duration::from_secs(5)).unwrap();
+        assert!(client.status(&node).await.is_err());
+        assert_eq!(&server.join().unwrap(), b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+    }
+
+    #[test]
+    fn test_packages_query() {
+        let client = Client::new(Duration::from_secs(5)).unwrap();
+        let node = Node::new("10.0.0.1:8080");
+        let request = client
+            .request(&node, Method::GET, "/packages")
+            .query(&PackagesQuery {
+                state: Some(PackageState::Upgradable),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(request.url().as_str(), "http://10.0.0.1:8080/packages?state=upgradable");
+        let request = client
+            .request(&node, Method::GET, "/packages")
+            .query(&PackagesQuery::default())
+            .build()
+            .unwrap();
+        assert_eq!(request.url().as_str(), "http://10.0.0.1:8080/packages");
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = Error::Status {
+            status: StatusCode::PRECONDITION_FAILED,
+            error: ErrorResponse {
+                retry_after: Some(30),
+                ..ErrorResponse::new(ErrorCode::UpgradeInProgress, "a full upgrade is currently running")
+            },
+        };
+        assert_eq!(error.to_string(), "412 Precondition Failed a full upgrade is currently running");
+        assert_eq!(error.code(), Some(ErrorCode::UpgradeInProgress));
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(30)));
+        let error = Error::Status {
+            status: StatusCode::BAD_GATEWAY,
+            error: ErrorResponse::new(ErrorCode::Unknown, ""),
+        };
+        assert_eq!(error.to_string(), "502 Bad Gateway");
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_connection_errors() {
+        // Bind and drop a listener to get a local port that refuses connections.
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let client = Client::new(Duration::from_secs(5)).unwrap().on_retry(move |node, _, attempt, retries| {
+            assert_eq!(node.label(), "offsite");
+            assert_eq!(retries, 1);
+            counter.store(attempt, Ordering::SeqCst);
+        });
+        let node = Node {
+            name: Some("offsite".to_string()),
+            address: address.to_string(),
+            retries: 1,
+            ..Default::default()
+        };
+
+        let Err(Error::Http(err)) = client.status(&node).await else {
+            panic!("expected a connection error");
+        };
+        assert!(err.is_connect());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(error_chain(&err).starts_with(&err.to_string()));
+        assert!(error_chain(&err).contains("Connection refused"));
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_timeouts_only_when_safe() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let node = Node {
+            address: listener.local_addr().unwrap().to_string(),
+            retries: 1,
+            ..Default::default()
+        };
+        // Reads every request and never answers it.
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                std::thread::spawn(move || while stream.read(&mut [0; 1024]).is_ok_and(|read| read > 0) {});
+            }
+        });
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let client = Client::new(Duration::from_millis(200)).unwrap().on_retry(move |_, _, _, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let Err(Error::Http(err)) = client.full_upgrade(&node, &FullUpgradeRequest::default()).await else {
+            panic!("expected a timeout");
+        };
+        assert!(err.is_timeout());
+        assert_eq!(attempts.load(Ordering::SeqCst), 0);
+        assert!(client.cancel_job(&node, "5f0c6a8e").await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(client.status(&node).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_draining_daemon() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let node = Node {
+            address: listener.local_addr().unwrap().to_string(),
+            retries: 1,
+            ..Default::default()
+        };
+        let server = std::thread::spawn(move || {
+            let draining = r#"{"code":"SHUTTING_DOWN","message":"the daemon is shutting down","retry_after":0}"#;
+            let responses = [
+                format!("HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{draining}", draining.len()),
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}".to_string(),
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let retried = Arc::new(AtomicU32::new(0));
+        let counter = retried.clone();
+        let client = Client::new(Duration::from_secs(5)).unwrap().on_retry(move |_, err, attempt, _| {
+            assert_eq!(err.code(), Some(ErrorCode::ShuttingDown));
+            counter.store(attempt, Ordering::SeqCst);
+        });
+
+        let response = client.send_json(&node, Method::GET, "/version", None).await.unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(retried.load(Ordering::SeqCst), 1);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Embeds the git commit and build time for `GET /version`. Both can be overridden with
+/// `COBBLER_GIT_COMMIT` and `SOURCE_DATE_EPOCH` for builds outside a git checkout or
+/// reproducible builds.
+fn main() {
+    let commit = env::var("COBBLER_GIT_COMMIT").ok().or_else(git_commit);
+    println!(
+        "cargo:rustc-env=COBBLER_GIT_COMMIT={}",
+        commit.as_deref().unwrap_or("unknown")
+    );
+
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+    println!("cargo:rustc-env=COBBLER_BUILD_TIMESTAMP={build_timestamp}");
+
+    println!("cargo:rerun-if-env-changed=COBBLER_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|commit| !commit.is_empty())
+}
@@ -0,0 +1,706 @@
+//! Security advisories fixed by pending updates, looked up in an OSV database (osv.dev or a
+//! mirror of it). OSV carries the Debian (DSA/DLA) and Ubuntu (USN) advisories along with the
+//! versions that fix them, their CVEs and, for most, a CVSS vector or the distribution's own
+//! rating. Advisories name source packages and source versions, so updates are matched by
+//! those: an update fixes a vulnerability if the fix lies between the installed version
+//! (exclusive) and the candidate (inclusive).
+
+use cobbler_api::{Severity, UpdateDetail};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a background lookup may take. What was fetched until then stays cached.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(20);
+/// How long the vulnerabilities found for a package version are reused.
+const QUERY_TTL: Duration = Duration::from_secs(60 * 60);
+const ADVISORY_PREFIXES: [&str; 5] = ["DSA-", "DLA-", "DTSA-", "USN-", "LSN-"];
+
+/// A pending update in the terms of advisories.
+pub struct SourceUpdate {
+    /// The binary package, as in [`UpdateDetail::name`]
+    pub name: String,
+    pub source: String,
+    /// Source version of the installed package
+    pub installed: String,
+    /// Source version of the candidate
+    pub candidate: String,
+}
+
+/// The OSV ecosystem of the running release, e.g. `Debian:12` or `Ubuntu:24.04:LTS`, from
+/// `/etc/os-release`. `None` for other distributions and for Debian testing, which has no
+/// version.
+pub fn ecosystem(os_release: &str) -> Option<String> {
+    let value = |key: &str| {
+        os_release
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value.trim().trim_matches('"'))
+    };
+    let version = value("VERSION_ID").filter(|version| !version.is_empty())?;
+    match value("ID")? {
+        "debian" | "raspbian" => Some(format!("Debian:{version}")),
+        "ubuntu" if value("VERSION").is_some_and(|name| name.contains("LTS")) => Some(format!("Ubuntu:{version}:LTS")),
+        "ubuntu" => Some(format!("Ubuntu:{version}")),
+        _ => None,
+    }
+}
+
+/// Looks up the advisories of pending updates. A default `Advisories` looks up nothing.
+#[derive(Clone, Default)]
+pub struct Advisories {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    url: String,
+    ecosystem: String,
+    cache: Mutex<Cache>,
+    /// Whether a lookup is running, so that status requests don't start another one
+    refreshing: AtomicBool,
+}
+
+/// IDs and modification times of vulnerabilities.
+type VulnIds = Vec<(String, String)>;
+
+#[derive(Default)]
+struct Cache {
+    /// Vulnerabilities affecting `(source, version)`, with when they were queried
+    queries: HashMap<(String, String), (Instant, VulnIds)>,
+    /// Vulnerabilities by ID, with the modification time they were fetched at
+    vulns: HashMap<String, (String, Arc<Vuln>)>,
+    /// Severities of CVE records, for vulnerabilities that come without one
+    cves: HashMap<String, Option<Severity>>,
+}
+
+/// What matters of an OSV vulnerability for the node's ecosystem.
+#[derive(Debug, Default, PartialEq)]
+struct Vuln {
+    advisories: BTreeSet<String>,
+    cves: BTreeSet<String>,
+    severity: Option<Severity>,
+    /// `(source package, fixed version)`
+    fixed: Vec<(String, String)>,
+}
+
+impl Advisories {
+    /// `url` is the OSV API, e.g. `https://api.osv.dev`. `ecosystem` overrides the one
+    /// derived from `os_release`, for derivatives of Debian or Ubuntu.
+    pub fn new(url: Option<String>, ecosystem: Option<String>, os_release: &str) -> Result<Self, String> {
+        let Some(url) = url else {
+            return Ok(Self::default());
+        };
+        let ecosystem = ecosystem.or_else(|| self::ecosystem(os_release)).ok_or(
+            "can't tell the OSV ecosystem of this system; set it with --osv-ecosystem, e.g. Debian:12",
+        )?;
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| format!("failed to create OSV client: {err}"))?;
+        info!("looking up security advisories for {ecosystem} in {url}");
+        Ok(Self {
+            inner: Some(Arc::new(Inner {
+                client,
+                url: url.trim_end_matches('/').to_string(),
+                ecosystem,
+                cache: Mutex::default(),
+                refreshing: AtomicBool::new(false),
+            })),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Fills in the advisories, CVEs and severity of `updates` from what was looked up
+    /// before, without waiting for the OSV server. Packages that weren't looked up yet, or
+    /// not within [`QUERY_TTL`], are looked up in the background for later requests; a
+    /// failed lookup is logged.
+    pub fn annotate(&self, updates: &mut [UpdateDetail], sources: &[SourceUpdate]) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let wanted: BTreeSet<(String, String)> = sources
+            .iter()
+            .map(|source| (source.source.clone(), source.installed.clone()))
+            .collect();
+        let cache = inner.cache.lock().unwrap_or_else(|e| e.into_inner());
+        let stale = cache.stale(&wanted);
+        if !stale.is_empty() {
+            inner.clone().refresh_in_background(stale);
+        }
+        for source in sources {
+            let Some(update) = updates.iter_mut().find(|update| update.name == source.name) else {
+                continue;
+            };
+            for vuln in cache.vulns_of(&(source.source.clone(), source.installed.clone())) {
+                if !vuln.fixed_by(source) {
+                    continue;
+                }
+                let severity = vuln.severity.or_else(|| {
+                    vuln.cves
+                        .iter()
+                        .filter_map(|cve| cache.cves.get(cve).copied().flatten())
+                        .max()
+                });
+                update.advisories.extend(vuln.advisories.iter().cloned());
+                update.cves.extend(vuln.cves.iter().cloned());
+                update.severity = update.severity.max(severity);
+            }
+            for list in [&mut update.advisories, &mut update.cves] {
+                list.sort();
+                list.dedup();
+            }
+        }
+    }
+}
+
+impl Cache {
+    /// The `(source, version)` pairs that weren't looked up within [`QUERY_TTL`].
+    fn stale(&self, wanted: &BTreeSet<(String, String)>) -> BTreeSet<(String, String)> {
+        wanted
+            .iter()
+            .filter(|key| self.queries.get(*key).is_none_or(|(at, _)| at.elapsed() >= QUERY_TTL))
+            .cloned()
+            .collect()
+    }
+
+    /// The known vulnerabilities affecting `(source, version)`.
+    fn vulns_of(&self, key: &(String, String)) -> Vec<Arc<Vuln>> {
+        self.queries
+            .get(key)
+            .into_iter()
+            .flat_map(|(_, ids)| ids)
+            .filter_map(|(id, _)| self.vulns.get(id).map(|(_, vuln)| vuln.clone()))
+            .collect()
+    }
+}
+
+impl Inner {
+    /// Starts a lookup of `packages` unless one is running already.
+    fn refresh_in_background(self: Arc<Self>, packages: BTreeSet<(String, String)>) {
+        if self.refreshing.swap(true, AtomicOrdering::AcqRel) {
+            return;
+        }
+        tokio::spawn(async move {
+            match tokio::time::timeout(LOOKUP_TIMEOUT, self.lookup(packages)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => warn!("failed to look up security advisories: {err}"),
+                Err(_) => warn!("looking up security advisories timed out"),
+            }
+            self.refreshing.store(false, AtomicOrdering::Release);
+        });
+    }
+
+    /// Caches the vulnerabilities affecting each `(source, version)`. The packages count as
+    /// looked up only once everything about them is fetched, so that a failed lookup is
+    /// retried by the next status request.
+    async fn lookup(&self, packages: BTreeSet<(String, String)>) -> Result<(), String> {
+        let packages: Vec<_> = packages.into_iter().collect();
+        let results = self.query_batch(&packages).await?;
+        let missing: BTreeSet<String> = {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            results
+                .iter()
+                .flatten()
+                .filter(|(id, modified)| cache.vulns.get(id).is_none_or(|(fetched, _)| fetched != modified))
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        let fetched = self.fetch_all(missing).await?;
+        let cves_without_severity: BTreeSet<String> = {
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            for (id, modified, vuln) in fetched {
+                cache.vulns.insert(id, (modified, Arc::new(Vuln::from_osv(vuln, &self.ecosystem))));
+            }
+            cache
+                .vulns
+                .values()
+                .filter(|(_, vuln)| vuln.severity.is_none())
+                .flat_map(|(_, vuln)| vuln.cves.iter())
+                .filter(|cve| !cache.cves.contains_key(*cve))
+                .cloned()
+                .collect()
+        };
+        // Debian's advisories come without a rating; the CVE records have the CVSS vector.
+        let ratings = self.fetch_all(cves_without_severity.clone()).await?;
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        for cve in cves_without_severity {
+            cache.cves.insert(cve, None);
+        }
+        for (id, _, vuln) in ratings {
+            cache.cves.insert(id, rating(&vuln.severity, &[]));
+        }
+        for (key, ids) in packages.into_iter().zip(results) {
+            cache.queries.insert(key, (Instant::now(), ids));
+        }
+        Ok(())
+    }
+
+    /// IDs and modification times of the vulnerabilities affecting each `(source, version)`.
+    async fn query_batch(&self, packages: &[(String, String)]) -> Result<Vec<VulnIds>, String> {
+        let queries: Vec<_> = packages
+            .iter()
+            .map(|(name, version)| {
+                serde_json::json!({
+                    "package": { "name": name, "ecosystem": self.ecosystem },
+                    "version": version,
+                })
+            })
+            .collect();
+        let body = serde_json::json!({ "queries": queries }).to_string();
+        let response = self
+            .client
+            .post(format!("{}/v1/querybatch", self.url))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("OSV query failed: {err}"))?;
+        let text = response.text().await.map_err(|err| format!("OSV query failed: {err}"))?;
+        let batch: OsvBatch = serde_json::from_str(&text).map_err(|err| format!("invalid OSV response: {err}"))?;
+        if batch.results.len() != packages.len() {
+            return Err(format!("OSV answered {} of {} queries", batch.results.len(), packages.len()));
+        }
+        Ok(batch
+            .results
+            .into_iter()
+            .map(|result| result.vulns.into_iter().map(|vuln| (vuln.id, vuln.modified)).collect())
+            .collect())
+    }
+
+    /// Fetches the vulnerabilities `ids` in parallel. Unknown IDs are left out.
+    async fn fetch_all(&self, ids: BTreeSet<String>) -> Result<Vec<(String, String, OsvVuln)>, String> {
+        let mut tasks = JoinSet::new();
+        for id in ids {
+            let request = self.client.get(format!("{}/v1/vulns/{id}", self.url));
+            tasks.spawn(async move {
+                let response = request.send().await.map_err(|err| format!("failed to fetch {id}: {err}"))?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                let text = response
+                    .error_for_status()
+                    .map_err(|err| format!("failed to fetch {id}: {err}"))?
+                    .text()
+                    .await
+                    .map_err(|err| format!("failed to fetch {id}: {err}"))?;
+                let vuln: OsvVuln = serde_json::from_str(&text).map_err(|err| format!("invalid OSV record {id}: {err}"))?;
+                Ok::<_, String>(Some((id, vuln.modified.clone(), vuln)))
+            });
+        }
+        let mut vulns = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            let result = result.map_err(|err| err.to_string())?;
+            vulns.extend(result?);
+        }
+        Ok(vulns)
+    }
+}
+
+impl Vuln {
+    fn from_osv(osv: OsvVuln, ecosystem: &str) -> Self {
+        let mut vuln = Vuln::default();
+        for id in std::iter::once(&osv.id).chain(&osv.aliases).chain(&osv.related).chain(&osv.upstream) {
+            // Distributions track CVEs under IDs like `UBUNTU-CVE-2024-1234`.
+            if let Some(start) = id.find("CVE-") {
+                vuln.cves.insert(id[start..].to_string());
+            } else if ADVISORY_PREFIXES.iter().any(|prefix| id.starts_with(prefix)) {
+                vuln.advisories.insert(id.clone());
+            }
+        }
+        let affected: Vec<_> = osv
+            .affected
+            .iter()
+            .filter(|affected| affected.package.as_ref().is_some_and(|package| package.ecosystem == ecosystem))
+            .collect();
+        let ratings: Vec<&str> = affected
+            .iter()
+            .flat_map(|affected| [&affected.ecosystem_specific, &affected.database_specific])
+            .chain([&osv.database_specific])
+            .filter_map(|specific| {
+                let specific = specific.as_ref()?;
+                specific.get("urgency").or_else(|| specific.get("severity"))?.as_str()
+            })
+            .collect();
+        let severities: Vec<&OsvSeverity> = osv
+            .severity
+            .iter()
+            .chain(affected.iter().flat_map(|affected| &affected.severity))
+            .collect();
+        vuln.severity = rating(severities, &ratings);
+        for affected in affected {
+            let Some(package) = &affected.package else {
+                continue;
+            };
+            let fixes = affected.ranges.iter().flat_map(|range| &range.events).filter_map(|event| event.fixed.as_ref());
+            vuln.fixed.extend(fixes.map(|fixed| (package.name.clone(), fixed.clone())));
+        }
+        vuln
+    }
+
+    fn fixed_by(&self, update: &SourceUpdate) -> bool {
+        self.fixed.iter().any(|(package, fixed)| {
+            *package == update.source
+                && compare_versions(&update.installed, fixed) == Ordering::Less
+                && compare_versions(fixed, &update.candidate) != Ordering::Greater
+        })
+    }
+}
+
+/// The severity by the highest CVSS v3 base score among `severities`, or, without one, the
+/// highest of the distribution's ratings: those among `severities` (Ubuntu's priority) and
+/// `ratings`.
+fn rating<'a>(severities: impl IntoIterator<Item = &'a OsvSeverity>, ratings: &[&'a str]) -> Option<Severity> {
+    let (cvss, named): (Vec<_>, Vec<_>) = severities
+        .into_iter()
+        .partition(|severity| severity.kind.starts_with("CVSS_"));
+    let cvss = cvss
+        .into_iter()
+        .filter_map(|severity| cvss3_base_score(&severity.score))
+        .max_by(f64::total_cmp);
+    match cvss {
+        Some(score) if score >= 9.0 => Some(Severity::Critical),
+        Some(score) if score >= 7.0 => Some(Severity::High),
+        Some(score) if score >= 4.0 => Some(Severity::Medium),
+        Some(score) if score > 0.0 => Some(Severity::Low),
+        Some(_) => Some(Severity::Negligible),
+        None => named
+            .into_iter()
+            .map(|severity| severity.score.as_str())
+            .chain(ratings.iter().copied())
+            .filter_map(|rating| match rating.trim_end_matches('*').to_ascii_lowercase().as_str() {
+                "negligible" | "unimportant" => Some(Severity::Negligible),
+                "low" => Some(Severity::Low),
+                "medium" | "moderate" => Some(Severity::Medium),
+                "high" | "important" => Some(Severity::High),
+                "critical" => Some(Severity::Critical),
+                _ => None,
+            })
+            .max(),
+    }
+}
+
+/// Base score of a CVSS v3.x vector such as `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`.
+fn cvss3_base_score(vector: &str) -> Option<f64> {
+    let mut parts = vector.split('/');
+    if !parts.next()?.starts_with("CVSS:3.") {
+        return None;
+    }
+    let metrics: HashMap<&str, &str> = parts.filter_map(|part| part.split_once(':')).collect();
+    let metric = |name: &str, values: &[(&str, f64)]| {
+        let value = metrics.get(name)?;
+        values.iter().find(|(key, _)| key == value).map(|(_, weight)| *weight)
+    };
+    let changed = match *metrics.get("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = metric("AV", &[("N", 0.85), ("A", 0.62), ("L", 0.55), ("P", 0.2)])?;
+    let attack_complexity = metric("AC", &[("L", 0.77), ("H", 0.44)])?;
+    let privileges = if changed {
+        metric("PR", &[("N", 0.85), ("L", 0.68), ("H", 0.5)])?
+    } else {
+        metric("PR", &[("N", 0.85), ("L", 0.62), ("H", 0.27)])?
+    };
+    let user_interaction = metric("UI", &[("N", 0.85), ("R", 0.62)])?;
+    let impacts = [("H", 0.56), ("L", 0.22), ("N", 0.0)];
+    let (confidentiality, integrity, availability) = (
+        metric("C", &impacts)?,
+        metric("I", &impacts)?,
+        metric("A", &impacts)?,
+    );
+
+    let base_impact = 1.0 - (1.0 - confidentiality) * (1.0 - integrity) * (1.0 - availability);
+    let impact = if changed {
+        7.52 * (base_impact - 0.029) - 3.25 * (base_impact - 0.02).powi(15)
+    } else {
+        6.42 * base_impact
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges * user_interaction;
+    let score = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    Some(round_up(score.min(10.0)))
+}
+
+/// Rounds up to one decimal the way CVSS 3.1 specifies, immune to floating point noise.
+fn round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round() as u64;
+    if scaled.is_multiple_of(10_000) {
+        scaled as f64 / 100_000.0
+    } else {
+        (scaled / 10_000 + 1) as f64 / 10.0
+    }
+}
+
+/// Compares Debian package versions like `dpkg --compare-versions`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_upstream, a_revision) = split_version(a);
+    let (b_epoch, b_upstream, b_revision) = split_version(b);
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| compare_fragment(a_upstream, b_upstream))
+        .then_with(|| compare_fragment(a_revision, b_revision))
+}
+
+/// Splits `[epoch:]upstream[-revision]`.
+fn split_version(version: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+        None => (0, version),
+    };
+    let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+    (epoch, upstream, revision)
+}
+
+/// dpkg's `verrevcmp`: alternating runs of non-digits, compared character by character with
+/// `~` before anything (even the end) and letters before other characters, and numbers.
+fn compare_fragment(a: &str, b: &str) -> Ordering {
+    fn order(c: Option<&u8>) -> i32 {
+        match c {
+            None => 0,
+            Some(b'~') => -1,
+            Some(c) if c.is_ascii_alphabetic() => i32::from(*c),
+            Some(c) => i32::from(*c) + 256,
+        }
+    }
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    while !a.is_empty() || !b.is_empty() {
+        loop {
+            let a_char = a.first().filter(|c| !c.is_ascii_digit());
+            let b_char = b.first().filter(|c| !c.is_ascii_digit());
+            if a_char.is_none() && b_char.is_none() {
+                break;
+            }
+            match order(a_char).cmp(&order(b_char)) {
+                Ordering::Equal => (a, b) = (&a[1..], &b[1..]),
+                unequal => return unequal,
+            }
+        }
+        let number = |s: &[u8]| {
+            let digits = s.iter().take_while(|c| c.is_ascii_digit()).count();
+            let start = s[..digits].iter().take_while(|c| **c == b'0').count();
+            (s[start..digits].to_vec(), digits)
+        };
+        let ((a_number, a_len), (b_number, b_len)) = (number(a), number(b));
+        match a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(&b_number)) {
+            Ordering::Equal => (a, b) = (&a[a_len..], &b[b_len..]),
+            unequal => return unequal,
+        }
+    }
+    Ordering::Equal
+}
+
+#[derive(Deserialize)]
+struct OsvBatch {
+    #[serde(default)]
+    results: Vec<OsvBatchResult>,
+}
+
+#[derive(Deserialize)]
+struct OsvBatchResult {
+    #[serde(default)]
+    vulns: Vec<OsvVulnRef>,
+}
+
+#[derive(Deserialize)]
+struct OsvVulnRef {
+    id: String,
+    #[serde(default)]
+    modified: String,
+}
+
+#[derive(Deserialize)]
+struct OsvVuln {
+    id: String,
+    #[serde(default)]
+    modified: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    related: Vec<String>,
+    #[serde(default)]
+    upstream: Vec<String>,
+    #[serde(default)]
+    severity: Vec<OsvSeverity>,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+    database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct OsvSeverity {
+    #[serde(rename = "type")]
+    kind: String,
+    score: String,
+}
+
+#[derive(Deserialize)]
+struct OsvAffected {
+    package: Option<OsvPackage>,
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+    #[serde(default)]
+    severity: Vec<OsvSeverity>,
+    ecosystem_specific: Option<serde_json::Value>,
+    database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct OsvPackage {
+    name: String,
+    ecosystem: String,
+}
+
+#[derive(Deserialize)]
+struct OsvRange {
+    #[serde(default)]
+    events: Vec<OsvEvent>,
+}
+
+#[derive(Deserialize)]
+struct OsvEvent {
+    fixed: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecosystem() {
+        let debian = "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"\nVERSION_ID=\"12\"\nID=debian\n";
+        assert_eq!(ecosystem(debian).as_deref(), Some("Debian:12"));
+        let ubuntu = "VERSION=\"24.04.1 LTS (Noble Numbat)\"\nID=ubuntu\nID_LIKE=debian\nVERSION_ID=\"24.04\"\n";
+        assert_eq!(ecosystem(ubuntu).as_deref(), Some("Ubuntu:24.04:LTS"));
+        assert_eq!(ecosystem("ID=debian\nVERSION_CODENAME=trixie\n"), None);
+        assert_eq!(ecosystem("ID=fedora\nVERSION_ID=40\n"), None);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let less = [
+            ("1.0", "1.0.1"),
+            ("1.0~rc1", "1.0"),
+            ("1.9", "1.10"),
+            ("2.36-9+deb12u4", "2.36-9+deb12u6"),
+            ("3.0.11-1~deb12u2", "3.0.13-1~deb12u1"),
+            ("2.0", "1:1.0"),
+            ("1.0a", "1.0+"),
+            ("1.0-1", "1.0-1ubuntu0.1"),
+        ];
+        for (a, b) in less {
+            assert_eq!(compare_versions(a, b), Ordering::Less, "{a} < {b}");
+            assert_eq!(compare_versions(b, a), Ordering::Greater, "{b} > {a}");
+        }
+        assert_eq!(compare_versions("1.01-1", "1.1-1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_cvss3_base_score() {
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), Some(10.0));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"), Some(6.1));
+        assert_eq!(cvss3_base_score("CVSS:3.0/AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:N/A:N"), Some(5.5));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), Some(0.0));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L"), None);
+        assert_eq!(cvss3_base_score("AV:N/AC:L/Au:N/C:P/I:P/A:P"), None);
+    }
+
+    #[test]
+    fn test_vuln_from_osv() {
+        let osv: OsvVuln = serde_json::from_value(serde_json::json!({
+            "id": "USN-6587-1",
+            "modified": "2024-01-17T00:00:00Z",
+            "upstream": ["CVE-2023-5678", "UBUNTU-CVE-2023-6129"],
+            "severity": [{ "type": "Ubuntu", "score": "medium" }],
+            "affected": [
+                {
+                    "package": { "ecosystem": "Ubuntu:22.04:LTS", "name": "openssl" },
+                    "ranges": [{ "type": "ECOSYSTEM", "events": [{ "introduced": "0" }, { "fixed": "3.0.2-0ubuntu1.13" }] }],
+                },
+                {
+                    "package": { "ecosystem": "Ubuntu:20.04:LTS", "name": "openssl" },
+                    "ranges": [{ "type": "ECOSYSTEM", "events": [{ "introduced": "0" }, { "fixed": "1.1.1f-1ubuntu2.21" }] }],
+                },
+            ],
+        }))
+        .unwrap();
+        let vuln = Vuln::from_osv(osv, "Ubuntu:22.04:LTS");
+        assert_eq!(vuln.advisories, BTreeSet::from(["USN-6587-1".to_string()]));
+        assert_eq!(vuln.cves, BTreeSet::from(["CVE-2023-5678".to_string(), "CVE-2023-6129".to_string()]));
+        assert_eq!(vuln.severity, Some(Severity::Medium));
+        assert_eq!(vuln.fixed, [("openssl".to_string(), "3.0.2-0ubuntu1.13".to_string())]);
+
+        let update = |installed: &str, candidate: &str| SourceUpdate {
+            name: "libssl3".to_string(),
+            source: "openssl".to_string(),
+            installed: installed.to_string(),
+            candidate: candidate.to_string(),
+        };
+        assert!(vuln.fixed_by(&update("3.0.2-0ubuntu1.12", "3.0.2-0ubuntu1.14")));
+        assert!(!vuln.fixed_by(&update("3.0.2-0ubuntu1.13", "3.0.2-0ubuntu1.14")));
+        assert!(!vuln.fixed_by(&update("3.0.2-0ubuntu1.10", "3.0.2-0ubuntu1.12")));
+
+        let severities = [OsvSeverity {
+            kind: "CVSS_V3".to_string(),
+            score: "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H".to_string(),
+        }];
+        assert_eq!(rating(&severities, &["low"]), Some(Severity::Critical));
+        assert_eq!(rating([], &["unimportant", "not yet assigned"]), Some(Severity::Negligible));
+    }
+
+    #[tokio::test]
+    async fn test_annotate_from_cache() {
+        // Nothing listens on the discard port, so the background lookup fails.
+        let url = Some("http://127.0.0.1:9".to_string());
+        let advisories = Advisories::new(url, Some("Debian:12".to_string()), "").unwrap();
+        let inner = advisories.inner.as_ref().unwrap();
+        let vuln = Vuln {
+            advisories: BTreeSet::from(["DSA-5678-1".to_string()]),
+            cves: BTreeSet::from(["CVE-2024-2961".to_string()]),
+            severity: Some(Severity::High),
+            fixed: vec![("glibc".to_string(), "2.36-9+deb12u7".to_string())],
+        };
+        {
+            let mut cache = inner.cache.lock().unwrap();
+            let ids = vec![("DSA-5678-1".to_string(), "2024-05-01T00:00:00Z".to_string())];
+            cache.queries.insert(("glibc".to_string(), "2.36-9+deb12u4".to_string()), (Instant::now(), ids));
+            cache.vulns.insert("DSA-5678-1".to_string(), ("2024-05-01T00:00:00Z".to_string(), Arc::new(vuln)));
+        }
+        let source = |name: &str, source: &str, installed: &str, candidate: &str| SourceUpdate {
+            name: name.to_string(),
+            source: source.to_string(),
+            installed: installed.to_string(),
+            candidate: candidate.to_string(),
+        };
+        let update = |name: &str| UpdateDetail {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        let mut updates = vec![update("libc6")];
+        advisories.annotate(&mut updates, &[source("libc6", "glibc", "2.36-9+deb12u4", "2.36-9+deb12u7")]);
+        assert_eq!(updates[0].advisories, ["DSA-5678-1"]);
+        assert_eq!(updates[0].severity, Some(Severity::High));
+        assert!(!inner.refreshing.load(AtomicOrdering::Acquire));
+
+        // Packages not looked up yet are left as they are and looked up in the background.
+        let mut updates = vec![update("openssl")];
+        advisories.annotate(&mut updates, &[source("openssl", "openssl", "3.0.11-1", "3.0.13-1")]);
+        assert_eq!(updates[0], update("openssl"));
+        assert!(inner.refreshing.load(AtomicOrdering::Acquire));
+    }
+}
@@ -0,0 +1,354 @@
+//! Alerts pushed to Prometheus Alertmanager, for fleets that want patch alerts without a
+//! scrape pipeline. Firing alerts are re-sent on every check with an `endsAt` a few checks
+//! ahead, so they resolve on their own if the daemon goes away; a cleared condition is sent
+//! once more with `endsAt` set to now.
+
+use crate::jobs::unix_now;
+use crate::syslog::format_timestamp;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::warn;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SECURITY_UPDATES: &str = "CobblerSecurityUpdatesPending";
+const UPGRADE_FAILED: &str = "CobblerUpgradeFailed";
+const REBOOT_REQUIRED: &str = "CobblerRebootRequired";
+
+/// Parses an `--alert-label` of the form `name=value`.
+pub fn parse_label(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got {value:?}")),
+    }
+}
+
+/// Sends alerts to the configured Alertmanagers. A default `Alerts` has none and ignores
+/// every condition.
+#[derive(Clone, Default)]
+pub struct Alerts {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    /// Labels of every alert, after `alertname`, `node` and `severity`
+    labels: BTreeMap<String, String>,
+    /// How long security updates may be pending before their alert fires
+    security_after: Duration,
+    /// How long a firing alert stays valid without being re-sent
+    lifetime: Duration,
+    /// Keeps `security_pending_since` across restarts, so they don't reset the count
+    since_file: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    security_pending_since: Option<u64>,
+    firing: BTreeMap<&'static str, Alert>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Alert {
+    starts_at: u64,
+    severity: &'static str,
+    summary: String,
+}
+
+impl State {
+    /// Starts or updates the alert `name` if `summary` is set, and otherwise clears it.
+    /// Returns the alert if it was just cleared, to be sent as resolved.
+    fn set(
+        &mut self,
+        name: &'static str,
+        severity: &'static str,
+        summary: Option<String>,
+        now: u64,
+    ) -> Option<Alert> {
+        match summary {
+            Some(summary) => {
+                let starts_at = self.firing.get(name).map_or(now, |alert| alert.starts_at);
+                self.firing.insert(
+                    name,
+                    Alert {
+                        starts_at,
+                        severity,
+                        summary,
+                    },
+                );
+                None
+            }
+            None => self.firing.remove(name),
+        }
+    }
+}
+
+impl Alerts {
+    /// `urls` are Alertmanager base URLs such as `http://alertmanager:9093`. `check_interval`
+    /// is how often [`Alerts::observe`] is called. `since_file` keeps the time security updates
+    /// were first seen pending.
+    pub fn new(
+        urls: Vec<String>,
+        labels: Vec<(String, String)>,
+        security_after: Duration,
+        check_interval: Duration,
+        node_id: String,
+        since_file: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        if urls.is_empty() {
+            return Ok(Self::default());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| format!("failed to create Alertmanager client: {err}"))?;
+        let mut all_labels = BTreeMap::from([("node".to_string(), node_id)]);
+        all_labels.extend(labels);
+        Ok(Self {
+            inner: Some(Arc::new(Inner {
+                client,
+                urls: urls
+                    .iter()
+                    .map(|url| format!("{}/api/v2/alerts", url.trim_end_matches('/')))
+                    .collect(),
+                labels: all_labels,
+                security_after,
+                lifetime: check_interval * 3,
+                state: Mutex::new(State {
+                    security_pending_since: since_file.as_deref().and_then(load_since),
+                    ..State::default()
+                }),
+                since_file,
+            })),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Updates the alerts that depend on the system state and (re-)sends all of them.
+    pub fn observe(&self, security_updates: usize, reboot_required: bool) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let now = unix_now();
+        let mut state = inner.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut resolved = Vec::new();
+
+        let since = match security_updates {
+            0 => None,
+            _ => Some(state.security_pending_since.unwrap_or(now)),
+        };
+        if since != state.security_pending_since {
+            state.security_pending_since = since;
+            if let Some(path) = &inner.since_file
+                && let Err(err) = save_since(path, since)
+            {
+                warn!("failed to record when security updates were first pending in {}: {err}", path.display());
+            }
+        }
+        // The clock may have been set back since.
+        let pending_for = since.map(|since| now.saturating_sub(since));
+        let overdue = pending_for.filter(|pending_for| *pending_for >= inner.security_after.as_secs());
+        let summary = overdue.map(|pending_for| {
+            format!("{security_updates} security update(s) pending for {} day(s)", pending_for / 86_400)
+        });
+        resolved.extend(
+            state
+                .set(SECURITY_UPDATES, "warning", summary, now)
+                .map(|alert| (SECURITY_UPDATES, alert)),
+        );
+
+        let summary = reboot_required.then(|| "a reboot is required".to_string());
+        resolved.extend(
+            state
+                .set(REBOOT_REQUIRED, "info", summary, now)
+                .map(|alert| (REBOOT_REQUIRED, alert)),
+        );
+
+        let body = inner.payload(&state.firing, &resolved, now);
+        drop(state);
+        inner.send(body);
+    }
+
+    /// Fires the upgrade alert for a failed upgrade, and resolves it when one succeeds.
+    pub fn upgrade_finished(&self, succeeded: bool, message: &str) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let now = unix_now();
+        let mut state = inner.state.lock().unwrap_or_else(|e| e.into_inner());
+        let summary = (!succeeded).then(|| format!("full upgrade failed: {message}"));
+        let resolved: Vec<_> = state
+            .set(UPGRADE_FAILED, "critical", summary, now)
+            .map(|alert| (UPGRADE_FAILED, alert))
+            .into_iter()
+            .collect();
+        let body = inner.payload(&state.firing, &resolved, now);
+        drop(state);
+        inner.send(body);
+    }
+}
+
+impl Inner {
+    /// The Alertmanager v2 request body with the firing and the just resolved alerts.
+    fn payload(
+        &self,
+        firing: &BTreeMap<&'static str, Alert>,
+        resolved: &[(&'static str, Alert)],
+        now: u64,
+    ) -> serde_json::Value {
+        let timestamp = |seconds: u64| format_timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+        let firing_until = now + self.lifetime.as_secs();
+        let alerts: Vec<_> = firing
+            .iter()
+            .map(|(name, alert)| (*name, alert, firing_until))
+            .chain(resolved.iter().map(|(name, alert)| (*name, alert, now)))
+            .map(|(name, alert, ends_at)| {
+                let mut labels = self.labels.clone();
+                labels.insert("alertname".to_string(), name.to_string());
+                labels.insert("severity".to_string(), alert.severity.to_string());
+                serde_json::json!({
+                    "labels": labels,
+                    "annotations": { "summary": alert.summary },
+                    "startsAt": timestamp(alert.starts_at),
+                    "endsAt": timestamp(ends_at),
+                })
+            })
+            .collect();
+        serde_json::Value::Array(alerts)
+    }
+
+    /// Posts the alerts to every Alertmanager in the background. A lost update is made up
+    /// for by the next check, so there are no retries.
+    fn send(&self, body: serde_json::Value) {
+        if body.as_array().is_some_and(Vec::is_empty) {
+            return;
+        }
+        let body = body.to_string();
+        for url in &self.urls {
+            let request = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            let url = url.clone();
+            tokio::spawn(async move {
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => warn!("Alertmanager {url} rejected alerts with status {}", response.status()),
+                    Err(err) => warn!("failed to send alerts to Alertmanager {url}: {err}"),
+                }
+            });
+        }
+    }
+}
+
+fn load_since(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Records `since`, or removes the record once nothing is pending.
+fn save_since(path: &Path, since: Option<u64>) -> io::Result<()> {
+    match since {
+        Some(since) => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, format!("{since}\n"))
+        }
+        None => match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(parse_label("team=ops"), Ok(("team".to_string(), "ops".to_string())));
+        assert_eq!(parse_label("env="), Ok(("env".to_string(), String::new())));
+        assert!(parse_label("=ops").is_err());
+        assert!(parse_label("team").is_err());
+    }
+
+    #[test]
+    fn test_alert_lifecycle() {
+        let alerts = Alerts::new(
+            vec!["http://alertmanager:9093/".to_string()],
+            vec![("team".to_string(), "ops".to_string())],
+            Duration::from_secs(86_400),
+            Duration::from_secs(300),
+            "web1".to_string(),
+            None,
+        )
+        .unwrap();
+        let inner = alerts.inner.unwrap();
+        assert_eq!(inner.urls, ["http://alertmanager:9093/api/v2/alerts"]);
+
+        let mut state = State::default();
+        assert_eq!(state.set(UPGRADE_FAILED, "critical", Some("failed".into()), 100), None);
+        assert_eq!(state.set(UPGRADE_FAILED, "critical", Some("failed again".into()), 200), None);
+        assert_eq!(state.firing[UPGRADE_FAILED].starts_at, 100);
+
+        let payload = inner.payload(&state.firing, &[], 200);
+        assert_eq!(
+            payload,
+            serde_json::json!([{
+                "labels": { "alertname": UPGRADE_FAILED, "node": "web1", "severity": "critical", "team": "ops" },
+                "annotations": { "summary": "failed again" },
+                "startsAt": "1970-01-01T00:01:40.000Z",
+                "endsAt": "1970-01-01T00:18:20.000Z",
+            }])
+        );
+
+        let resolved = state.set(UPGRADE_FAILED, "critical", None, 300).unwrap();
+        let payload = inner.payload(&state.firing, &[(UPGRADE_FAILED, resolved)], 300);
+        assert_eq!(payload[0]["endsAt"], "1970-01-01T00:05:00.000Z");
+        assert!(state.firing.is_empty());
+    }
+
+    #[test]
+    fn test_security_pending_since_survives_restart() {
+        let path = std::env::temp_dir().join(format!("cobblerd-alerts-{}", std::process::id()));
+        let alerts = || {
+            Alerts::new(
+                vec!["http://127.0.0.1:9/".to_string()],
+                Vec::new(),
+                Duration::from_secs(86_400),
+                Duration::from_secs(300),
+                "web1".to_string(),
+                Some(path.clone()),
+            )
+            .unwrap()
+        };
+        let since = |alerts: &Alerts| alerts.inner.as_ref().unwrap().state.lock().unwrap().security_pending_since;
+
+        save_since(&path, Some(unix_now() - 2 * 86_400)).unwrap();
+        let restarted = alerts();
+        assert_eq!(since(&restarted), load_since(&path));
+        assert!(since(&restarted).is_some());
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            restarted.observe(1, false);
+            let state = restarted.inner.as_ref().unwrap().state.lock().unwrap();
+            assert!(state.firing.contains_key(SECURITY_UPDATES));
+            assert!(state.firing[SECURITY_UPDATES].summary.ends_with("for 2 day(s)"));
+            drop(state);
+            restarted.observe(0, false);
+        });
+        assert_eq!(load_since(&path), None);
+        assert_eq!(since(&alerts()), None);
+    }
+}
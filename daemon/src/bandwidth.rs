@@ -0,0 +1,27 @@
+//! Download throttling, so that patch runs across a site don't saturate a shared or metered
+//! uplink.
+
+/// apt options limiting downloads to `kbps` kilobytes per second; none for `0`.
+pub fn apt_options(kbps: u32) -> Vec<String> {
+    if kbps == 0 {
+        return Vec::new();
+    }
+    ["http", "https"]
+        .iter()
+        .flat_map(|scheme| ["-o".to_string(), format!("Acquire::{scheme}::Dl-Limit={kbps}")])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apt_options() {
+        assert!(apt_options(0).is_empty());
+        assert_eq!(
+            apt_options(512),
+            ["-o", "Acquire::http::Dl-Limit=512", "-o", "Acquire::https::Dl-Limit=512"]
+        );
+    }
+}
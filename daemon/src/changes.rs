@@ -0,0 +1,29 @@
+//! Change counters for what the status reports on, so that `GET /status/wait` recomputes the
+//! status when something changed rather than polling for it.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Counts changes; clones share the count.
+#[derive(Clone)]
+pub struct Changes(Arc<watch::Sender<u64>>);
+
+impl Default for Changes {
+    fn default() -> Self {
+        Changes(Arc::new(watch::Sender::new(0)))
+    }
+}
+
+impl Changes {
+    pub fn notify(&self) {
+        self.0.send_modify(|count| *count = count.wrapping_add(1));
+    }
+
+    pub fn count(&self) -> u64 {
+        *self.0.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.0.subscribe()
+    }
+}
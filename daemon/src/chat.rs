@@ -0,0 +1,274 @@
+//! Chat and push notifications. Events are rendered from text templates and posted to Slack
+//! or Discord incoming webhooks, sent to a Matrix room, or pushed through ntfy or Gotify.
+
+use crate::webhooks::EventKind;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Slack,
+    Discord,
+    Matrix,
+    Ntfy,
+    Gotify,
+}
+
+/// Where to post messages, given as `slack=URL`, `discord=URL`, `matrix=URL`, `ntfy=URL` or
+/// `gotify=URL`. For Matrix the URL is the room's `.../rooms/{room_id}/send/m.room.message`
+/// endpoint, for ntfy the topic's URL, e.g. `https://ntfy.sh/my-topic`, and for Gotify the
+/// server's base URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatSink {
+    pub service: Service,
+    pub url: String,
+}
+
+impl FromStr for ChatSink {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (service, url) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected SERVICE=URL, got {value:?}"))?;
+        let service = match service {
+            "slack" => Service::Slack,
+            "discord" => Service::Discord,
+            "matrix" => Service::Matrix,
+            "ntfy" => Service::Ntfy,
+            "gotify" => Service::Gotify,
+            _ => {
+                return Err(format!(
+                    "unknown chat service {service:?}; expected slack, discord, matrix, ntfy or gotify"
+                ));
+            }
+        };
+        let Some(location) = url
+            .strip_prefix("http://")
+            .or_else(|| url.strip_prefix("https://"))
+        else {
+            return Err(format!(
+                "invalid {value:?}: the URL must start with http:// or https://"
+            ));
+        };
+        let url = url.trim_end_matches('/');
+        if service == Service::Ntfy && !location.trim_end_matches('/').contains('/') {
+            return Err(format!(
+                "invalid {value:?}: the URL must include the topic, as in https://ntfy.sh/TOPIC"
+            ));
+        }
+        Ok(ChatSink {
+            service,
+            url: url.to_string(),
+        })
+    }
+}
+
+impl ChatSink {
+    /// The URL to send a message to. ntfy takes JSON messages at the server's root, with
+    /// the topic in the body; Gotify at `/message`.
+    pub fn endpoint(&self) -> String {
+        match self.service {
+            Service::Slack | Service::Discord | Service::Matrix => self.url.clone(),
+            Service::Ntfy => self.ntfy_topic().0.to_string(),
+            Service::Gotify => format!("{}/message", self.url),
+        }
+    }
+
+    /// The request body carrying `text`. Push services also get a title and a priority,
+    /// raised for events that need someone to act.
+    pub fn payload(&self, kind: EventKind, title: &str, text: &str) -> serde_json::Value {
+        let urgent = matches!(
+            kind,
+            EventKind::UpgradeFailed | EventKind::RebootRequired | EventKind::GuardTripped
+        );
+        match self.service {
+            Service::Slack => serde_json::json!({ "text": text }),
+            Service::Discord => serde_json::json!({ "content": text }),
+            Service::Matrix => serde_json::json!({ "msgtype": "m.text", "body": text }),
+            Service::Ntfy => serde_json::json!({
+                "topic": self.ntfy_topic().1,
+                "title": title,
+                "message": text,
+                "priority": if urgent { 4 } else { 3 },
+            }),
+            Service::Gotify => serde_json::json!({
+                "title": title,
+                "message": text,
+                "priority": if urgent { 8 } else { 5 },
+            }),
+        }
+    }
+
+    /// Splits an ntfy topic URL into the server URL and the topic.
+    fn ntfy_topic(&self) -> (&str, &str) {
+        self.url.rsplit_once('/').unwrap_or((&self.url, ""))
+    }
+}
+
+/// Message templates by event, falling back to the built-in ones.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    overrides: HashMap<EventKind, String>,
+}
+
+impl Templates {
+    /// Reads `event: template` lines, e.g. `upgrade.failed: {node} failed: {message}`; blank
+    /// lines and `#` comments are ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&content).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut overrides = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_number = index + 1;
+            let (event, template) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {line_number}: expected `event: template`"))?;
+            let event: EventKind = event
+                .trim()
+                .parse()
+                .map_err(|err| format!("line {line_number}: {err}"))?;
+            overrides.insert(event, template.trim().to_string());
+        }
+        Ok(Templates { overrides })
+    }
+
+    /// Renders the message for an event on `node`.
+    pub fn render(&self, kind: EventKind, node: &str, data: &serde_json::Value) -> String {
+        let template = self
+            .overrides
+            .get(&kind)
+            .map_or(default_template(kind), String::as_str);
+        render(template, kind, node, data)
+    }
+}
+
+fn default_template(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::UpdatesAvailable => "{node}: {updates} update(s) available",
+        EventKind::UpgradeStarted => "{node}: upgrade started (job {job_id})",
+        EventKind::UpgradeFinished => "{node}: upgrade finished (job {job_id})",
+        EventKind::UpgradeFailed => "{node}: upgrade failed (job {job_id}): {message}",
+        EventKind::RebootRequired => "{node}: reboot required",
+        EventKind::GuardTripped => "{node}: upgrade refused: {message}",
+        EventKind::SecurityDigest => {
+            "{node}: {security_updates} security update(s) pending: {packages}"
+        }
+    }
+}
+
+/// Replaces `{node}`, `{event}` and `{field}` for each field of the event data. Lists are
+/// joined with commas; unknown placeholders are kept as they are.
+fn render(template: &str, kind: EventKind, node: &str, data: &serde_json::Value) -> String {
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            message.push_str(&rest[start..]);
+            return message;
+        };
+        let name = &rest[start + 1..end];
+        let value = match name {
+            "node" => Some(node.to_string()),
+            "event" => Some(kind.as_str().to_string()),
+            _ => data.get(name).map(format_value),
+        };
+        match value {
+            Some(value) => message.push_str(&value),
+            None => message.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(format_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink() {
+        assert_eq!(
+            "discord=https://discord.com/api/webhooks/1/abc".parse(),
+            Ok(ChatSink {
+                service: Service::Discord,
+                url: "https://discord.com/api/webhooks/1/abc".to_string(),
+            })
+        );
+        assert!("teams=https://example.com".parse::<ChatSink>().is_err());
+        assert!("slack=hooks.slack.com".parse::<ChatSink>().is_err());
+        assert!("https://hooks.slack.com".parse::<ChatSink>().is_err());
+        assert!("ntfy=https://ntfy.sh/".parse::<ChatSink>().is_err());
+    }
+
+    #[test]
+    fn test_push_payload() {
+        let ntfy: ChatSink = "ntfy=https://push.example.com/ntfy/updates".parse().unwrap();
+        assert_eq!(ntfy.endpoint(), "https://push.example.com/ntfy");
+        assert_eq!(
+            ntfy.payload(EventKind::UpgradeFinished, "web1", "done"),
+            serde_json::json!({ "topic": "updates", "title": "web1", "message": "done", "priority": 3 })
+        );
+
+        let gotify: ChatSink = "gotify=https://gotify.example.com/".parse().unwrap();
+        assert_eq!(gotify.endpoint(), "https://gotify.example.com/message");
+        assert_eq!(
+            gotify.payload(EventKind::GuardTripped, "web1", "refused"),
+            serde_json::json!({ "title": "web1", "message": "refused", "priority": 8 })
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let data =
+            serde_json::json!({ "job_id": "abc", "message": "dpkg error", "exit_code": null });
+        let templates =
+            Templates::parse("# custom\nupgrade.failed: :x: {node} {event} {exit_code}{unknown}\n")
+                .unwrap();
+        assert_eq!(
+            templates.render(EventKind::UpgradeFailed, "web1", &data),
+            ":x: web1 upgrade.failed {unknown}"
+        );
+        assert_eq!(
+            templates.render(EventKind::UpgradeFinished, "web1", &data),
+            "web1: upgrade finished (job abc)"
+        );
+
+        let digest =
+            serde_json::json!({ "security_updates": 2, "packages": ["openssl", "libssl3"] });
+        assert_eq!(
+            templates.render(EventKind::SecurityDigest, "web1", &digest),
+            "web1: 2 security update(s) pending: openssl, libssl3"
+        );
+        assert!(
+            Templates::parse("upgrade.done: x")
+                .unwrap_err()
+                .contains("line 1")
+        );
+    }
+}
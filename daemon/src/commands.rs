@@ -0,0 +1,138 @@
+//! Allowlisted commands. The operator defines named shell commands in a file, and clients
+//! can run exactly those by name via `POST /commands/{name}`, never arbitrary ones.
+
+use crate::error::ApiError;
+use axum::http::StatusCode;
+use cobbler_api::{CommandResponse, ErrorCode};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Clone, Default)]
+pub struct CommandAllowlist {
+    commands: Arc<BTreeMap<String, String>>,
+}
+
+impl CommandAllowlist {
+    /// Reads `name: command` lines; blank lines and `#` comments are ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&content).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut commands = BTreeMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_number = index + 1;
+            let (name, command) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {line_number}: expected `name: command`"))?;
+            let (name, command) = (name.trim(), command.trim());
+            if !is_valid_name(name) {
+                return Err(format!("line {line_number}: invalid command name {name:?}"));
+            }
+            if command.is_empty() {
+                return Err(format!("line {line_number}: no command given for {name}"));
+            }
+            if commands.insert(name.to_string(), command.to_string()).is_some() {
+                return Err(format!("line {line_number}: {name} is defined twice"));
+            }
+        }
+        Ok(CommandAllowlist {
+            commands: Arc::new(commands),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&str, ApiError> {
+        self.commands.get(name).map(String::as_str).ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                format!("no command named {name} is allowlisted"),
+            )
+        })
+    }
+}
+
+/// Names end up in URL paths, so they are limited to letters, digits, `-`, `_` and `.`.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Runs `command` through `sh -c` and waits for it. A non-zero exit is a `CommandFailed`
+/// error whose details carry the exit code and output.
+pub fn run(name: &str, command: &str) -> Result<CommandResponse, ApiError> {
+    info!("running command {name}: {command}");
+    let result = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| {
+            error!("failed to execute command {name}: {err}");
+            ApiError::internal(ErrorCode::Internal, format!("failed to execute {name}: {err}"))
+        })?;
+
+    let mut output = String::from_utf8_lossy(&result.stdout).into_owned();
+    output.push_str(&String::from_utf8_lossy(&result.stderr));
+    let response = CommandResponse {
+        name: name.to_string(),
+        exit_code: result.status.code(),
+        output: crate::tail_lines(&output, 20),
+    };
+    if result.status.success() {
+        return Ok(response);
+    }
+
+    error!("command {name} failed with status: {}", result.status);
+    let message = match response.output.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => format!("{name} failed with {}: {}", result.status, line.trim()),
+        None => format!("{name} failed with {}", result.status),
+    };
+    Err(ApiError::internal(ErrorCode::CommandFailed, message).with_details(serde_json::json!({
+        "exit_code": response.exit_code,
+        "output": response.output,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_allowlist() {
+        let allowlist = CommandAllowlist::parse(
+            "# follow-ups\nrestart-nginx: systemctl restart nginx\n\nflush_cache: redis-cli FLUSHALL\n",
+        )
+        .unwrap();
+        assert_eq!(allowlist.len(), 2);
+        assert_eq!(allowlist.get("restart-nginx").ok(), Some("systemctl restart nginx"));
+        assert!(allowlist.get("reboot").is_err());
+
+        assert!(CommandAllowlist::parse("restart nginx\n").is_err());
+        assert!(CommandAllowlist::parse("../x: true\n").is_err());
+        assert!(CommandAllowlist::parse("a: true\na: false\n").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let response = run("greet", "echo hello; echo world >&2").unwrap();
+        assert_eq!(response.exit_code, Some(0));
+        assert_eq!(response.output, "hello\nworld");
+
+        assert!(run("fail", "echo nope; exit 3").is_err());
+    }
+}
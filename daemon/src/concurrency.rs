@@ -0,0 +1,103 @@
+//! Caps on in-flight requests. A dashboard fanning out dozens of requests at once gets `429`
+//! for the excess instead of pushing a small node into swap. Open connections are capped where
+//! they are accepted, in `server`.
+
+use crate::error::ApiError;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use cobbler_api::ErrorCode;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Defaults of `--max-connections`, `--max-requests` and `--max-waiters`.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+pub const DEFAULT_MAX_REQUESTS: usize = 64;
+pub const DEFAULT_MAX_WAITERS: usize = 128;
+/// Suggested `retry_after` (seconds) of the `429` answers.
+const RETRY_AFTER: u64 = 1;
+/// Long polls, which spend most of their time waiting and so get a budget of their own rather
+/// than crowding out the requests that do work.
+const LONG_POLLS: &[&str] = &["/status/wait"];
+
+/// Budgets of requests in flight; `None` for no limit.
+#[derive(Clone, Default)]
+pub struct Permits {
+    pub requests: Option<Arc<Semaphore>>,
+    pub waiters: Option<Arc<Semaphore>>,
+}
+
+impl Permits {
+    /// Budgets of `requests` and `waiters`, where `0` disables a limit.
+    pub fn new(requests: usize, waiters: usize) -> Self {
+        let budget = |max: usize| (max > 0).then(|| Arc::new(Semaphore::new(max)));
+        Permits {
+            requests: budget(requests),
+            waiters: budget(waiters),
+        }
+    }
+}
+
+fn too_many(what: &str) -> Response {
+    ApiError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::TooManyRequests,
+        format!("too many concurrent {what}"),
+    )
+    .with_retry_after(RETRY_AFTER)
+    .into_response()
+}
+
+/// Answers requests beyond their budget in flight with `429`.
+pub async fn limit_requests(State(permits): State<Permits>, req: Request, next: Next) -> Response {
+    let (budget, what) = if LONG_POLLS.contains(&req.uri().path()) {
+        (permits.waiters, "waiting requests")
+    } else {
+        (permits.requests, "requests")
+    };
+    let Some(budget) = budget else {
+        return next.run(req).await;
+    };
+    let Ok(_permit) = budget.try_acquire_owned() else {
+        return too_many(what);
+    };
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_limit_requests() {
+        let permits = Permits::new(1, 1);
+        let app = Router::new()
+            .route("/status", get(|| async { "ok" }))
+            .route("/status/wait", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(permits.clone(), limit_requests));
+        let get = |uri| Request::get(uri).body(Body::empty()).unwrap();
+
+        assert_eq!(app.clone().oneshot(get("/status")).await.unwrap().status(), StatusCode::OK);
+        let busy = permits.requests.as_ref().unwrap().try_acquire().unwrap();
+        let response = app.clone().oneshot(get("/status")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        // Long polls draw on their own budget.
+        assert_eq!(app.clone().oneshot(get("/status/wait")).await.unwrap().status(), StatusCode::OK);
+        drop(busy);
+        assert_eq!(app.clone().oneshot(get("/status")).await.unwrap().status(), StatusCode::OK);
+
+        let waiting = permits.waiters.as_ref().unwrap().try_acquire().unwrap();
+        let response = app.clone().oneshot(get("/status/wait")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(app.clone().oneshot(get("/status")).await.unwrap().status(), StatusCode::OK);
+        drop(waiting);
+        assert_eq!(app.oneshot(get("/status/wait")).await.unwrap().status(), StatusCode::OK);
+    }
+}
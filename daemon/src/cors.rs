@@ -0,0 +1,115 @@
+//! CORS for browser dashboards that call the API directly instead of through a proxy. Off
+//! unless origins are configured; the API key still has to come with every request.
+
+use axum::http::header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER};
+use axum::http::{HeaderName, HeaderValue, Method};
+use cobbler_api::{API_KEY_HEADER, REQUEST_ID_HEADER};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Methods a dashboard needs to read status and start and follow jobs.
+pub const DEFAULT_METHODS: &str = "GET,POST,PUT,DELETE";
+const METHODS: [Method; 6] = [Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE];
+/// How long browsers may cache a preflight answer.
+const MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Parses `--cors-method`.
+pub fn parse_method(value: &str) -> Result<Method, String> {
+    METHODS
+        .into_iter()
+        .find(|method| method.as_str().eq_ignore_ascii_case(value))
+        .ok_or_else(|| format!("expected one of GET, HEAD, POST, PUT, PATCH or DELETE, got {value:?}"))
+}
+
+/// The layer answering preflights and adding CORS headers for `origins`, where `*` allows
+/// any origin. `None` without origins.
+pub fn layer(origins: &[String], methods: Vec<Method>) -> Result<Option<CorsLayer>, String> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|origin| parse_origin(origin))
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers([
+                header(API_KEY_HEADER),
+                header(REQUEST_ID_HEADER),
+                CONTENT_TYPE,
+                IF_NONE_MATCH,
+            ])
+            .expose_headers([header(REQUEST_ID_HEADER), RETRY_AFTER, ETAG])
+            .max_age(MAX_AGE),
+    ))
+}
+
+/// Header names are case-insensitive; `HeaderName` wants them lowercase.
+fn header(name: &str) -> HeaderName {
+    HeaderName::from_bytes(name.as_bytes()).expect("valid header name")
+}
+
+/// Browsers send the origin as `scheme://host[:port]`, which has to match exactly.
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let origin = origin.trim_end_matches('/');
+    let host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+        .filter(|host| !host.is_empty() && !host.contains('/'))
+        .ok_or_else(|| format!("invalid CORS origin {origin:?}, expected e.g. https://dashboard.example.com"))?;
+    HeaderValue::from_str(origin).map_err(|_| format!("invalid CORS origin host {host:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_method("delete"), Ok(Method::DELETE));
+        assert!(parse_method("CONNECT").is_err());
+        assert_eq!(parse_origin("https://dash.example.com/").unwrap(), "https://dash.example.com");
+        assert!(parse_origin("dash.example.com").is_err());
+        assert!(parse_origin("https://dash.example.com/status").is_err());
+        assert!(layer(&[], vec![Method::GET]).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let origins = ["http://localhost:3000".to_string()];
+        let cors = layer(&origins, vec![Method::GET, Method::POST]).unwrap().unwrap();
+        let app = Router::new().route("/status", get(|| async { "ok" })).layer(cors);
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/status")
+                .header("origin", origin)
+                .header("access-control-request-method", "GET")
+                .header("access-control-request-headers", API_KEY_HEADER)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(preflight("http://localhost:3000")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "http://localhost:3000");
+        assert_eq!(headers["access-control-allow-methods"], "GET,POST");
+        assert!(headers["access-control-allow-headers"].to_str().unwrap().contains("x-api-key"));
+
+        let response = app.oneshot(preflight("https://evil.example.com")).await.unwrap();
+        assert!(!response.headers().contains_key("access-control-allow-origin"));
+    }
+}
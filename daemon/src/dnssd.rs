@@ -0,0 +1,175 @@
+use cobbler_api::SERVICE_TYPE;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const RECORD_TTL: u32 = 300;
+
+/// Unicast DNS-SD records published via RFC 2136 dynamic updates (`nsupdate`).
+#[derive(Debug, Clone)]
+pub struct Registration {
+    pub domain: String,
+    pub server: Option<String>,
+    pub key_file: Option<PathBuf>,
+    pub instance: String,
+    pub hostname: String,
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+    pub id: String,
+}
+
+impl Registration {
+    fn zone(&self) -> String {
+        format!("{}.", self.domain.trim_end_matches('.'))
+    }
+
+    fn service_name(&self) -> String {
+        format!("{SERVICE_TYPE}.{}", self.zone())
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, self.service_name())
+    }
+
+    fn target(&self) -> String {
+        if self.hostname.contains('.') {
+            format!("{}.", self.hostname.trim_end_matches('.'))
+        } else {
+            format!("{}.{}", self.hostname, self.zone())
+        }
+    }
+
+    fn header(&self) -> String {
+        let mut script = String::new();
+        if let Some(server) = &self.server {
+            script.push_str(&format!("server {server}\n"));
+        }
+        script.push_str(&format!("zone {}\n", self.zone()));
+        script
+    }
+
+    fn delete_lines(&self) -> String {
+        let instance = self.instance_name();
+        format!(
+            "update delete {} PTR {instance}\nupdate delete {instance} SRV\nupdate delete {instance} TXT\n",
+            self.service_name()
+        )
+    }
+
+    pub fn register_script(&self) -> String {
+        let instance = self.instance_name();
+        let target = self.target();
+        let mut script = self.header();
+        script.push_str(&self.delete_lines());
+        if let Some(ip) = self.ip {
+            let record_type = if ip.is_ipv4() { "A" } else { "AAAA" };
+            script.push_str(&format!("update delete {target} {record_type}\n"));
+            script.push_str(&format!("update add {target} {RECORD_TTL} {record_type} {ip}\n"));
+        }
+        script.push_str(&format!(
+            "update add {} {RECORD_TTL} PTR {instance}\n",
+            self.service_name()
+        ));
+        script.push_str(&format!(
+            "update add {instance} {RECORD_TTL} SRV 0 0 {} {target}\n",
+            self.port
+        ));
+        script.push_str(&format!("update add {instance} {RECORD_TTL} TXT \"id={}\"\n", self.id));
+        script.push_str("send\n");
+        script
+    }
+
+    pub fn deregister_script(&self) -> String {
+        let mut script = self.header();
+        script.push_str(&self.delete_lines());
+        script.push_str("send\n");
+        script
+    }
+
+    pub fn register(&self) -> Result<(), String> {
+        self.run_nsupdate(&self.register_script())
+    }
+
+    pub fn deregister(&self) -> Result<(), String> {
+        self.run_nsupdate(&self.deregister_script())
+    }
+
+    fn run_nsupdate(&self, script: &str) -> Result<(), String> {
+        let mut command = Command::new("nsupdate");
+        if let Some(key_file) = &self.key_file {
+            command.arg("-k").arg(key_file);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to execute nsupdate: {e}"))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(script.as_bytes())
+                .map_err(|e| format!("failed to write nsupdate script: {e}"))?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("failed to wait for nsupdate: {e}"))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "nsupdate failed with status {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration() -> Registration {
+        Registration {
+            domain: "fleet.example.com".to_string(),
+            server: Some("10.0.0.53".to_string()),
+            key_file: None,
+            instance: "cobblerd-raspi1".to_string(),
+            hostname: "raspi1".to_string(),
+            ip: Some("10.0.0.21".parse().unwrap()),
+            port: 8080,
+            id: "raspi1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_register_script() {
+        let script = registration().register_script();
+        assert!(script.starts_with("server 10.0.0.53\nzone fleet.example.com.\n"));
+        assert!(script.contains("update add raspi1.fleet.example.com. 300 A 10.0.0.21\n"));
+        assert!(script.contains(
+            "update add _cobbler._tcp.fleet.example.com. 300 PTR cobblerd-raspi1._cobbler._tcp.fleet.example.com.\n"
+        ));
+        assert!(script.contains(
+            "update add cobblerd-raspi1._cobbler._tcp.fleet.example.com. 300 SRV 0 0 8080 raspi1.fleet.example.com.\n"
+        ));
+        assert!(script.contains("TXT \"id=raspi1\"\n"));
+        assert!(script.ends_with("send\n"));
+    }
+
+    #[test]
+    fn test_deregister_script_uses_fqdn_hostname() {
+        let mut registration = registration();
+        registration.hostname = "raspi1.lan.example.com".to_string();
+        registration.server = None;
+        assert_eq!(registration.target(), "raspi1.lan.example.com.");
+
+        let script = registration.deregister_script();
+        assert!(script.starts_with("zone fleet.example.com.\n"));
+        assert!(!script.contains("update add"));
+        assert!(script.contains("update delete cobblerd-raspi1._cobbler._tcp.fleet.example.com. SRV\n"));
+    }
+}
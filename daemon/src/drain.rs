@@ -0,0 +1,79 @@
+//! Draining on shutdown: for a short while after SIGTERM the daemon keeps listening and
+//! answers new requests with 503, so a restart looks like one to clients instead of
+//! refused connections from a node that seems to be gone.
+
+use crate::error::ApiError;
+use axum::extract::{Request, State};
+use axum::http::header::CONNECTION;
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use cobbler_api::ErrorCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Default of `--drain-period`, in seconds.
+pub const DEFAULT_DRAIN_PERIOD: u64 = 5;
+/// Suggested `retry_after` (seconds); a restarted daemon is usually back by then.
+const RETRY_AFTER: u64 = 5;
+
+#[derive(Debug, Clone, Default)]
+pub struct Draining(Arc<AtomicBool>);
+
+impl Draining {
+    pub fn start(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Answers every request with `SHUTTING_DOWN` once draining has started, and closes the
+/// connection so that keep-alive clients reconnect to whatever comes up next.
+pub async fn reject(State(draining): State<Draining>, req: Request, next: Next) -> Response {
+    if !draining.is_draining() {
+        return next.run(req).await;
+    }
+    let mut response = ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ShuttingDown,
+        "the daemon is shutting down",
+    )
+    .with_retry_after(RETRY_AFTER)
+    .into_response();
+    response.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header::RETRY_AFTER as RETRY_AFTER_HEADER;
+    use axum::middleware;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_reject() {
+        let draining = Draining::default();
+        let app = Router::new()
+            .route("/version", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(draining.clone(), reject));
+        let get = || Request::get("/version").body(Body::empty()).unwrap();
+
+        assert_eq!(app.clone().oneshot(get()).await.unwrap().status(), StatusCode::OK);
+
+        draining.start();
+        let response = app.oneshot(get()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER_HEADER], "5");
+        assert_eq!(response.headers()[CONNECTION], "close");
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        let error: cobbler_api::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, ErrorCode::ShuttingDown);
+    }
+}
@@ -0,0 +1,108 @@
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::http::header::RETRY_AFTER;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use cobbler_api::{ErrorCode, ErrorResponse};
+
+/// An error response in the shared envelope (`code`, `message`, `details`, `retry_after`).
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: ErrorResponse,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            body: ErrorResponse::new(code, message),
+        }
+    }
+
+    pub fn not_debian() -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            ErrorCode::NotDebian,
+            "the system is not a Debian-based Linux system",
+        )
+    }
+
+    pub fn not_installed(package: &str) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            ErrorCode::NotInstalled,
+            format!("{package} is not installed"),
+        )
+        .with_details(serde_json::json!({ "package": package }))
+    }
+
+    pub fn internal(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.body.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.body.message
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.body.details = Some(details);
+        self
+    }
+
+    /// Also sets the `Retry-After` header.
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.body.retry_after = Some(seconds);
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(&self.body)).into_response();
+        if let Some(seconds) = self.body.retry_after {
+            response.headers_mut().insert(RETRY_AFTER, seconds.into());
+        }
+        response
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), ErrorCode::InvalidRequest, rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), ErrorCode::InvalidRequest, rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let response = ApiError::new(
+            StatusCode::PRECONDITION_FAILED,
+            ErrorCode::UpgradeInProgress,
+            "a full upgrade is currently running",
+        )
+        .with_retry_after(30)
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, ErrorCode::UpgradeInProgress);
+        assert_eq!(error.retry_after, Some(30));
+    }
+}
@@ -0,0 +1,65 @@
+//! Weak ETags and `If-None-Match` handling, so pollers don't re-transfer unchanged payloads.
+
+use axum::http::{header::IF_NONE_MATCH, HeaderMap};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Weak ETag of `value`, ignoring the top-level keys in `ignore`. Used for fields that
+/// change on every request (like uptime) but don't make the payload meaningfully different.
+pub fn weak(value: &Value, ignore: &[&str]) -> String {
+    let mut value = value.clone();
+    if let Value::Object(map) = &mut value {
+        map.retain(|key, _| !ignore.contains(&key.as_str()));
+    }
+    let digest: String = Sha256::digest(value.to_string().as_bytes())[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("W/\"{digest}\"")
+}
+
+/// Whether `If-None-Match` matches `etag`, using the weak comparison GET requests call for.
+pub fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || same(tag, etag))
+}
+
+/// Weak comparison of two ETags, which ignores the `W/` prefix. The quotes may be left
+/// out, as they tend to be in query parameters.
+pub fn same(a: &str, b: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").trim_matches('"').to_string();
+    opaque(a) == opaque(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak_etag() {
+        let status = serde_json::json!({ "message": "System is up to date", "uptime_seconds": 10 });
+        let etag = weak(&status, &["uptime_seconds"]);
+        assert!(etag.starts_with("W/\"") && etag.ends_with('"'));
+        assert_eq!(etag.len(), 2 + 32 + 2);
+
+        let later = serde_json::json!({ "message": "System is up to date", "uptime_seconds": 70 });
+        assert_eq!(weak(&later, &["uptime_seconds"]), etag);
+        assert_ne!(weak(&later, &[]), weak(&status, &[]));
+
+        let mut headers = HeaderMap::new();
+        assert!(!matches(&headers, &etag));
+        headers.insert(IF_NONE_MATCH, format!("\"other\", {}", etag.trim_start_matches("W/")).parse().unwrap());
+        assert!(matches(&headers, &etag));
+        headers.insert(IF_NONE_MATCH, "\"other\"".parse().unwrap());
+        assert!(!matches(&headers, &etag));
+        headers.insert(IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(matches(&headers, &etag));
+
+        assert!(same(etag.trim_start_matches("W/").trim_matches('"'), &etag));
+        assert!(!same("\"other\"", &etag));
+    }
+}
@@ -0,0 +1,79 @@
+//! The `fields` query parameter, which trims responses to what the client asked for.
+
+use crate::error::ApiError;
+use axum::http::StatusCode;
+use cobbler_api::ErrorCode;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Fields selected by `?fields=a,b`. Everything is selected if the parameter is absent or
+/// empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields(Option<Vec<String>>);
+
+impl Fields {
+    /// Parses the comma-separated `param`, rejecting names not in `allowed`.
+    pub fn parse(param: Option<&str>, allowed: &[&str]) -> Result<Self, ApiError> {
+        let names: Vec<String> = param
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if let Some(unknown) = names.iter().find(|name| !allowed.contains(&name.as_str())) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                format!("unknown field `{unknown}`"),
+            )
+            .with_details(serde_json::json!({ "field": unknown, "allowed": allowed })));
+        }
+        Ok(Fields((!names.is_empty()).then_some(names)))
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.0.as_ref().is_none_or(|names| names.iter().any(|name| name == field))
+    }
+
+    pub fn contains_any(&self, fields: &[&str]) -> bool {
+        fields.iter().any(|field| self.contains(field))
+    }
+
+    /// Serializes `value` and drops the top-level keys that were not selected.
+    pub fn select<T: Serialize>(&self, value: &T) -> Value {
+        let mut value = serde_json::to_value(value).unwrap_or_default();
+        if let Value::Object(map) = &mut value {
+            map.retain(|key, _| self.contains(key));
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALLOWED: &[&str] = &["message", "is_upgrading", "reboot_required"];
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Fields::parse(None, ALLOWED).unwrap(), Fields(None));
+        assert_eq!(Fields::parse(Some(""), ALLOWED).unwrap(), Fields(None));
+
+        let fields = Fields::parse(Some("message, reboot_required,"), ALLOWED).unwrap();
+        assert!(fields.contains("message"));
+        assert!(!fields.contains("is_upgrading"));
+
+        assert!(Fields::parse(Some("message,bogus"), ALLOWED).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let value = serde_json::json!({ "message": "ok", "is_upgrading": false, "reboot_required": true });
+        assert_eq!(Fields::default().select(&value), value);
+
+        let fields = Fields::parse(Some("reboot_required"), ALLOWED).unwrap();
+        assert_eq!(fields.select(&value), serde_json::json!({ "reboot_required": true }));
+    }
+}
@@ -0,0 +1,27 @@
+//! Patterns for service allowlists and package exclusions, where `*` stands for any text.
+
+/// Matches `name` against a pattern where `*` stands for any text.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            (0..=name.len())
+                .filter(|&index| name.is_char_boundary(index))
+                .any(|index| matches(rest, &name[index..]))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("nvidia-*", "nvidia-driver"));
+        assert!(matches("*ssl*", "libssl3"));
+        assert!(matches("postgresql-16", "postgresql-16"));
+        assert!(!matches("postgresql-16", "postgresql-16-jit"));
+        assert!(!matches("nvidia-*", "libnvidia-gl"));
+    }
+}
@@ -0,0 +1,193 @@
+//! Conditions under which a node should not start an upgrade: running on battery, where a
+//! long dpkg run may be cut off mid-way, a metered connection, where downloads cost money,
+//! and a nearly full disk, where dpkg may fail half-way through unpacking.
+
+use crate::error::ApiError;
+use axum::http::StatusCode;
+use clap::ValueEnum;
+use cobbler_api::ErrorCode;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+/// Where an upgrade writes: the root filesystem and apt's download cache.
+const UPGRADE_PATHS: [&str; 2] = ["/", "/var/cache/apt/archives"];
+/// How long clients are asked to wait before retrying a deferred upgrade.
+const DEFER_RETRY_AFTER: u64 = 300;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GuardMode {
+    /// Upgrade regardless
+    #[default]
+    Off,
+    /// Refuse the upgrade
+    Refuse,
+    /// Refuse the upgrade, but ask the client to retry later
+    Defer,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Guards {
+    pub battery: GuardMode,
+    pub metered: GuardMode,
+    /// Free space in MiB required on each of the upgrade paths; `0` disables the check.
+    pub min_free_space: u64,
+}
+
+impl Guards {
+    /// Fails with `ON_BATTERY`, `METERED_CONNECTION` or `LOW_DISK_SPACE` if an enabled guard
+    /// applies.
+    pub fn check(&self) -> Result<(), ApiError> {
+        if self.battery != GuardMode::Off && on_battery(Path::new(POWER_SUPPLY_DIR)) {
+            return Err(refusal(
+                self.battery,
+                ErrorCode::OnBattery,
+                "the node is running on battery",
+            ));
+        }
+        if self.metered != GuardMode::Off && is_metered() {
+            return Err(refusal(
+                self.metered,
+                ErrorCode::MeteredConnection,
+                "the node is on a metered connection",
+            ));
+        }
+        let free = (self.min_free_space > 0).then(free_space).flatten();
+        if let Some(free) = free.filter(|&free| free < self.min_free_space) {
+            return Err(ApiError::new(
+                StatusCode::PRECONDITION_FAILED,
+                ErrorCode::LowDiskSpace,
+                format!(
+                    "only {free} MiB of disk space free, {} MiB required",
+                    self.min_free_space
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn refusal(mode: GuardMode, code: ErrorCode, message: &str) -> ApiError {
+    let error = ApiError::new(StatusCode::PRECONDITION_FAILED, code, message);
+    if mode == GuardMode::Defer {
+        error.with_retry_after(DEFER_RETRY_AFTER)
+    } else {
+        error
+    }
+}
+
+/// On battery means a system battery (not that of a wireless mouse) and no mains or USB
+/// supply online. Hosts without batteries never are.
+fn on_battery(power_supply_dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(power_supply_dir) else {
+        return false;
+    };
+    let read = |dir: &Path, name: &str| {
+        fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+
+    let mut has_battery = false;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        match read(&dir, "type").as_deref() {
+            Some("Mains" | "USB") if read(&dir, "online").as_deref() == Some("1") => return false,
+            Some("Battery") if read(&dir, "scope").as_deref() != Some("Device") => has_battery = true,
+            _ => {}
+        }
+    }
+    has_battery
+}
+
+/// Asks NetworkManager; without it, no connection counts as metered.
+fn is_metered() -> bool {
+    Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output()
+        .is_ok_and(|output| output.status.success() && parse_metered(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the `u <NMMetered>` busctl prints, where 1 is yes and 3 is a guessed yes.
+fn parse_metered(output: &str) -> bool {
+    matches!(output.trim().strip_prefix("u "), Some("1" | "3"))
+}
+
+/// The least free space in MiB among the upgrade paths, as reported by df. Paths that
+/// don't exist are left out; if df fails, the check is skipped.
+fn free_space() -> Option<u64> {
+    let paths: Vec<&str> = UPGRADE_PATHS
+        .into_iter()
+        .filter(|path| Path::new(path).exists())
+        .collect();
+    let output = Command::new("df")
+        .args(["--output=avail", "-BM"])
+        .args(&paths)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the `Avail` column of `df --output=avail -BM`, one `1234M` line per path after
+/// the header, into the smallest value.
+fn parse_df(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.trim().strip_suffix('M')?.parse().ok())
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supply(dir: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), format!("{content}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_on_battery() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-guards-{}", std::process::id()));
+        assert!(!on_battery(&dir));
+
+        supply(&dir, "hidpp_battery_0", &[("type", "Battery"), ("scope", "Device")]);
+        assert!(!on_battery(&dir));
+        supply(&dir, "BAT0", &[("type", "Battery"), ("status", "Discharging")]);
+        supply(&dir, "AC", &[("type", "Mains"), ("online", "0")]);
+        assert!(on_battery(&dir));
+        supply(&dir, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert!(!on_battery(&dir));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_metered() {
+        assert!(parse_metered("u 1\n"));
+        assert!(parse_metered("u 3\n"));
+        assert!(!parse_metered("u 2\n"));
+        assert!(!parse_metered("u 0\n"));
+        assert!(!parse_metered(""));
+    }
+
+    #[test]
+    fn test_parse_df() {
+        assert_eq!(parse_df("Avail\n 20480M\n  512M\n"), Some(512));
+        assert_eq!(parse_df("Avail\n"), None);
+        assert_eq!(parse_df(""), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! apt's own record of what it did, from `/var/log/apt/history.log` and its rotations
+//! (`history.log.1`, `history.log.2.gz`, ...). It covers every apt run on the node, not just
+//! those cobbler started.
+
+use cobbler_api::{AptTransaction, PackageChange};
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+pub const HISTORY_DIR: &str = "/var/log/apt";
+const HISTORY_FILE: &str = "history.log";
+
+/// The `limit` most recent transactions, newest first. Rotated logs are only read as far as
+/// needed.
+pub fn recent(dir: &Path, limit: usize) -> io::Result<Vec<AptTransaction>> {
+    let mut transactions = Vec::new();
+    for path in log_files(dir)? {
+        let content = read_log(&path)?;
+        transactions.extend(parse(&content).into_iter().rev());
+        if transactions.len() >= limit {
+            break;
+        }
+    }
+    transactions.truncate(limit);
+    Ok(transactions)
+}
+
+/// `history.log` followed by its rotations, newest first.
+fn log_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut rotations = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(rest) = name.to_str().and_then(|name| name.strip_prefix(HISTORY_FILE)) else {
+            continue;
+        };
+        let index = match rest.strip_prefix('.') {
+            None if rest.is_empty() => Some(0),
+            Some(suffix) => suffix.strip_suffix(".gz").unwrap_or(suffix).parse::<u32>().ok(),
+            None => None,
+        };
+        if let Some(index) = index {
+            rotations.push((index, entry.path()));
+        }
+    }
+    rotations.sort();
+    Ok(rotations.into_iter().map(|(_, path)| path).collect())
+}
+
+fn read_log(path: &Path) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        GzDecoder::new(File::open(path)?).read_to_end(&mut bytes)?;
+    } else {
+        File::open(path)?.read_to_end(&mut bytes)?;
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Parses the blank-line separated entries of a history log, oldest first. Unknown keys
+/// (such as `Reinstall:`) are ignored, and so is anything before the first `Start-Date:`.
+fn parse(content: &str) -> Vec<AptTransaction> {
+    let mut transactions: Vec<AptTransaction> = Vec::new();
+    let mut current: Option<AptTransaction> = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(": ") else {
+            continue;
+        };
+        let value = value.trim();
+        if key == "Start-Date" {
+            transactions.extend(current.take());
+            current = Some(AptTransaction {
+                start: timestamp(value),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(transaction) = current.as_mut() else {
+            continue;
+        };
+        match key {
+            "End-Date" => transaction.end = Some(timestamp(value)),
+            "Commandline" => transaction.command_line = Some(value.to_string()),
+            "Requested-By" => transaction.requested_by = Some(value.to_string()),
+            "Install" => transaction.installed.extend(parse_packages(value)),
+            "Upgrade" => transaction.upgraded.extend(parse_packages(value)),
+            "Downgrade" => transaction.downgraded.extend(parse_packages(value)),
+            "Remove" | "Purge" => transaction.removed.extend(parse_packages(value)),
+            "Error" => transaction.error = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    transactions.extend(current);
+    transactions
+}
+
+/// apt logs `2024-05-01  10:12:03`; the API uses ISO 8601 without a zone.
+fn timestamp(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join("T")
+}
+
+/// Parses `libc6:amd64 (2.36-9+deb12u4, 2.36-9+deb12u7), vim:amd64 (2:9.0.1378-2)`. The
+/// parentheses hold the version, the old and new version of an upgrade, or the version
+/// followed by `automatic`.
+fn parse_packages(value: &str) -> Vec<PackageChange> {
+    let mut packages = Vec::new();
+    let mut rest = value;
+    while let Some(open) = rest.find(" (") {
+        let Some(close) = rest[open..].find(')').map(|close| open + close) else {
+            break;
+        };
+        let package = rest[..open].trim_start_matches([',', ' ']);
+        let (name, arch) = match package.split_once(':') {
+            Some((name, arch)) => (name, Some(arch.to_string())),
+            None => (package, None),
+        };
+        let mut details: Vec<&str> = rest[open + 2..close].split(", ").collect();
+        let automatic = details.last() == Some(&"automatic");
+        if automatic {
+            details.pop();
+        }
+        let (old_version, version) = match details.as_slice() {
+            [old, new] => (Some(old.to_string()), new.to_string()),
+            [version, ..] => (None, version.to_string()),
+            [] => (None, String::new()),
+        };
+        packages.push(PackageChange {
+            name: name.to_string(),
+            arch,
+            old_version,
+            version,
+            automatic,
+        });
+        rest = &rest[close + 1..];
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const LOG: &str = "
+Start-Date: 2024-05-01  10:12:03
+Commandline: apt-get -y dist-upgrade
+Requested-By: admin (1000)
+Install: linux-image-6.1.0-21-amd64:amd64 (6.1.90-1, automatic)
+Upgrade: libc6:amd64 (2.36-9+deb12u4, 2.36-9+deb12u7), vim:amd64 (2:9.0.1378-2, 2:9.0.1378-2+deb12u1)
+Purge: old-tool:amd64 (1.0-1)
+End-Date: 2024-05-01  10:12:30
+
+Start-Date: 2024-05-02  08:00:00
+Commandline: apt-get install broken
+Error: Sub-process /usr/bin/dpkg returned an error code (1)
+";
+
+    #[test]
+    fn test_parse() {
+        let transactions = parse(LOG);
+        assert_eq!(transactions.len(), 2);
+        let upgrade = &transactions[0];
+        assert_eq!(upgrade.start, "2024-05-01T10:12:03");
+        assert_eq!(upgrade.end.as_deref(), Some("2024-05-01T10:12:30"));
+        assert_eq!(upgrade.requested_by.as_deref(), Some("admin (1000)"));
+        assert_eq!(
+            upgrade.installed,
+            [PackageChange {
+                name: "linux-image-6.1.0-21-amd64".to_string(),
+                arch: Some("amd64".to_string()),
+                old_version: None,
+                version: "6.1.90-1".to_string(),
+                automatic: true,
+            }]
+        );
+        assert_eq!(upgrade.upgraded.len(), 2);
+        assert_eq!(upgrade.upgraded[1].name, "vim");
+        assert_eq!(upgrade.upgraded[1].old_version.as_deref(), Some("2:9.0.1378-2"));
+        assert_eq!(upgrade.upgraded[1].version, "2:9.0.1378-2+deb12u1");
+        assert_eq!(upgrade.removed[0].name, "old-tool");
+
+        assert_eq!(transactions[1].end, None);
+        assert_eq!(
+            transactions[1].error.as_deref(),
+            Some("Sub-process /usr/bin/dpkg returned an error code (1)")
+        );
+    }
+
+    #[test]
+    fn test_recent() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("history.log"), "Start-Date: 2024-06-01  09:00:00\nEnd-Date: 2024-06-01  09:00:10\n").unwrap();
+        let mut encoder = GzEncoder::new(File::create(dir.join("history.log.1.gz")).unwrap(), Compression::default());
+        encoder.write_all(LOG.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        fs::write(dir.join("history.log.bak"), LOG).unwrap();
+
+        let starts = |limit| -> Vec<String> {
+            recent(&dir, limit).unwrap().into_iter().map(|transaction| transaction.start).collect()
+        };
+        assert_eq!(starts(2), ["2024-06-01T09:00:00", "2024-05-02T08:00:00"]);
+        assert_eq!(starts(10).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(recent(&dir, 10).unwrap().is_empty());
+    }
+}
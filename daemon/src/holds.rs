@@ -0,0 +1,164 @@
+//! Packages a full upgrade must never touch, such as a GPU driver or a pinned database
+//! engine. They are put on hold with `apt-mark` for the duration of the upgrade and released
+//! afterwards; packages the operator held themselves stay held. The temporary holds are
+//! recorded first, so a daemon that stops in between releases them when it starts again.
+
+use crate::glob;
+use cobbler_api::PackageInfo;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Packages held for one upgrade.
+#[derive(Debug, Default)]
+pub struct TemporaryHolds {
+    packages: Vec<String>,
+    record: Option<PathBuf>,
+}
+
+/// The installed packages matching one of `patterns` that aren't on hold yet, sorted.
+pub fn select(installed: &[PackageInfo], patterns: &[String]) -> Vec<String> {
+    let mut packages: Vec<String> = installed
+        .iter()
+        .filter(|pkg| !pkg.held && patterns.iter().any(|pattern| glob::matches(pattern, &pkg.name)))
+        .map(|pkg| pkg.name.clone())
+        .collect();
+    packages.sort();
+    packages.dedup();
+    packages
+}
+
+/// Holds `packages`, after recording them in `record` if set.
+pub fn hold(packages: Vec<String>, record: Option<&Path>) -> io::Result<TemporaryHolds> {
+    let holds = TemporaryHolds {
+        packages,
+        record: record.map(Path::to_path_buf),
+    };
+    if holds.packages.is_empty() {
+        return Ok(holds);
+    }
+    if let Some(record) = &holds.record {
+        save(record, &holds.packages)?;
+    }
+    if let Err(err) = apt_mark("hold", &holds.packages) {
+        // Some of them may be held already.
+        holds.release();
+        return Err(err);
+    }
+    info!("temporarily holding {}", holds.packages.join(", "));
+    Ok(holds)
+}
+
+impl TemporaryHolds {
+    pub fn packages(&self) -> &[String] {
+        &self.packages
+    }
+
+    /// Releases the holds. If that fails, the record stays for the next daemon start to
+    /// try again.
+    pub fn release(self) {
+        if self.packages.is_empty() {
+            return;
+        }
+        if let Err(err) = apt_mark("unhold", &self.packages) {
+            warn!("failed to release the temporary holds on {}: {err}", self.packages.join(", "));
+            return;
+        }
+        info!("released the temporary holds on {}", self.packages.join(", "));
+        if let Some(record) = &self.record
+            && let Err(err) = fs::remove_file(record)
+        {
+            warn!("failed to remove {}: {err}", record.display());
+        }
+    }
+}
+
+/// Releases the holds a previous daemon recorded in `record` but never released.
+pub fn release_recorded(record: &Path) {
+    let packages = match load(record) {
+        Ok(Some(packages)) => packages,
+        Ok(None) => return,
+        Err(err) => {
+            warn!("failed to read {}: {err}", record.display());
+            return;
+        }
+    };
+    warn!("releasing temporary holds left behind by a previous daemon");
+    TemporaryHolds {
+        packages,
+        record: Some(record.to_path_buf()),
+    }
+    .release();
+}
+
+/// Writes through a temporary file, like the job state file.
+fn save(record: &Path, packages: &[String]) -> io::Result<()> {
+    if let Some(dir) = record.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = record.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(packages)?)?;
+    fs::rename(&tmp, record)
+}
+
+fn load(record: &Path) -> io::Result<Option<Vec<String>>> {
+    match fs::read(record) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn apt_mark(action: &str, packages: &[String]) -> io::Result<()> {
+    let output = Command::new("apt-mark").arg(action).args(packages).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("apt-mark {action} failed: {}", stderr.trim())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, held: bool) -> PackageInfo {
+        PackageInfo {
+            name: name.to_string(),
+            installed_version: "1.0-1".to_string(),
+            candidate_version: None,
+            upgradable: false,
+            held,
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let installed = [
+            package("nvidia-driver", false),
+            package("nvidia-kernel-dkms", true),
+            package("postgresql-16", false),
+            package("libnvidia-gl", false),
+            package("vim", false),
+        ];
+        let patterns = ["nvidia-*".to_string(), "postgresql-16".to_string()];
+        assert_eq!(select(&installed, &patterns), ["nvidia-driver", "postgresql-16"]);
+        assert!(select(&installed, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_record() {
+        let record = std::env::temp_dir().join(format!("cobblerd-holds-{}", std::process::id())).join("holds.json");
+        assert!(load(&record).unwrap().is_none());
+        save(&record, &["nvidia-driver".to_string()]).unwrap();
+        assert_eq!(load(&record).unwrap(), Some(vec!["nvidia-driver".to_string()]));
+        fs::remove_dir_all(record.parent().unwrap()).unwrap();
+
+        // Nothing to hold needs neither apt-mark nor a record.
+        let holds = hold(Vec::new(), Some(&record)).unwrap();
+        assert!(holds.packages().is_empty());
+        assert!(!record.exists());
+    }
+}
@@ -0,0 +1,663 @@
+pub use cobbler_api::{Job, JobKind, JobState};
+use cobbler_api::{ErrorCode, JobProgress, JobsQuery, JobsSummary};
+use crate::changes::Changes;
+use crate::progress;
+use crate::recovery::{InFlight, StateFile};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+const MAX_RETAINED_JOBS: usize = 50;
+/// Output lines kept per job; a longer job loses its oldest ones.
+const MAX_LOG_LINES: usize = 10_000;
+/// How long a stopped job gets to exit after `SIGTERM` before it is killed.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// How often output written to files is checked for more.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<Vec<Job>>>,
+    processes: Arc<Mutex<Processes>>,
+    logs: Arc<Mutex<HashMap<String, JobLog>>>,
+    state_file: Option<StateFile>,
+    /// Package changes in progress that aren't jobs: installs, removals and repairs.
+    actions: Arc<AtomicUsize>,
+    /// Jobs starting or finishing, and actions.
+    changes: Changes,
+}
+
+/// Counts as a package change in progress until dropped.
+pub struct Action(Arc<AtomicUsize>, Changes);
+
+impl Drop for Action {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+        self.1.notify();
+    }
+}
+
+/// Output of a job's process, stdout and stderr interleaved as they arrive.
+#[derive(Default)]
+struct JobLog {
+    lines: VecDeque<String>,
+    /// Lines dropped from the front, so that line numbers stay stable as cursors.
+    dropped: u64,
+}
+
+/// The process groups of running jobs, and the jobs asked to stop.
+#[derive(Default)]
+struct Processes {
+    pids: HashMap<String, u32>,
+    stopping: HashMap<String, StopReason>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Cancelled,
+    /// The job exceeded its maximum runtime.
+    TimedOut,
+}
+
+impl JobRegistry {
+    /// Records the job and process of every running upgrade in `state_file`.
+    pub fn with_state_file(state_file: StateFile) -> Self {
+        JobRegistry {
+            state_file: Some(state_file),
+            ..Default::default()
+        }
+    }
+
+    pub fn state_file(&self) -> Option<&StateFile> {
+        self.state_file.as_ref()
+    }
+
+    /// Notified when a job is added, starts or finishes, and when an action starts or ends.
+    pub fn changes(&self) -> &Changes {
+        &self.changes
+    }
+
+    pub fn start(&self, kind: JobKind) -> Job {
+        self.add(kind, JobState::Running)
+    }
+
+    /// Records a job that waits for other package operations before it starts.
+    pub fn enqueue(&self, kind: JobKind) -> Job {
+        self.add(kind, JobState::Queued)
+    }
+
+    /// Brings back a running job from a previous daemon.
+    pub fn restore(&self, in_flight: &InFlight) -> Job {
+        self.insert(new_job(in_flight.job_id.clone(), in_flight.kind, JobState::Running, in_flight.started_at))
+    }
+
+    /// Lets a process the daemon did not start itself be stopped like the job's own one.
+    pub fn adopt_process(&self, id: &str, pid: u32) {
+        self.processes().pids.insert(id.to_string(), pid);
+    }
+
+    fn add(&self, kind: JobKind, state: JobState) -> Job {
+        self.insert(new_job(uuid::Uuid::new_v4().to_string(), kind, state, unix_now()))
+    }
+
+    fn insert(&self, job: Job) -> Job {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.push(job.clone());
+        if jobs.len() > MAX_RETAINED_JOBS {
+            let excess = jobs.len() - MAX_RETAINED_JOBS;
+            let mut logs = self.logs();
+            for pruned in jobs.drain(..excess) {
+                logs.remove(&pruned.id);
+            }
+        }
+        drop(jobs);
+        self.changes.notify();
+        job
+    }
+
+    /// Moves a queued job to running; its start time becomes now.
+    pub fn run(&self, id: &str) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.state = JobState::Running;
+            job.started_at = unix_now();
+        }
+        drop(jobs);
+        self.changes.notify();
+    }
+
+    pub fn finish(&self, id: &str, state: JobState, exit_code: Option<i32>, message: String) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.state = state;
+            job.finished_at = Some(unix_now());
+            job.exit_code = exit_code;
+            job.message = message;
+            job.progress = None;
+        }
+        drop(jobs);
+        self.changes.notify();
+    }
+
+    pub fn set_progress(&self, id: &str, progress: JobProgress) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.progress = Some(progress);
+        }
+    }
+
+    pub fn set_code(&self, id: &str, code: ErrorCode) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.code = Some(code);
+        }
+    }
+
+    pub fn record_stats(&self, id: &str, duration: Duration, downloaded_bytes: Option<u64>) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.duration_seconds = Some(duration.as_secs_f64());
+            job.downloaded_bytes = downloaded_bytes;
+        }
+    }
+
+    /// Records a package change that runs outside of a job, such as an install, for
+    /// [`JobRegistry::is_upgrading`].
+    pub fn start_action(&self) -> Action {
+        self.actions.fetch_add(1, Ordering::SeqCst);
+        self.changes.notify();
+        Action(self.actions.clone(), self.changes.clone())
+    }
+
+    /// Whether packages are being changed: a full upgrade job, or an install, removal or
+    /// repair, is running. Other users of the operation queue, like refreshing the package
+    /// lists or a prefetch, don't count.
+    pub fn is_upgrading(&self) -> bool {
+        if self.actions.load(Ordering::SeqCst) > 0 {
+            return true;
+        }
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter()
+            .any(|job| job.kind == JobKind::FullUpgrade && job.state == JobState::Running)
+    }
+
+    /// The most recent job of `kind`, finished or not.
+    pub fn last(&self, kind: JobKind) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().rev().find(|job| job.kind == kind).cloned()
+    }
+
+    pub fn last_finished(&self, kind: JobKind, state: JobState) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter()
+            .rev()
+            .find(|job| job.kind == kind && job.state == state)
+            .cloned()
+    }
+
+    /// The jobs matching the filters of `query`, newest first. Its `limit` and `offset` are
+    /// left to the caller.
+    pub fn matching(&self, query: &JobsQuery) -> Vec<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter()
+            .rev()
+            .filter(|job| query.state.is_none_or(|state| job.state == state))
+            .filter(|job| query.kind.is_none_or(|kind| job.kind == kind))
+            .filter(|job| query.since.is_none_or(|since| job.started_at >= since))
+            .cloned()
+            .collect()
+    }
+
+    /// The running and queued jobs, oldest first, and the job that finished last.
+    pub fn summary(&self) -> JobsSummary {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        JobsSummary {
+            active: jobs
+                .iter()
+                .filter(|job| matches!(job.state, JobState::Running | JobState::Queued))
+                .cloned()
+                .collect(),
+            last_finished: jobs
+                .iter()
+                .filter(|job| job.finished_at.is_some())
+                .max_by_key(|job| job.finished_at)
+                .cloned(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    fn append_log(&self, id: &str, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let mut logs = self.logs();
+        let log = logs.entry(id.to_string()).or_default();
+        log.lines.push_back(line.trim_end_matches('\r').to_string());
+        if log.lines.len() > MAX_LOG_LINES {
+            log.lines.pop_front();
+            log.dropped += 1;
+        }
+    }
+
+    /// The output of a job from line `since` on (counting from 0 at its first line), only
+    /// the last `tail` of them if set, and the line to continue from. Lines that were
+    /// dropped for [`MAX_LOG_LINES`] are skipped.
+    pub fn log(&self, id: &str, since: u64, tail: Option<usize>) -> (Vec<String>, u64) {
+        let logs = self.logs();
+        let Some(log) = logs.get(id) else {
+            return (Vec::new(), since);
+        };
+        let next = log.dropped + log.lines.len() as u64;
+        let start = since.saturating_sub(log.dropped).min(log.lines.len() as u64) as usize;
+        let mut lines: Vec<String> = log.lines.range(start..).cloned().collect();
+        if let Some(tail) = tail {
+            lines.drain(..lines.len().saturating_sub(tail));
+        }
+        (lines, next)
+    }
+
+    /// Runs `command` for a job in its own process group, so that cancelling the job stops
+    /// it together with everything it started, such as dpkg and maintainer scripts. apt
+    /// status lines on stdout become the job's progress and are left out of the output;
+    /// everything else is also kept as the job's log. With a state file, the output goes
+    /// through files next to it, so that the process survives a daemon restart.
+    pub fn run_process(&self, id: &str, mut command: Command) -> io::Result<Output> {
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let (stdout, stderr, files) = match &self.state_file {
+            Some(file) => {
+                let [(stdout, stdout_reader), (stderr, stderr_reader)] = file.create_output()?;
+                (Stdio::from(stdout), Stdio::from(stderr), Some((stdout_reader, stderr_reader)))
+            }
+            None => (Stdio::piped(), Stdio::piped(), None),
+        };
+        let child = {
+            let mut processes = self.processes();
+            if processes.stopping.contains_key(id) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "stopped before it started"));
+            }
+            let child = command.stdin(Stdio::null()).stdout(stdout).stderr(stderr).spawn()?;
+            processes.pids.insert(id.to_string(), child.id());
+            child
+        };
+        if let (Some(file), Some(job)) = (&self.state_file, self.get(id)) {
+            let in_flight = InFlight {
+                job_id: job.id,
+                kind: job.kind,
+                pid: child.id(),
+                started_at: job.started_at,
+            };
+            if let Err(err) = file.save(&in_flight) {
+                warn!("failed to record job {id} in {}: {err}", file.path().display());
+            }
+        }
+        let output = self.wait_with_progress(id, child, files);
+        if let Some(file) = &self.state_file {
+            file.clear();
+        }
+        output
+    }
+
+    fn wait_with_progress(&self, id: &str, mut child: Child, files: Option<(File, File)>) -> io::Result<Output> {
+        let exited = Arc::new(AtomicBool::new(false));
+        let follow = |reader: Box<dyn Read + Send>| Follow { reader, exited: exited.clone() };
+        let (stdout, stderr) = match files {
+            Some((stdout, stderr)) => (Some(follow(Box::new(stdout))), Some(follow(Box::new(stderr)))),
+            None => (
+                child.stdout.take().map(|pipe| follow(Box::new(pipe))),
+                child.stderr.take().map(|pipe| follow(Box::new(pipe))),
+            ),
+        };
+        let waiter = {
+            let exited = exited.clone();
+            thread::spawn(move || {
+                let status = child.wait();
+                exited.store(true, Ordering::SeqCst);
+                status
+            })
+        };
+
+        // Drained on its own thread, so that neither pipe can fill up and stall apt.
+        let stderr = stderr.map(|stderr| {
+            let (jobs, id) = (self.clone(), id.to_string());
+            thread::spawn(move || {
+                let (mut reader, mut buf) = (BufReader::new(stderr), Vec::new());
+                loop {
+                    let start = buf.len();
+                    if reader.read_until(b'\n', &mut buf)? == 0 {
+                        return Ok::<_, io::Error>(buf);
+                    }
+                    let line = &buf[start..];
+                    jobs.append_log(&id, line.strip_suffix(b"\n").unwrap_or(line));
+                }
+            })
+        });
+
+        let mut output = Vec::new();
+        if let Some(stdout) = stdout {
+            for line in BufReader::new(stdout).split(b'\n') {
+                let Ok(line) = line else { break };
+                match progress::parse(&String::from_utf8_lossy(&line)) {
+                    Some(progress) => self.set_progress(id, progress),
+                    None => {
+                        self.append_log(id, &line);
+                        output.extend_from_slice(&line);
+                        output.push(b'\n');
+                    }
+                }
+            }
+        }
+
+        let status = waiter.join().map_err(|_| io::Error::other("process waiter panicked"))??;
+        let stderr = match stderr.map(|reader| reader.join()) {
+            Some(Ok(result)) => result?,
+            Some(Err(_)) => return Err(io::Error::other("stderr reader panicked")),
+            None => Vec::new(),
+        };
+        Ok(Output { status, stdout: output, stderr })
+    }
+
+    /// The process group of a running job.
+    pub fn process(&self, id: &str) -> Option<u32> {
+        self.processes().pids.get(id).copied()
+    }
+
+    /// Asks a job to stop and returns its process group if it has one to signal. A job
+    /// whose process has not started yet never starts it. The first reason given sticks.
+    fn request_stop(&self, id: &str, reason: StopReason) -> Option<u32> {
+        let mut processes = self.processes();
+        processes.stopping.entry(id.to_string()).or_insert(reason);
+        processes.pids.get(id).copied()
+    }
+
+    /// Stops a job's process group with `SIGTERM`, and with `SIGKILL` if it is still
+    /// running after a grace period.
+    pub fn stop(&self, id: &str, reason: StopReason) -> io::Result<()> {
+        let Some(pid) = self.request_stop(id, reason) else {
+            return Ok(());
+        };
+        signal_group(pid, "TERM")?;
+        let (jobs, id) = (self.clone(), id.to_string());
+        tokio::spawn(async move {
+            tokio::time::sleep(STOP_GRACE_PERIOD).await;
+            if jobs.process(&id) == Some(pid) {
+                warn!("job {id} did not stop within {}s, killing it", STOP_GRACE_PERIOD.as_secs());
+                if let Err(err) = signal_group(pid, "KILL") {
+                    error!("failed to kill job {id}: {err}");
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Forgets a job's process once it has exited, and returns why it was stopped, if it was.
+    pub fn detach_process(&self, id: &str) -> Option<StopReason> {
+        let mut processes = self.processes();
+        processes.pids.remove(id);
+        processes.stopping.remove(id)
+    }
+
+    fn processes(&self) -> MutexGuard<'_, Processes> {
+        self.processes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn logs(&self) -> MutexGuard<'_, HashMap<String, JobLog>> {
+        self.logs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sends `signal` (e.g. `TERM`) to a process group.
+/// Reads output that a process may still be writing, which for a file means waiting at its
+/// end until the process has exited.
+struct Follow {
+    reader: Box<dyn Read + Send>,
+    exited: Arc<AtomicBool>,
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Checked before reading, so that nothing written before the exit is missed.
+            let exited = self.exited.load(Ordering::SeqCst);
+            let read = self.reader.read(buf)?;
+            if read > 0 || exited {
+                return Ok(read);
+            }
+            thread::sleep(FOLLOW_INTERVAL);
+        }
+    }
+}
+
+fn signal_group(pgid: u32, signal: &str) -> io::Result<()> {
+    let status = Command::new("kill")
+        .args(["-s", signal, "--", &format!("-{pgid}")])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("kill -s {signal} exited with {status}")))
+    }
+}
+
+fn new_job(id: String, kind: JobKind, state: JobState, started_at: u64) -> Job {
+    Job {
+        id,
+        kind,
+        state,
+        started_at,
+        finished_at: None,
+        exit_code: None,
+        message: String::new(),
+        duration_seconds: None,
+        downloaded_bytes: None,
+        queue_position: None,
+        code: None,
+        progress: None,
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let registry = JobRegistry::default();
+        let job = registry.start(JobKind::FullUpgrade);
+        assert_eq!(job.state, JobState::Running);
+        assert!(job.finished_at.is_none());
+
+        registry.finish(&job.id, JobState::Failed, Some(100), "apt failed".to_string());
+
+        let job = registry.get(&job.id).unwrap();
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.exit_code, Some(100));
+        assert_eq!(job.message, "apt failed");
+        assert!(job.finished_at.is_some());
+        assert!(registry.get("unknown").is_none());
+
+        let queued = registry.enqueue(JobKind::FullUpgrade);
+        assert_eq!(queued.state, JobState::Queued);
+        registry.run(&queued.id);
+        assert_eq!(registry.get(&queued.id).unwrap().state, JobState::Running);
+    }
+
+    #[test]
+    fn test_is_upgrading() {
+        let registry = JobRegistry::default();
+        let prefetch = registry.start(JobKind::Prefetch);
+        let queued = registry.enqueue(JobKind::FullUpgrade);
+        assert!(!registry.is_upgrading());
+
+        let action = registry.start_action();
+        assert!(registry.is_upgrading());
+        drop(action);
+        assert!(!registry.is_upgrading());
+
+        registry.finish(&prefetch.id, JobState::Succeeded, Some(0), String::new());
+        registry.run(&queued.id);
+        assert!(registry.is_upgrading());
+        registry.finish(&queued.id, JobState::Failed, Some(100), String::new());
+        assert!(!registry.is_upgrading());
+    }
+
+    #[test]
+    fn test_last_finished() {
+        let registry = JobRegistry::default();
+        assert!(registry.last_finished(JobKind::FullUpgrade, JobState::Succeeded).is_none());
+
+        let first = registry.start(JobKind::FullUpgrade);
+        registry.finish(&first.id, JobState::Succeeded, Some(0), String::new());
+        let second = registry.start(JobKind::FullUpgrade);
+        registry.finish(&second.id, JobState::Failed, Some(1), String::new());
+        registry.start(JobKind::FullUpgrade);
+
+        let last = registry.last_finished(JobKind::FullUpgrade, JobState::Succeeded).unwrap();
+        assert_eq!(last.id, first.id);
+    }
+
+    #[test]
+    fn test_matching() {
+        let registry = JobRegistry::default();
+        let first = registry.start(JobKind::FullUpgrade);
+        registry.finish(&first.id, JobState::Failed, Some(1), String::new());
+        let second = registry.start(JobKind::FullUpgrade);
+        registry.finish(&second.id, JobState::Failed, Some(1), String::new());
+        let running = registry.start(JobKind::FullUpgrade);
+        let prefetch = registry.start(JobKind::Prefetch);
+
+        let ids = |query: JobsQuery| -> Vec<String> { registry.matching(&query).into_iter().map(|job| job.id).collect() };
+        assert_eq!(ids(JobsQuery::default()), [prefetch.id.as_str(), &running.id, &second.id, &first.id]);
+        assert_eq!(
+            ids(JobsQuery {
+                kind: Some(JobKind::Prefetch),
+                ..Default::default()
+            }),
+            [prefetch.id.as_str()]
+        );
+
+        let summary = registry.summary();
+        let active: Vec<&str> = summary.active.iter().map(|job| job.id.as_str()).collect();
+        assert_eq!(active, [running.id.as_str(), &prefetch.id]);
+        assert_eq!(summary.last_finished.map(|job| job.id), Some(second.id.clone()));
+        let failed = JobsQuery {
+            state: Some(JobState::Failed),
+            ..Default::default()
+        };
+        assert_eq!(ids(failed.clone()), [second.id.as_str(), &first.id]);
+        assert_eq!(
+            ids(JobsQuery {
+                since: Some(unix_now() + 60),
+                ..failed
+            }),
+            Vec::<String>::new()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stop_process() {
+        let registry = JobRegistry::default();
+        let job = registry.start(JobKind::FullUpgrade);
+        let runner = {
+            let (registry, id) = (registry.clone(), job.id.clone());
+            std::thread::spawn(move || {
+                let mut command = Command::new("sleep");
+                command.arg("30");
+                registry.run_process(&id, command)
+            })
+        };
+        let pid = loop {
+            if let Some(pid) = registry.process(&job.id) {
+                break pid;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(registry.request_stop(&job.id, StopReason::TimedOut), Some(pid));
+        assert_eq!(registry.request_stop(&job.id, StopReason::Cancelled), Some(pid));
+        signal_group(pid, "TERM").unwrap();
+        assert!(!runner.join().unwrap().unwrap().status.success());
+        assert_eq!(registry.detach_process(&job.id), Some(StopReason::TimedOut));
+        assert!(registry.process(&job.id).is_none());
+
+        let queued = registry.enqueue(JobKind::FullUpgrade);
+        assert_eq!(registry.request_stop(&queued.id, StopReason::Cancelled), None);
+        assert!(registry.run_process(&queued.id, Command::new("true")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_log() {
+        let registry = JobRegistry::default();
+        let job = registry.start(JobKind::FullUpgrade);
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo one; echo pmstatus:dpkg-exec:50:Running dpkg; echo two; echo three"]);
+        registry.run_process(&job.id, command).unwrap();
+
+        assert_eq!(registry.log(&job.id, 0, None), (vec!["one".into(), "two".into(), "three".into()], 3));
+        assert_eq!(registry.log(&job.id, 1, Some(1)), (vec!["three".into()], 3));
+        assert_eq!(registry.log(&job.id, 3, None), (Vec::new(), 3));
+        assert_eq!(registry.log("unknown", 0, None), (Vec::new(), 0));
+
+        // Lines dropped from the front keep the numbering of the rest.
+        if let Some(log) = registry.logs().get_mut(&job.id) {
+            log.lines.pop_front();
+            log.dropped = 1;
+        }
+        assert_eq!(registry.log(&job.id, 0, None).0, ["two", "three"]);
+        assert_eq!(registry.log(&job.id, 2, None), (vec!["three".into()], 3));
+
+        let job = registry.start(JobKind::FullUpgrade);
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo failed >&2"]);
+        let output = registry.run_process(&job.id, command).unwrap();
+        assert_eq!(output.stderr, b"failed\n");
+        assert_eq!(registry.log(&job.id, 0, None).0, ["failed"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_files() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-jobs-{}", std::process::id()));
+        let registry = JobRegistry::with_state_file(StateFile::new(dir.join("in-flight.json")));
+        let job = registry.start(JobKind::FullUpgrade);
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo one; sleep 0.3; echo pmstatus:dpkg-exec:50:Running dpkg; echo failed >&2; echo two"]);
+        let output = registry.run_process(&job.id, command).unwrap();
+        assert_eq!(output.stdout, b"one\ntwo\n");
+        assert_eq!(output.stderr, b"failed\n");
+        assert_eq!(registry.get(&job.id).unwrap().progress.unwrap().percent, 50.0);
+        assert!(registry.state_file().unwrap().load().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_registry_retains_most_recent_jobs() {
+        let registry = JobRegistry::default();
+        let first = registry.start(JobKind::FullUpgrade);
+        registry.append_log(&first.id, b"output");
+        for _ in 0..MAX_RETAINED_JOBS {
+            registry.start(JobKind::FullUpgrade);
+        }
+
+        assert!(registry.get(&first.id).is_none());
+        assert!(registry.logs().is_empty());
+        assert_eq!(registry.jobs.lock().unwrap().len(), MAX_RETAINED_JOBS);
+    }
+}
@@ -0,0 +1,152 @@
+//! Limits on the size and duration of requests, so that a misbehaving or hostile client can't
+//! exhaust the daemon's memory or keep its connections busy for good.
+
+use crate::error::ApiError;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use cobbler_api::ErrorCode;
+use std::time::Duration;
+
+/// Defaults of `--max-body-size` and `--max-header-size` (KiB) and `--request-timeout` (seconds).
+/// The timeout leaves room for the longest `GET /status/wait`.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024;
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 16;
+pub const DEFAULT_REQUEST_TIMEOUT: u64 = 600;
+/// How long a client may take to send the request body.
+pub const BODY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Room for the request line and header separators, which `max_header_bytes` doesn't count,
+/// when capping how much of a request hyper buffers before it has read the headers.
+pub const FRAMING_BYTES: usize = 4096;
+/// Routes that run apt, dpkg or an allowlisted command to completion before answering.
+/// Abandoning them would answer 503 while the change goes on, so they aren't timed.
+const UNTIMED: &[(Method, &str)] = &[
+    (Method::POST, "/packages/install"),
+    (Method::POST, "/packages/remove"),
+    (Method::POST, "/packages/repair"),
+    (Method::POST, "/sources"),
+    (Method::DELETE, "/sources/:name"),
+    (Method::POST, "/services/restart-outdated"),
+    (Method::POST, "/commands/:name"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Larger request bodies are refused with 413.
+    pub max_body_bytes: usize,
+    /// Longer request targets and headers, taken together, are refused with 431.
+    pub max_header_bytes: usize,
+    /// How long a request may take before it is abandoned with 503; `None` for no limit.
+    pub request_timeout: Option<Duration>,
+}
+
+impl Limits {
+    /// The most hyper buffers of a request while reading its headers; it answers 431 beyond.
+    pub fn header_buffer_bytes(&self) -> usize {
+        // hyper doesn't accept less than 8 KiB.
+        (self.max_header_bytes + FRAMING_BYTES).max(8192)
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_body_bytes: DEFAULT_MAX_BODY_SIZE * 1024,
+            max_header_bytes: DEFAULT_MAX_HEADER_SIZE * 1024,
+            request_timeout: Some(Duration::from_secs(DEFAULT_REQUEST_TIMEOUT)),
+        }
+    }
+}
+
+/// Refuses oversized requests up front and answers requests that take too long with a
+/// `TIMEOUT` error, except on `UNTIMED` routes. Bodies sent without a `Content-Length` are
+/// capped where they are read.
+pub async fn enforce(State(limits): State<Limits>, req: Request, next: Next) -> Response {
+    let header_bytes = req.uri().to_string().len()
+        + req
+            .headers()
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum::<usize>();
+    if header_bytes > limits.max_header_bytes {
+        return ApiError::new(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ErrorCode::InvalidRequest,
+            format!("request headers exceed {} bytes", limits.max_header_bytes),
+        )
+        .into_response();
+    }
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > limits.max_body_bytes as u64) {
+        return ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::InvalidRequest,
+            format!("request body exceeds {} bytes", limits.max_body_bytes),
+        )
+        .into_response();
+    }
+
+    let untimed = req.extensions().get::<MatchedPath>().is_some_and(|path| {
+        UNTIMED
+            .iter()
+            .any(|(method, route)| method == req.method() && *route == path.as_str())
+    });
+    let Some(timeout) = limits.request_timeout.filter(|_| !untimed) else {
+        return next.run(req).await;
+    };
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Timeout,
+            format!("the request took longer than {} seconds", timeout.as_secs()),
+        )
+        .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_enforce() {
+        let limits = Limits {
+            max_body_bytes: 16,
+            max_header_bytes: 64,
+            request_timeout: Some(Duration::from_millis(50)),
+        };
+        let app = Router::new()
+            .route("/echo", post(|body: String| async { body }))
+            .route("/slow", get(|| tokio::time::sleep(Duration::from_secs(5))))
+            .route("/packages/install", post(|| tokio::time::sleep(Duration::from_millis(100))))
+            .layer(middleware::from_fn_with_state(limits, enforce));
+        let send = |request: Request| app.clone().oneshot(request);
+
+        let request = Request::post("/echo").header(CONTENT_LENGTH, 5).body(Body::from("hello")).unwrap();
+        assert_eq!(send(request).await.unwrap().status(), StatusCode::OK);
+        let request = Request::post("/echo").header(CONTENT_LENGTH, 17).body(Body::from("x".repeat(17))).unwrap();
+        assert_eq!(send(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let request = Request::post("/echo").header("x-padding", "x".repeat(64)).body(Body::empty()).unwrap();
+        assert_eq!(send(request).await.unwrap().status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+
+        let response = send(Request::get("/slow").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        let error: cobbler_api::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, ErrorCode::Timeout);
+        let response = send(Request::post("/packages/install").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
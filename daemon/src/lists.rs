@@ -0,0 +1,168 @@
+//! Throttling of `apt-get update`. Every status check wants fresh package lists, but several
+//! monitoring systems polling `/status` must not turn into continuous mirror traffic.
+
+use crate::changes::Changes;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::info;
+
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Where apt keeps the package lists; `apt-get update` changes it or its `partial`
+/// directory even when no list changed.
+const LISTS_DIR: &str = "/var/lib/apt/lists";
+
+#[derive(Default)]
+struct State {
+    last: Option<Instant>,
+    /// Set while `update` runs, which is outside the lock.
+    running: bool,
+}
+
+#[derive(Clone)]
+pub struct ListsRefresh {
+    min_interval: Duration,
+    state: Arc<(Mutex<State>, Condvar)>,
+    changes: Changes,
+    lists_dir: PathBuf,
+}
+
+impl Default for ListsRefresh {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_INTERVAL)
+    }
+}
+
+impl ListsRefresh {
+    pub fn new(min_interval: Duration) -> Self {
+        ListsRefresh {
+            min_interval,
+            state: Arc::default(),
+            changes: Changes::default(),
+            lists_dir: PathBuf::from(LISTS_DIR),
+        }
+    }
+
+    /// Reads when the lists were last refreshed from `dir` instead of apt's directory.
+    #[cfg(test)]
+    pub fn with_lists_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.lists_dir = dir.into();
+        self
+    }
+
+    /// Notified after each run of `update`.
+    pub fn changes(&self) -> &Changes {
+        &self.changes
+    }
+
+    /// Runs `update` unless it last ran less than the minimum interval ago, and returns
+    /// whether it ran. Callers arriving while it runs wait for it instead of starting
+    /// another one.
+    /// Blocks while another caller runs `update`, so call it from blocking code.
+    pub fn run(&self, update: impl FnOnce()) -> bool {
+        let (_, finished) = &*self.state;
+        let mut state = self.lock();
+        if state.running {
+            drop(finished.wait_while(state, |state| state.running).unwrap_or_else(|e| e.into_inner()));
+            return false;
+        }
+        if let Some(last) = state.last
+            && last.elapsed() < self.min_interval
+        {
+            info!("package lists were refreshed {}s ago, skipping apt-get update", last.elapsed().as_secs());
+            return false;
+        }
+        state.running = true;
+        drop(state);
+
+        // Clears `running` even if `update` panics, so that waiters aren't stuck.
+        let running = Running(self);
+        update();
+        drop(running);
+        self.changes.notify();
+        true
+    }
+
+    /// When the package lists were last refreshed, in Unix seconds, by this daemon or
+    /// anyone else, e.g. before a restart or by apt's daily timer.
+    pub fn last_run(&self) -> Option<u64> {
+        [self.lists_dir.clone(), self.lists_dir.join("partial")]
+            .iter()
+            .filter_map(|dir| modified(dir))
+            .max()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|since| since.as_secs())
+}
+
+/// Marks the end of a run of `update`.
+struct Running<'a>(&'a ListsRefresh);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.last = Some(Instant::now());
+        state.running = false;
+        self.0.state.1.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_throttles() {
+        let lists = ListsRefresh::new(Duration::from_secs(60));
+        let mut runs = 0;
+        assert!(lists.run(|| runs += 1));
+        assert!(!lists.run(|| runs += 1));
+        assert_eq!(runs, 1);
+
+        let unthrottled = ListsRefresh::new(Duration::ZERO);
+        assert!(unthrottled.run(|| ()));
+        assert!(unthrottled.run(|| ()));
+    }
+
+    #[test]
+    fn test_run_waits_without_holding_the_lock() {
+        let lists = ListsRefresh::new(Duration::from_secs(60));
+        let (started, start) = std::sync::mpsc::channel();
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let lists = &lists;
+        std::thread::scope(|scope| {
+            let running = scope.spawn(move || {
+                lists.run(|| {
+                    started.send(()).unwrap();
+                    released.recv().unwrap();
+                })
+            });
+            start.recv().unwrap();
+            let waiting = scope.spawn(|| lists.run(|| panic!("ran twice")));
+            release.send(()).unwrap();
+            assert!(running.join().unwrap());
+            assert!(!waiting.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_last_run_from_lists_dir() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-lists-{}", std::process::id()));
+        let lists = ListsRefresh::default().with_lists_dir(&dir);
+        assert_eq!(lists.last_run(), None);
+
+        // Like `apt-get update`, which also leaves `partial` behind.
+        fs::create_dir_all(dir.join("partial")).unwrap();
+        lists.run(|| fs::write(dir.join("deb.debian.org_debian_dists_trixie_InRelease"), "").unwrap());
+        assert!(lists.last_run().is_some_and(|at| at.abs_diff(crate::jobs::unix_now()) <= 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
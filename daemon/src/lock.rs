@@ -0,0 +1,189 @@
+//! apt and dpkg lock contention. Another process holding the locks (unattended-upgrades, a
+//! human running apt) is reported as `LOCK_HELD` naming the holder, instead of as an apt
+//! failure with the reason buried in its stderr.
+
+use crate::error::ApiError;
+use axum::http::StatusCode;
+use cobbler_api::ErrorCode;
+use std::fs;
+
+const LISTS_LOCK: &str = "/var/lib/apt/lists/lock";
+
+/// Taken by `apt-get update`.
+pub const UPDATE_LOCKS: &[&str] = &[LISTS_LOCK];
+
+/// Taken by anything that installs or removes packages.
+pub const PACKAGE_LOCKS: &[&str] = &[
+    "/var/lib/dpkg/lock-frontend",
+    "/var/lib/dpkg/lock",
+    "/var/cache/apt/archives/lock",
+    LISTS_LOCK,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub lock: String,
+    pub pid: Option<u32>,
+    /// Command name of the process, truncated to 15 characters by the kernel.
+    pub process: Option<String>,
+}
+
+impl LockHolder {
+    pub fn error(&self) -> ApiError {
+        let holder = match (self.pid, &self.process) {
+            (Some(pid), Some(process)) => format!("process {pid} ({process})"),
+            (Some(pid), None) => format!("process {pid}"),
+            _ => "another process".to_string(),
+        };
+        ApiError::new(
+            StatusCode::CONFLICT,
+            ErrorCode::LockHeld,
+            format!("{} is held by {holder}", self.lock),
+        )
+        .with_details(serde_json::json!({
+            "lock": self.lock,
+            "pid": self.pid,
+            "process": self.process,
+        }))
+        .with_retry_after(crate::BUSY_RETRY_AFTER)
+    }
+}
+
+/// Fails with `LOCK_HELD` if another process holds one of `locks`.
+pub fn check(locks: &[&str]) -> Result<(), ApiError> {
+    match holder(locks) {
+        Some(holder) => Err(holder.error()),
+        None => Ok(()),
+    }
+}
+
+/// The holder of the first of `locks` that is locked, according to `/proc/locks`.
+pub fn holder(locks: &[&str]) -> Option<LockHolder> {
+    let proc_locks = fs::read_to_string("/proc/locks").ok()?;
+    let held = parse_proc_locks(&proc_locks);
+    locks.iter().find_map(|lock| {
+        let (device, inode) = file_id(lock)?;
+        let pid = held
+            .iter()
+            .find(|entry| entry.device == device && entry.inode == inode)?
+            .pid;
+        Some(LockHolder {
+            lock: lock.to_string(),
+            pid,
+            process: pid.and_then(process_name),
+        })
+    })
+}
+
+/// The holder named in apt's "Could not get lock" error, for when the lock was taken after
+/// [`check`] ran.
+pub fn from_apt_error(stderr: &str) -> Option<LockHolder> {
+    let (_, rest) = stderr.split_once("Could not get lock ")?;
+    let lock = rest.split_whitespace().next()?.trim_end_matches('.').to_string();
+    let line = rest.lines().next().unwrap_or_default();
+    let held_by = line.split_once("held by process ").map(|(_, held_by)| held_by);
+    let pid = held_by.and_then(|held_by| held_by.split_whitespace().next()?.parse().ok());
+    let process = held_by
+        .and_then(|held_by| held_by.split_once('(')?.1.split_once(')'))
+        .map(|(process, _)| process.to_string());
+    Some(LockHolder { lock, pid, process })
+}
+
+struct HeldLock {
+    device: (u32, u32),
+    inode: u64,
+    /// Unset for open file description locks, which belong to no single process.
+    pid: Option<u32>,
+}
+
+/// Parses lines like `1: POSIX  ADVISORY  WRITE 1234 08:02:1311779 0 EOF`, skipping the
+/// `->` lines of processes waiting for a lock.
+fn parse_proc_locks(content: &str) -> Vec<HeldLock> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(1) == Some(&"->") || fields.len() < 6 {
+                return None;
+            }
+            let mut id = fields[5].split(':');
+            let major = u32::from_str_radix(id.next()?, 16).ok()?;
+            let minor = u32::from_str_radix(id.next()?, 16).ok()?;
+            let inode = id.next()?.parse().ok()?;
+            Some(HeldLock {
+                device: (major, minor),
+                inode,
+                pid: fields[4].parse().ok(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn file_id(path: &str) -> Option<((u32, u32), u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).ok()?;
+    Some((split_device(metadata.dev()), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_path: &str) -> Option<((u32, u32), u64)> {
+    None
+}
+
+/// Major and minor number of a Linux `dev_t`.
+fn split_device(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & 0xffff_f000);
+    let minor = (dev & 0xff) | ((dev >> 12) & 0xffff_ff00);
+    (major as u32, minor as u32)
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|name| name.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_locks() {
+        let content = "\
+1: POSIX  ADVISORY  WRITE 1234 08:02:1311779 0 EOF
+1: -> POSIX  ADVISORY  WRITE 5678 08:02:1311779 0 EOF
+2: OFDLCK ADVISORY  READ  -1 00:1a:42 0 EOF
+";
+        let held = parse_proc_locks(content);
+        assert_eq!(held.len(), 2);
+        assert_eq!(held[0].device, (8, 2));
+        assert_eq!(held[0].inode, 1311779);
+        assert_eq!(held[0].pid, Some(1234));
+        assert_eq!(held[1].device, (0, 0x1a));
+        assert_eq!(held[1].pid, None);
+
+        assert_eq!(split_device(0x0802), (8, 2));
+        assert_eq!(split_device(0x10305), (259, 5));
+    }
+
+    #[test]
+    fn test_from_apt_error() {
+        let stderr = "\
+E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by process 4242 (unattended-upgr)
+N: Be aware that removing the lock file is not a solution and may break your system.
+E: Unable to acquire the dpkg frontend lock (/var/lib/dpkg/lock-frontend), is another process using it?
+";
+        let holder = from_apt_error(stderr).unwrap();
+        assert_eq!(holder.lock, "/var/lib/dpkg/lock-frontend");
+        assert_eq!(holder.pid, Some(4242));
+        assert_eq!(holder.process.as_deref(), Some("unattended-upgr"));
+
+        let stderr = "E: Could not get lock /var/lib/dpkg/lock - open (11: Resource temporarily unavailable)";
+        let holder = from_apt_error(stderr).unwrap();
+        assert_eq!(holder.lock, "/var/lib/dpkg/lock");
+        assert_eq!(holder.pid, None);
+        assert!(from_apt_error("E: Unable to locate package foo").is_none());
+    }
+}
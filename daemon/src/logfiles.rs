@@ -0,0 +1,92 @@
+//! Excerpts of the package managers' own logs: apt's terminal log has the full output of
+//! every apt run, dpkg's log each package state change. `GET /logs/{name}` serves them so a
+//! failed upgrade can be looked into without logging in to the node.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The logs `GET /logs/{name}` serves, by name.
+const LOG_FILES: [(&str, &str); 2] = [("apt", "/var/log/apt/term.log"), ("dpkg", "/var/log/dpkg.log")];
+/// How much of the end of a log one request reads at most. Lines before that are left out,
+/// so even a large `tail` stays cheap.
+const MAX_EXCERPT_BYTES: u64 = 1024 * 1024;
+
+/// The path of the log called `name`.
+pub fn path(name: &str) -> Option<&'static Path> {
+    LOG_FILES
+        .iter()
+        .find(|(log, _)| *log == name)
+        .map(|(_, path)| Path::new(*path))
+}
+
+/// The complete lines of `path` from byte offset `since` on (the end of the file less
+/// [`MAX_EXCERPT_BYTES`] if unset), only the last `tail` of them if set, and the offset to
+/// continue from. A log that became shorter than `since` was rotated and is read from the
+/// start. A missing log has no lines.
+pub fn read(path: &Path, since: Option<u64>, tail: Option<usize>) -> io::Result<(Vec<String>, u64)> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(err) => return Err(err),
+    };
+    let len = file.metadata()?.len();
+    let since = since.filter(|since| *since <= len).unwrap_or(0);
+    let start = since.max(len.saturating_sub(MAX_EXCERPT_BYTES));
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(len - start).read_to_end(&mut bytes)?;
+
+    // Skip the rest of a line cut off at the start, and leave one still being written.
+    let first = match start > since {
+        true => bytes.iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| i + 1),
+        false => 0,
+    };
+    let end = bytes.iter().rposition(|&b| b == b'\n').map_or(first, |i| i + 1).max(first);
+    let mut lines: Vec<String> = String::from_utf8_lossy(&bytes[first..end])
+        .lines()
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect();
+    if let Some(tail) = tail {
+        lines.drain(..lines.len().saturating_sub(tail));
+    }
+    Ok((lines, start + end as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_read() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-logfiles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dpkg.log");
+        assert_eq!(read(&path, None, None).unwrap(), (Vec::new(), 0));
+
+        fs::write(&path, "one\r\ntwo\nthree\npart").unwrap();
+        let (lines, next) = read(&path, None, None).unwrap();
+        assert_eq!(lines, ["one", "two", "three"]);
+        assert_eq!(next, 15);
+        assert_eq!(read(&path, None, Some(1)).unwrap(), (vec!["three".to_string()], 15));
+        assert_eq!(read(&path, Some(next), None).unwrap(), (Vec::new(), 15));
+
+        fs::write(&path, "one\r\ntwo\nthree\npartial\nfour\n").unwrap();
+        assert_eq!(read(&path, Some(next), None).unwrap().0, ["partial", "four"]);
+        // Rotated: shorter than the cursor.
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(read(&path, Some(next), None).unwrap(), (vec!["new".to_string()], 4));
+
+        // Only the end of a large log is read, without the line cut off at its start.
+        let count = MAX_EXCERPT_BYTES as usize / 100 + 1;
+        fs::write(&path, format!("cut off{}", ("x".repeat(99) + "\n").repeat(count))).unwrap();
+        let (lines, _) = read(&path, Some(0), None).unwrap();
+        assert_eq!(lines.len(), count - 1);
+        assert!(lines.iter().all(|line| line.len() == 99));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(super::path("apt"), Some(Path::new("/var/log/apt/term.log")));
+        assert_eq!(super::path("syslog"), None);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::syslog::{Endpoint, SyslogWriter};
+use clap::ValueEnum;
+use std::str::FromStr;
+use tracing::warn;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LogTarget {
+    /// Lines on stdout
+    #[default]
+    Stdout,
+    /// The systemd journal, with priorities derived from log levels
+    Journald,
+    /// RFC 5424 messages to the local syslog socket or a remote UDP/TCP endpoint
+    Syslog(Endpoint),
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "stdout" => Ok(LogTarget::Stdout),
+            "journald" => Ok(LogTarget::Journald),
+            _ => Endpoint::parse(value).map(LogTarget::Syslog).ok_or_else(|| {
+                format!(
+                    "expected stdout, journald, syslog or syslog[+udp|+tcp]://host[:port], got {value:?}"
+                )
+            }),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of the current span (e.g. request_id)
+    Json,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Installs the global subscriber. Falls back to stdout if the requested target is unavailable.
+pub fn init(target: &LogTarget, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "cobblerd=info".into());
+    let (layer, fallback_reason) = match target_layer(target, format) {
+        Ok(layer) => (layer, None),
+        Err(err) => (stdout_layer(format), Some(err)),
+    };
+
+    tracing_subscriber::registry().with(layer).with(filter).init();
+
+    if let Some(err) = fallback_reason {
+        warn!("log target {target:?} unavailable, logging to stdout: {err}");
+    }
+}
+
+fn target_layer(target: &LogTarget, format: LogFormat) -> Result<BoxedLayer, String> {
+    match target {
+        LogTarget::Stdout => Ok(stdout_layer(format)),
+        LogTarget::Journald => journald_layer(),
+        LogTarget::Syslog(endpoint) => syslog_layer(endpoint, format),
+    }
+}
+
+fn stdout_layer(format: LogFormat) -> BoxedLayer {
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
+/// Syslog messages carry their own timestamp and severity, so text lines omit both.
+fn syslog_layer(endpoint: &Endpoint, format: LogFormat) -> Result<BoxedLayer, String> {
+    let writer = SyslogWriter::connect(endpoint)
+        .map_err(|err| format!("failed to connect to syslog {endpoint:?}: {err}"))?;
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+    Ok(match format {
+        LogFormat::Text => layer.without_time().with_level(false).boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn journald_layer() -> Result<BoxedLayer, String> {
+    tracing_journald::layer()
+        .map(|layer| layer.with_syslog_identifier("cobblerd".to_string()).boxed())
+        .map_err(|err| format!("failed to connect to journald: {err}"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn journald_layer() -> Result<BoxedLayer, String> {
+    Err("journald is only available on Linux".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_target_from_str() {
+        assert_eq!("journald".parse(), Ok(LogTarget::Journald));
+        assert_eq!(
+            "syslog+tcp://logs.lan".parse(),
+            Ok(LogTarget::Syslog(Endpoint::Tcp("logs.lan:514".to_string())))
+        );
+        assert!("loki".parse::<LogTarget>().is_err());
+        assert_eq!(LogTarget::default(), LogTarget::Stdout);
+        assert_eq!(LogFormat::from_str("json", true), Ok(LogFormat::Json));
+        assert!(target_layer(&LogTarget::Stdout, LogFormat::Json).is_ok());
+    }
+}
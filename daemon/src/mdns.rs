@@ -0,0 +1,186 @@
+use cobbler_api::{MDNS_DOMAIN, SERVICE_TYPE};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use tracing::{error, info};
+
+/// Non-loopback addresses of all local interfaces, sorted so snapshots can be compared.
+pub fn local_addresses() -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .iter()
+        .filter(|interface| !interface.is_loopback())
+        .map(|interface| interface.ip())
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+/// Address of the named interface to bind and advertise on, preferring IPv4 over
+/// routable IPv6 over link-local IPv6. A link-local address carries the interface's scope
+/// id, without which it can't be bound; the port is 0.
+pub fn interface_address(name: &str) -> Option<SocketAddr> {
+    let interfaces = if_addrs::get_if_addrs().ok()?;
+    pick_interface_address(
+        name,
+        interfaces.iter().map(|interface| (interface.name.as_str(), interface.ip(), interface.index)),
+    )
+}
+
+fn pick_interface_address<'a>(
+    name: &str,
+    interfaces: impl Iterator<Item = (&'a str, IpAddr, Option<u32>)>,
+) -> Option<SocketAddr> {
+    interfaces
+        .filter(|(interface, _, _)| *interface == name)
+        .min_by_key(|(_, ip, _)| match ip {
+            IpAddr::V4(_) => 0,
+            IpAddr::V6(v6) if !is_link_local(v6) => 1,
+            IpAddr::V6(_) => 2,
+        })
+        .map(|(_, ip, index)| match ip {
+            IpAddr::V6(v6) if is_link_local(&v6) => SocketAddrV6::new(v6, 0, 0, index.unwrap_or(0)).into(),
+            ip => SocketAddr::new(ip, 0),
+        })
+}
+
+fn is_link_local(ip: &std::net::Ipv6Addr) -> bool {
+    (ip.segments()[0] & 0xffc0) == 0xfe80
+}
+
+pub fn instance_name(hostname: &str) -> String {
+    let instance_hostname = hostname.split('.').next().unwrap_or(hostname);
+    format!("cobblerd-{instance_hostname}")
+}
+
+pub struct MdnsService {
+    daemon: ServiceDaemon,
+    instance: String,
+    host_name: String,
+    ip: Option<IpAddr>,
+    port: u16,
+}
+
+impl MdnsService {
+    /// Registers the service on every interface, or only on `interface` if given, so that
+    /// queries from other networks go unanswered.
+    pub fn register(
+        port: u16,
+        hostname: &str,
+        ip: Option<IpAddr>,
+        interface: Option<&str>,
+        properties: &[(String, String)],
+    ) -> Option<Self> {
+        let daemon = match ServiceDaemon::new() {
+            Ok(daemon) => {
+                info!("mDNS daemon started");
+                daemon
+            }
+            Err(err) => {
+                error!("FAILED to start mDNS daemon: {err}");
+                return None;
+            }
+        };
+        if let Some(name) = interface {
+            let selected = daemon
+                .disable_interface(IfKind::All)
+                .and_then(|()| daemon.enable_interface(IfKind::Name(name.to_string())));
+            if let Err(err) = selected {
+                error!("FAILED to restrict mDNS to interface {name}: {err}");
+                return None;
+            }
+            info!("mDNS restricted to interface {name}");
+        }
+
+        let instance_hostname = hostname.split('.').next().unwrap_or(hostname);
+        let service = MdnsService {
+            daemon,
+            instance: instance_name(hostname),
+            host_name: format!("{instance_hostname}.local."),
+            ip,
+            port,
+        };
+
+        info!("Registering mDNS service:");
+        info!("  Instance: {}", service.instance);
+        info!("  Host: {}", service.host_name);
+        info!("  Port: {}", port);
+        if let Some(ip) = ip {
+            info!("Using explicit IP: {}", ip);
+        }
+
+        if let Err(err) = service.announce(properties) {
+            error!("FAILED to register mDNS service: {err}");
+            return None;
+        }
+
+        info!("mDNS service registered successfully");
+        Some(service)
+    }
+
+    /// Re-registers the service, replacing its TXT properties.
+    pub fn announce(&self, properties: &[(String, String)]) -> Result<(), String> {
+        let service_type = format!("{SERVICE_TYPE}.{MDNS_DOMAIN}");
+        let info = match self.ip {
+            Some(ip) => ServiceInfo::new(
+                &service_type,
+                &self.instance,
+                &self.host_name,
+                ip,
+                self.port,
+                properties,
+            ),
+            None => ServiceInfo::new(
+                &service_type,
+                &self.instance,
+                &self.host_name,
+                "",
+                self.port,
+                properties,
+            )
+            .map(ServiceInfo::enable_addr_auto),
+        }
+        .map_err(|err| format!("failed to create mDNS service info: {err}"))?;
+
+        self.daemon.register(info).map_err(|err| err.to_string())
+    }
+
+    pub fn shutdown(&self) -> Result<(), String> {
+        self.daemon.shutdown().map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_name() {
+        assert_eq!(instance_name("raspi1.lan"), "cobblerd-raspi1");
+        assert_eq!(instance_name("raspi1"), "cobblerd-raspi1");
+    }
+
+    #[test]
+    fn test_pick_interface_address() {
+        let interfaces = [
+            ("docker0", "172.17.0.1".parse().unwrap(), Some(3)),
+            ("eth0", "fe80::1".parse().unwrap(), Some(2)),
+            ("eth0", "2001:db8::5".parse().unwrap(), Some(2)),
+            ("eth0", "192.168.1.20".parse().unwrap(), Some(2)),
+            ("wg0", "fe80::2".parse().unwrap(), Some(7)),
+            ("wg1", "2001:db8::7".parse().unwrap(), Some(8)),
+        ];
+        let pick = |name| pick_interface_address(name, interfaces.iter().copied());
+        assert_eq!(pick("eth0"), Some("192.168.1.20:0".parse().unwrap()));
+        assert_eq!(pick("wg0"), Some("[fe80::2%7]:0".parse().unwrap()));
+        assert_eq!(pick("wg1"), Some("[2001:db8::7]:0".parse().unwrap()));
+        assert_eq!(pick("eth1"), None);
+    }
+
+    #[test]
+    fn test_local_addresses_skip_loopback() {
+        let addresses = local_addresses();
+        assert!(addresses.iter().all(|ip| !ip.is_loopback()));
+        assert!(addresses.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the upgrade duration histogram buckets.
+const DURATION_BUCKETS: [f64; 8] = [30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0];
+
+#[derive(Default)]
+struct UpgradeMetrics {
+    bucket_counts: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+    succeeded: u64,
+    failed: u64,
+    downloaded_bytes_total: u64,
+    last_downloaded_bytes: u64,
+}
+
+/// In-process Prometheus metrics, rendered in the text exposition format by `GET /metrics`.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    upgrades: Arc<Mutex<UpgradeMetrics>>,
+}
+
+impl Metrics {
+    pub fn observe_upgrade(&self, duration: Duration, succeeded: bool, downloaded_bytes: Option<u64>) {
+        let mut upgrades = self.upgrades.lock().unwrap_or_else(|e| e.into_inner());
+        let seconds = duration.as_secs_f64();
+        for (count, bound) in upgrades.bucket_counts.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        upgrades.duration_sum += seconds;
+        upgrades.duration_count += 1;
+        if succeeded {
+            upgrades.succeeded += 1;
+        } else {
+            upgrades.failed += 1;
+        }
+        if let Some(bytes) = downloaded_bytes {
+            upgrades.downloaded_bytes_total += bytes;
+            upgrades.last_downloaded_bytes = bytes;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let upgrades = self.upgrades.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP cobblerd_upgrade_duration_seconds Duration of full upgrade jobs.");
+        let _ = writeln!(out, "# TYPE cobblerd_upgrade_duration_seconds histogram");
+        for (count, bound) in upgrades.bucket_counts.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(out, "cobblerd_upgrade_duration_seconds_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "cobblerd_upgrade_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            upgrades.duration_count
+        );
+        let _ = writeln!(out, "cobblerd_upgrade_duration_seconds_sum {}", upgrades.duration_sum);
+        let _ = writeln!(out, "cobblerd_upgrade_duration_seconds_count {}", upgrades.duration_count);
+
+        let _ = writeln!(out, "# HELP cobblerd_upgrade_jobs_total Finished full upgrade jobs by result.");
+        let _ = writeln!(out, "# TYPE cobblerd_upgrade_jobs_total counter");
+        let _ = writeln!(out, "cobblerd_upgrade_jobs_total{{result=\"succeeded\"}} {}", upgrades.succeeded);
+        let _ = writeln!(out, "cobblerd_upgrade_jobs_total{{result=\"failed\"}} {}", upgrades.failed);
+
+        let _ = writeln!(out, "# HELP cobblerd_upgrade_downloaded_bytes_total Package archive bytes downloaded by full upgrades.");
+        let _ = writeln!(out, "# TYPE cobblerd_upgrade_downloaded_bytes_total counter");
+        let _ = writeln!(out, "cobblerd_upgrade_downloaded_bytes_total {}", upgrades.downloaded_bytes_total);
+
+        let _ = writeln!(out, "# HELP cobblerd_upgrade_last_downloaded_bytes Package archive bytes downloaded by the last full upgrade.");
+        let _ = writeln!(out, "# TYPE cobblerd_upgrade_last_downloaded_bytes gauge");
+        let _ = writeln!(out, "cobblerd_upgrade_last_downloaded_bytes {}", upgrades.last_downloaded_bytes);
+
+        out
+    }
+}
+
+/// Extracts the download size from apt's "Need to get 12.3 MB of archives." line.
+/// For partially downloaded runs ("Need to get 1,024 kB/12.3 MB of archives.") the first
+/// figure is the amount actually fetched.
+pub fn parse_downloaded_bytes(apt_output: &str) -> Option<u64> {
+    let line = apt_output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Need to get "))?;
+    let mut parts = line.split([' ', '/']);
+    let amount: f64 = parts.next()?.replace(',', "").parse().ok()?;
+    let multiplier = match parts.next()? {
+        "B" => 1.0,
+        "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        _ => return None,
+    };
+    Some((amount * multiplier).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_downloaded_bytes() {
+        let output = "Reading package lists...\nNeed to get 12.3 MB of archives.\nAfter this operation, 1024 kB of additional disk space will be used.\n";
+        assert_eq!(parse_downloaded_bytes(output), Some(12_300_000));
+        assert_eq!(
+            parse_downloaded_bytes("Need to get 1,024 kB/12.3 MB of archives."),
+            Some(1_024_000)
+        );
+        assert_eq!(parse_downloaded_bytes("Need to get 0 B of archives."), Some(0));
+        assert_eq!(parse_downloaded_bytes("0 upgraded, 0 newly installed"), None);
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.observe_upgrade(Duration::from_secs(90), true, Some(2_000));
+        metrics.observe_upgrade(Duration::from_secs(4000), false, None);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("cobblerd_upgrade_duration_seconds_bucket{le=\"60\"} 0\n"));
+        assert!(rendered.contains("cobblerd_upgrade_duration_seconds_bucket{le=\"120\"} 1\n"));
+        assert!(rendered.contains("cobblerd_upgrade_duration_seconds_bucket{le=\"3600\"} 1\n"));
+        assert!(rendered.contains("cobblerd_upgrade_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("cobblerd_upgrade_duration_seconds_sum 4090\n"));
+        assert!(rendered.contains("cobblerd_upgrade_jobs_total{result=\"succeeded\"} 1\n"));
+        assert!(rendered.contains("cobblerd_upgrade_jobs_total{result=\"failed\"} 1\n"));
+        assert!(rendered.contains("cobblerd_upgrade_downloaded_bytes_total 2000\n"));
+    }
+}
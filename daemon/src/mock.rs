@@ -0,0 +1,213 @@
+//! Fake package backend for `--mock`: a synthetic Debian package set, and upgrades that only
+//! pretend, printing apt's progress lines with pauses in between. Lets the CLI be developed
+//! and demoed against a daemon on macOS or in CI containers, without apt.
+
+use crate::error::ApiError;
+use crate::glob;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use cobbler_api::{ErrorCode, PackageInfo, UpdateDetail};
+use std::collections::HashSet;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// Name, installed version, candidate version and whether the candidate is a security update.
+const PACKAGES: &[(&str, &str, &str, bool)] = &[
+    ("base-files", "12.4+deb12u5", "12.4+deb12u6", false),
+    ("bash", "5.2.15-2+b2", "5.2.15-2+b2", false),
+    ("ca-certificates", "20230311", "20230311", false),
+    ("coreutils", "9.1-1", "9.1-1", false),
+    ("curl", "7.88.1-10+deb12u5", "7.88.1-10+deb12u7", true),
+    ("libc6", "2.36-9+deb12u4", "2.36-9+deb12u7", true),
+    ("libssl3", "3.0.11-1~deb12u2", "3.0.13-1~deb12u1", true),
+    ("nginx", "1.22.1-9", "1.22.1-9", false),
+    ("openssh-server", "1:9.2p1-2+deb12u1", "1:9.2p1-2+deb12u2", true),
+    ("systemd", "252.22-1~deb12u1", "252.26-1~deb12u2", false),
+    ("tzdata", "2024a-0+deb12u1", "2025b-0+deb12u1", false),
+    ("vim", "2:9.0.1378-2", "2:9.0.1378-2", false),
+];
+/// Upgrading one of these makes the node want a reboot, as on a real system.
+const REBOOT_PACKAGES: &[&str] = &["libc6", "systemd"];
+/// Routes that would change the host rather than the mock packages.
+const HOST_CHANGES: &[(Method, &str)] = &[
+    (Method::POST, "/packages/install"),
+    (Method::POST, "/packages/remove"),
+    (Method::POST, "/packages/repair"),
+    (Method::POST, "/sources"),
+    (Method::DELETE, "/sources/:name"),
+    (Method::POST, "/system/reboot"),
+    (Method::DELETE, "/system/reboot"),
+    (Method::POST, "/system/shutdown"),
+    (Method::DELETE, "/system/shutdown"),
+    (Method::POST, "/services/restart-outdated"),
+    (Method::POST, "/services/:name/:action"),
+    (Method::POST, "/commands/:name"),
+    (Method::PUT, "/config/unattended-upgrades"),
+];
+
+/// Prints what `apt <strategy> -o APT::Status-Fd=1` would, for the packages passed as
+/// arguments, taking a second per package and stage. With `download-only` as `$1` it stops
+/// after the downloads.
+const UPGRADE_SCRIPT: &str = r#"
+mode=$1; shift
+total=$#
+echo "Reading package lists..."
+echo "Calculating upgrade..."
+echo "$total upgraded, 0 newly installed, 0 to remove and 0 not upgraded."
+echo "Need to get $((total * 1536)) kB of archives."
+i=0
+for pkg in "$@"; do
+    i=$((i + 1))
+    echo "Get:$i http://deb.debian.org/debian bookworm/main $pkg"
+    echo "dlstatus:$i:$((i * 100 / total)):Retrieving file $i of $total"
+    sleep 1
+done
+if [ "$mode" = download-only ]; then
+    echo "Download complete and in download only mode"
+    exit 0
+fi
+i=0
+for pkg in "$@"; do
+    i=$((i + 1))
+    echo "pmstatus:$pkg:$((i * 50 / total)):Unpacking $pkg"
+    echo "Unpacking $pkg ..."
+    sleep 1
+done
+i=0
+for pkg in "$@"; do
+    i=$((i + 1))
+    echo "pmstatus:$pkg:$((50 + i * 50 / total)):Configuring $pkg"
+    echo "Setting up $pkg ..."
+    sleep 1
+done
+"#;
+
+/// The mock backend, remembering which packages its upgrades have upgraded.
+#[derive(Debug, Clone, Default)]
+pub struct Mock {
+    upgraded: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Mock {
+    /// The installed packages, sorted by name.
+    pub fn packages(&self) -> Vec<PackageInfo> {
+        let upgraded = self.upgraded.lock().unwrap_or_else(|e| e.into_inner());
+        PACKAGES
+            .iter()
+            .map(|&(name, installed, candidate, _)| {
+                let installed = if upgraded.contains(name) { candidate } else { installed };
+                PackageInfo {
+                    name: name.to_string(),
+                    installed_version: installed.to_string(),
+                    candidate_version: Some(candidate.to_string()),
+                    upgradable: installed != candidate,
+                    held: false,
+                }
+            })
+            .collect()
+    }
+
+    /// The pending updates, the security ones from `bookworm-security`.
+    pub fn updates(&self) -> Vec<UpdateDetail> {
+        let upgraded = self.upgraded.lock().unwrap_or_else(|e| e.into_inner());
+        PACKAGES
+            .iter()
+            .filter(|&&(name, installed, candidate, _)| installed != candidate && !upgraded.contains(name))
+            .map(|&(name, _, _, security)| UpdateDetail {
+                name: name.to_string(),
+                archive: Some(if security { "bookworm-security" } else { "bookworm-updates" }.to_string()),
+                security,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub fn reboot_required(&self) -> bool {
+        let upgraded = self.upgraded.lock().unwrap_or_else(|e| e.into_inner());
+        REBOOT_PACKAGES.iter().any(|name| upgraded.contains(*name))
+    }
+
+    /// The pending updates an upgrade restricted to `only` (unless empty) and leaving out
+    /// `exclude` would install. Patterns are matched as for real upgrades.
+    pub fn plan(&self, exclude: &[String], only: &[String]) -> Vec<String> {
+        let matches = |name: &str, patterns: &[String]| patterns.iter().any(|pattern| glob::matches(pattern, name));
+        self.updates()
+            .into_iter()
+            .map(|update| update.name)
+            .filter(|name| !matches(name, exclude) && (only.is_empty() || matches(name, only)))
+            .collect()
+    }
+
+    /// Records a successful upgrade of `packages`.
+    pub fn upgraded(&self, packages: &[String]) {
+        self.upgraded.lock().unwrap_or_else(|e| e.into_inner()).extend(packages.iter().cloned());
+    }
+}
+
+/// Answers requests that would install packages, edit sources, reboot or otherwise change the
+/// host with `403 NOT_ALLOWED` when serving the mock, so that a demo can't touch the machine it
+/// runs on.
+pub async fn reject_host_changes(State(mock): State<Option<Mock>>, req: Request, next: Next) -> Response {
+    let changes_host = req.extensions().get::<MatchedPath>().is_some_and(|path| {
+        HOST_CHANGES
+            .iter()
+            .any(|(method, route)| method == req.method() && *route == path.as_str())
+    });
+    if mock.is_some() && changes_host {
+        return ApiError::new(
+            StatusCode::FORBIDDEN,
+            ErrorCode::NotAllowed,
+            "the daemon serves mock packages and doesn't change the host",
+        )
+        .into_response();
+    }
+    next.run(req).await
+}
+
+/// The process pretending to upgrade `packages`, or only to download them.
+pub fn upgrade_command(packages: &[String], download_only: bool) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .args(["-c", UPGRADE_SCRIPT, "sh"])
+        .arg(if download_only { "download-only" } else { "upgrade" })
+        .args(packages);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics, progress};
+    use cobbler_api::JobPhase;
+
+    #[test]
+    fn test_upgrade() {
+        let mock = Mock::default();
+        assert_eq!(mock.updates().len(), 7);
+        assert_eq!(mock.plan(&["lib*".to_string()], &[]).len(), 5);
+        assert_eq!(mock.plan(&[], &["curl".to_string(), "vim".to_string()]), ["curl"]);
+        assert!(!mock.reboot_required());
+
+        mock.upgraded(&["libc6".to_string()]);
+        assert!(mock.reboot_required());
+        assert!(mock.updates().iter().all(|update| update.name != "libc6"));
+        let libc = mock.packages().into_iter().find(|pkg| pkg.name == "libc6").unwrap();
+        assert_eq!(libc.installed_version, "2.36-9+deb12u7");
+        assert!(!libc.upgradable);
+    }
+
+    #[test]
+    fn test_upgrade_command() {
+        let output = upgrade_command(&["curl".to_string()], false).output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let phases: Vec<_> = stdout.lines().filter_map(progress::parse).map(|progress| progress.phase).collect();
+        assert_eq!(phases, [JobPhase::Downloading, JobPhase::Unpacking, JobPhase::Configuring]);
+        assert_eq!(metrics::parse_downloaded_bytes(&stdout), Some(1_536_000));
+
+        let output = upgrade_command(&[], true).output().unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).contains("download only mode"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Newline-delimited JSON responses, used to stream large listings without holding all of
+//! them in memory.
+
+use axum::body::Body;
+use axum::http::{header::ACCEPT, HeaderMap};
+use serde::Serialize;
+use std::io;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::error;
+
+pub const CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Lines serialized ahead of the client. Bounds memory when the client reads slowly.
+const BUFFER_LINES: usize = 64;
+
+/// Whether the `Accept` header asks for NDJSON.
+pub fn accepts(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| media.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case(CONTENT_TYPE))
+}
+
+/// Streams the items passed to the sink of `produce`, one JSON object per line. `produce`
+/// runs on a blocking thread; the sink returns `false` once the client has gone away, and
+/// `produce` should stop then. If it fails, the body ends with an error, which cuts off
+/// the response.
+pub fn body<T, F>(produce: F) -> Body
+where
+    T: Serialize,
+    F: FnOnce(&mut dyn FnMut(T) -> bool) -> Result<(), String> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<io::Result<Vec<u8>>>(BUFFER_LINES);
+    tokio::task::spawn_blocking(move || {
+        let result = produce(&mut |item| {
+            let line = serde_json::to_vec(&item).map(|mut line| {
+                line.push(b'\n');
+                line
+            });
+            tx.blocking_send(line.map_err(io::Error::other)).is_ok()
+        });
+        if let Err(err) = result {
+            error!("failed to stream response: {err}");
+            let _ = tx.blocking_send(Err(io::Error::other(err)));
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[test]
+    fn test_accepts() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts(&headers));
+        headers.insert(ACCEPT, "application/json".parse().unwrap());
+        assert!(!accepts(&headers));
+        headers.insert(ACCEPT, "application/json;q=0.5, application/x-ndjson".parse().unwrap());
+        assert!(accepts(&headers));
+    }
+
+    #[tokio::test]
+    async fn test_body() {
+        let response = body(|sink| {
+            for n in 1..=3 {
+                if !sink(serde_json::json!({ "n": n })) {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        let bytes = to_bytes(response, usize::MAX).await.unwrap();
+        assert_eq!(bytes, "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n");
+
+        let response = body(|sink: &mut dyn FnMut(u32) -> bool| {
+            sink(1);
+            Err("apt failed".to_string())
+        });
+        assert!(to_bytes(response, usize::MAX).await.is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! apt and dpkg without a terminal. Nobody can answer a debconf question or a conffile
+//! prompt for the daemon's children, so any prompt would leave them waiting forever.
+
+use crate::priority::Priority;
+use cobbler_api::ConffilePolicy;
+use std::process::{Command, Stdio};
+
+/// A command that never asks: debconf takes its defaults, apt-listchanges stays quiet and,
+/// given a policy, changed configuration files are resolved without a prompt.
+pub fn command(program: &str, conffiles: Option<ConffilePolicy>, priority: Priority) -> Command {
+    let mut command = priority.command(program);
+    command
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .env("APT_LISTCHANGES_FRONTEND", "none")
+        .stdin(Stdio::null());
+    if let Some(policy) = conffiles {
+        for option in ["--force-confdef", dpkg_option(policy)] {
+            if program == "dpkg" {
+                command.arg(option);
+            } else {
+                command.args(["-o", &format!("Dpkg::Options::={option}")]);
+            }
+        }
+    }
+    command
+}
+
+fn dpkg_option(policy: ConffilePolicy) -> &'static str {
+    match policy {
+        ConffilePolicy::ConfOld => "--force-confold",
+        ConffilePolicy::ConfNew => "--force-confnew",
+    }
+}
+
+/// Parses `--conffiles`.
+pub fn parse_policy(value: &str) -> Result<ConffilePolicy, String> {
+    [ConffilePolicy::ConfOld, ConffilePolicy::ConfNew]
+        .into_iter()
+        .find(|policy| policy.as_str() == value)
+        .ok_or_else(|| format!("expected confold or confnew, got {value:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_command() {
+        let command = command("apt", Some(ConffilePolicy::ConfNew), Priority::default());
+        let args: Vec<&OsStr> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["-o", "Dpkg::Options::=--force-confdef", "-o", "Dpkg::Options::=--force-confnew"]
+        );
+        assert!(command
+            .get_envs()
+            .any(|(key, value)| key == "DEBIAN_FRONTEND" && value == Some(OsStr::new("noninteractive"))));
+
+        let command = super::command("dpkg", Some(ConffilePolicy::ConfOld), Priority::default());
+        let args: Vec<&OsStr> = command.get_args().collect();
+        assert_eq!(args, ["--force-confdef", "--force-confold"]);
+        assert_eq!(super::command("apt-get", None, Priority::default()).get_args().count(), 0);
+
+        assert_eq!(parse_policy("confnew"), Ok(ConffilePolicy::ConfNew));
+        assert!(parse_policy("new").is_err());
+    }
+}
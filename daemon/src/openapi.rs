@@ -0,0 +1,112 @@
+use axum::{Json, Router};
+use cobbler_api::API_KEY_HEADER;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// OpenAPI document of every route, built from the `#[utoipa::path]` annotations on the
+/// handlers and the schemas of `cobbler-api`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "cobblerd"),
+    paths(
+        crate::identify_handler,
+        crate::version_handler,
+        crate::metrics_handler,
+        crate::status_handler,
+        crate::status_wait_handler,
+        crate::snapshot_handler,
+        crate::packages_handler,
+        crate::policy_handler,
+        crate::transactions_handler,
+        crate::install_handler,
+        crate::remove_handler,
+        crate::full_upgrade_handler,
+        crate::prefetch_handler,
+        crate::repair_handler,
+        crate::jobs_handler,
+        crate::job_handler,
+        crate::job_log_handler,
+        crate::cancel_job_handler,
+        crate::system_log_handler,
+        crate::reboot_handler,
+        crate::cancel_reboot_handler,
+        crate::shutdown_handler,
+        crate::cancel_shutdown_handler,
+        crate::sensors_handler,
+        crate::resources_handler,
+        crate::services_handler,
+        crate::restart_services_handler,
+        crate::service_action_handler,
+        crate::command_handler,
+        crate::sources_handler,
+        crate::add_source_handler,
+        crate::remove_source_handler,
+        crate::unattended_upgrades_handler,
+        crate::update_unattended_upgrades_handler,
+    ),
+    modifiers(&ApiKeyScheme),
+    tags(
+        (name = "node", description = "Identity, build info and metrics; no API key needed"),
+        (name = "system", description = "System status and reboots"),
+        (name = "packages", description = "Package listing and apt operations"),
+        (name = "sources", description = "apt repositories"),
+        (name = "jobs", description = "Asynchronous operations"),
+        (name = "config", description = "Node configuration"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `api_key` security scheme referenced by the authenticated routes.
+struct ApiKeyScheme;
+
+impl Modify for ApiKeyScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+    }
+}
+
+pub async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI at `/swagger-ui`, rendering `/openapi.json`. Only built with the
+/// `swagger-ui` feature, which embeds its assets in the binary.
+#[cfg(feature = "swagger-ui")]
+pub fn swagger_ui<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    use utoipa_swagger_ui::{Config, SwaggerUi};
+
+    SwaggerUi::new("/swagger-ui")
+        .config(Config::new(["/openapi.json"]))
+        .into()
+}
+
+#[cfg(not(feature = "swagger-ui"))]
+pub fn swagger_ui<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_routes() {
+        let doc = ApiDoc::openapi();
+        let paths: Vec<&str> = doc.paths.paths.keys().map(String::as_str).collect();
+        for path in ["/status", "/packages", "/packages/full-upgrade", "/jobs/{id}", "/system/reboot", "/sources/{name}"] {
+            assert!(paths.contains(&path), "{path} missing from {paths:?}");
+        }
+
+        let reboot = &doc.paths.paths["/system/reboot"];
+        assert!(reboot.post.is_some() && reboot.delete.is_some());
+
+        let components = doc.components.unwrap();
+        assert!(components.security_schemes.contains_key("api_key"));
+        assert!(components.schemas.contains_key("ErrorResponse"));
+        assert!(components.schemas.contains_key("Job"));
+    }
+}
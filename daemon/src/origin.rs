@@ -0,0 +1,63 @@
+//! Which archive a pending update comes from, to tell security updates from regular ones.
+
+use cobbler_api::UpdateDetail;
+
+/// A package index a version is available from.
+pub struct PackageFile {
+    /// `bookworm-security`, `jammy-updates`, ... or `now` for the dpkg status file.
+    pub archive: String,
+    pub label: Option<String>,
+}
+
+/// Picks the archive of an update's candidate version from the files providing it. A
+/// version that is in a security archive counts as a security update even if it is also
+/// in a regular one (like `-updates`).
+pub fn classify(name: String, files: &[PackageFile]) -> UpdateDetail {
+    let security = files.iter().find(|file| is_security(file));
+    let archive = security
+        .or_else(|| files.iter().find(|file| !file.archive.is_empty() && file.archive != "now"))
+        .map(|file| file.archive.clone());
+    UpdateDetail {
+        name,
+        archive,
+        security: security.is_some(),
+        ..Default::default()
+    }
+}
+
+fn is_security(file: &PackageFile) -> bool {
+    file.archive.ends_with("-security")
+        || file.label.as_deref().is_some_and(|label| label.ends_with("-Security"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(archive: &str, label: Option<&str>) -> PackageFile {
+        PackageFile {
+            archive: archive.to_string(),
+            label: label.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let update = classify(
+            "openssl".to_string(),
+            &[file("jammy-updates", Some("Ubuntu")), file("jammy-security", Some("Ubuntu"))],
+        );
+        assert_eq!(update.archive.as_deref(), Some("jammy-security"));
+        assert!(update.security);
+
+        let update = classify("vim".to_string(), &[file("stable-security", Some("Debian-Security"))]);
+        assert!(update.security);
+
+        let update = classify("vim".to_string(), &[file("now", None), file("bookworm-backports", Some("Debian Backports"))]);
+        assert_eq!(update.archive.as_deref(), Some("bookworm-backports"));
+        assert!(!update.security);
+
+        let update = classify("local".to_string(), &[file("now", None)]);
+        assert_eq!(update.archive, None);
+    }
+}
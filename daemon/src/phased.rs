@@ -0,0 +1,37 @@
+//! Phased updates (Ubuntu): upgrades apt holds back until the node is part of their gradual
+//! rollout. They are reported apart from the pending updates, which they would otherwise
+//! inflate with nothing actionable.
+
+use cobbler_api::{PhasedUpdates, UpdateDetail};
+
+/// Splits `updates` into those a full upgrade would install and those `deferred` by phasing
+/// (see [`crate::plan::UpgradePlan`]).
+pub fn split_updates(updates: Vec<UpdateDetail>, deferred: &[String]) -> (Vec<UpdateDetail>, Vec<UpdateDetail>) {
+    updates.into_iter().partition(|update| !deferred.contains(&update.name))
+}
+
+/// The apt option overriding the rollout decision.
+pub fn apt_option(phased: PhasedUpdates) -> &'static str {
+    match phased {
+        PhasedUpdates::Include => "APT::Get::Always-Include-Phased-Updates=true",
+        PhasedUpdates::Exclude => "APT::Get::Never-Include-Phased-Updates=true",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_updates() {
+        let update = |name: &str| UpdateDetail {
+            name: name.to_string(),
+            archive: Some("noble-updates".to_string()),
+            security: false,
+            ..Default::default()
+        };
+        let (kept, deferred) = split_updates(vec![update("systemd"), update("vim")], &["systemd".to_string()]);
+        assert_eq!(kept, vec![update("vim")]);
+        assert_eq!(deferred, vec![update("systemd")]);
+    }
+}
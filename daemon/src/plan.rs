@@ -0,0 +1,90 @@
+//! What a full upgrade would do, according to a simulated `apt-get dist-upgrade`: which
+//! updates it defers due to phasing, which packages it keeps back, which it newly installs
+//! and which it removes.
+
+use cobbler_api::UpdateDetail;
+use std::process::Command;
+
+const DEFERRED_HEADER: &str = "deferred due to phasing:";
+const KEPT_BACK_HEADER: &str = "have been kept back:";
+const REMOVED_HEADER: &str = "will be REMOVED:";
+const NEW_HEADER: &str = "NEW packages will be installed:";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpgradePlan {
+    pub deferred: Vec<String>,
+    pub kept_back: Vec<String>,
+    pub removals: Vec<String>,
+    pub new_packages: Vec<String>,
+}
+
+/// Simulates a full upgrade if there are `updates`; without any there is nothing to plan.
+/// Empty where the simulation fails.
+pub fn for_updates(updates: &[UpdateDetail]) -> UpgradePlan {
+    if updates.is_empty() {
+        return UpgradePlan::default();
+    }
+    Command::new("apt-get")
+        .args(["-s", "-o", "Debug::NoLocking=1", "dist-upgrade"])
+        .env("LC_ALL", "C")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| parse(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+fn parse(output: &str) -> UpgradePlan {
+    UpgradePlan {
+        deferred: section(output, DEFERRED_HEADER),
+        kept_back: section(output, KEPT_BACK_HEADER),
+        removals: section(output, REMOVED_HEADER),
+        new_packages: section(output, NEW_HEADER),
+    }
+}
+
+/// Collects the indented package names following the line ending in `header`, like
+/// "The following packages will be REMOVED:". Purges are marked with a trailing `*`.
+fn section(output: &str, header: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_end().ends_with(header))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .flat_map(str::split_whitespace)
+        .map(|name| name.trim_end_matches('*').to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = "\
+Reading package lists...
+Building dependency tree...
+Calculating upgrade...
+The following packages will be REMOVED:
+  libfoo1* python3-legacy
+The following NEW packages will be installed:
+  linux-image-6.1.0-21-amd64
+The following upgrades have been deferred due to phasing:
+  libnss-systemd libpam-systemd
+  systemd
+The following packages have been kept back:
+  linux-image-generic
+The following packages will be upgraded:
+  vim vim-common
+2 upgraded, 1 newly installed, 2 to remove and 4 not upgraded.
+";
+        let plan = parse(output);
+        assert_eq!(plan.deferred, vec!["libnss-systemd", "libpam-systemd", "systemd"]);
+        assert_eq!(plan.kept_back, vec!["linux-image-generic"]);
+        assert_eq!(plan.removals, vec!["libfoo1", "python3-legacy"]);
+        assert_eq!(plan.new_packages, vec!["linux-image-6.1.0-21-amd64"]);
+
+        assert_eq!(parse("The following packages will be upgraded:\n  vim\n"), UpgradePlan::default());
+    }
+}
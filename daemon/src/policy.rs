@@ -0,0 +1,144 @@
+//! Pin priorities from `apt-cache policy`, which explain why apt's candidate isn't always
+//! the newest available version.
+
+use cobbler_api::{PackagePolicy, PolicySource, PolicyVersion};
+use std::process::Command;
+
+pub fn package_policies(packages: &[String]) -> Result<Vec<PackagePolicy>, String> {
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+    let output = Command::new("apt-cache")
+        .arg("policy")
+        .args(packages)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|err| format!("failed to run apt-cache policy: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "apt-cache policy failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_policy(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the per-package blocks of `apt-cache policy <packages>`:
+///
+/// ```text
+/// vim:
+///   Installed: 2:9.0.1378-2
+///   Candidate: 2:9.0.1378-2
+///   Version table:
+///      2:9.1.0016-1~bpo12+1 100
+///         100 http://deb.debian.org/debian bookworm-backports/main amd64 Packages
+///  *** 2:9.0.1378-2 500
+///         500 http://deb.debian.org/debian bookworm/main amd64 Packages
+///         100 /var/lib/dpkg/status
+/// ```
+fn parse_policy(output: &str) -> Vec<PackagePolicy> {
+    let mut policies: Vec<PackagePolicy> = Vec::new();
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            if let Some(name) = line.trim_end().strip_suffix(':') {
+                policies.push(PackagePolicy {
+                    name: name.to_string(),
+                    installed: None,
+                    candidate: None,
+                    versions: Vec::new(),
+                });
+            }
+            continue;
+        }
+        let Some(policy) = policies.last_mut() else {
+            continue;
+        };
+
+        let trimmed = line.trim();
+        if let Some(version) = trimmed.strip_prefix("Installed:") {
+            policy.installed = parse_version_field(version);
+        } else if let Some(version) = trimmed.strip_prefix("Candidate:") {
+            policy.candidate = parse_version_field(version);
+        } else if trimmed.ends_with(':') || trimmed.starts_with("Package pin:") {
+            // "Version table:" and the pin apt reports for the package as a whole.
+        } else if let Some(entry) = trimmed.strip_prefix("*** ") {
+            policy.versions.extend(parse_version_line(entry));
+        } else if indentation(line) <= 5 {
+            policy.versions.extend(parse_version_line(trimmed));
+        } else if let Some(version) = policy.versions.last_mut()
+            && let Some((priority, source)) = trimmed.split_once(' ')
+            && let Ok(priority) = priority.parse()
+        {
+            version.sources.push(PolicySource {
+                priority,
+                source: source.trim().to_string(),
+            });
+        }
+    }
+    policies
+}
+
+fn parse_version_field(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty() && value != "(none)").then(|| value.to_string())
+}
+
+fn parse_version_line(entry: &str) -> Option<PolicyVersion> {
+    let mut parts = entry.split_whitespace();
+    let version = parts.next()?.to_string();
+    let priority = parts.next()?.parse().ok()?;
+    Some(PolicyVersion {
+        version,
+        priority,
+        sources: Vec::new(),
+    })
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        let output = "\
+vim:
+  Installed: 2:9.0.1378-2
+  Candidate: 2:9.0.1378-2
+  Version table:
+     2:9.1.0016-1~bpo12+1 100
+        100 http://deb.debian.org/debian bookworm-backports/main amd64 Packages
+ *** 2:9.0.1378-2 500
+        500 http://deb.debian.org/debian bookworm/main amd64 Packages
+        100 /var/lib/dpkg/status
+curl:
+  Installed: (none)
+  Candidate: 7.88.1-10+deb12u5
+  Version table:
+     7.88.1-10+deb12u5 -1
+        500 http://deb.debian.org/debian bookworm/main amd64 Packages
+";
+        let policies = parse_policy(output);
+        assert_eq!(policies.len(), 2);
+
+        let vim = &policies[0];
+        assert_eq!(vim.name, "vim");
+        assert_eq!(vim.installed.as_deref(), Some("2:9.0.1378-2"));
+        assert_eq!(vim.versions.len(), 2);
+        let newest = vim.newest().unwrap();
+        assert_eq!(newest.version, "2:9.1.0016-1~bpo12+1");
+        assert_eq!(newest.priority, 100);
+        let installed = vim.version("2:9.0.1378-2").unwrap();
+        assert_eq!(installed.priority, 500);
+        assert_eq!(installed.sources.len(), 2);
+        assert_eq!(installed.sources[1].source, "/var/lib/dpkg/status");
+
+        let curl = &policies[1];
+        assert_eq!(curl.installed, None);
+        assert_eq!(curl.versions[0].priority, -1);
+        assert_eq!(curl.versions[0].sources[0].priority, 500);
+    }
+}
@@ -0,0 +1,73 @@
+//! CPU and I/O priority of package operations, so that patching doesn't starve the
+//! workloads of the node, e.g. a Raspberry Pi that also serves DNS.
+
+use clap::ValueEnum;
+use std::process::Command;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IoClass {
+    /// The I/O priority derived from the CPU priority
+    #[default]
+    Normal,
+    /// The lowest best-effort priority
+    Low,
+    /// Disk time only when no other process wants it
+    Idle,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Priority {
+    /// Niceness from 0 (normal) to 19 (lowest).
+    pub nice: u8,
+    pub io_class: IoClass,
+}
+
+impl Priority {
+    /// A command that runs `program` through `ionice` and `nice` as needed. Both exec the
+    /// program, so the spawned process ID stays the program's, and its children inherit
+    /// the priority.
+    pub fn command(self, program: &str) -> Command {
+        let mut argv: Vec<String> = Vec::new();
+        match self.io_class {
+            IoClass::Normal => {}
+            IoClass::Low => argv.extend(["ionice", "-c", "2", "-n", "7"].map(String::from)),
+            IoClass::Idle => argv.extend(["ionice", "-c", "3"].map(String::from)),
+        }
+        if self.nice > 0 {
+            argv.extend(["nice".to_string(), "-n".to_string(), self.nice.to_string()]);
+        }
+        argv.push(program.to_string());
+
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(priority: Priority) -> Vec<String> {
+        let command = priority.command("apt");
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(argv(Priority::default()), ["apt"]);
+        let priority = Priority {
+            nice: 10,
+            io_class: IoClass::Idle,
+        };
+        assert_eq!(argv(priority), ["ionice", "-c", "3", "nice", "-n", "10", "apt"]);
+        let priority = Priority {
+            nice: 0,
+            io_class: IoClass::Low,
+        };
+        assert_eq!(argv(priority), ["ionice", "-c", "2", "-n", "7", "apt"]);
+    }
+}
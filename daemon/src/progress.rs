@@ -0,0 +1,87 @@
+//! apt's machine-readable progress. With `-o APT::Status-Fd=1`, apt interleaves lines like
+//! `dlstatus:2:37.5:Retrieving file 2 of 4` and `pmstatus:vim:62.5:Configuring vim (amd64)`
+//! with its regular output.
+
+use cobbler_api::{JobPhase, JobProgress};
+
+/// Makes apt report its progress on stdout.
+pub const APT_STATUS_OPTION: &str = "APT::Status-Fd=1";
+
+/// Parses a download or package manager status line; any other line is `None`.
+pub fn parse(line: &str) -> Option<JobProgress> {
+    if let Some(rest) = line.strip_prefix("dlstatus:") {
+        let (_, percent, _) = split_fields(rest)?;
+        return Some(JobProgress {
+            phase: JobPhase::Downloading,
+            percent,
+            package: None,
+        });
+    }
+
+    let rest = line.strip_prefix("pmstatus:")?;
+    let (package, percent, description) = split_fields(rest)?;
+    Some(JobProgress {
+        phase: phase(description),
+        percent,
+        // apt reports the dpkg runs themselves as this pseudo package.
+        package: (package != "dpkg-exec").then(|| package.to_string()),
+    })
+}
+
+/// Splits `<item>:<percent>:<description>`. The item may contain colons itself
+/// (`libc6:amd64`), so the percentage is the first field that is a number.
+fn split_fields(rest: &str) -> Option<(&str, f64, &str)> {
+    let mut offset = 0;
+    for field in rest.split(':') {
+        let start = offset;
+        offset += field.len() + 1;
+        if start == 0 {
+            continue;
+        }
+        if let Ok(percent) = field.parse::<f64>() {
+            let item = &rest[..start - 1];
+            let description = rest.get(offset..).unwrap_or_default();
+            return Some((item, percent.clamp(0.0, 100.0), description));
+        }
+    }
+    None
+}
+
+fn phase(description: &str) -> JobPhase {
+    const CONFIGURING: &[&str] = &["Preparing to configure", "Configuring", "Installed"];
+    const REMOVING: &[&str] = &["Preparing for removal", "Removing", "Removed", "Completely removed"];
+    if CONFIGURING.iter().any(|prefix| description.starts_with(prefix)) {
+        JobPhase::Configuring
+    } else if REMOVING.iter().any(|prefix| description.starts_with(prefix)) {
+        JobPhase::Removing
+    } else {
+        JobPhase::Unpacking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let progress = parse("dlstatus:2:37.5:Retrieving file 2 of 4").unwrap();
+        assert_eq!(progress.phase, JobPhase::Downloading);
+        assert_eq!(progress.percent, 37.5);
+        assert_eq!(progress.package, None);
+
+        let progress = parse("pmstatus:libc6:amd64:20:Unpacking libc6:amd64 (2.36-9)").unwrap();
+        assert_eq!(progress.phase, JobPhase::Unpacking);
+        assert_eq!(progress.percent, 20.0);
+        assert_eq!(progress.package.as_deref(), Some("libc6:amd64"));
+
+        let progress = parse("pmstatus:vim:62.5:Configuring vim (amd64)").unwrap();
+        assert_eq!(progress.phase, JobPhase::Configuring);
+        assert_eq!(parse("pmstatus:telnet:90:Removing telnet").unwrap().phase, JobPhase::Removing);
+        assert_eq!(parse("pmstatus:dpkg-exec:0:Running dpkg").unwrap().package, None);
+
+        assert!(parse("Reading package lists...").is_none());
+        assert!(parse("pmerror:vim:50:subprocess failed").is_none());
+        assert!(parse("pmstatus:vim:Unpacking").is_none());
+    }
+}
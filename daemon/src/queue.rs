@@ -0,0 +1,214 @@
+//! Serializes package operations. apt and dpkg can only do one thing at a time, so
+//! overlapping requests line up here in arrival order instead of being rejected.
+
+use crate::changes::Changes;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Operations that may wait at once, including the running one.
+pub const MAX_QUEUED: usize = 16;
+
+#[derive(Clone, Default)]
+pub struct OperationQueue {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: Mutex<Entries>,
+    changed: Notify,
+    /// Jobs joining or leaving the queue.
+    job_changes: Changes,
+}
+
+#[derive(Default)]
+struct Entries {
+    next_ticket: u64,
+    /// The front entry is the running operation.
+    queue: VecDeque<Entry>,
+}
+
+struct Entry {
+    ticket: u64,
+    job_id: Option<String>,
+}
+
+/// A place in the queue. Dropping it leaves the queue, or finishes the operation if it
+/// was running, and lets the next one start.
+pub struct Ticket {
+    id: u64,
+    queue: OperationQueue,
+}
+
+impl OperationQueue {
+    /// Lines up behind all operations already queued, or returns `None` if `MAX_QUEUED`
+    /// are already waiting.
+    pub fn enqueue(&self) -> Option<Ticket> {
+        self.push(MAX_QUEUED)
+    }
+
+    /// Takes a ticket only if nothing is queued, for work that is not worth waiting for.
+    pub fn try_enqueue(&self) -> Option<Ticket> {
+        self.push(1)
+    }
+
+    fn push(&self, limit: usize) -> Option<Ticket> {
+        let mut entries = self.entries();
+        if entries.queue.len() >= limit {
+            return None;
+        }
+        let id = entries.next_ticket;
+        entries.next_ticket += 1;
+        entries.queue.push_back(Entry { ticket: id, job_id: None });
+        Some(Ticket {
+            id,
+            queue: self.clone(),
+        })
+    }
+
+    /// Notified when a job joins or leaves the queue.
+    pub fn job_changes(&self) -> &Changes {
+        &self.inner.job_changes
+    }
+
+    /// Whether an operation is running.
+    pub fn is_busy(&self) -> bool {
+        !self.entries().queue.is_empty()
+    }
+
+    /// The number of operations ahead of a job, or `None` if it is not queued.
+    pub fn job_position(&self, job_id: &str) -> Option<usize> {
+        self.job_position_in(&self.entries(), job_id)
+    }
+
+    /// Takes a waiting job out of the queue. The running operation cannot be withdrawn.
+    pub fn withdraw_job(&self, job_id: &str) -> bool {
+        let withdrawn = {
+            let mut entries = self.entries();
+            match self.job_position_in(&entries, job_id) {
+                Some(position) if position > 0 => entries.queue.remove(position).is_some(),
+                _ => false,
+            }
+        };
+        if withdrawn {
+            self.inner.changed.notify_waiters();
+            self.inner.job_changes.notify();
+        }
+        withdrawn
+    }
+
+    fn job_position_in(&self, entries: &Entries, job_id: &str) -> Option<usize> {
+        entries
+            .queue
+            .iter()
+            .position(|entry| entry.job_id.as_deref() == Some(job_id))
+    }
+
+    /// `None` once the ticket has been withdrawn.
+    fn position(&self, ticket: u64) -> Option<usize> {
+        self.entries().queue.iter().position(|entry| entry.ticket == ticket)
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Ticket {
+    /// Links the ticket to a job, so that the job's position can be looked up.
+    pub fn set_job(&self, job_id: &str) {
+        let mut entries = self.queue.entries();
+        if let Some(entry) = entries.queue.iter_mut().find(|entry| entry.ticket == self.id) {
+            entry.job_id = Some(job_id.to_string());
+        }
+        drop(entries);
+        self.queue.inner.job_changes.notify();
+    }
+
+    /// The number of operations ahead; `0` once it is this ticket's turn.
+    pub fn position(&self) -> usize {
+        self.queue.position(self.id).unwrap_or_default()
+    }
+
+    /// Waits until all operations ahead have finished. Returns `false` instead if the
+    /// ticket's job was withdrawn from the queue.
+    pub async fn turn(&self) -> bool {
+        loop {
+            let changed = self.queue.inner.changed.notified();
+            tokio::pin!(changed);
+            // Registers for the notification before checking, so none is missed in between.
+            changed.as_mut().enable();
+            match self.queue.position(self.id) {
+                Some(0) => return true,
+                None => return false,
+                Some(_) => changed.await,
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut entries = self.queue.entries();
+        let position = entries.queue.iter().position(|entry| entry.ticket == self.id);
+        let entry = position.and_then(|position| entries.queue.remove(position));
+        drop(entries);
+        self.queue.inner.changed.notify_waiters();
+        // Status refreshes take tickets too, but the status doesn't report on those.
+        if entry.is_some_and(|entry| entry.job_id.is_some()) {
+            self.queue.inner.job_changes.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_queue_order() {
+        let queue = OperationQueue::default();
+        assert!(!queue.is_busy());
+
+        let first = queue.enqueue().unwrap();
+        let second = queue.enqueue().unwrap();
+        second.set_job("job");
+        assert_eq!(first.position(), 0);
+        assert_eq!(second.position(), 1);
+        assert_eq!(queue.job_position("job"), Some(1));
+        assert!(queue.try_enqueue().is_none());
+
+        let waiting = tokio::spawn(async move {
+            assert!(second.turn().await);
+            second.position()
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap(), 0);
+        assert_eq!(queue.job_position("job"), None);
+        assert!(!queue.is_busy());
+        assert!(queue.try_enqueue().is_some());
+
+        let full: Vec<_> = (0..MAX_QUEUED).map(|_| queue.enqueue().unwrap()).collect();
+        assert!(queue.enqueue().is_none());
+        drop(full);
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_job() {
+        let queue = OperationQueue::default();
+        let running = queue.enqueue().unwrap();
+        running.set_job("running");
+        let waiting = queue.enqueue().unwrap();
+        waiting.set_job("waiting");
+
+        assert!(!queue.withdraw_job("running"));
+        assert!(queue.withdraw_job("waiting"));
+        assert!(!waiting.turn().await);
+        assert_eq!(queue.job_position("waiting"), None);
+    }
+}
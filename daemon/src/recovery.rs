@@ -0,0 +1,203 @@
+//! Upgrades across daemon restarts. While apt runs for a job, the job and apt's process ID
+//! are kept in a state file, so that a daemon started after a crash or restart learns
+//! about the upgrade its predecessor lost track of.
+
+use crate::holds;
+use crate::jobs::{JobKind, JobRegistry, JobState, StopReason};
+use crate::queue::OperationQueue;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const DEFAULT_STATE_FILE: &str = "/var/lib/cobblerd/in-flight.json";
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InFlight {
+    pub job_id: String,
+    #[serde(rename = "type")]
+    pub kind: JobKind,
+    /// apt's process ID, which is also its process group ID.
+    pub pid: u32,
+    pub started_at: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf) -> Self {
+        StateFile { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the packages held for the running upgrade are recorded, next to the state file.
+    pub fn holds_path(&self) -> PathBuf {
+        self.path.with_file_name("temporary-holds.json")
+    }
+
+    /// Where the time security updates were first seen pending is kept across restarts.
+    pub fn security_pending_path(&self) -> PathBuf {
+        self.path.with_file_name("security-pending-since")
+    }
+
+    /// Fresh files for the stdout and stderr of the recorded process, next to the state file,
+    /// each opened for writing by the process and for reading by the daemon. Unlike pipes,
+    /// they outlive the daemon: apt writing to a pipe whose reader has exited would be killed
+    /// by `SIGPIPE` in the middle of dpkg.
+    pub fn create_output(&self) -> io::Result<[(fs::File, fs::File); 2]> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let [stdout, stderr] = ["stdout", "stderr"].map(|stream| {
+            let path = self.path.with_extension(stream);
+            Ok::<_, io::Error>((fs::File::create(&path)?, fs::File::open(&path)?))
+        });
+        Ok([stdout?, stderr?])
+    }
+
+    /// Writes through a temporary file, so that a crash never leaves half a record behind.
+    pub fn save(&self, in_flight: &InFlight) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(in_flight)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    pub fn load(&self) -> Option<InFlight> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!("failed to read {}: {err}", self.path.display());
+                return None;
+            }
+        };
+        serde_json::from_slice(&content)
+            .inspect_err(|err| warn!("ignoring malformed {}: {err}", self.path.display()))
+            .ok()
+    }
+
+    pub fn clear(&self) {
+        if let Err(err) = fs::remove_file(&self.path)
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            warn!("failed to remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Restores the job of an upgrade that was running when the previous daemon stopped. If
+/// its apt process is still alive, the job stays running and holds the operation queue
+/// until apt exits. Either way it ends up `interrupted`, since apt's exit status is lost.
+/// Temporary holds the previous daemon left are released once apt is done.
+pub fn recover(jobs: &JobRegistry, queue: &OperationQueue) {
+    let Some(file) = jobs.state_file().cloned() else {
+        return;
+    };
+    let Some(in_flight) = file.load() else {
+        holds::release_recorded(&file.holds_path());
+        return;
+    };
+    let job = jobs.restore(&in_flight);
+    let pid = in_flight.pid;
+    let name = match job.kind {
+        JobKind::FullUpgrade => "full upgrade",
+        JobKind::Prefetch => "prefetch",
+    };
+
+    if !is_apt_running(pid) {
+        warn!("{name} (job {}) was interrupted by a daemon restart", job.id);
+        // A prefetch only downloads, so it leaves nothing for dpkg to repair.
+        let advice = match job.kind {
+            JobKind::FullUpgrade => "run a repair",
+            JobKind::Prefetch => "run it again",
+        };
+        jobs.finish(
+            &job.id,
+            JobState::Interrupted,
+            None,
+            format!("the daemon restarted during the {name} and apt did not finish; {advice}"),
+        );
+        file.clear();
+        holds::release_recorded(&file.holds_path());
+        return;
+    }
+
+    let Some(ticket) = queue.enqueue() else {
+        return;
+    };
+    ticket.set_job(&job.id);
+    jobs.adopt_process(&job.id, pid);
+    info!("reattached to {name} (job {}), apt is still running as pid {pid}", job.id);
+
+    let jobs = jobs.clone();
+    tokio::spawn(async move {
+        while is_apt_running(pid) {
+            tokio::time::sleep(PROCESS_POLL_INTERVAL).await;
+        }
+        let (state, message) = match jobs.detach_process(&job.id) {
+            Some(StopReason::Cancelled) => (JobState::Cancelled, format!("{name} cancelled")),
+            _ => (
+                JobState::Interrupted,
+                format!("the daemon restarted during the {name}; apt has since exited, but its outcome is unknown"),
+            ),
+        };
+        info!("reattached {name} (job {}) ended", job.id);
+        jobs.finish(&job.id, state, None, message);
+        file.clear();
+        holds::release_recorded(&file.holds_path());
+        drop(ticket);
+    });
+}
+
+/// Checks the program name as well, so that a reused process ID is not mistaken for apt.
+fn is_apt_running(pid: u32) -> bool {
+    fs::read(format!("/proc/{pid}/cmdline")).is_ok_and(|cmdline| is_apt_cmdline(&cmdline))
+}
+
+fn is_apt_cmdline(cmdline: &[u8]) -> bool {
+    let program = cmdline.split(|&byte| byte == 0).next().unwrap_or_default();
+    program.rsplit(|&byte| byte == b'/').next() == Some(b"apt".as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-recovery-{}", std::process::id()));
+        let file = StateFile::new(dir.join("in-flight.json"));
+        assert_eq!(file.load(), None);
+        file.create_output().unwrap();
+        assert!(dir.join("in-flight.stdout").exists());
+
+        let in_flight = InFlight {
+            job_id: "5f0c6a8e".to_string(),
+            kind: JobKind::FullUpgrade,
+            pid: 4242,
+            started_at: 1767225600,
+        };
+        file.save(&in_flight).unwrap();
+        assert_eq!(file.load(), Some(in_flight));
+        file.clear();
+        assert_eq!(file.load(), None);
+        fs::remove_dir_all(dir).unwrap();
+
+        assert!(is_apt_cmdline(b"apt\0full-upgrade\0-y\0"));
+        assert!(is_apt_cmdline(b"/usr/bin/apt\0full-upgrade\0"));
+        assert!(!is_apt_cmdline(b"apt-get\0update\0"));
+        assert!(!is_apt_cmdline(b""));
+    }
+}
@@ -0,0 +1,127 @@
+//! Recovery from interrupted package operations: `dpkg --configure -a` finishes unpacked but
+//! unconfigured packages, and `apt-get install -f` resolves the broken dependencies left
+//! behind.
+
+use crate::error::ApiError;
+use crate::priority::Priority;
+use cobbler_api::{ConffilePolicy, ErrorCode, RepairResponse};
+use std::process::Output;
+use tracing::{error, info};
+
+const STEPS: [(&str, &[&str]); 2] = [
+    ("dpkg", &["--configure", "-a"]),
+    ("apt-get", &["install", "-f", "-y"]),
+];
+
+/// Runs both steps, and `dpkg --configure -a` once more if it failed before `apt-get
+/// install -f` fixed the dependencies it was missing.
+pub fn repair(conffiles: ConffilePolicy, priority: Priority) -> Result<RepairResponse, ApiError> {
+    let mut output = String::new();
+    let mut dpkg_failed = false;
+    for (program, args) in STEPS {
+        let result = run(program, args, conffiles, priority, &mut output)?;
+        if program == "dpkg" && !result.status.success() {
+            info!("dpkg --configure -a failed, retrying after apt-get install -f");
+            dpkg_failed = true;
+            continue;
+        }
+        check(program, args, &result, &output)?;
+    }
+    if dpkg_failed {
+        let (program, args) = STEPS[0];
+        let result = run(program, args, conffiles, priority, &mut output)?;
+        check(program, args, &result, &output)?;
+    }
+
+    let fixed = fixed_packages(&output);
+    let message = if fixed.is_empty() {
+        "nothing to repair".to_string()
+    } else {
+        format!("repaired {} package(s)", fixed.len())
+    };
+    info!("{message}");
+    Ok(RepairResponse {
+        message,
+        fixed,
+        output: crate::tail_lines(&output, 20),
+    })
+}
+
+fn run(
+    program: &str,
+    args: &[&str],
+    conffiles: ConffilePolicy,
+    priority: Priority,
+    output: &mut String,
+) -> Result<Output, ApiError> {
+    info!("running {program} {}", args.join(" "));
+    let mut command = crate::noninteractive::command(program, Some(conffiles), priority);
+    let result = command.args(args).env("LC_ALL", "C").output().map_err(|e| {
+        error!("failed to execute {program}: {e}");
+        ApiError::internal(ErrorCode::AptFailed, format!("failed to execute {program}: {e}"))
+    })?;
+    output.push_str(&String::from_utf8_lossy(&result.stdout));
+    Ok(result)
+}
+
+fn check(program: &str, args: &[&str], result: &Output, output: &str) -> Result<(), ApiError> {
+    if result.status.success() {
+        return Ok(());
+    }
+    let stderr = crate::tail_lines(&String::from_utf8_lossy(&result.stderr), 20);
+    error!("{program} {} failed with status: {}. stderr: {stderr}", args.join(" "), result.status);
+    if let Some(holder) = crate::lock::from_apt_error(&stderr) {
+        return Err(holder.error());
+    }
+    Err(ApiError::internal(
+        ErrorCode::AptFailed,
+        format!("{program} {} failed: {stderr}", args.join(" ")),
+    )
+    .with_details(serde_json::json!({
+        "exit_code": result.status.code(),
+        "fixed": fixed_packages(output),
+    })))
+}
+
+/// Packages dpkg touched, from its `Setting up`, `Unpacking` and `Removing` lines, without
+/// architecture qualifiers.
+fn fixed_packages(output: &str) -> Vec<String> {
+    let mut packages: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let Some(rest) = ["Setting up ", "Unpacking ", "Removing "]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))
+        else {
+            continue;
+        };
+        let Some(word) = rest.split_whitespace().next() else {
+            continue;
+        };
+        let name = word.split(':').next().unwrap_or(word);
+        if !packages.iter().any(|package| package == name) {
+            packages.push(name.to_string());
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_packages() {
+        let output = "\
+Setting up libssl3:amd64 (3.0.11-1~deb12u2) ...
+Setting up openssl (3.0.11-1~deb12u2) ...
+Processing triggers for libc-bin (2.36-9+deb12u3) ...
+Reading package lists...
+Correcting dependencies... Done
+Removing broken-tool (1.0) ...
+Unpacking libssl3:amd64 (3.0.11-1~deb12u2) over (3.0.9-1) ...
+0 upgraded, 0 newly installed, 1 to remove and 0 not upgraded.
+";
+        assert_eq!(fixed_packages(output), vec!["libssl3", "openssl", "broken-tool"]);
+        assert!(fixed_packages("Reading package lists...\n0 upgraded, 0 newly installed").is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Load, memory and disk usage, so a node that is about to run out of space shows up next to
+//! one that needs patches.
+
+use cobbler_api::{DiskUsage, LoadAverage, MemoryUsage, ResourcesResponse};
+use std::fs;
+use std::process::Command;
+use std::thread;
+
+const LOADAVG_FILE: &str = "/proc/loadavg";
+const MEMINFO_FILE: &str = "/proc/meminfo";
+/// Filesystems `df` would list but that don't hold data worth watching. Snaps are squashfs
+/// images, which are always full.
+const IGNORED_FILESYSTEMS: [&str; 4] = ["tmpfs", "devtmpfs", "squashfs", "efivarfs"];
+
+pub fn read() -> ResourcesResponse {
+    let (memory, swap) = fs::read_to_string(MEMINFO_FILE)
+        .map(|content| parse_meminfo(&content))
+        .unwrap_or_default();
+    ResourcesResponse {
+        load_average: fs::read_to_string(LOADAVG_FILE)
+            .ok()
+            .and_then(|content| parse_loadavg(&content)),
+        cpus: thread::available_parallelism().ok().map(usize::from),
+        memory,
+        swap,
+        disks: disks().unwrap_or_default(),
+    }
+}
+
+/// Local filesystems as reported by df, which leaves out pseudo filesystems and lists each
+/// device once. Network filesystems are skipped, so an unreachable server can't stall it.
+fn disks() -> Option<Vec<DiskUsage>> {
+    let output = Command::new("df")
+        .args(["--local", "--output=fstype,size,used,avail,target", "-B1"])
+        .args(IGNORED_FILESYSTEMS.iter().flat_map(|filesystem| ["-x", filesystem]))
+        .output()
+        .ok()?;
+    // df exits with 1 when it can't read some mount point, but still lists the others.
+    if output.stdout.is_empty() {
+        return None;
+    }
+    Some(parse_df(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses `0.52 0.58 0.59 1/467 12345`.
+fn parse_loadavg(content: &str) -> Option<LoadAverage> {
+    let mut fields = content.split_whitespace().map(|field| field.parse().ok());
+    Some(LoadAverage {
+        one: fields.next()??,
+        five: fields.next()??,
+        fifteen: fields.next()??,
+    })
+}
+
+/// Memory and swap from `/proc/meminfo`, whose values are in KiB. Swap is `None` without any.
+fn parse_meminfo(content: &str) -> (Option<MemoryUsage>, Option<MemoryUsage>) {
+    let value = |key: &str| -> Option<u64> {
+        content.lines().find_map(|line| {
+            let kib = line.strip_prefix(key)?.strip_prefix(':')?.trim().strip_suffix("kB")?;
+            kib.trim().parse::<u64>().ok().map(|kib| kib * 1024)
+        })
+    };
+    let usage = |total: Option<u64>, available: Option<u64>| {
+        let (total, available) = (total?, available?);
+        Some(MemoryUsage {
+            total_bytes: total,
+            used_bytes: total.saturating_sub(available),
+            available_bytes: available,
+        })
+    };
+    let memory = usage(value("MemTotal"), value("MemAvailable"));
+    let swap = usage(value("SwapTotal"), value("SwapFree")).filter(|swap| swap.total_bytes > 0);
+    (memory, swap)
+}
+
+/// Parses `df --output=fstype,size,used,avail,target -B1` after its header. The mount point
+/// comes last, as it may contain spaces.
+fn parse_df(output: &str) -> Vec<DiskUsage> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut rest = line.trim_start();
+            let mut field = || {
+                let (field, tail) = rest.split_once(char::is_whitespace)?;
+                rest = tail.trim_start();
+                Some(field)
+            };
+            let filesystem = field()?.to_string();
+            let total_bytes = field()?.parse().ok()?;
+            let used_bytes = field()?.parse().ok()?;
+            let available_bytes = field()?.parse().ok()?;
+            Some(DiskUsage {
+                mount_point: rest.trim_end().to_string(),
+                filesystem,
+                total_bytes,
+                used_bytes,
+                available_bytes,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        assert_eq!(
+            parse_loadavg("0.52 0.58 1.10 1/467 12345\n"),
+            Some(LoadAverage {
+                one: 0.52,
+                five: 0.58,
+                fifteen: 1.1,
+            })
+        );
+        assert_eq!(parse_loadavg(""), None);
+
+        let meminfo = "MemTotal:        8000000 kB\nMemFree:          500000 kB\nMemAvailable:    6000000 kB\nSwapTotal:             0 kB\nSwapFree:              0 kB\n";
+        let (memory, swap) = parse_meminfo(meminfo);
+        assert_eq!(
+            memory,
+            Some(MemoryUsage {
+                total_bytes: 8_192_000_000,
+                used_bytes: 2_048_000_000,
+                available_bytes: 6_144_000_000,
+            })
+        );
+        assert_eq!(swap, None);
+        assert_eq!(parse_meminfo("SwapTotal: 1024 kB\nSwapFree: 256 kB\n").1.unwrap().used_bytes, 768 * 1024);
+    }
+
+    #[test]
+    fn test_parse_df() {
+        let output = "\
+Type   1B-blocks         Used       Avail Mounted on
+ext4   31526391808  26843545600  3072000000 /
+vfat     535805952      6180864   529625088 /boot/efi
+ext4  105089261568   1073741824 98630000000 /media/backup disk
+";
+        let disks = parse_df(output);
+        assert_eq!(disks.len(), 3);
+        assert_eq!(
+            disks[0],
+            DiskUsage {
+                mount_point: "/".to_string(),
+                filesystem: "ext4".to_string(),
+                total_bytes: 31_526_391_808,
+                used_bytes: 26_843_545_600,
+                available_bytes: 3_072_000_000,
+            }
+        );
+        assert_eq!(disks[2].mount_point, "/media/backup disk");
+        assert!(parse_df("Type 1B-blocks Used Avail Mounted on\n").is_empty());
+    }
+}
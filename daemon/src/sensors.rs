@@ -0,0 +1,207 @@
+//! Temperatures, fans and throttling, for single-board computers where heat decides whether
+//! a long upgrade is a good idea. Read from the kernel's thermal zones and hwmon chips, and on
+//! Raspberry Pis from the firmware through `vcgencmd`.
+
+use cobbler_api::{Fan, SensorsResponse, Temperature, ThrottleCondition, Throttling};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+pub const SYS_CLASS_DIR: &str = "/sys/class";
+/// Thermal zone types and hwmon chips that measure the CPU, the most telling first.
+const CPU_SENSORS: [&str; 5] = ["x86_pkg_temp", "coretemp", "k10temp", "cpu_thermal", "soc_thermal"];
+/// `vcgencmd get_throttled` bits 0-3 are conditions in effect, bits 16-19 the same since boot.
+const THROTTLE_CONDITIONS: [ThrottleCondition; 4] = [
+    ThrottleCondition::UnderVoltage,
+    ThrottleCondition::FrequencyCapped,
+    ThrottleCondition::Throttled,
+    ThrottleCondition::SoftTemperatureLimit,
+];
+
+/// Temperatures and fans under `sys_class`, normally [`SYS_CLASS_DIR`]. Throttling is left to
+/// [`throttling`].
+pub fn read(sys_class: &Path) -> SensorsResponse {
+    let mut temperatures = thermal_zones(&sys_class.join("thermal"));
+    let mut fans = Vec::new();
+    hwmon(&sys_class.join("hwmon"), &mut temperatures, &mut fans);
+    let cpu_temperature_celsius = CPU_SENSORS.iter().find_map(|sensor| {
+        temperatures
+            .iter()
+            .find(|temperature| chip(&temperature.name) == *sensor)
+            .map(|temperature| temperature.celsius)
+    });
+    SensorsResponse {
+        cpu_temperature_celsius,
+        temperatures,
+        fans,
+        throttling: None,
+    }
+}
+
+/// The Raspberry Pi firmware's throttling state; `None` elsewhere, where `vcgencmd` is missing.
+pub fn throttling() -> Option<Throttling> {
+    let output = Command::new("vcgencmd").arg("get_throttled").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_throttled(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// `thermal_zone*/type` and `temp`, in millidegrees Celsius, ordered by zone number.
+fn thermal_zones(dir: &Path) -> Vec<Temperature> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut zones: Vec<(u32, Temperature)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let index = entry.file_name().to_str()?.strip_prefix("thermal_zone")?.parse().ok()?;
+            let path = entry.path();
+            let name = fs::read_to_string(path.join("type")).ok()?.trim().to_string();
+            let celsius = read_millis(&path.join("temp"))?;
+            Some((index, Temperature { name, celsius }))
+        })
+        .collect();
+    zones.sort_by_key(|(index, _)| *index);
+    zones.into_iter().map(|(_, zone)| zone).collect()
+}
+
+/// Adds the `temp*_input` and `fan*_input` readings of every hwmon chip, named after the chip
+/// and the reading's label. Chips that only mirror a thermal zone already listed are skipped.
+fn hwmon(dir: &Path, temperatures: &mut Vec<Temperature>, fans: &mut Vec<Fan>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut chips: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    chips.sort();
+    let zones: Vec<String> = temperatures.iter().map(|zone| chip(&zone.name)).collect();
+    for path in chips {
+        let Ok(chip_name) = fs::read_to_string(path.join("name")) else {
+            continue;
+        };
+        let chip_name = chip_name.trim();
+        let Ok(files) = fs::read_dir(&path) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = files
+            .flatten()
+            .filter_map(|file| file.file_name().to_str()?.strip_suffix("_input").map(str::to_string))
+            .collect();
+        inputs.sort_by_key(|input| {
+            let (kind, number) = input.split_at(input.find(|c: char| c.is_ascii_digit()).unwrap_or(input.len()));
+            (kind.to_string(), number.parse::<u32>().unwrap_or_default())
+        });
+        for input in inputs {
+            let label = fs::read_to_string(path.join(format!("{input}_label")))
+                .map(|label| label.trim().to_string())
+                .unwrap_or_else(|_| input.clone());
+            let name = format!("{chip_name} {label}");
+            let value = path.join(format!("{input}_input"));
+            if input.starts_with("temp") && !zones.contains(&chip(chip_name)) {
+                if let Some(celsius) = read_millis(&value) {
+                    temperatures.push(Temperature { name, celsius });
+                }
+            } else if input.starts_with("fan")
+                && let Some(rpm) = fs::read_to_string(&value).ok().and_then(|rpm| rpm.trim().parse().ok())
+            {
+                fans.push(Fan { name, rpm });
+            }
+        }
+    }
+}
+
+/// The chip or zone type a sensor name starts with, with `-` as `_`, as thermal zones and
+/// hwmon spell the same sensor differently (`cpu-thermal` and `cpu_thermal`).
+fn chip(name: &str) -> String {
+    name.split(' ').next().unwrap_or_default().replace('-', "_")
+}
+
+/// Reads a value in thousandths and returns it in units, rounded to one decimal.
+fn read_millis(path: &Path) -> Option<f64> {
+    let millis: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some((millis as f64 / 100.0).round() / 10.0)
+}
+
+/// Parses `throttled=0x50005`.
+fn parse_throttled(output: &str) -> Option<Throttling> {
+    let raw = output.trim().strip_prefix("throttled=")?;
+    let bits = u32::from_str_radix(raw.trim_start_matches("0x"), 16).ok()?;
+    let conditions = |bits: u32| -> Vec<ThrottleCondition> {
+        THROTTLE_CONDITIONS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bits & (1 << bit) != 0)
+            .map(|(_, condition)| *condition)
+            .collect()
+    };
+    Some(Throttling {
+        raw: raw.to_string(),
+        active: conditions(bits),
+        occurred: conditions(bits | (bits >> 16)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let dir = std::env::temp_dir().join(format!("cobblerd-sensors-{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("thermal/thermal_zone0/type", "cpu-thermal\n");
+        write("thermal/thermal_zone0/temp", "61835\n");
+        write("thermal/cooling_device0/type", "pwm-fan\n");
+        write("hwmon/hwmon0/name", "cpu_thermal\n");
+        write("hwmon/hwmon0/temp1_input", "61835\n");
+        write("hwmon/hwmon1/name", "nvme\n");
+        write("hwmon/hwmon1/temp1_input", "38850\n");
+        write("hwmon/hwmon1/temp1_label", "Composite\n");
+        write("hwmon/hwmon2/name", "pwmfan\n");
+        write("hwmon/hwmon2/fan1_input", "2950\n");
+
+        let sensors = read(&dir);
+        assert_eq!(sensors.cpu_temperature_celsius, Some(61.8));
+        assert_eq!(
+            sensors.temperatures,
+            [
+                Temperature {
+                    name: "cpu-thermal".to_string(),
+                    celsius: 61.8,
+                },
+                Temperature {
+                    name: "nvme Composite".to_string(),
+                    celsius: 38.9,
+                },
+            ]
+        );
+        assert_eq!(
+            sensors.fans,
+            [Fan {
+                name: "pwmfan fan1".to_string(),
+                rpm: 2950,
+            }]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(read(&dir), SensorsResponse::default());
+    }
+
+    #[test]
+    fn test_parse_throttled() {
+        let throttling = parse_throttled("throttled=0x50005\n").unwrap();
+        assert_eq!(throttling.raw, "0x50005");
+        assert_eq!(throttling.active, [ThrottleCondition::UnderVoltage, ThrottleCondition::Throttled]);
+        assert_eq!(throttling.occurred, [ThrottleCondition::UnderVoltage, ThrottleCondition::Throttled]);
+
+        let throttling = parse_throttled("throttled=0x80000").unwrap();
+        assert!(throttling.active.is_empty());
+        assert_eq!(throttling.occurred, [ThrottleCondition::SoftTemperatureLimit]);
+        assert!(parse_throttled("throttled=0x0").unwrap().occurred.is_empty());
+        assert_eq!(parse_throttled("error=1"), None);
+    }
+}
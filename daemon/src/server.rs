@@ -0,0 +1,300 @@
+//! Accepts connections and serves the router on them over HTTP/1 and HTTP/2. Unlike
+//! `axum::serve`, it stops accepting while `--max-connections` are open, and closes
+//! connections that sit idle, so that clients which connect and go quiet can't lock everyone
+//! else out.
+
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::Router;
+use crate::limits::Limits;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tower::ServiceExt;
+use tracing::error;
+
+/// Default of `--idle-timeout` (seconds).
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 60;
+
+#[derive(Clone)]
+pub struct Server {
+    pub app: Router,
+    /// Open connections, shared by all listeners.
+    pub connections: Arc<Semaphore>,
+    /// How long a connection may go without a request in flight, and how long a client may
+    /// take to send a request's headers; `None` for no limit.
+    pub idle_timeout: Option<Duration>,
+    /// Bounds how much of a request's headers is buffered.
+    pub limits: Limits,
+}
+
+impl Server {
+    /// Serves connections from `listener` until `stopped` turns true, then waits for the
+    /// requests in flight to be answered.
+    pub async fn serve(&self, listener: TcpListener, mut stopped: watch::Receiver<bool>) {
+        // Every connection holds a receiver; `closed` resolves once all of them are gone.
+        let (open, connection) = watch::channel(());
+        loop {
+            // Connections beyond the limit wait in the listen backlog until one closes.
+            let permit = tokio::select! {
+                permit = self.connections.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+                _ = until_stopped(&mut stopped) => break,
+            };
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        if !is_connection_error(&err) {
+                            // Most likely out of file descriptors; give them time to be freed.
+                            error!("failed to accept a connection: {err}");
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                        continue;
+                    }
+                },
+                _ = until_stopped(&mut stopped) => break,
+            };
+            let _ = stream.set_nodelay(true);
+            let server = self.clone();
+            let (stopped, connection) = (stopped.clone(), connection.clone());
+            tokio::spawn(async move {
+                server.connection(stream, addr, stopped).await;
+                drop((permit, connection));
+            });
+        }
+        drop(connection);
+        open.closed().await;
+    }
+
+    async fn connection(self, stream: TcpStream, addr: SocketAddr, mut stopped: watch::Receiver<bool>) {
+        let (in_flight, mut requests) = watch::channel(0usize);
+        let in_flight = Arc::new(in_flight);
+        let service = self
+            .app
+            .map_request(move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(addr));
+                req
+            })
+            .map_future(move |future| {
+                let request = InFlight::new(in_flight.clone());
+                async move {
+                    let response = future.await;
+                    drop(request);
+                    response
+                }
+            });
+
+        let mut builder = Builder::new(TokioExecutor::new());
+        // Oversized headers are refused before they are buffered in full; `limits::enforce`
+        // then applies the exact limit.
+        let header_bytes = self.limits.header_buffer_bytes();
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.idle_timeout)
+            .max_buf_size(header_bytes);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_header_list_size(u32::try_from(header_bytes).unwrap_or(u32::MAX));
+        let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service));
+        tokio::pin!(connection);
+        tokio::select! {
+            _ = connection.as_mut() => return,
+            _ = until_stopped(&mut stopped) => {}
+            _ = until_idle(&mut requests, self.idle_timeout) => {}
+        }
+        // Finishes the requests in flight, then closes.
+        connection.as_mut().graceful_shutdown();
+        let _ = connection.await;
+    }
+}
+
+/// Counts a request in flight on a connection until dropped.
+struct InFlight(Arc<watch::Sender<usize>>);
+
+impl InFlight {
+    fn new(requests: Arc<watch::Sender<usize>>) -> Self {
+        requests.send_modify(|count| *count += 1);
+        InFlight(requests)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Resolves once `stopped` turns true, or its sender is gone.
+async fn until_stopped(stopped: &mut watch::Receiver<bool>) {
+    let _ = stopped.wait_for(|stop| *stop).await;
+}
+
+/// Resolves once a connection has had no request in flight for `timeout`; never if `None`.
+async fn until_idle(requests: &mut watch::Receiver<usize>, timeout: Option<Duration>) {
+    let Some(timeout) = timeout else {
+        return std::future::pending().await;
+    };
+    loop {
+        if requests.wait_for(|count| *count == 0).await.is_err() {
+            return;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(timeout) => return,
+            changed = requests.changed() => if changed.is_err() {
+                return;
+            },
+        }
+    }
+}
+
+/// Errors that concern only the connection being accepted, not the listener.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::io::{Read, Write};
+
+    async fn start(app: Router, connections: usize, idle_timeout: Option<Duration>) -> (SocketAddr, watch::Sender<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = watch::channel(false);
+        let server = Server {
+            app,
+            connections: Arc::new(Semaphore::new(connections)),
+            idle_timeout,
+            limits: Limits::default(),
+        };
+        tokio::spawn(async move { server.serve(listener, stopped).await });
+        (addr, stop)
+    }
+
+    fn read_status(stream: &mut std::net::TcpStream) -> io::Result<String> {
+        let mut response = [0; 12];
+        stream.read_exact(&mut response)?;
+        Ok(String::from_utf8_lossy(&response[9..]).into_owned())
+    }
+
+    const REQUEST: &[u8] = b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let app = Router::new().route("/status", get(|| async { "ok" }));
+        let (addr, _stop) = start(app, 1, None).await;
+
+        tokio::task::spawn_blocking(move || {
+            let mut first = std::net::TcpStream::connect(addr).unwrap();
+            first.write_all(REQUEST).unwrap();
+            assert_eq!(read_status(&mut first).unwrap(), "200");
+            // The first connection is kept alive, so the second isn't accepted until it closes.
+            let mut second = std::net::TcpStream::connect(addr).unwrap();
+            second.write_all(REQUEST).unwrap();
+            second.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+            let mut buffer = [0; 1];
+            assert!(second.read(&mut buffer).is_err());
+            drop(first);
+            second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            assert_eq!(read_status(&mut second).unwrap(), "200");
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_header_buffer() {
+        let app = Router::new().route("/status", get(|| async { "ok" }));
+        let (addr, _stop) = start(app, 1, None).await;
+
+        let status = tokio::task::spawn_blocking(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            // hyper may read up to about twice the buffer size before it parses.
+            let padding = "x".repeat(Limits::default().header_buffer_bytes() * 4);
+            // The server may answer and close before it has read everything.
+            let _ = write!(stream, "GET /status HTTP/1.1\r\nHost: localhost\r\nX-Padding: {padding}\r\n\r\n");
+            read_status(&mut stream).unwrap()
+        })
+        .await
+        .unwrap();
+        assert_eq!(status, "431");
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let app = Router::new()
+            .route("/status", get(|| async { "ok" }))
+            .route("/slow", get(|| async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                "ok"
+            }));
+        let (addr, _stop) = start(app, 1, Some(Duration::from_millis(200))).await;
+
+        tokio::task::spawn_blocking(move || {
+            // Reading to the end fails on the read timeout unless the server closes.
+            let close = |mut stream: std::net::TcpStream| stream.read_to_end(&mut Vec::new()).unwrap();
+            // Silent, idle and slow-header connections are closed, freeing the slot.
+            let silent = std::net::TcpStream::connect(addr).unwrap();
+            silent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            close(silent);
+            let mut idle = std::net::TcpStream::connect(addr).unwrap();
+            idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            idle.write_all(REQUEST).unwrap();
+            assert_eq!(read_status(&mut idle).unwrap(), "200");
+            close(idle);
+            let mut partial = std::net::TcpStream::connect(addr).unwrap();
+            partial.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            partial.write_all(b"GET /status HTTP/1.1\r\n").unwrap();
+            close(partial);
+            // A request taking longer than the timeout is still answered.
+            let mut slow = std::net::TcpStream::connect(addr).unwrap();
+            slow.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            slow.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            assert_eq!(read_status(&mut slow).unwrap(), "200");
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let app = Router::new().route("/status", get(|| async { "ok" }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = watch::channel(false);
+        let server = Server {
+            app,
+            connections: Arc::new(Semaphore::new(1)),
+            idle_timeout: None,
+            limits: Limits::default(),
+        };
+        let serving = tokio::spawn(async move { server.serve(listener, stopped).await });
+
+        let mut stream = tokio::task::spawn_blocking(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(REQUEST).unwrap();
+            assert_eq!(read_status(&mut stream).unwrap(), "200");
+            stream
+        })
+        .await
+        .unwrap();
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), serving).await.unwrap().unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+    }
+}
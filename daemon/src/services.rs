@@ -0,0 +1,378 @@
+//! Services still running code that an upgrade replaced. A process keeps the old versions of
+//! its executable and libraries mapped until it restarts, so a patched package isn't in
+//! effect before that. Like needrestart, this looks for mappings of deleted files in
+//! `/proc/<pid>/maps` and attributes the processes to systemd services by their cgroup.
+//!
+//! Beyond that, clients can list the system services and start, stop and restart those the
+//! operator allowlisted, e.g. the one a package upgrade just replaced.
+
+use crate::glob;
+use cobbler_api::{RestartServicesResponse, ServiceAction, ServiceFailure, ServiceInfo};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub const PROC_DIR: &str = "/proc";
+/// Where packages install executables and libraries. Deleted files elsewhere, such as
+/// shared memory or temporary files, are no sign of an upgrade.
+const PACKAGE_DIRS: [&str; 5] = ["/usr/", "/lib", "/bin/", "/sbin/", "/opt/"];
+/// Services whose restart would end sessions or take the system bus down; needrestart
+/// leaves these alone too. Templates are matched up to the `@`.
+const NEVER_RESTART: [&str; 9] = [
+    "dbus.service",
+    "dbus-broker.service",
+    "systemd-logind.service",
+    "getty@",
+    "serial-getty@",
+    "user@",
+    "gdm.service",
+    "sddm.service",
+    "lightdm.service",
+];
+
+/// Units of the system services with at least one process using a replaced executable or
+/// library, sorted.
+pub fn outdated(proc_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(proc_dir) else {
+        return Vec::new();
+    };
+    let mut units = BTreeSet::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if !entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        // Processes may exit while we look; kernel threads have no maps.
+        let Ok(maps) = fs::read_to_string(dir.join("maps")) else {
+            continue;
+        };
+        if !uses_deleted_files(&maps) {
+            continue;
+        }
+        if let Some(unit) = fs::read_to_string(dir.join("cgroup"))
+            .ok()
+            .and_then(|cgroup| service_unit(&cgroup))
+        {
+            units.insert(unit);
+        }
+    }
+    units.into_iter().collect()
+}
+
+/// Restarts the outdated services one by one, except those in [`NEVER_RESTART`] and the
+/// daemon's own, which would cut off this request.
+pub fn restart_outdated() -> RestartServicesResponse {
+    let own_unit = own_unit();
+    let mut response = RestartServicesResponse::default();
+    for unit in outdated(Path::new(PROC_DIR)) {
+        if Some(&unit) == own_unit.as_ref() || is_protected(&unit) {
+            response.skipped.push(unit);
+            continue;
+        }
+        info!("restarting {unit}, which uses outdated libraries");
+        match Command::new("systemctl").args(["restart", "--", &unit]).output() {
+            Ok(output) if output.status.success() => response.restarted.push(unit),
+            Ok(output) => {
+                let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                warn!("failed to restart {unit}: {message}");
+                response.failed.push(ServiceFailure { service: unit, message });
+            }
+            Err(err) => {
+                warn!("failed to run systemctl: {err}");
+                response.failed.push(ServiceFailure {
+                    service: unit,
+                    message: format!("failed to run systemctl: {err}"),
+                });
+            }
+        }
+    }
+    response
+}
+
+/// The service the daemon itself runs as, if any.
+pub fn own_unit() -> Option<String> {
+    fs::read_to_string(Path::new(PROC_DIR).join("self/cgroup"))
+        .ok()
+        .and_then(|cgroup| service_unit(&cgroup))
+}
+
+/// Services that clients may start, stop and restart, as unit names or patterns with `*`
+/// such as `php*-fpm.service`. A default allowlist allows none.
+#[derive(Clone, Default)]
+pub struct ServiceAllowlist {
+    patterns: Arc<Vec<String>>,
+}
+
+impl ServiceAllowlist {
+    /// Patterns without a unit type are services: `nginx` means `nginx.service`.
+    pub fn new(patterns: Vec<String>) -> Self {
+        ServiceAllowlist {
+            patterns: Arc::new(patterns.iter().map(|pattern| unit_name(pattern)).collect()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn allows(&self, unit: &str) -> bool {
+        self.patterns.iter().any(|pattern| glob::matches(pattern, unit))
+    }
+}
+
+/// The unit systemctl takes `name` for: `nginx` is `nginx.service`.
+pub fn unit_name(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((_, kind)) if !kind.is_empty() && kind.bytes().all(|b| b.is_ascii_lowercase()) => name.to_string(),
+        _ => format!("{name}.service"),
+    }
+}
+
+/// Whether `unit` is a valid name of a service unit, which keeps paths and options out of
+/// systemctl's arguments.
+pub fn is_valid_service(unit: &str) -> bool {
+    unit.strip_suffix(".service").is_some_and(|prefix| {
+        !prefix.is_empty()
+            && !prefix.starts_with('-')
+            && prefix.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_' | '.' | '\\' | '@'))
+    })
+}
+
+/// The system services systemd has loaded, running or not, sorted by name.
+pub fn list(allowlist: &ServiceAllowlist) -> io::Result<Vec<ServiceInfo>> {
+    let units = systemctl(&["list-units", "--type=service", "--all", "--plain", "--no-legend", "--no-pager"])?;
+    let files = systemctl(&["list-unit-files", "--type=service", "--no-legend", "--no-pager"])?;
+    let mut services = parse_units(&units, &parse_unit_files(&files));
+    for service in &mut services {
+        service.allowed = allowlist.allows(&service.name);
+    }
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(services)
+}
+
+/// The state of one service, `None` if systemd doesn't know it.
+pub fn show(unit: &str, allowlist: &ServiceAllowlist) -> io::Result<Option<ServiceInfo>> {
+    let properties = "--property=Id,Description,LoadState,ActiveState,SubState,UnitFileState";
+    let output = systemctl(&["show", properties, "--", unit])?;
+    Ok(parse_show(&output).map(|service| ServiceInfo {
+        allowed: allowlist.allows(&service.name),
+        ..service
+    }))
+}
+
+/// Runs `systemctl <action>` on a service and waits for the job to finish. The error is
+/// what systemctl reported.
+pub fn control(unit: &str, action: ServiceAction) -> Result<(), String> {
+    let verb = match action {
+        ServiceAction::Start => "starting",
+        ServiceAction::Stop => "stopping",
+        ServiceAction::Restart => "restarting",
+    };
+    info!("{verb} {unit}");
+    let output = Command::new("systemctl")
+        .args([action.as_str(), "--", unit])
+        .output()
+        .map_err(|err| format!("failed to run systemctl: {err}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(match message.is_empty() {
+        true => format!("systemctl {} exited with {}", action.as_str(), output.status),
+        false => message,
+    })
+}
+
+fn systemctl(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("systemctl").args(args).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("systemctl {} failed: {message}", args[0])));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses `systemctl list-units --plain --no-legend` lines: unit, load state, active state,
+/// sub state and description. Units that are referenced but don't exist are left out.
+fn parse_units(output: &str, files: &HashMap<String, String>) -> Vec<ServiceInfo> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start_matches(['●', '*', ' ']);
+            let mut fields = line.split_whitespace();
+            let (name, load, active, sub) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+            if load == "not-found" {
+                return None;
+            }
+            // Instances such as `getty@tty1.service` are enabled through their template.
+            let template = name.split_once('@').map(|(prefix, _)| format!("{prefix}@.service"));
+            let file_state = files.get(name).or_else(|| template.and_then(|template| files.get(&template)));
+            Some(ServiceInfo {
+                name: name.to_string(),
+                description: fields.collect::<Vec<_>>().join(" "),
+                active_state: active.to_string(),
+                sub_state: sub.to_string(),
+                enabled: file_state.is_some_and(|state| is_enabled(state)),
+                allowed: false,
+            })
+        })
+        .collect()
+}
+
+/// Parses `systemctl list-unit-files --no-legend` into the state of each unit file.
+fn parse_unit_files(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Parses the `key=value` lines of `systemctl show`.
+fn parse_show(output: &str) -> Option<ServiceInfo> {
+    let properties: HashMap<&str, &str> = output.lines().filter_map(|line| line.split_once('=')).collect();
+    if properties.get("LoadState").is_none_or(|state| *state == "not-found") {
+        return None;
+    }
+    let property = |key| properties.get(key).copied().unwrap_or_default().to_string();
+    Some(ServiceInfo {
+        name: property("Id"),
+        description: property("Description"),
+        active_state: property("ActiveState"),
+        sub_state: property("SubState"),
+        enabled: properties.get("UnitFileState").is_some_and(|state| is_enabled(state)),
+        allowed: false,
+    })
+}
+
+fn is_enabled(unit_file_state: &str) -> bool {
+    matches!(unit_file_state, "enabled" | "enabled-runtime")
+}
+
+fn is_protected(unit: &str) -> bool {
+    NEVER_RESTART.iter().any(|name| match name.strip_suffix('@') {
+        Some(template) => unit.split_once('@').is_some_and(|(prefix, _)| prefix == template),
+        None => unit == *name,
+    })
+}
+
+/// Whether a `maps` file has a mapping of a deleted file from a package directory.
+fn uses_deleted_files(maps: &str) -> bool {
+    maps.lines().any(|line| {
+        // address perms offset dev inode path; the path may contain spaces.
+        let Some(path) = line.splitn(6, ' ').nth(5).map(str::trim_start) else {
+            return false;
+        };
+        path.strip_suffix(" (deleted)")
+            .is_some_and(|path| PACKAGE_DIRS.iter().any(|dir| path.starts_with(dir)))
+    })
+}
+
+/// The system service a process belongs to, from its `cgroup` file: the first `.service`
+/// under `/system.slice` of the unified (`0::`) or systemd (`name=systemd`) hierarchy.
+/// Processes of user sessions and scopes belong to none.
+fn service_unit(cgroup: &str) -> Option<String> {
+    let path = cgroup.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        matches!(controllers, "" | "name=systemd").then_some(path)
+    })?;
+    path.strip_prefix("/system.slice/")?
+        .split('/')
+        .find(|name| name.ends_with(".service"))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uses_deleted_files() {
+        let maps = "\
+55d1c2a00000-55d1c2a2a000 r--p 00000000 08:01 393304                     /usr/sbin/nginx
+7f3a1c000000-7f3a1c021000 rw-p 00000000 00:00 0
+7f3a1d200000-7f3a1d2a0000 r-xp 00000000 08:01 400000                     /dev/shm/cache (deleted)
+";
+        assert!(!uses_deleted_files(maps));
+        let maps = format!(
+            "{maps}7f3a1e400000-7f3a1e4a8000 r-xp 00000000 08:01 401234                     /usr/lib/x86_64-linux-gnu/libssl.so.3 (deleted)\n"
+        );
+        assert!(uses_deleted_files(&maps));
+    }
+
+    #[test]
+    fn test_service_unit() {
+        assert_eq!(service_unit("0::/system.slice/nginx.service\n").as_deref(), Some("nginx.service"));
+        assert_eq!(
+            service_unit("12:pids:/system.slice/cron.service\n1:name=systemd:/system.slice/cron.service\n")
+                .as_deref(),
+            Some("cron.service")
+        );
+        assert_eq!(service_unit("0::/user.slice/user-1000.slice/session-2.scope\n"), None);
+        assert_eq!(service_unit("0::/init.scope\n"), None);
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let allowlist = ServiceAllowlist::new(vec!["nginx".to_string(), "php*-fpm.service".to_string()]);
+        assert!(allowlist.allows("nginx.service"));
+        assert!(allowlist.allows("php8.2-fpm.service"));
+        assert!(!allowlist.allows("nginx-debug.service"));
+        assert!(!allowlist.allows("php8.2-cgi.service"));
+        assert!(!ServiceAllowlist::default().allows("nginx.service"));
+
+        assert_eq!(unit_name("nginx"), "nginx.service");
+        assert_eq!(unit_name("php8.2-fpm"), "php8.2-fpm.service");
+        assert_eq!(unit_name("getty@tty1.service"), "getty@tty1.service");
+        assert!(is_valid_service("getty@tty1.service"));
+        assert!(!is_valid_service("nginx.socket"));
+        assert!(!is_valid_service("../nginx.service"));
+        assert!(!is_valid_service("--now.service"));
+    }
+
+    #[test]
+    fn test_parse_units() {
+        let files = parse_unit_files("nginx.service enabled enabled\ngetty@.service enabled enabled\ncron.service disabled enabled\n");
+        let units = "\
+nginx.service loaded active running A high performance web server and a reverse proxy server
+getty@tty1.service loaded active running Getty on tty1
+cron.service loaded failed failed Regular background program processing daemon
+plymouth-start.service not-found inactive dead plymouth-start.service
+";
+        let services = parse_units(units, &files);
+        assert_eq!(services.len(), 3);
+        assert_eq!(
+            services[0],
+            ServiceInfo {
+                name: "nginx.service".to_string(),
+                description: "A high performance web server and a reverse proxy server".to_string(),
+                active_state: "active".to_string(),
+                sub_state: "running".to_string(),
+                enabled: true,
+                allowed: false,
+            }
+        );
+        assert!(services[1].enabled);
+        assert!(!services[2].enabled);
+        assert_eq!(services[2].active_state, "failed");
+
+        let show = "Id=nginx.service\nDescription=nginx\nLoadState=loaded\nActiveState=active\nSubState=running\nUnitFileState=enabled\n";
+        assert_eq!(parse_show(show).unwrap().sub_state, "running");
+        assert_eq!(parse_show("Id=nope.service\nLoadState=not-found\nActiveState=inactive\n"), None);
+    }
+
+    #[test]
+    fn test_is_protected() {
+        assert!(is_protected("dbus.service"));
+        assert!(is_protected("getty@tty1.service"));
+        assert!(!is_protected("nginx.service"));
+        assert!(!is_protected("gettysburg.service"));
+    }
+}
@@ -0,0 +1,379 @@
+//! apt repositories. They are listed from `sources.list` and `sources.list.d`, added as
+//! deb822 `.sources` files and removed by file name.
+
+use crate::priority::Priority;
+use cobbler_api::{AddSourceRequest, AptSource};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const SOURCES_LIST: &str = "/etc/apt/sources.list";
+const SOURCES_DIR: &str = "/etc/apt/sources.list.d";
+const EXTENSIONS: [&str; 2] = ["list", "sources"];
+const ARMORED_KEY_HEADER: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+
+/// Every entry apt reads, including commented-out and disabled ones.
+pub fn list() -> io::Result<Vec<AptSource>> {
+    let mut sources = match fs::read_to_string(SOURCES_LIST) {
+        Ok(content) => parse_one_line(&content, SOURCES_LIST, None),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+
+    let mut paths: Vec<PathBuf> = match fs::read_dir(SOURCES_DIR) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    paths.sort();
+    for path in paths {
+        let (Some(name), Some(extension)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.extension().and_then(|extension| extension.to_str()),
+        ) else {
+            continue;
+        };
+        let file = path.display().to_string();
+        match extension {
+            "list" => sources.extend(parse_one_line(&fs::read_to_string(&path)?, &file, Some(name))),
+            "sources" => sources.extend(parse_deb822(&fs::read_to_string(&path)?, &file, Some(name))),
+            _ => {}
+        }
+    }
+    Ok(sources)
+}
+
+/// Writes `{name}.sources`. Fails with `AlreadyExists` if a `.list` or `.sources` file of
+/// that name exists.
+pub fn add(request: &AddSourceRequest) -> io::Result<()> {
+    if EXTENSIONS.iter().any(|extension| source_path(&request.name, extension).exists()) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("source {} already exists", request.name),
+        ));
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(source_path(&request.name, "sources"))?
+        .write_all(render(request).as_bytes())
+}
+
+/// Deletes `{name}.list` and `{name}.sources`. Fails with `NotFound` if neither exists.
+pub fn remove(name: &str) -> io::Result<()> {
+    let mut removed = false;
+    for extension in EXTENSIONS {
+        match fs::remove_file(source_path(name, extension)) {
+            Ok(()) => removed = true,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if removed {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("source {name} not found")))
+    }
+}
+
+/// Runs `apt-get update` and returns the last lines of its output. apt only warns about
+/// repositories it could not fetch, so fetch failures of `uris` count as errors too.
+pub fn refresh(uris: &[String]) -> Result<String, String> {
+    let output = crate::noninteractive::command("apt-get", None, Priority::default())
+        .arg("update")
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|err| format!("failed to execute apt-get update: {err}"))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("apt-get update failed: {}", crate::tail_lines(&stderr, 20)));
+    }
+    let failed = failed_fetches(&stderr, uris);
+    if !failed.is_empty() {
+        return Err(format!("apt-get update failed: {}", failed.join("\n")));
+    }
+    Ok(crate::tail_lines(&String::from_utf8_lossy(&output.stdout), 20))
+}
+
+/// Checks a request before anything is written. Returns the offending field and why.
+pub fn validate(request: &AddSourceRequest) -> Result<(), (&'static str, String)> {
+    if !is_valid_name(&request.name) {
+        return Err(("name", format!("invalid source name: {:?}", request.name)));
+    }
+    if let Some(kind) = request.types.iter().find(|kind| !matches!(kind.as_str(), "deb" | "deb-src")) {
+        return Err(("types", format!("invalid type {kind:?}, expected deb or deb-src")));
+    }
+    if request.uris.is_empty() {
+        return Err(("uris", "no URIs given".to_string()));
+    }
+    if let Some(uri) = request.uris.iter().find(|uri| !is_valid_word(uri) || !uri.contains(':')) {
+        return Err(("uris", format!("invalid URI: {uri:?}")));
+    }
+    if request.suites.is_empty() {
+        return Err(("suites", "no suites given".to_string()));
+    }
+    if let Some(suite) = request.suites.iter().find(|suite| !is_valid_word(suite)) {
+        return Err(("suites", format!("invalid suite: {suite:?}")));
+    }
+    // A suite ending in `/` is an exact path (flat repository), which takes no components.
+    let flat = request.suites.iter().all(|suite| suite.ends_with('/'));
+    if flat != request.components.is_empty() {
+        return Err((
+            "components",
+            if flat { "flat repositories take no components" } else { "no components given" }.to_string(),
+        ));
+    }
+    if let Some(component) = request.components.iter().find(|component| !is_valid_word(component)) {
+        return Err(("components", format!("invalid component: {component:?}")));
+    }
+    if let Some(signed_by) = &request.signed_by {
+        let is_key = signed_by.trim_start().starts_with(ARMORED_KEY_HEADER);
+        let is_path = signed_by.starts_with('/') && is_valid_word(signed_by);
+        if !is_key && !is_path {
+            return Err((
+                "signed_by",
+                "expected an absolute keyring path or an ASCII-armored public key".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// apt only reads files in `sources.list.d` whose names consist of these characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+}
+
+fn is_valid_word(value: &str) -> bool {
+    !value.is_empty() && !value.chars().any(|c| c.is_whitespace() || c.is_control() || c == '#')
+}
+
+fn source_path(name: &str, extension: &str) -> PathBuf {
+    Path::new(SOURCES_DIR).join(format!("{name}.{extension}"))
+}
+
+fn failed_fetches(stderr: &str, uris: &[String]) -> Vec<String> {
+    stderr
+        .lines()
+        .filter(|line| line.contains("Failed to fetch"))
+        .filter(|line| uris.iter().any(|uri| line.contains(uri.trim_end_matches('/'))))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The deb822 paragraph for `request`. An inline key becomes a multi-line field, with
+/// empty lines written as ` .`.
+fn render(request: &AddSourceRequest) -> String {
+    let types = if request.types.is_empty() {
+        "deb".to_string()
+    } else {
+        request.types.join(" ")
+    };
+    let mut content = format!(
+        "# Added by cobblerd (POST /sources)\nTypes: {types}\nURIs: {}\nSuites: {}\n",
+        request.uris.join(" "),
+        request.suites.join(" ")
+    );
+    if !request.components.is_empty() {
+        content.push_str(&format!("Components: {}\n", request.components.join(" ")));
+    }
+    match request.signed_by.as_deref().map(str::trim) {
+        Some(key) if key.starts_with(ARMORED_KEY_HEADER) => {
+            content.push_str("Signed-By:\n");
+            for line in key.lines().map(str::trim) {
+                content.push_str(if line.is_empty() { " ." } else { " " });
+                content.push_str(line);
+                content.push('\n');
+            }
+        }
+        Some(path) => content.push_str(&format!("Signed-By: {path}\n")),
+        None => {}
+    }
+    content
+}
+
+/// Parses one-line style entries like `deb [signed-by=/usr/share/keyrings/x.gpg] URI suite
+/// component...`. Commented-out entries are returned as disabled.
+fn parse_one_line(content: &str, file: &str, name: Option<&str>) -> Vec<AptSource> {
+    let mut sources = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let (enabled, line) = match line.strip_prefix('#') {
+            Some(commented) => (false, commented.trim_start()),
+            None => (true, line),
+        };
+        let line = line.split('#').next().unwrap_or_default();
+        let Some((kind, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        if kind != "deb" && kind != "deb-src" {
+            continue;
+        }
+
+        let mut rest = rest.trim_start();
+        let mut signed_by = None;
+        if let Some(options) = rest.strip_prefix('[') {
+            let Some((options, after)) = options.split_once(']') else {
+                continue;
+            };
+            signed_by = options
+                .split_whitespace()
+                .find_map(|option| option.strip_prefix("signed-by="))
+                .map(str::to_string);
+            rest = after;
+        }
+
+        let mut words = rest.split_whitespace().map(str::to_string);
+        let (Some(uri), Some(suite)) = (words.next(), words.next()) else {
+            continue;
+        };
+        sources.push(AptSource {
+            name: name.map(str::to_string),
+            file: file.to_string(),
+            enabled,
+            types: vec![kind.to_string()],
+            uris: vec![uri],
+            suites: vec![suite],
+            components: words.collect(),
+            signed_by,
+        });
+    }
+    sources
+}
+
+/// Parses deb822 paragraphs. Field names are case-insensitive and continuation lines start
+/// with whitespace.
+fn parse_deb822(content: &str, file: &str, name: Option<&str>) -> Vec<AptSource> {
+    let mut paragraphs: Vec<Vec<(String, String)>> = vec![Vec::new()];
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let paragraph = paragraphs.last_mut().expect("at least one paragraph");
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(Vec::new());
+            }
+        } else if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = paragraph.last_mut() {
+                let line = line.trim();
+                value.push('\n');
+                value.push_str(if line == "." { "" } else { line });
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            paragraph.push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    paragraphs
+        .into_iter()
+        .filter_map(|fields| {
+            let field = |key: &str| {
+                fields
+                    .iter()
+                    .find(|(field, _)| field == key)
+                    .map(|(_, value)| value.trim().to_string())
+            };
+            let words = |key: &str| -> Vec<String> {
+                field(key)
+                    .map(|value| value.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default()
+            };
+            let source = AptSource {
+                name: name.map(str::to_string),
+                file: file.to_string(),
+                enabled: field("enabled").is_none_or(|value| value != "no"),
+                types: words("types"),
+                uris: words("uris"),
+                suites: words("suites"),
+                components: words("components"),
+                signed_by: field("signed-by").filter(|value| !value.is_empty()),
+            };
+            (!source.types.is_empty() && !source.uris.is_empty() && !source.suites.is_empty()).then_some(source)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> AddSourceRequest {
+        AddSourceRequest {
+            name: "internal".to_string(),
+            types: vec![],
+            uris: vec!["https://apt.example.com/debian".to_string()],
+            suites: vec!["bookworm".to_string()],
+            components: vec!["main".to_string()],
+            signed_by: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_one_line() {
+        let content = "\
+deb http://deb.debian.org/debian bookworm main contrib
+# deb-src http://deb.debian.org/debian bookworm main
+# Security updates
+deb [arch=amd64 signed-by=/usr/share/keyrings/docker.gpg] https://download.docker.com/linux/debian bookworm stable # docker
+";
+        let sources = parse_one_line(content, SOURCES_LIST, None);
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[0].components, vec!["main", "contrib"]);
+        assert!(sources[0].enabled && sources[0].name.is_none());
+        assert!(!sources[1].enabled);
+        assert_eq!(sources[1].types, vec!["deb-src"]);
+        assert_eq!(sources[2].uris, vec!["https://download.docker.com/linux/debian"]);
+        assert_eq!(sources[2].components, vec!["stable"]);
+        assert_eq!(sources[2].signed_by.as_deref(), Some("/usr/share/keyrings/docker.gpg"));
+    }
+
+    #[test]
+    fn test_render_round_trip() {
+        let mut request = request();
+        request.signed_by = Some(format!("{ARMORED_KEY_HEADER}\n\nmQINBGR\n-----END PGP PUBLIC KEY BLOCK-----\n"));
+        let content = render(&request);
+        assert!(content.contains("Signed-By:\n -----BEGIN PGP PUBLIC KEY BLOCK-----\n .\n mQINBGR\n"));
+
+        let file = "/etc/apt/sources.list.d/internal.sources";
+        let content = format!("{content}\nTypes: deb-src\nURIs: x:\nSuites: s\nEnabled: no\n");
+        let sources = parse_deb822(&content, file, Some("internal"));
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].types, vec!["deb"]);
+        assert_eq!(sources[0].uris, request.uris);
+        assert_eq!(sources[0].components, vec!["main"]);
+        assert_eq!(sources[0].signed_by.as_deref(), request.signed_by.as_deref().map(str::trim));
+        assert!(sources[0].enabled && !sources[1].enabled);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&request()).is_ok());
+
+        let invalid = |change: fn(&mut AddSourceRequest)| {
+            let mut request = request();
+            change(&mut request);
+            validate(&request).unwrap_err().0
+        };
+        assert_eq!(invalid(|r| r.name = "../evil".to_string()), "name");
+        assert_eq!(invalid(|r| r.types = vec!["rpm".to_string()]), "types");
+        assert_eq!(invalid(|r| r.uris = vec!["https://x/ bookworm".to_string()]), "uris");
+        assert_eq!(invalid(|r| r.suites = vec!["./".to_string()]), "components");
+        assert_eq!(invalid(|r| r.components.clear()), "components");
+        assert_eq!(invalid(|r| r.signed_by = Some("keyring.gpg".to_string())), "signed_by");
+    }
+
+    #[test]
+    fn test_failed_fetches() {
+        let stderr = "\
+W: Failed to fetch https://apt.example.com/debian/dists/bookworm/InRelease  Could not resolve 'apt.example.com'
+W: Failed to fetch http://other.example.org/dists/x/InRelease  404  Not Found
+W: Some index files failed to download. They have been ignored, or old ones used instead.
+";
+        let failed = failed_fetches(stderr, &request().uris);
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].contains("apt.example.com"));
+    }
+}
@@ -0,0 +1,329 @@
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+const DEFAULT_PORT: u16 = 514;
+const LOCAL_SOCKET: &str = "/dev/log";
+/// The `daemon` facility.
+const FACILITY: u8 = 3;
+const APP_NAME: &str = "cobblerd";
+/// Messages waiting for the writer thread; more are dropped rather than block logging.
+const QUEUE_CAPACITY: usize = 1024;
+/// Bounds how long a syslog server that doesn't answer holds up the writer thread.
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// The local syslog socket (`/dev/log`)
+    Local,
+    Udp(String),
+    Tcp(String),
+}
+
+impl Endpoint {
+    /// Parses `syslog`, `syslog://host[:port]`, `syslog+udp://host[:port]` or `syslog+tcp://host[:port]`.
+    pub fn parse(target: &str) -> Option<Endpoint> {
+        if target == "syslog" {
+            return Some(Endpoint::Local);
+        }
+        let (scheme, authority) = target.split_once("://")?;
+        let authority = authority.trim_end_matches('/');
+        if authority.is_empty() {
+            return None;
+        }
+        let address = match authority.matches(':').count() {
+            0 => format!("{authority}:{DEFAULT_PORT}"),
+            1 => authority.to_string(),
+            _ if authority.starts_with('[') && !authority.ends_with(']') => authority.to_string(),
+            _ if authority.starts_with('[') => format!("{authority}:{DEFAULT_PORT}"),
+            _ => format!("[{authority}]:{DEFAULT_PORT}"),
+        };
+        match scheme {
+            "syslog" | "syslog+udp" => Some(Endpoint::Udp(address)),
+            "syslog+tcp" => Some(Endpoint::Tcp(address)),
+            _ => None,
+        }
+    }
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Local(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp { address: String, stream: Option<TcpStream> },
+}
+
+impl Transport {
+    fn connect(endpoint: &Endpoint) -> io::Result<Transport> {
+        match endpoint {
+            #[cfg(unix)]
+            Endpoint::Local => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(LOCAL_SOCKET)?;
+                Ok(Transport::Local(socket))
+            }
+            #[cfg(not(unix))]
+            Endpoint::Local => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "local syslog is only available on Unix",
+            )),
+            Endpoint::Udp(address) => {
+                let socket = UdpSocket::bind(if address.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" })?;
+                socket.connect(address)?;
+                Ok(Transport::Udp(socket))
+            }
+            Endpoint::Tcp(address) => Ok(Transport::Tcp {
+                address: address.clone(),
+                stream: Some(connect_tcp(address)?),
+            }),
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Transport::Local(socket) => socket.send(message).map(|_| ()),
+            Transport::Udp(socket) => socket.send(message).map(|_| ()),
+            Transport::Tcp { address, stream } => {
+                // RFC 6587 octet counting, reconnecting once if the connection was dropped.
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(message);
+                for _ in 0..2 {
+                    let connection = match stream {
+                        Some(connection) => connection,
+                        None => stream.insert(connect_tcp(address)?),
+                    };
+                    match connection.write_all(&frame) {
+                        Ok(()) => return Ok(()),
+                        Err(_) => *stream = None,
+                    }
+                }
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "syslog connection lost"))
+            }
+        }
+    }
+}
+
+fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, format!("{address} has no address"));
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TCP_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+/// `MakeWriter` for the fmt layer that turns every formatted event into one RFC 5424 message.
+/// Messages are sent by a thread of their own, so that a slow or unreachable syslog server
+/// never holds up the code that logs.
+#[derive(Clone)]
+pub struct SyslogWriter {
+    queue: SyncSender<String>,
+    hostname: String,
+}
+
+impl SyslogWriter {
+    pub fn connect(endpoint: &Endpoint) -> io::Result<SyslogWriter> {
+        let mut transport = Transport::connect(endpoint)?;
+        let (queue, messages) = mpsc::sync_channel::<String>(QUEUE_CAPACITY);
+        thread::Builder::new().name("syslog".to_string()).spawn(move || {
+            for message in messages {
+                // There is nowhere left to report a failure to deliver a log line.
+                let _ = transport.send(message.as_bytes());
+            }
+        })?;
+        Ok(SyslogWriter {
+            queue,
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+        })
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogWriter {
+    type Writer = SyslogMessage;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.message(&Level::INFO)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.message(meta.level())
+    }
+}
+
+impl SyslogWriter {
+    fn message(&self, level: &Level) -> SyslogMessage {
+        SyslogMessage {
+            queue: self.queue.clone(),
+            header: header(level, &self.hostname, SystemTime::now()),
+            body: Vec::new(),
+        }
+    }
+}
+
+pub struct SyslogMessage {
+    queue: SyncSender<String>,
+    header: String,
+    body: Vec<u8>,
+}
+
+impl Write for SyslogMessage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.body.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogMessage {
+    fn drop(&mut self) {
+        let body = String::from_utf8_lossy(&self.body);
+        let body = body.trim_end();
+        if body.is_empty() {
+            return;
+        }
+        // Dropped if the queue is full, like a datagram the server can't keep up with.
+        let _ = self.queue.try_send(format!("{}{body}", self.header));
+    }
+}
+
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// RFC 5424 header up to and including the space before MSG.
+fn header(level: &Level, hostname: &str, time: SystemTime) -> String {
+    let priority = FACILITY * 8 + severity(level);
+    format!(
+        "<{priority}>1 {} {hostname} {APP_NAME} {} - - ",
+        format_timestamp(time),
+        std::process::id()
+    )
+}
+
+/// Formats a UTC timestamp as RFC 3339 with millisecond precision.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, second_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(Endpoint::parse("syslog"), Some(Endpoint::Local));
+        assert_eq!(
+            Endpoint::parse("syslog://logs.lan"),
+            Some(Endpoint::Udp("logs.lan:514".to_string()))
+        );
+        assert_eq!(
+            Endpoint::parse("syslog+tcp://10.0.0.5:6514"),
+            Some(Endpoint::Tcp("10.0.0.5:6514".to_string()))
+        );
+        assert_eq!(
+            Endpoint::parse("syslog+udp://[fd00::1]:514"),
+            Some(Endpoint::Udp("[fd00::1]:514".to_string()))
+        );
+        assert_eq!(Endpoint::parse("syslog://fd00::1"), Some(Endpoint::Udp("[fd00::1]:514".to_string())));
+        assert_eq!(Endpoint::parse("http://logs.lan"), None);
+        assert_eq!(Endpoint::parse("syslog://"), None);
+    }
+
+    #[test]
+    fn test_header() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_767_225_600_123);
+        let header = header(&Level::WARN, "raspi1", time);
+        assert_eq!(
+            header,
+            format!("<28>1 2026-01-01T00:00:00.123Z raspi1 cobblerd {} - - ", std::process::id())
+        );
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(951_827_696)), "2000-02-29T12:34:56.000Z");
+    }
+
+    #[test]
+    fn test_udp_delivery() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Udp(receiver.local_addr().unwrap().to_string());
+        let writer = SyslogWriter::connect(&endpoint).unwrap();
+
+        let mut message = writer.message(&Level::ERROR);
+        message.write_all(b"upgrade failed\n").unwrap();
+        drop(message);
+
+        let mut buffer = [0; 512];
+        let len = receiver.recv(&mut buffer).unwrap();
+        let received = String::from_utf8_lossy(&buffer[..len]);
+        assert!(received.starts_with("<27>1 "), "{received}");
+        assert!(received.ends_with(" - - upgrade failed"), "{received}");
+    }
+
+    #[test]
+    fn test_tcp_delivery() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+        let writer = SyslogWriter::connect(&endpoint).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut message = writer.message(&Level::WARN);
+        message.write_all(b"disk almost full\n").unwrap();
+        drop(message);
+
+        let mut received = Vec::new();
+        let mut buffer = [0; 512];
+        while !received.ends_with(b"disk almost full") {
+            let len = stream.read(&mut buffer).unwrap();
+            assert!(len > 0);
+            received.extend_from_slice(&buffer[..len]);
+        }
+        let received = String::from_utf8_lossy(&received);
+        let (length, frame) = received.split_once(' ').unwrap();
+        assert_eq!(length.parse::<usize>().unwrap(), frame.len());
+        assert!(frame.starts_with("<28>1 "), "{frame}");
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::process::Command;
+
+const REBOOT_REQUIRED_FILE: &str = "/var/run/reboot-required";
+const UPTIME_FILE: &str = "/proc/uptime";
+const KERNEL_RELEASE_FILE: &str = "/proc/sys/kernel/osrelease";
+pub const OS_RELEASE_FILE: &str = "/etc/os-release";
+const ROUTE_FILE: &str = "/proc/net/route";
+
+pub fn is_reboot_required() -> bool {
+    Path::new(REBOOT_REQUIRED_FILE).exists()
+}
+
+pub fn uptime_seconds() -> Option<u64> {
+    fs::read_to_string(UPTIME_FILE)
+        .ok()
+        .and_then(|content| parse_uptime(&content))
+}
+
+pub fn kernel_release() -> Option<String> {
+    fs::read_to_string(KERNEL_RELEASE_FILE)
+        .ok()
+        .map(|release| release.trim().to_string())
+        .filter(|release| !release.is_empty())
+}
+
+pub fn os_name() -> Option<String> {
+    fs::read_to_string(OS_RELEASE_FILE)
+        .ok()
+        .and_then(|content| parse_os_release(&content))
+}
+
+/// MAC address of the interface carrying the IPv4 default route, for Wake-on-LAN.
+pub fn mac_address() -> Option<String> {
+    let routes = fs::read_to_string(ROUTE_FILE).ok()?;
+    let interface = default_route_interface(&routes)?;
+    let mac = fs::read_to_string(format!("/sys/class/net/{interface}/address")).ok()?;
+    let mac = mac.trim();
+    (!mac.is_empty() && mac != "00:00:00:00:00:00").then(|| mac.to_string())
+}
+
+fn default_route_interface(routes: &str) -> Option<&str> {
+    routes.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        (fields.next()? == "00000000").then_some(interface)
+    })
+}
+
+fn parse_os_release(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|value| value.trim().trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_uptime(content: &str) -> Option<u64> {
+    content
+        .split_whitespace()
+        .next()
+        .and_then(|value| value.parse::<f64>().ok())
+        .map(|seconds| seconds as u64)
+}
+
+pub fn schedule_reboot(delay_minutes: u32) -> Result<(), String> {
+    run_shutdown(&["-r", &format!("+{delay_minutes}")])
+}
+
+/// Powers the system off once `delay_minutes` have passed.
+pub fn schedule_poweroff(delay_minutes: u32) -> Result<(), String> {
+    run_shutdown(&["-P", &format!("+{delay_minutes}")])
+}
+
+/// Cancels a scheduled reboot or power-off, whichever is pending.
+pub fn cancel_shutdown() -> Result<(), String> {
+    run_shutdown(&["-c"])
+}
+
+#[cfg(target_os = "linux")]
+fn run_shutdown(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("shutdown")
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to execute shutdown: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "shutdown failed with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_shutdown(_args: &[&str]) -> Result<(), String> {
+    Err("scheduling reboots and shutdowns is only supported on Linux".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uptime() {
+        assert_eq!(parse_uptime("350735.47 234388.90\n"), Some(350735));
+        assert_eq!(parse_uptime("12.00 1.00"), Some(12));
+        assert_eq!(parse_uptime(""), None);
+        assert_eq!(parse_uptime("garbage"), None);
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let content = "NAME=\"Debian GNU/Linux\"\nPRETTY_NAME=\"Debian GNU/Linux 13 (trixie)\"\nID=debian\n";
+        assert_eq!(parse_os_release(content).as_deref(), Some("Debian GNU/Linux 13 (trixie)"));
+        assert_eq!(parse_os_release("ID=debian\n"), None);
+    }
+
+    #[test]
+    fn test_default_route_interface() {
+        let routes = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+";
+        assert_eq!(default_route_interface(routes), Some("eth0"));
+        assert_eq!(default_route_interface("Iface\tDestination\n"), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! unattended-upgrades settings. They are read from apt's merged configuration and written
+//! to a snippet of our own that overrides the distribution defaults, which stay untouched.
+
+use cobbler_api::UnattendedUpgradesConfig;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+const UNATTENDED_UPGRADE_BIN: &str = "/usr/bin/unattended-upgrade";
+/// Sorts after `20auto-upgrades` and `50unattended-upgrades`, so its values win.
+const SNIPPET_FILE: &str = "/etc/apt/apt.conf.d/52cobbler-unattended-upgrades";
+
+const ENABLED_KEY: &str = "APT::Periodic::Unattended-Upgrade";
+const ALLOWED_ORIGINS_KEY: &str = "Unattended-Upgrade::Allowed-Origins";
+const ORIGINS_PATTERN_KEY: &str = "Unattended-Upgrade::Origins-Pattern";
+const AUTOMATIC_REBOOT_KEY: &str = "Unattended-Upgrade::Automatic-Reboot";
+const AUTOMATIC_REBOOT_TIME_KEY: &str = "Unattended-Upgrade::Automatic-Reboot-Time";
+
+pub fn is_installed() -> bool {
+    Path::new(UNATTENDED_UPGRADE_BIN).exists()
+}
+
+/// The effective settings, as `apt-config dump` reports them.
+pub fn read() -> Result<UnattendedUpgradesConfig, String> {
+    Ok(parse_dump(&apt_config_dump()?))
+}
+
+/// Replaces the settings by rewriting our snippet. The previous snippet is restored if apt
+/// can't parse the configuration afterwards.
+pub fn write(config: &UnattendedUpgradesConfig) -> Result<UnattendedUpgradesConfig, String> {
+    let previous = match fs::read_to_string(SNIPPET_FILE) {
+        Ok(content) => Some(content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(format!("failed to read {SNIPPET_FILE}: {err}")),
+    };
+    write_atomically(SNIPPET_FILE, &render(config))
+        .map_err(|err| format!("failed to write {SNIPPET_FILE}: {err}"))?;
+
+    match apt_config_dump() {
+        Ok(dump) => Ok(parse_dump(&dump)),
+        Err(err) => {
+            let restored = match previous {
+                Some(content) => write_atomically(SNIPPET_FILE, &content),
+                None => fs::remove_file(SNIPPET_FILE),
+            };
+            if let Err(restore_err) = restored {
+                return Err(format!("{err}; restoring {SNIPPET_FILE} failed: {restore_err}"));
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Checks the values before they are written into apt's configuration syntax. Returns the
+/// offending field and why.
+pub fn validate(config: &UnattendedUpgradesConfig) -> Result<(), (&'static str, String)> {
+    for (field, origins) in [
+        ("allowed_origins", &config.allowed_origins),
+        ("origins_pattern", &config.origins_pattern),
+    ] {
+        if let Some(origin) = origins.iter().find(|origin| !is_valid_value(origin)) {
+            return Err((field, format!("invalid origin: {origin:?}")));
+        }
+    }
+    if let Some(time) = &config.automatic_reboot_time
+        && !is_valid_reboot_time(time)
+    {
+        return Err((
+            "automatic_reboot_time",
+            format!("invalid reboot time {time:?}, expected HH:MM or \"now\""),
+        ));
+    }
+    Ok(())
+}
+
+fn is_valid_value(value: &str) -> bool {
+    !value.trim().is_empty() && !value.chars().any(|c| c == '"' || c == ';' || c.is_control())
+}
+
+fn is_valid_reboot_time(time: &str) -> bool {
+    if time == "now" {
+        return true;
+    }
+    let Some((hours, minutes)) = time.split_once(':') else {
+        return false;
+    };
+    let in_range = |value: &str, max: u8| {
+        value.len() == 2 && value.chars().all(|c| c.is_ascii_digit()) && value.parse::<u8>().is_ok_and(|v| v <= max)
+    };
+    in_range(hours, 23) && in_range(minutes, 59)
+}
+
+fn apt_config_dump() -> Result<String, String> {
+    let output = Command::new("apt-config")
+        .arg("dump")
+        .output()
+        .map_err(|err| format!("failed to run apt-config: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "apt-config dump failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn write_atomically(path: &str, content: &str) -> io::Result<()> {
+    let tmp = format!("{path}.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Parses `apt-config dump` lines like `Key "value";` and, for list items, `Key:: "value";`.
+fn parse_dump(dump: &str) -> UnattendedUpgradesConfig {
+    let mut config = UnattendedUpgradesConfig::default();
+    for line in dump.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let value = value.trim().trim_end_matches(';').trim_matches('"').to_string();
+        match key {
+            ENABLED_KEY => config.enabled = value.parse::<u32>().is_ok_and(|days| days > 0),
+            AUTOMATIC_REBOOT_KEY => config.automatic_reboot = matches!(value.as_str(), "true" | "1" | "yes"),
+            AUTOMATIC_REBOOT_TIME_KEY => config.automatic_reboot_time = Some(value).filter(|v| !v.is_empty()),
+            _ => match key.strip_suffix("::") {
+                Some(ALLOWED_ORIGINS_KEY) if !value.is_empty() => config.allowed_origins.push(value),
+                Some(ORIGINS_PATTERN_KEY) if !value.is_empty() => config.origins_pattern.push(value),
+                _ => {}
+            },
+        }
+    }
+    config
+}
+
+/// The snippet for `config`. Lists are cleared first, since apt appends list items from
+/// later files instead of replacing them.
+fn render(config: &UnattendedUpgradesConfig) -> String {
+    let mut snippet = String::from("// Managed by cobblerd (PUT /config/unattended-upgrades); manual changes are overwritten.\n");
+    if config.enabled {
+        snippet.push_str("APT::Periodic::Update-Package-Lists \"1\";\n");
+    }
+    snippet.push_str(&format!("{ENABLED_KEY} \"{}\";\n", u8::from(config.enabled)));
+    for (key, values) in [
+        (ALLOWED_ORIGINS_KEY, &config.allowed_origins),
+        (ORIGINS_PATTERN_KEY, &config.origins_pattern),
+    ] {
+        snippet.push_str(&format!("#clear {key};\n{key} {{\n"));
+        for value in values {
+            snippet.push_str(&format!("        \"{value}\";\n"));
+        }
+        snippet.push_str("};\n");
+    }
+    snippet.push_str(&format!("{AUTOMATIC_REBOOT_KEY} \"{}\";\n", config.automatic_reboot));
+    match &config.automatic_reboot_time {
+        Some(time) => snippet.push_str(&format!("{AUTOMATIC_REBOOT_TIME_KEY} \"{time}\";\n")),
+        None => snippet.push_str(&format!("#clear {AUTOMATIC_REBOOT_TIME_KEY};\n")),
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dump() {
+        let dump = r#"APT::Periodic::Update-Package-Lists "1";
+APT::Periodic::Unattended-Upgrade "1";
+Unattended-Upgrade "";
+Unattended-Upgrade::Origins-Pattern "";
+Unattended-Upgrade::Origins-Pattern:: "origin=Debian,codename=${distro_codename},label=Debian";
+Unattended-Upgrade::Origins-Pattern:: "origin=Debian,codename=${distro_codename},label=Debian-Security";
+Unattended-Upgrade::Automatic-Reboot "false";
+Unattended-Upgrade::Automatic-Reboot-Time "02:00";
+"#;
+        let config = parse_dump(dump);
+        assert!(config.enabled);
+        assert!(config.allowed_origins.is_empty());
+        assert_eq!(config.origins_pattern.len(), 2);
+        assert!(config.origins_pattern[1].ends_with("label=Debian-Security"));
+        assert!(!config.automatic_reboot);
+        assert_eq!(config.automatic_reboot_time.as_deref(), Some("02:00"));
+
+        assert!(!parse_dump("APT::Periodic::Unattended-Upgrade \"0\";\n").enabled);
+    }
+
+    #[test]
+    fn test_render_round_trip() {
+        let config = UnattendedUpgradesConfig {
+            enabled: true,
+            allowed_origins: vec!["${distro_id}:${distro_codename}-security".to_string()],
+            origins_pattern: vec![],
+            automatic_reboot: true,
+            automatic_reboot_time: Some("03:30".to_string()),
+        };
+        let snippet = render(&config);
+        assert!(snippet.contains("#clear Unattended-Upgrade::Allowed-Origins;\n"));
+        assert!(snippet.contains("        \"${distro_id}:${distro_codename}-security\";\n"));
+
+        // apt-config dump prints the merged tree in this form.
+        let dump = "APT::Periodic::Unattended-Upgrade \"1\";\n\
+Unattended-Upgrade::Allowed-Origins \"\";\n\
+Unattended-Upgrade::Allowed-Origins:: \"${distro_id}:${distro_codename}-security\";\n\
+Unattended-Upgrade::Automatic-Reboot \"true\";\n\
+Unattended-Upgrade::Automatic-Reboot-Time \"03:30\";\n";
+        assert_eq!(parse_dump(dump), config);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = UnattendedUpgradesConfig {
+            origins_pattern: vec!["origin=Debian,label=Debian-Security".to_string()],
+            automatic_reboot_time: Some("now".to_string()),
+            ..Default::default()
+        };
+        assert!(validate(&config).is_ok());
+
+        config.automatic_reboot_time = Some("24:00".to_string());
+        assert_eq!(validate(&config).unwrap_err().0, "automatic_reboot_time");
+
+        config.automatic_reboot_time = None;
+        config.allowed_origins = vec!["evil\"; APT::Foo \"1".to_string()];
+        assert_eq!(validate(&config).unwrap_err().0, "allowed_origins");
+    }
+}
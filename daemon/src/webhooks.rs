@@ -0,0 +1,334 @@
+use crate::chat::{ChatSink, Service, Templates};
+use crate::jobs::unix_now;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const EVENT_HEADER: &str = "X-Cobbler-Event";
+const SIGNATURE_HEADER: &str = "X-Cobbler-Signature";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    #[serde(rename = "updates.available")]
+    UpdatesAvailable,
+    #[serde(rename = "upgrade.started")]
+    UpgradeStarted,
+    #[serde(rename = "upgrade.finished")]
+    UpgradeFinished,
+    #[serde(rename = "upgrade.failed")]
+    UpgradeFailed,
+    #[serde(rename = "reboot.required")]
+    RebootRequired,
+    #[serde(rename = "security.digest")]
+    SecurityDigest,
+    #[serde(rename = "guard.tripped")]
+    GuardTripped,
+}
+
+impl EventKind {
+    const ALL: [EventKind; 7] = [
+        EventKind::UpdatesAvailable,
+        EventKind::UpgradeStarted,
+        EventKind::UpgradeFinished,
+        EventKind::UpgradeFailed,
+        EventKind::RebootRequired,
+        EventKind::SecurityDigest,
+        EventKind::GuardTripped,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::UpdatesAvailable => "updates.available",
+            EventKind::UpgradeStarted => "upgrade.started",
+            EventKind::UpgradeFinished => "upgrade.finished",
+            EventKind::UpgradeFailed => "upgrade.failed",
+            EventKind::RebootRequired => "reboot.required",
+            EventKind::SecurityDigest => "security.digest",
+            EventKind::GuardTripped => "guard.tripped",
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        EventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("unknown event {value:?}"))
+    }
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    event: EventKind,
+    node_id: &'a str,
+    timestamp: u64,
+    data: serde_json::Value,
+}
+
+/// Chat sinks and the templates of their messages.
+#[derive(Clone, Default)]
+pub struct ChatConfig {
+    pub sinks: Vec<ChatSink>,
+    pub templates: Templates,
+    /// Access token for Matrix rooms
+    pub matrix_token: Option<String>,
+    /// Access token for ntfy topics that require one
+    pub ntfy_token: Option<String>,
+    /// Application token for Gotify, required by Gotify sinks
+    pub gotify_token: Option<String>,
+}
+
+/// Delivers events to the configured webhook URLs and chat sinks. A default `Notifier` has
+/// neither and drops every event.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    secret: Option<String>,
+    chat: ChatConfig,
+    node_id: String,
+}
+
+impl Notifier {
+    pub fn new(
+        urls: Vec<String>,
+        secret: Option<String>,
+        chat: ChatConfig,
+        node_id: String,
+    ) -> Result<Self, String> {
+        if urls.is_empty() && chat.sinks.is_empty() {
+            return Ok(Self::default());
+        }
+        if chat.gotify_token.is_none() && chat.sinks.iter().any(|sink| sink.service == Service::Gotify) {
+            return Err("Gotify notifications need an application token (--gotify-token)".to_string());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| format!("failed to create webhook client: {err}"))?;
+        Ok(Self {
+            inner: Some(Arc::new(Inner {
+                client,
+                urls,
+                secret,
+                chat,
+                node_id,
+            })),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Sends the event to every URL and chat sink in the background, retrying with
+    /// exponential backoff.
+    pub fn notify(&self, kind: EventKind, data: serde_json::Value) {
+        let Some(inner) = self.inner.clone() else {
+            return;
+        };
+        if !inner.chat.sinks.is_empty() {
+            let text = inner.chat.templates.render(kind, &inner.node_id, &data);
+            for sink in &inner.chat.sinks {
+                let request = chat_request(&inner.client, &inner.chat, sink, kind, &inner.node_id, &text);
+                tokio::spawn(deliver(request, sink.url.clone(), kind));
+            }
+        }
+        if inner.urls.is_empty() {
+            return;
+        }
+
+        let event = Event {
+            event: kind,
+            node_id: &inner.node_id,
+            timestamp: unix_now(),
+            data,
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(err) => {
+                error!("failed to serialize {} webhook event: {err}", kind.as_str());
+                return;
+            }
+        };
+        let signature = inner.secret.as_deref().map(|secret| sign(secret, &body));
+
+        for url in &inner.urls {
+            let mut request = inner
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, kind.as_str())
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            tokio::spawn(deliver(request, url.clone(), kind));
+        }
+    }
+}
+
+/// A Matrix message goes to `{url}/{txn_id}` with PUT, so that retries of it aren't
+/// posted twice. The other services take a plain POST; push notifications are titled with
+/// the node.
+fn chat_request(
+    client: &reqwest::Client,
+    chat: &ChatConfig,
+    sink: &ChatSink,
+    kind: EventKind,
+    node_id: &str,
+    text: &str,
+) -> reqwest::RequestBuilder {
+    let request = match sink.service {
+        Service::Matrix => {
+            let request = client.put(format!("{}/{}", sink.endpoint(), uuid::Uuid::new_v4()));
+            match &chat.matrix_token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        }
+        Service::Ntfy => {
+            let request = client.post(sink.endpoint());
+            match &chat.ntfy_token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        }
+        Service::Gotify => client
+            .post(sink.endpoint())
+            .header("X-Gotify-Key", chat.gotify_token.as_deref().unwrap_or_default()),
+        Service::Slack | Service::Discord => client.post(sink.endpoint()),
+    };
+    request
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(sink.payload(kind, node_id, text).to_string())
+}
+
+/// Sends the request, retrying with exponential backoff. `url` only labels log messages.
+async fn deliver(request: reqwest::RequestBuilder, url: String, kind: EventKind) {
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let Some(request) = request.try_clone() else {
+            error!("can't resend {} event to {url}", kind.as_str());
+            return;
+        };
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => warn!(
+                "webhook {url} rejected {} event with status {} (attempt {attempt}/{MAX_ATTEMPTS})",
+                kind.as_str(),
+                response.status()
+            ),
+            Err(err) => warn!(
+                "failed to deliver {} event to webhook {url}: {err} (attempt {attempt}/{MAX_ATTEMPTS})",
+                kind.as_str()
+            ),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    error!("giving up on {} event for webhook {url}", kind.as_str());
+}
+
+/// HMAC-SHA256 of the body, formatted as `sha256=<hex>`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={digest}")
+}
+
+/// Remembers the last observed system state so that threshold events fire only on transitions.
+#[derive(Default)]
+pub struct SystemWatch {
+    updates_above_threshold: bool,
+    reboot_required: bool,
+}
+
+impl SystemWatch {
+    pub fn observe(&mut self, updates: usize, threshold: usize, reboot_required: bool) -> Vec<EventKind> {
+        let mut events = Vec::new();
+
+        let above = updates >= threshold;
+        if above && !self.updates_above_threshold {
+            events.push(EventKind::UpdatesAvailable);
+        }
+        self.updates_above_threshold = above;
+
+        if reboot_required && !self.reboot_required {
+            events.push(EventKind::RebootRequired);
+        }
+        self.reboot_required = reboot_required;
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = Event {
+            event: EventKind::UpgradeFailed,
+            node_id: "raspi1",
+            timestamp: 1,
+            data: serde_json::json!({ "job_id": "abc" }),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "event": "upgrade.failed",
+                "node_id": "raspi1",
+                "timestamp": 1,
+                "data": { "job_id": "abc" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_system_watch_fires_on_transitions() {
+        let mut watch = SystemWatch::default();
+        assert_eq!(watch.observe(2, 5, false), vec![]);
+        assert_eq!(watch.observe(5, 5, false), vec![EventKind::UpdatesAvailable]);
+        assert_eq!(watch.observe(7, 5, true), vec![EventKind::RebootRequired]);
+        assert_eq!(watch.observe(7, 5, true), vec![]);
+        assert_eq!(watch.observe(0, 5, false), vec![]);
+        assert_eq!(
+            watch.observe(9, 5, true),
+            vec![EventKind::UpdatesAvailable, EventKind::RebootRequired]
+        );
+    }
+}